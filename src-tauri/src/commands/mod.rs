@@ -306,6 +306,12 @@ pub async fn save_config(
     proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
     config: AppConfig,
 ) -> Result<(), String> {
+    // 预热计划在保存前校验 (cron 表达式 / 时间窗口)
+    for schedule in &config.scheduled_warmup.schedules {
+        schedule
+            .validate()
+            .map_err(|e| format!("Invalid warmup schedule '{}': {}", schedule.name, e))?;
+    }
     modules::save_app_config(&config)?;
 
     // 通知托盘配置已更新
@@ -327,6 +333,24 @@ pub async fn save_config(
         instance.axum_server.update_zai(&config.proxy).await;
         // 更新实验性配置
         instance.axum_server.update_experimental(&config.proxy).await;
+        // 更新后台任务分类规则
+        instance.axum_server.update_background_tasks(&config.proxy).await;
+        // 更新配额保护配置
+        instance.token_manager.update_quota_protection(config.quota_protection.clone()).await;
+        // 更新日志策略
+        if let Some(monitor) = proxy_state.monitor.read().await.as_ref() {
+            monitor.set_policy(config.proxy.log_policy.clone());
+        }
+        // 更新链路追踪导出
+        if let Err(e) = crate::proxy::telemetry::apply_config(&config.proxy.telemetry) {
+            tracing::warn!("[Telemetry] Failed to apply tracing export config: {}", e);
+        }
+        // 更新价格表与预算规则
+        crate::proxy::budget::apply_config(&config.proxy.cost);
+        // 更新图片存储配置
+        crate::proxy::image_store::apply_config(&config.proxy.image_store);
+        // 更新批处理执行配置
+        crate::proxy::batch::apply_config(&config.proxy.batch);
         tracing::debug!("已同步热更新反代服务配置");
     }
 
@@ -770,6 +794,53 @@ pub async fn warm_up_account(account_id: String) -> Result<String, String> {
     modules::quota::warm_up_account(&account_id).await
 }
 
+/// 获取最近的预热运行记录
+#[tauri::command]
+pub async fn get_warmup_runs(limit: Option<usize>) -> Result<Vec<modules::warmup_db::WarmupRun>, String> {
+    modules::warmup_db::get_runs(limit.unwrap_or(50))
+}
+
+/// 获取某次预热运行的逐账号结果
+#[tauri::command]
+pub async fn get_warmup_run_results(run_id: i64) -> Result<Vec<modules::warmup_db::WarmupRunResult>, String> {
+    modules::warmup_db::get_run_results(run_id)
+}
+
+/// 立即执行指定的预热计划，返回运行记录 ID
+#[tauri::command]
+pub async fn run_warmup_schedule(name: String) -> Result<i64, String> {
+    let config = modules::config::load_app_config()?;
+    let schedule = config
+        .scheduled_warmup
+        .schedules
+        .iter()
+        .find(|s| s.name == name)
+        .cloned()
+        .ok_or_else(|| format!("Warmup schedule not found: {}", name))?;
+    schedule.validate()?;
+    let run_id = modules::scheduler::run_schedule(&schedule, &config.scheduled_warmup, "manual").await?;
+    let _ = modules::account::refresh_all_quotas_logic().await;
+    Ok(run_id)
+}
+
+/// 立即执行账号健康检查 (不传 account_id 时检查全部账号)
+#[tauri::command]
+pub async fn run_account_health_check(
+    app: tauri::AppHandle,
+    account_id: Option<String>,
+) -> Result<Vec<modules::account_health::HealthCheckRecord>, String> {
+    modules::account_health::run_checks(&app, account_id.as_deref()).await
+}
+
+/// 获取账号健康检查历史
+#[tauri::command]
+pub async fn get_account_health_history(
+    account_id: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<modules::account_health::HealthCheckRecord>, String> {
+    modules::account_health::get_history(account_id.as_deref(), limit.unwrap_or(100))
+}
+
 // ============================================================================
 // HTTP API 设置命令
 // ============================================================================
@@ -845,3 +916,32 @@ pub async fn get_token_stats_account_trend_hourly(hours: i64) -> Result<Vec<crat
 pub async fn get_token_stats_account_trend_daily(days: i64) -> Result<Vec<crate::modules::token_stats::AccountTrendPoint>, String> {
     crate::modules::token_stats::get_account_trend_daily(days)
 }
+
+#[tauri::command]
+pub async fn get_token_stats_by_session(hours: i64, limit: Option<usize>) -> Result<Vec<crate::modules::token_stats::SessionTokenStats>, String> {
+    crate::modules::token_stats::get_session_stats(hours, limit.unwrap_or(100))
+}
+
+/// 按任意时间范围、粒度与维度组合查询用量
+#[tauri::command]
+pub async fn query_usage_report(
+    query: crate::modules::token_stats::UsageQuery,
+) -> Result<Vec<crate::modules::token_stats::UsageReportRow>, String> {
+    crate::modules::token_stats::query_usage(&query)
+}
+
+/// 导出用量报表 (csv / json)，返回文件内容
+#[tauri::command]
+pub async fn export_usage_report(
+    query: crate::modules::token_stats::UsageQuery,
+    format: Option<String>,
+) -> Result<String, String> {
+    let format = crate::modules::usage_report::UsageReportFormat::parse(format.as_deref().unwrap_or("csv"))?;
+    crate::modules::usage_report::export(&query, format)
+}
+
+/// 各预算规则在当前周期内的费用与超额状态
+#[tauri::command]
+pub async fn get_budget_status() -> Result<Vec<crate::proxy::budget::BudgetStatus>, String> {
+    Ok(crate::proxy::budget::statuses())
+}
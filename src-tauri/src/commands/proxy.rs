@@ -62,8 +62,25 @@ pub async fn start_proxy_service(
         // Sync enabled state from config
         if let Some(monitor) = monitor_lock.as_ref() {
             monitor.set_enabled(config.enable_logging);
+            monitor.set_policy(config.log_policy.clone());
+            // 保留策略需在配置应用后再启动，首轮清理才会使用用户配置
+            monitor.start_retention();
         }
     }
+
+    // OTLP 链路追踪导出
+    if let Err(e) = crate::proxy::telemetry::apply_config(&config.telemetry) {
+        tracing::warn!("[Telemetry] Failed to apply tracing export config: {}", e);
+    }
+
+    // 价格表与预算规则
+    crate::proxy::budget::apply_config(&config.cost);
+
+    // 图片存储与链接签名
+    crate::proxy::image_store::apply_config(&config.image_store);
+
+    // 批处理执行配置
+    crate::proxy::batch::apply_config(&config.batch);
     
     let monitor = state.monitor.read().await.as_ref().unwrap().clone();
     
@@ -77,6 +94,10 @@ pub async fn start_proxy_service(
     token_manager.start_auto_cleanup(); // 启动限流记录自动清理后台任务
     // 同步 UI 传递的调度配置
     token_manager.update_sticky_config(config.scheduling.clone()).await;
+    // 配额保护配置不在 ProxyConfig 中，从应用配置同步
+    if let Ok(app_config) = crate::modules::config::load_app_config() {
+        token_manager.update_quota_protection(app_config.quota_protection).await;
+    }
     
     // 3. 加载账号
     let active_accounts = token_manager.load_accounts().await
@@ -103,7 +124,8 @@ pub async fn start_proxy_service(
             config.zai.clone(),
             monitor.clone(),
             config.experimental.clone(),
-
+            config.circuit_breaker.clone(),
+            config.background_tasks.clone(),
         ).await {
             Ok((server, handle)) => (server, handle),
             Err(e) => return Err(format!("启动 Axum 服务器失败: {}", e)),
@@ -229,6 +251,56 @@ pub async fn clear_proxy_logs(
     Ok(())
 }
 
+/// 按当前日志策略重新处理已存储的日志 (脱敏 / 截断 / 保留期清理)
+///
+/// 返回 (重写的日志数, 清除正文的日志数, 删除的日志数)
+#[tauri::command]
+pub async fn reapply_proxy_log_policy() -> Result<(usize, usize, usize), String> {
+    let config = crate::modules::config::load_app_config()?.proxy.log_policy;
+    tokio::task::spawn_blocking(move || {
+        let (cleared, deleted) = crate::modules::proxy_db::apply_retention(
+            config.body_retention_days,
+            config.metadata_retention_days,
+        )?;
+        let policy = crate::proxy::log_policy::LogPolicy::new(config);
+        let rewritten = crate::modules::proxy_db::reapply_log_policy(&policy)?;
+        Ok((rewritten, cleared, deleted))
+    })
+    .await
+    .map_err(|e| format!("Log policy task failed: {}", e))?
+}
+
+/// 重放一条已记录的请求 (可覆盖模型 / 账号 / 实验性配置)
+#[tauri::command]
+pub async fn replay_proxy_log(
+    state: State<'_, ProxyServiceState>,
+    log_id: String,
+    options: Option<crate::proxy::replay::ReplayOptions>,
+) -> Result<crate::proxy::replay::ReplayOutcome, String> {
+    // 只在取状态时持有锁，避免长时间重放阻塞停止服务
+    let (app_state, api_key) = {
+        let instance_lock = state.instance.read().await;
+        match instance_lock.as_ref() {
+            Some(instance) => (instance.axum_server.app_state(), instance.config.api_key.clone()),
+            None => return Err("服务未运行".to_string()),
+        }
+    };
+    // 原始日志未记录请求头时，以当前配置的 API Key 通过鉴权
+    let mut headers = axum::http::HeaderMap::new();
+    if let Ok(value) = axum::http::HeaderValue::from_str(&format!("Bearer {}", api_key)) {
+        headers.insert(axum::http::header::AUTHORIZATION, value);
+    }
+    crate::proxy::replay::replay_log(app_state, &log_id, options.unwrap_or_default(), &headers).await
+}
+
+/// 获取某条日志的全部重放结果
+#[tauri::command]
+pub async fn get_proxy_log_replays(
+    log_id: String,
+) -> Result<Vec<crate::proxy::replay::LogReplay>, String> {
+    crate::modules::proxy_db::get_replays(&log_id)
+}
+
 /// 获取反代请求日志 (分页)
 #[tauri::command]
 pub async fn get_proxy_logs_paginated(
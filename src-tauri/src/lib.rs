@@ -54,6 +54,26 @@ pub fn run() {
     if let Err(e) = modules::token_stats::init_db() {
         error!("Failed to initialize token stats database: {}", e);
     }
+
+    // Initialize warmup history / run log database
+    if let Err(e) = modules::warmup_db::init_db() {
+        error!("Failed to initialize warmup database: {}", e);
+    }
+
+    // Initialize account health history database
+    if let Err(e) = modules::account_health::init_db() {
+        error!("Failed to initialize account health database: {}", e);
+    }
+
+    // Initialize response cache database
+    if let Err(e) = modules::response_cache::init_db() {
+        error!("Failed to initialize response cache database: {}", e);
+    }
+
+    // Initialize batch job / checkpoint database
+    if let Err(e) = modules::batch_db::init_db() {
+        error!("Failed to initialize batch database: {}", e);
+    }
     
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
@@ -127,6 +147,15 @@ pub fn run() {
             
             // Start smart scheduler
             modules::scheduler::start_scheduler(app.handle().clone());
+
+            // Start daily usage report job
+            modules::usage_report::start_daily_report_job();
+
+            // Start background account health checker
+            modules::account_health::start_health_checker(app.handle().clone());
+
+            // Start generated image cleanup
+            proxy::image_store::start_cleanup_job();
             
             // Start HTTP API server (for external calls, e.g. VS Code plugin)
             match modules::http_api::load_settings() {
@@ -223,6 +252,9 @@ pub fn run() {
             commands::proxy::get_proxy_logs_filtered,
             commands::proxy::set_proxy_monitor_enabled,
             commands::proxy::clear_proxy_logs,
+            commands::proxy::reapply_proxy_log_policy,
+            commands::proxy::replay_proxy_log,
+            commands::proxy::get_proxy_log_replays,
             commands::proxy::generate_api_key,
             commands::proxy::reload_proxy_accounts,
             commands::proxy::update_model_mapping,
@@ -238,6 +270,11 @@ pub fn run() {
             // Warmup commands
             commands::warm_up_all_accounts,
             commands::warm_up_account,
+            commands::get_warmup_runs,
+            commands::get_warmup_run_results,
+            commands::run_warmup_schedule,
+            commands::run_account_health_check,
+            commands::get_account_health_history,
             // HTTP API settings commands
             commands::get_http_api_settings,
             commands::save_http_api_settings,
@@ -252,6 +289,10 @@ pub fn run() {
             commands::get_token_stats_model_trend_daily,
             commands::get_token_stats_account_trend_hourly,
             commands::get_token_stats_account_trend_daily,
+            commands::get_token_stats_by_session,
+            commands::query_usage_report,
+            commands::export_usage_report,
+            commands::get_budget_status,
             proxy::cli_sync::get_cli_sync_status,
             proxy::cli_sync::execute_cli_sync,
             proxy::cli_sync::execute_cli_restore,
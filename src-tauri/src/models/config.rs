@@ -23,6 +23,10 @@ pub struct AppConfig {
     pub quota_protection: QuotaProtectionConfig, // [NEW] Quota protection configuration
     #[serde(default)]
     pub pinned_quota_models: PinnedQuotaModelsConfig, // [NEW] Pinned quota models list
+    #[serde(default)]
+    pub usage_report: UsageReportConfig, // [NEW] Scheduled daily usage report
+    #[serde(default)]
+    pub account_health: AccountHealthConfig, // [NEW] Background account health checks
 }
 
 /// Scheduled warmup configuration
@@ -34,6 +38,22 @@ pub struct ScheduledWarmupConfig {
     /// List of models to warmup
     #[serde(default = "default_warmup_models")]
     pub monitored_models: Vec<String>,
+
+    /// Minimum interval between two warmups of the same account + model
+    #[serde(default = "default_warmup_cooldown_minutes")]
+    pub cooldown_minutes: u64,
+
+    /// Cron / time window schedules (in addition to the 100% quota scan)
+    #[serde(default)]
+    pub schedules: Vec<crate::modules::warmup_schedule::WarmupSchedule>,
+
+    /// Account tags usable in schedules: tag -> account emails
+    #[serde(default)]
+    pub account_tags: std::collections::HashMap<String, Vec<String>>,
+}
+
+fn default_warmup_cooldown_minutes() -> u64 {
+    240 // Pro accounts reset every 5h, 1h margin
 }
 
 fn default_warmup_models() -> Vec<String> {
@@ -50,6 +70,9 @@ impl ScheduledWarmupConfig {
         Self {
             enabled: false,
             monitored_models: default_warmup_models(),
+            cooldown_minutes: default_warmup_cooldown_minutes(),
+            schedules: Vec::new(),
+            account_tags: std::collections::HashMap::new(),
         }
     }
 }
@@ -130,6 +153,110 @@ impl Default for PinnedQuotaModelsConfig {
     }
 }
 
+/// Scheduled daily usage report configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReportConfig {
+    /// Whether to write a report file for each finished (UTC) day
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Output directory (defaults to `<data dir>/reports`)
+    #[serde(default)]
+    pub directory: Option<String>,
+
+    #[serde(default)]
+    pub format: crate::modules::usage_report::UsageReportFormat,
+
+    /// Bucket size inside the daily report
+    #[serde(default = "default_report_bucket")]
+    pub bucket: crate::modules::token_stats::UsageBucket,
+
+    #[serde(default = "default_report_group_by")]
+    pub group_by: Vec<crate::modules::token_stats::UsageDimension>,
+}
+
+fn default_report_bucket() -> crate::modules::token_stats::UsageBucket {
+    crate::modules::token_stats::UsageBucket::Total
+}
+
+fn default_report_group_by() -> Vec<crate::modules::token_stats::UsageDimension> {
+    use crate::modules::token_stats::UsageDimension;
+    vec![UsageDimension::Account, UsageDimension::Model]
+}
+
+impl UsageReportConfig {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            directory: None,
+            format: crate::modules::usage_report::UsageReportFormat::default(),
+            bucket: default_report_bucket(),
+            group_by: default_report_group_by(),
+        }
+    }
+}
+
+impl Default for UsageReportConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Background account health check configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountHealthConfig {
+    /// Whether to periodically check every account
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Minutes between two full check rounds
+    #[serde(default = "default_health_interval_minutes")]
+    pub interval_minutes: u64,
+
+    /// Consecutive failures (403 / 401 / missing project) before the proxy stops using an account.
+    /// `invalid_grant` disables immediately.
+    #[serde(default = "default_health_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// Set `disabled` / `proxy_disabled` on failing accounts
+    #[serde(default = "default_true")]
+    pub auto_disable: bool,
+
+    /// Re-enable accounts disabled by the checker (or by invalid_grant) once they pass again
+    #[serde(default = "default_true")]
+    pub auto_recover: bool,
+}
+
+fn default_health_interval_minutes() -> u64 {
+    60
+}
+
+fn default_health_failure_threshold() -> u32 {
+    2
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl AccountHealthConfig {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            interval_minutes: default_health_interval_minutes(),
+            failure_threshold: default_health_failure_threshold(),
+            auto_disable: true,
+            auto_recover: true,
+        }
+    }
+}
+
+impl Default for AccountHealthConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AppConfig {
     pub fn new() -> Self {
         Self {
@@ -147,6 +274,8 @@ impl AppConfig {
             scheduled_warmup: ScheduledWarmupConfig::default(),
             quota_protection: QuotaProtectionConfig::default(),
             pinned_quota_models: PinnedQuotaModelsConfig::default(),
+            usage_report: UsageReportConfig::default(),
+            account_health: AccountHealthConfig::default(),
         }
     }
 }
@@ -0,0 +1,609 @@
+//! Account health checks
+//! Periodically validates each account's refresh token, project resolution and quota fetch,
+//! disables failing accounts with structured reason codes (`health_check:<code>: <detail>`)
+//! and re-enables them once they pass again. Every check is kept in `account_health.db`.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::{Emitter, Manager};
+
+use crate::models::config::AccountHealthConfig;
+use crate::models::{Account, QuotaData, TokenData};
+use crate::modules::{account, config, logger, oauth, quota};
+
+/// Prefix of disable reasons written by the checker
+pub const REASON_PREFIX: &str = "health_check:";
+/// History retention
+const HISTORY_RETENTION_SECS: i64 = 30 * 86400;
+/// Re-check the config this often while the checker is disabled
+const IDLE_POLL_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthReason {
+    /// refresh_token revoked / expired
+    InvalidGrant,
+    /// 403 from quota or loadCodeAssist
+    Forbidden,
+    /// 401 even with a freshly refreshed token
+    Unauthorized,
+    /// loadCodeAssist returned no cloudaicompanionProject
+    NoProject,
+    /// Token refresh failed for another reason (network, 5xx)
+    RefreshFailed,
+    /// loadCodeAssist failed for another reason
+    ProjectError,
+    /// Quota fetch failed for another reason
+    QuotaError,
+}
+
+impl HealthReason {
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::InvalidGrant => "invalid_grant",
+            Self::Forbidden => "forbidden",
+            Self::Unauthorized => "unauthorized",
+            Self::NoProject => "no_project",
+            Self::RefreshFailed => "refresh_failed",
+            Self::ProjectError => "project_error",
+            Self::QuotaError => "quota_error",
+        }
+    }
+
+    /// Account-level problem (vs. a transient error that says nothing about the account)
+    pub fn is_account_fault(self) -> bool {
+        matches!(self, Self::InvalidGrant | Self::Forbidden | Self::Unauthorized | Self::NoProject)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Healthy,
+    /// Transient failure, account left untouched
+    Degraded,
+    Unhealthy,
+}
+
+impl HealthStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Healthy => "healthy",
+            Self::Degraded => "degraded",
+            Self::Unhealthy => "unhealthy",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "healthy" => Self::Healthy,
+            "unhealthy" => Self::Unhealthy,
+            _ => Self::Degraded,
+        }
+    }
+}
+
+/// What the checker did to the account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthAction {
+    Disabled,
+    ProxyDisabled,
+    Recovered,
+}
+
+impl HealthAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::ProxyDisabled => "proxy_disabled",
+            Self::Recovered => "recovered",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "disabled" => Some(Self::Disabled),
+            "proxy_disabled" => Some(Self::ProxyDisabled),
+            "recovered" => Some(Self::Recovered),
+            _ => None,
+        }
+    }
+}
+
+/// One health check of one account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheckRecord {
+    pub account_id: String,
+    pub email: String,
+    pub checked_at: i64,
+    pub status: HealthStatus,
+    pub reason: Option<HealthReason>,
+    pub message: Option<String>,
+    pub action: Option<HealthAction>,
+    pub duration_ms: u64,
+}
+
+/// Outcome of the three probes
+#[derive(Debug, Clone)]
+struct ProbeOutcome {
+    reason: Option<HealthReason>,
+    message: Option<String>,
+}
+
+impl ProbeOutcome {
+    fn healthy() -> Self {
+        Self { reason: None, message: None }
+    }
+
+    fn failed(reason: HealthReason, message: impl Into<String>) -> Self {
+        Self {
+            reason: Some(reason),
+            message: Some(truncate(&message.into(), 300)),
+        }
+    }
+
+    fn status(&self) -> HealthStatus {
+        match self.reason {
+            None => HealthStatus::Healthy,
+            Some(r) if r.is_account_fault() => HealthStatus::Unhealthy,
+            Some(_) => HealthStatus::Degraded,
+        }
+    }
+}
+
+fn truncate(value: &str, max_chars: usize) -> String {
+    if value.chars().count() <= max_chars {
+        return value.to_string();
+    }
+    let mut s: String = value.chars().take(max_chars).collect();
+    s.push('…');
+    s
+}
+
+/// Reason string stored on the account, e.g. "health_check:forbidden: 403 Forbidden"
+pub fn format_reason(reason: HealthReason, message: Option<&str>) -> String {
+    match message {
+        Some(m) if !m.is_empty() => format!("{}{}: {}", REASON_PREFIX, reason.code(), m),
+        _ => format!("{}{}", REASON_PREFIX, reason.code()),
+    }
+}
+
+fn set_by_checker(reason: Option<&String>) -> bool {
+    reason.is_some_and(|r| r.starts_with(REASON_PREFIX))
+}
+
+/// `disabled` was set by the checker or by a revoked token, not by a person
+fn disabled_recoverable(account: &Account) -> bool {
+    account.disabled
+        && (set_by_checker(account.disabled_reason.as_ref())
+            || account.disabled_reason.as_ref().is_some_and(|r| r.starts_with("invalid_grant")))
+}
+
+/// `proxy_disabled` was set by the checker
+fn proxy_disabled_recoverable(account: &Account) -> bool {
+    account.proxy_disabled && set_by_checker(account.proxy_disabled_reason.as_ref())
+}
+
+/// Decide the account change for a check; `consecutive_unhealthy` includes this check
+fn decide_action(
+    account: &Account,
+    outcome: &ProbeOutcome,
+    consecutive_unhealthy: u32,
+    config: &AccountHealthConfig,
+) -> Option<HealthAction> {
+    match outcome.reason {
+        None => (config.auto_recover && (disabled_recoverable(account) || proxy_disabled_recoverable(account)))
+            .then_some(HealthAction::Recovered),
+        Some(_) if !config.auto_disable => None,
+        Some(HealthReason::InvalidGrant) => (!account.disabled).then_some(HealthAction::Disabled),
+        Some(reason) if reason.is_account_fault() => {
+            (!account.disabled && !account.proxy_disabled && consecutive_unhealthy >= config.failure_threshold.max(1))
+                .then_some(HealthAction::ProxyDisabled)
+        }
+        Some(_) => None,
+    }
+}
+
+/// Apply the action to the account (in place); the caller saves it
+fn apply_action(account: &mut Account, action: &HealthAction, outcome: &ProbeOutcome, now: i64) {
+    match action {
+        HealthAction::Disabled => {
+            account.disabled = true;
+            account.disabled_at = Some(now);
+            account.disabled_reason = outcome.reason.map(|r| format_reason(r, outcome.message.as_deref()));
+        }
+        HealthAction::ProxyDisabled => {
+            account.proxy_disabled = true;
+            account.proxy_disabled_at = Some(now);
+            account.proxy_disabled_reason = outcome.reason.map(|r| format_reason(r, outcome.message.as_deref()));
+        }
+        HealthAction::Recovered => {
+            // Only clear the flags the checker is allowed to undo; manual disables stay
+            if disabled_recoverable(account) {
+                account.disabled = false;
+                account.disabled_at = None;
+                account.disabled_reason = None;
+            }
+            if proxy_disabled_recoverable(account) {
+                account.proxy_disabled = false;
+                account.proxy_disabled_at = None;
+                account.proxy_disabled_reason = None;
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Probes
+// ============================================================================
+
+/// Fields refreshed by a probe, written back onto the account as it is on disk when saving
+#[derive(Debug, Default)]
+struct ProbeUpdates {
+    token: Option<TokenData>,
+    quota: Option<QuotaData>,
+}
+
+impl ProbeUpdates {
+    fn apply(self, account: &mut Account) {
+        if let Some(token) = self.token {
+            account.token = token;
+        }
+        if let Some(quota) = self.quota {
+            account.update_quota(quota);
+        }
+    }
+}
+
+/// Refresh token -> project resolution -> quota fetch. The refreshed token / project / quota go into `updates`.
+async fn probe(account: &Account, updates: &mut ProbeUpdates) -> ProbeOutcome {
+    // 1. Refresh token
+    let token = match oauth::refresh_access_token(&account.token.refresh_token).await {
+        Ok(t) => t,
+        Err(e) if e.contains("invalid_grant") => return ProbeOutcome::failed(HealthReason::InvalidGrant, e),
+        Err(e) => return ProbeOutcome::failed(HealthReason::RefreshFailed, e),
+    };
+    let token = updates.token.insert(TokenData::new(
+        token.access_token,
+        account.token.refresh_token.clone(),
+        token.expires_in,
+        account.token.email.clone(),
+        account.token.project_id.clone(),
+        None,
+    ));
+
+    // 2. Project resolution
+    let project_id = match crate::proxy::project_resolver::load_code_assist_project(&token.access_token).await {
+        Ok(Some(project_id)) => project_id,
+        Ok(None) => return ProbeOutcome::failed(HealthReason::NoProject, "loadCodeAssist returned no cloudaicompanionProject"),
+        Err((Some(status), e)) if status == reqwest::StatusCode::FORBIDDEN => {
+            return ProbeOutcome::failed(HealthReason::Forbidden, e)
+        }
+        Err((Some(status), e)) if status == reqwest::StatusCode::UNAUTHORIZED => {
+            return ProbeOutcome::failed(HealthReason::Unauthorized, e)
+        }
+        Err((_, e)) => return ProbeOutcome::failed(HealthReason::ProjectError, e),
+    };
+    token.project_id = Some(project_id.clone());
+
+    // 3. Quota fetch
+    match quota::fetch_quota_with_cache(&token.access_token, &account.email, Some(&project_id)).await {
+        Ok((q, _)) if q.is_forbidden => ProbeOutcome::failed(HealthReason::Forbidden, "quota API returned 403 Forbidden"),
+        Ok((q, _)) => {
+            updates.quota = Some(q);
+            ProbeOutcome::healthy()
+        }
+        Err(e) => {
+            let message = e.to_string();
+            if message.contains("401") {
+                ProbeOutcome::failed(HealthReason::Unauthorized, message)
+            } else {
+                ProbeOutcome::failed(HealthReason::QuotaError, message)
+            }
+        }
+    }
+}
+
+/// Check one account, update it on disk when needed and record the result
+pub async fn check_account(account: Account, config: &AccountHealthConfig) -> Result<HealthCheckRecord, String> {
+    let started = std::time::Instant::now();
+    let mut updates = ProbeUpdates::default();
+    let outcome = probe(&account, &mut updates).await;
+    let now = chrono::Utc::now().timestamp();
+    let status = outcome.status();
+
+    let conn = connect_db()?;
+    let previous_unhealthy = query_consecutive_unhealthy(&conn, &account.id)?;
+    let consecutive = if status == HealthStatus::Unhealthy { previous_unhealthy + 1 } else { 0 };
+
+    // The snapshot may be stale after the probe (manual edits, TokenManager refreshes, quota refreshes):
+    // re-load right before saving and only write back what the probe changed
+    let mut account = account::load_account(&account.id)?;
+    updates.apply(&mut account);
+    let action = decide_action(&account, &outcome, consecutive, config);
+    if let Some(action) = &action {
+        apply_action(&mut account, action, &outcome, now);
+        logger::log_warn(&format!(
+            "[Health] {} -> {} ({})",
+            account.email,
+            action.as_str(),
+            outcome.reason.map(|r| r.code()).unwrap_or("ok")
+        ));
+    }
+    account::save_account(&account)?;
+
+    let record = HealthCheckRecord {
+        account_id: account.id.clone(),
+        email: account.email.clone(),
+        checked_at: now,
+        status,
+        reason: outcome.reason,
+        message: outcome.message,
+        action,
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+    insert_record(&conn, &record)?;
+    Ok(record)
+}
+
+/// Check all accounts (or one), then reload the proxy pool if any account changed state
+pub async fn run_checks(app_handle: &tauri::AppHandle, account_id: Option<&str>) -> Result<Vec<HealthCheckRecord>, String> {
+    let config = config::load_app_config()?.account_health;
+    let accounts: Vec<Account> = account::list_accounts()?
+        .into_iter()
+        .filter(|a| account_id.is_none_or(|id| a.id == id))
+        .collect();
+
+    let mut records = Vec::new();
+    for (i, account) in accounts.into_iter().enumerate() {
+        if i > 0 {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+        let email = account.email.clone();
+        match check_account(account, &config).await {
+            Ok(record) => records.push(record),
+            Err(e) => logger::log_error(&format!("[Health] Check failed for {}: {}", email, e)),
+        }
+    }
+
+    if records.iter().any(|r| r.action.is_some()) {
+        let state = app_handle.state::<crate::commands::proxy::ProxyServiceState>();
+        let _ = crate::commands::proxy::reload_proxy_accounts(state).await;
+        crate::modules::tray::update_tray_menus(app_handle);
+        let changed: Vec<&HealthCheckRecord> = records.iter().filter(|r| r.action.is_some()).collect();
+        let _ = app_handle.emit("account://health-changed", &changed);
+    }
+
+    let _ = prune(chrono::Utc::now().timestamp() - HISTORY_RETENTION_SECS);
+    Ok(records)
+}
+
+pub fn start_health_checker(app_handle: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let health_config = config::load_app_config()
+                .map(|c| c.account_health)
+                .unwrap_or_default();
+            if !health_config.enabled {
+                tokio::time::sleep(std::time::Duration::from_secs(IDLE_POLL_SECS)).await;
+                continue;
+            }
+
+            match run_checks(&app_handle, None).await {
+                Ok(records) => {
+                    let unhealthy = records.iter().filter(|r| r.status != HealthStatus::Healthy).count();
+                    logger::log_info(&format!(
+                        "[Health] Checked {} accounts, {} not healthy",
+                        records.len(),
+                        unhealthy
+                    ));
+                }
+                Err(e) => logger::log_error(&format!("[Health] Health check round failed: {}", e)),
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(health_config.interval_minutes.max(1) * 60)).await;
+        }
+    });
+}
+
+// ============================================================================
+// History
+// ============================================================================
+
+fn get_db_path() -> Result<PathBuf, String> {
+    let data_dir = account::get_data_dir()?;
+    Ok(data_dir.join("account_health.db"))
+}
+
+fn connect_db() -> Result<Connection, String> {
+    let db_path = get_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    // Enable WAL mode for better concurrency
+    conn.pragma_update(None, "journal_mode", "WAL")
+        .map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "busy_timeout", 5000)
+        .map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "synchronous", "NORMAL")
+        .map_err(|e| e.to_string())?;
+
+    Ok(conn)
+}
+
+/// Initialize the account health history database
+pub fn init_db() -> Result<(), String> {
+    let conn = connect_db()?;
+    create_schema(&conn)
+}
+
+fn create_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS account_health_checks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            account_id TEXT NOT NULL,
+            email TEXT NOT NULL,
+            checked_at INTEGER NOT NULL,
+            status TEXT NOT NULL,
+            reason TEXT,
+            message TEXT,
+            action TEXT,
+            duration_ms INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_health_account ON account_health_checks (account_id, id DESC);
+        CREATE INDEX IF NOT EXISTS idx_health_checked ON account_health_checks (checked_at DESC);",
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn insert_record(conn: &Connection, record: &HealthCheckRecord) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO account_health_checks (account_id, email, checked_at, status, reason, message, action, duration_ms)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            record.account_id,
+            record.email,
+            record.checked_at,
+            record.status.as_str(),
+            record.reason.map(|r| r.code()),
+            record.message,
+            record.action.as_ref().map(|a| a.as_str()),
+            record.duration_ms as i64
+        ],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Number of unhealthy checks since the last healthy / degraded one
+fn query_consecutive_unhealthy(conn: &Connection, account_id: &str) -> Result<u32, String> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM account_health_checks
+         WHERE account_id = ?1 AND status = 'unhealthy'
+           AND id > COALESCE((SELECT MAX(id) FROM account_health_checks
+                              WHERE account_id = ?1 AND status != 'unhealthy'), 0)",
+        [account_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Latest checks, newest first (optionally for one account)
+pub fn get_history(account_id: Option<&str>, limit: usize) -> Result<Vec<HealthCheckRecord>, String> {
+    let conn = connect_db()?;
+    query_history(&conn, account_id, limit)
+}
+
+fn query_history(conn: &Connection, account_id: Option<&str>, limit: usize) -> Result<Vec<HealthCheckRecord>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT account_id, email, checked_at, status, reason, message, action, duration_ms
+             FROM account_health_checks
+             WHERE ?1 IS NULL OR account_id = ?1
+             ORDER BY id DESC LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![account_id, limit as i64], |row| {
+            let reason: Option<String> = row.get(4)?;
+            let action: Option<String> = row.get(6)?;
+            Ok(HealthCheckRecord {
+                account_id: row.get(0)?,
+                email: row.get(1)?,
+                checked_at: row.get(2)?,
+                status: HealthStatus::parse(&row.get::<_, String>(3)?),
+                reason: reason.and_then(|r| serde_json::from_value(serde_json::Value::String(r)).ok()),
+                message: row.get(5)?,
+                action: action.as_deref().and_then(HealthAction::parse),
+                duration_ms: row.get::<_, i64>(7)? as u64,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
+fn prune(cutoff: i64) -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute("DELETE FROM account_health_checks WHERE checked_at < ?1", [cutoff])
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account() -> Account {
+        let token = TokenData::new("at".into(), "rt".into(), 3600, None, None, None);
+        Account::new("id-1".into(), "a@example.com".into(), token)
+    }
+
+    #[test]
+    fn test_decide_disable_and_recover() {
+        let config = AccountHealthConfig::default();
+        let forbidden = ProbeOutcome::failed(HealthReason::Forbidden, "403");
+        let mut acc = account();
+
+        // Below the threshold: keep the account
+        assert_eq!(decide_action(&acc, &forbidden, 1, &config), None);
+        assert_eq!(decide_action(&acc, &forbidden, 2, &config), Some(HealthAction::ProxyDisabled));
+        // Transient errors never disable
+        let network = ProbeOutcome::failed(HealthReason::QuotaError, "timeout");
+        assert_eq!(network.status(), HealthStatus::Degraded);
+        assert_eq!(decide_action(&acc, &network, 5, &config), None);
+        // invalid_grant disables immediately
+        let revoked = ProbeOutcome::failed(HealthReason::InvalidGrant, "invalid_grant");
+        assert_eq!(decide_action(&acc, &revoked, 1, &config), Some(HealthAction::Disabled));
+
+        apply_action(&mut acc, &HealthAction::ProxyDisabled, &forbidden, 100);
+        assert_eq!(acc.proxy_disabled_reason.as_deref(), Some("health_check:forbidden: 403"));
+
+        let healthy = ProbeOutcome::healthy();
+        assert_eq!(decide_action(&acc, &healthy, 0, &config), Some(HealthAction::Recovered));
+        apply_action(&mut acc, &HealthAction::Recovered, &healthy, 200);
+        assert!(!acc.proxy_disabled && acc.proxy_disabled_reason.is_none());
+
+        // Manually disabled accounts are left alone
+        acc.proxy_disabled = true;
+        acc.proxy_disabled_reason = Some("用户手动禁用".to_string());
+        assert_eq!(decide_action(&acc, &healthy, 0, &config), None);
+
+        // Recovering a checker-set proxy_disabled keeps a manual disable
+        acc.proxy_disabled_reason = Some("health_check:forbidden".to_string());
+        acc.disabled = true;
+        acc.disabled_reason = Some("用户手动禁用".to_string());
+        assert_eq!(decide_action(&acc, &healthy, 0, &config), Some(HealthAction::Recovered));
+        apply_action(&mut acc, &HealthAction::Recovered, &healthy, 300);
+        assert!(!acc.proxy_disabled);
+        assert!(acc.disabled && acc.disabled_reason.as_deref() == Some("用户手动禁用"));
+    }
+
+    #[test]
+    fn test_history_and_consecutive_failures() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        let record = |status: HealthStatus, reason: Option<HealthReason>, at: i64| HealthCheckRecord {
+            account_id: "id-1".to_string(),
+            email: "a@example.com".to_string(),
+            checked_at: at,
+            status,
+            reason,
+            message: None,
+            action: None,
+            duration_ms: 10,
+        };
+        insert_record(&conn, &record(HealthStatus::Unhealthy, Some(HealthReason::Forbidden), 1)).unwrap();
+        insert_record(&conn, &record(HealthStatus::Healthy, None, 2)).unwrap();
+        insert_record(&conn, &record(HealthStatus::Unhealthy, Some(HealthReason::NoProject), 3)).unwrap();
+        insert_record(&conn, &record(HealthStatus::Unhealthy, Some(HealthReason::NoProject), 4)).unwrap();
+        assert_eq!(query_consecutive_unhealthy(&conn, "id-1").unwrap(), 2);
+        assert_eq!(query_consecutive_unhealthy(&conn, "id-2").unwrap(), 0);
+
+        let history = query_history(&conn, Some("id-1"), 2).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].checked_at, 4);
+        assert_eq!(history[0].reason, Some(HealthReason::NoProject));
+        assert_eq!(query_history(&conn, None, 10).unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,884 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+/// A file uploaded through `/v1/files` (batch input) or produced by a batch (output / error file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchFile {
+    pub id: String,
+    pub filename: String,
+    /// "batch" for uploads, "batch_output" for generated files
+    pub purpose: String,
+    pub bytes: i64,
+    pub created_at: i64,
+}
+
+impl BatchFile {
+    /// OpenAI file object
+    pub fn to_api_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "object": "file",
+            "bytes": self.bytes,
+            "created_at": self.created_at,
+            "filename": self.filename,
+            "purpose": self.purpose,
+            "status": "processed",
+        })
+    }
+}
+
+/// One batch job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Batch {
+    pub id: String,
+    pub endpoint: String,
+    pub input_file_id: String,
+    pub completion_window: String,
+    /// "validating", "failed", "in_progress", "finalizing", "completed", "expired", "cancelling", "cancelled"
+    pub status: String,
+    pub output_file_id: Option<String>,
+    pub error_file_id: Option<String>,
+    /// Validation errors (`{"object": "list", "data": [...]}`)
+    pub errors: Option<Value>,
+    pub metadata: Option<Value>,
+    pub created_at: i64,
+    pub in_progress_at: Option<i64>,
+    pub expires_at: i64,
+    pub finalizing_at: Option<i64>,
+    pub completed_at: Option<i64>,
+    pub failed_at: Option<i64>,
+    pub expired_at: Option<i64>,
+    pub cancelling_at: Option<i64>,
+    pub cancelled_at: Option<i64>,
+    pub total: i64,
+    pub completed: i64,
+    pub failed: i64,
+    /// API key of the creator; sent with every internal request so budgets and
+    /// per-key queueing apply to the batch (never exposed in the API object)
+    #[serde(default, skip_serializing)]
+    pub api_key: Option<String>,
+}
+
+impl Batch {
+    /// OpenAI batch object
+    pub fn to_api_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "object": "batch",
+            "endpoint": self.endpoint,
+            "errors": self.errors,
+            "input_file_id": self.input_file_id,
+            "completion_window": self.completion_window,
+            "status": self.status,
+            "output_file_id": self.output_file_id,
+            "error_file_id": self.error_file_id,
+            "created_at": self.created_at,
+            "in_progress_at": self.in_progress_at,
+            "expires_at": self.expires_at,
+            "finalizing_at": self.finalizing_at,
+            "completed_at": self.completed_at,
+            "failed_at": self.failed_at,
+            "expired_at": self.expired_at,
+            "cancelling_at": self.cancelling_at,
+            "cancelled_at": self.cancelled_at,
+            "request_counts": {
+                "total": self.total,
+                "completed": self.completed,
+                "failed": self.failed,
+            },
+            "metadata": self.metadata,
+        })
+    }
+}
+
+/// One JSONL line of a batch, checkpointed until the batch is finalized
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchRequest {
+    pub batch_id: String,
+    /// 1-based line number in the input file
+    pub line: i64,
+    /// `batch_req_...` id reported in the output file
+    pub request_id: String,
+    pub custom_id: String,
+    pub body: String,
+    pub attempts: u32,
+    /// API key of the batch creator (joined from the batch when requests are claimed)
+    pub api_key: Option<String>,
+}
+
+fn get_db_path() -> Result<PathBuf, String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    Ok(data_dir.join("batches.db"))
+}
+
+fn connect_db() -> Result<Connection, String> {
+    let db_path = get_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    // Enable WAL mode for better concurrency
+    conn.pragma_update(None, "journal_mode", "WAL")
+        .map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "busy_timeout", 5000)
+        .map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "synchronous", "NORMAL")
+        .map_err(|e| e.to_string())?;
+
+    Ok(conn)
+}
+
+/// Initialize the batch database
+pub fn init_db() -> Result<(), String> {
+    let conn = connect_db()?;
+    create_schema(&conn)
+}
+
+fn create_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS batch_files (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            id TEXT NOT NULL UNIQUE,
+            filename TEXT NOT NULL,
+            purpose TEXT NOT NULL,
+            bytes INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS batches (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            id TEXT NOT NULL UNIQUE,
+            endpoint TEXT NOT NULL,
+            input_file_id TEXT NOT NULL,
+            completion_window TEXT NOT NULL,
+            status TEXT NOT NULL,
+            output_file_id TEXT,
+            error_file_id TEXT,
+            errors TEXT,
+            metadata TEXT,
+            created_at INTEGER NOT NULL,
+            in_progress_at INTEGER,
+            expires_at INTEGER NOT NULL,
+            finalizing_at INTEGER,
+            completed_at INTEGER,
+            failed_at INTEGER,
+            expired_at INTEGER,
+            cancelling_at INTEGER,
+            cancelled_at INTEGER,
+            total INTEGER NOT NULL DEFAULT 0,
+            completed INTEGER NOT NULL DEFAULT 0,
+            failed INTEGER NOT NULL DEFAULT 0,
+            api_key TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_batches_status ON batches (status);
+        CREATE TABLE IF NOT EXISTS batch_requests (
+            batch_id TEXT NOT NULL,
+            line INTEGER NOT NULL,
+            request_id TEXT NOT NULL,
+            custom_id TEXT NOT NULL,
+            body TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at INTEGER NOT NULL DEFAULT 0,
+            result TEXT,
+            PRIMARY KEY (batch_id, line)
+        );
+        CREATE INDEX IF NOT EXISTS idx_batch_requests_status ON batch_requests (status, next_attempt_at);",
+    )
+    .map_err(|e| e.to_string())?;
+
+    // Columns added after the first release (ignore errors if they exist)
+    let _ = conn.execute("ALTER TABLE batches ADD COLUMN api_key TEXT", []);
+    Ok(())
+}
+
+// ============================================================================
+// Files
+// ============================================================================
+
+const FILE_COLUMNS: &str = "id, filename, purpose, bytes, created_at";
+
+fn file_from_row(row: &rusqlite::Row) -> rusqlite::Result<BatchFile> {
+    Ok(BatchFile {
+        id: row.get(0)?,
+        filename: row.get(1)?,
+        purpose: row.get(2)?,
+        bytes: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+pub fn insert_file(file: &BatchFile) -> Result<(), String> {
+    let conn = connect_db()?;
+    insert_file_with(&conn, file)
+}
+
+fn insert_file_with(conn: &Connection, file: &BatchFile) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO batch_files (id, filename, purpose, bytes, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![file.id, file.filename, file.purpose, file.bytes, file.created_at],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+pub fn get_file(id: &str) -> Result<Option<BatchFile>, String> {
+    let conn = connect_db()?;
+    conn.query_row(
+        &format!("SELECT {} FROM batch_files WHERE id = ?1", FILE_COLUMNS),
+        [id],
+        file_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// Newest first; returns the page and whether more entries follow
+pub fn list_files(
+    purpose: Option<&str>,
+    after: Option<&str>,
+    limit: usize,
+) -> Result<(Vec<BatchFile>, bool), String> {
+    let conn = connect_db()?;
+    query_files(&conn, purpose, after, limit)
+}
+
+fn query_files(
+    conn: &Connection,
+    purpose: Option<&str>,
+    after: Option<&str>,
+    limit: usize,
+) -> Result<(Vec<BatchFile>, bool), String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM batch_files
+             WHERE (?1 IS NULL OR purpose = ?1)
+               AND (?2 IS NULL OR seq < (SELECT seq FROM batch_files WHERE id = ?2))
+             ORDER BY seq DESC LIMIT ?3",
+            FILE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![purpose, after, limit as i64 + 1], file_from_row)
+        .map_err(|e| e.to_string())?;
+    let mut files = rows
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let has_more = files.len() > limit;
+    files.truncate(limit);
+    Ok((files, has_more))
+}
+
+/// Returns true when an entry was removed
+pub fn delete_file(id: &str) -> Result<bool, String> {
+    let conn = connect_db()?;
+    conn.execute("DELETE FROM batch_files WHERE id = ?1", [id])
+        .map(|n| n > 0)
+        .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Batches
+// ============================================================================
+
+const BATCH_COLUMNS: &str = "id, endpoint, input_file_id, completion_window, status, output_file_id, error_file_id,
+    errors, metadata, created_at, in_progress_at, expires_at, finalizing_at, completed_at, failed_at, expired_at,
+    cancelling_at, cancelled_at, total, completed, failed, api_key";
+
+fn batch_from_row(row: &rusqlite::Row) -> rusqlite::Result<Batch> {
+    let json_column = |idx: usize| -> rusqlite::Result<Option<Value>> {
+        Ok(row
+            .get::<_, Option<String>>(idx)?
+            .and_then(|s| serde_json::from_str(&s).ok()))
+    };
+    Ok(Batch {
+        id: row.get(0)?,
+        endpoint: row.get(1)?,
+        input_file_id: row.get(2)?,
+        completion_window: row.get(3)?,
+        status: row.get(4)?,
+        output_file_id: row.get(5)?,
+        error_file_id: row.get(6)?,
+        errors: json_column(7)?,
+        metadata: json_column(8)?,
+        created_at: row.get(9)?,
+        in_progress_at: row.get(10)?,
+        expires_at: row.get(11)?,
+        finalizing_at: row.get(12)?,
+        completed_at: row.get(13)?,
+        failed_at: row.get(14)?,
+        expired_at: row.get(15)?,
+        cancelling_at: row.get(16)?,
+        cancelled_at: row.get(17)?,
+        total: row.get(18)?,
+        completed: row.get(19)?,
+        failed: row.get(20)?,
+        api_key: row.get(21)?,
+    })
+}
+
+pub fn insert_batch(batch: &Batch) -> Result<(), String> {
+    let conn = connect_db()?;
+    insert_batch_with(&conn, batch)
+}
+
+fn insert_batch_with(conn: &Connection, batch: &Batch) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO batches (id, endpoint, input_file_id, completion_window, status, metadata, created_at, expires_at, api_key)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            batch.id,
+            batch.endpoint,
+            batch.input_file_id,
+            batch.completion_window,
+            batch.status,
+            batch.metadata.as_ref().map(|m| m.to_string()),
+            batch.created_at,
+            batch.expires_at,
+            batch.api_key
+        ],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+pub fn get_batch(id: &str) -> Result<Option<Batch>, String> {
+    let conn = connect_db()?;
+    query_batch(&conn, id)
+}
+
+fn query_batch(conn: &Connection, id: &str) -> Result<Option<Batch>, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM batches WHERE id = ?1", BATCH_COLUMNS),
+        [id],
+        batch_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// Newest first; returns the page and whether more entries follow
+pub fn list_batches(after: Option<&str>, limit: usize) -> Result<(Vec<Batch>, bool), String> {
+    let conn = connect_db()?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM batches
+             WHERE (?1 IS NULL OR seq < (SELECT seq FROM batches WHERE id = ?1))
+             ORDER BY seq DESC LIMIT ?2",
+            BATCH_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![after, limit as i64 + 1], batch_from_row)
+        .map_err(|e| e.to_string())?;
+    let mut batches = rows
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let has_more = batches.len() > limit;
+    batches.truncate(limit);
+    Ok((batches, has_more))
+}
+
+/// Batches in any of the given states, oldest first
+pub fn batches_with_status(statuses: &[&str]) -> Result<Vec<Batch>, String> {
+    let conn = connect_db()?;
+    query_batches_with_status(&conn, statuses)
+}
+
+fn query_batches_with_status(conn: &Connection, statuses: &[&str]) -> Result<Vec<Batch>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM batches WHERE status IN (SELECT value FROM json_each(?1)) ORDER BY seq ASC",
+            BATCH_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([json!(statuses).to_string()], batch_from_row)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Store the parsed input lines and move a validating batch to in_progress.
+/// Returns false when the batch left the validating state meanwhile (e.g. cancelled)
+pub fn start_batch(id: &str, requests: &[BatchRequest], now: i64) -> Result<bool, String> {
+    let mut conn = connect_db()?;
+    start_batch_with(&mut conn, id, requests, now)
+}
+
+fn start_batch_with(
+    conn: &mut Connection,
+    id: &str,
+    requests: &[BatchRequest],
+    now: i64,
+) -> Result<bool, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let updated = tx
+        .execute(
+            "UPDATE batches SET status = 'in_progress', in_progress_at = ?2, total = ?3
+             WHERE id = ?1 AND status = 'validating'",
+            params![id, now, requests.len() as i64],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Ok(false);
+    }
+    {
+        let mut stmt = tx
+            .prepare(
+                "INSERT OR IGNORE INTO batch_requests (batch_id, line, request_id, custom_id, body)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .map_err(|e| e.to_string())?;
+        for req in requests {
+            stmt.execute(params![
+                id,
+                req.line,
+                req.request_id,
+                req.custom_id,
+                req.body
+            ])
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// Reject a validating batch with its validation errors
+pub fn fail_batch(id: &str, errors: &Value, now: i64) -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute(
+        "UPDATE batches SET status = 'failed', errors = ?2, failed_at = ?3 WHERE id = ?1 AND status = 'validating'",
+        params![id, errors.to_string(), now],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Returns the batch after the request; only validating / in_progress batches move to cancelling
+pub fn request_cancel(id: &str, now: i64) -> Result<Option<Batch>, String> {
+    let conn = connect_db()?;
+    conn.execute(
+        "UPDATE batches SET status = 'cancelling', cancelling_at = ?2
+         WHERE id = ?1 AND status IN ('validating', 'in_progress')",
+        params![id, now],
+    )
+    .map_err(|e| e.to_string())?;
+    query_batch(&conn, id)
+}
+
+/// Mark an in-progress batch as past its completion window
+pub fn mark_expired(id: &str, now: i64) -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute(
+        "UPDATE batches SET expired_at = ?2 WHERE id = ?1 AND expired_at IS NULL",
+        params![id, now],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+pub fn set_finalizing(id: &str, now: i64) -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute(
+        "UPDATE batches SET status = 'finalizing', finalizing_at = ?2 WHERE id = ?1 AND status = 'in_progress'",
+        params![id, now],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Move a batch to its terminal state and drop its checkpointed requests
+pub fn finish_batch(
+    id: &str,
+    status: &str,
+    output_file_id: Option<&str>,
+    error_file_id: Option<&str>,
+    now: i64,
+) -> Result<(), String> {
+    let mut conn = connect_db()?;
+    finish_batch_with(&mut conn, id, status, output_file_id, error_file_id, now)
+}
+
+fn finish_batch_with(
+    conn: &mut Connection,
+    id: &str,
+    status: &str,
+    output_file_id: Option<&str>,
+    error_file_id: Option<&str>,
+    now: i64,
+) -> Result<(), String> {
+    let sql = match status {
+        "completed" => "UPDATE batches SET status = ?2, output_file_id = ?3, error_file_id = ?4, completed_at = ?5 WHERE id = ?1",
+        "cancelled" => "UPDATE batches SET status = ?2, output_file_id = ?3, error_file_id = ?4, cancelled_at = ?5 WHERE id = ?1",
+        "expired" => "UPDATE batches SET status = ?2, output_file_id = ?3, error_file_id = ?4, expired_at = COALESCE(expired_at, ?5) WHERE id = ?1",
+        other => return Err(format!("Invalid terminal batch status: {}", other)),
+    };
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute(sql, params![id, status, output_file_id, error_file_id, now])
+        .map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM batch_requests WHERE batch_id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Requests
+// ============================================================================
+
+/// Put requests interrupted by a restart back into the queue
+pub fn requeue_interrupted() -> Result<usize, String> {
+    let conn = connect_db()?;
+    conn.execute(
+        "UPDATE batch_requests SET status = 'pending' WHERE status = 'running'",
+        [],
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Claim up to `limit` due requests of in-progress batches (oldest batch first) and mark them running
+pub fn claim_requests(limit: usize, now: i64) -> Result<Vec<BatchRequest>, String> {
+    let mut conn = connect_db()?;
+    claim_requests_with(&mut conn, limit, now)
+}
+
+fn claim_requests_with(
+    conn: &mut Connection,
+    limit: usize,
+    now: i64,
+) -> Result<Vec<BatchRequest>, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let claimed = {
+        let mut stmt = tx
+            .prepare(
+                "SELECT r.batch_id, r.line, r.request_id, r.custom_id, r.body, r.attempts, b.api_key
+                 FROM batch_requests r JOIN batches b ON b.id = r.batch_id
+                 WHERE r.status = 'pending' AND r.next_attempt_at <= ?1 AND b.status = 'in_progress'
+                 ORDER BY b.seq ASC, r.line ASC LIMIT ?2",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![now, limit as i64], |row| {
+                Ok(BatchRequest {
+                    batch_id: row.get(0)?,
+                    line: row.get(1)?,
+                    request_id: row.get(2)?,
+                    custom_id: row.get(3)?,
+                    body: row.get(4)?,
+                    attempts: row.get::<_, u32>(5)? + 1,
+                    api_key: row.get(6)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?
+    };
+    for req in &claimed {
+        tx.execute(
+            "UPDATE batch_requests SET status = 'running', attempts = ?3 WHERE batch_id = ?1 AND line = ?2",
+            params![req.batch_id, req.line, req.attempts],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(claimed)
+}
+
+/// Checkpoint the final result (an output or error file line) of a request
+pub fn complete_request(
+    batch_id: &str,
+    line: i64,
+    succeeded: bool,
+    result: &str,
+) -> Result<(), String> {
+    let mut conn = connect_db()?;
+    complete_request_with(&mut conn, batch_id, line, succeeded, result)
+}
+
+fn complete_request_with(
+    conn: &mut Connection,
+    batch_id: &str,
+    line: i64,
+    succeeded: bool,
+    result: &str,
+) -> Result<(), String> {
+    let status = if succeeded { "succeeded" } else { "failed" };
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let updated = tx
+        .execute(
+            "UPDATE batch_requests SET status = ?3, result = ?4
+             WHERE batch_id = ?1 AND line = ?2 AND status IN ('pending', 'running')",
+            params![batch_id, line, status, result],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated > 0 {
+        let sql = if succeeded {
+            "UPDATE batches SET completed = completed + 1 WHERE id = ?1"
+        } else {
+            "UPDATE batches SET failed = failed + 1 WHERE id = ?1"
+        };
+        tx.execute(sql, [batch_id]).map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// Return a running request to the queue; `refund` gives back the attempt (throttled, not failed)
+pub fn retry_request(
+    batch_id: &str,
+    line: i64,
+    next_attempt_at: i64,
+    refund: bool,
+) -> Result<(), String> {
+    let conn = connect_db()?;
+    retry_request_with(&conn, batch_id, line, next_attempt_at, refund)
+}
+
+fn retry_request_with(
+    conn: &Connection,
+    batch_id: &str,
+    line: i64,
+    next_attempt_at: i64,
+    refund: bool,
+) -> Result<(), String> {
+    conn.execute(
+        "UPDATE batch_requests SET status = 'pending', next_attempt_at = ?3,
+            attempts = CASE WHEN ?4 THEN MAX(attempts - 1, 0) ELSE attempts END
+         WHERE batch_id = ?1 AND line = ?2 AND status = 'running'",
+        params![batch_id, line, next_attempt_at, refund],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Requests of a batch still waiting to run
+pub fn pending_requests(batch_id: &str) -> Result<Vec<BatchRequest>, String> {
+    let conn = connect_db()?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT batch_id, line, request_id, custom_id, body, attempts FROM batch_requests
+             WHERE batch_id = ?1 AND status = 'pending' ORDER BY line ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([batch_id], |row| {
+            Ok(BatchRequest {
+                batch_id: row.get(0)?,
+                line: row.get(1)?,
+                request_id: row.get(2)?,
+                custom_id: row.get(3)?,
+                body: row.get(4)?,
+                attempts: row.get(5)?,
+                api_key: None,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Drop the pending requests of a cancelled batch (they are not reported in any file)
+pub fn cancel_pending(batch_id: &str) -> Result<usize, String> {
+    let conn = connect_db()?;
+    conn.execute(
+        "UPDATE batch_requests SET status = 'cancelled' WHERE batch_id = ?1 AND status = 'pending'",
+        [batch_id],
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Number of requests of a batch that are pending or running
+pub fn unfinished_count(batch_id: &str) -> Result<i64, String> {
+    let conn = connect_db()?;
+    query_unfinished_count(&conn, batch_id)
+}
+
+fn query_unfinished_count(conn: &Connection, batch_id: &str) -> Result<i64, String> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM batch_requests WHERE batch_id = ?1 AND status IN ('pending', 'running')",
+        [batch_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Checkpointed result lines in input order
+pub fn request_results(batch_id: &str, succeeded: bool) -> Result<Vec<String>, String> {
+    let conn = connect_db()?;
+    query_request_results(&conn, batch_id, succeeded)
+}
+
+fn query_request_results(
+    conn: &Connection,
+    batch_id: &str,
+    succeeded: bool,
+) -> Result<Vec<String>, String> {
+    let status = if succeeded { "succeeded" } else { "failed" };
+    let mut stmt = conn
+        .prepare("SELECT result FROM batch_requests WHERE batch_id = ?1 AND status = ?2 ORDER BY line ASC")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![batch_id, status], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_batch(id: &str) -> Batch {
+        Batch {
+            id: id.to_string(),
+            endpoint: "/v1/chat/completions".to_string(),
+            input_file_id: "file-in".to_string(),
+            completion_window: "24h".to_string(),
+            status: "validating".to_string(),
+            output_file_id: None,
+            error_file_id: None,
+            errors: None,
+            metadata: Some(json!({"job": "nightly"})),
+            created_at: 100,
+            in_progress_at: None,
+            expires_at: 100 + 86400,
+            finalizing_at: None,
+            completed_at: None,
+            failed_at: None,
+            expired_at: None,
+            cancelling_at: None,
+            cancelled_at: None,
+            total: 0,
+            completed: 0,
+            failed: 0,
+            api_key: Some("sk-creator".to_string()),
+        }
+    }
+
+    fn new_request(line: i64) -> BatchRequest {
+        BatchRequest {
+            batch_id: "batch_a".to_string(),
+            line,
+            request_id: format!("batch_req_{}", line),
+            custom_id: format!("req-{}", line),
+            body: "{}".to_string(),
+            attempts: 0,
+            api_key: None,
+        }
+    }
+
+    #[test]
+    fn test_request_checkpoint_flow() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        insert_batch_with(&conn, &new_batch("batch_a")).unwrap();
+
+        let requests: Vec<_> = (1..=3).map(new_request).collect();
+        assert!(start_batch_with(&mut conn, "batch_a", &requests, 110).unwrap());
+        // A batch that already started is not imported twice
+        assert!(!start_batch_with(&mut conn, "batch_a", &requests, 111).unwrap());
+
+        let claimed = claim_requests_with(&mut conn, 2, 120).unwrap();
+        assert_eq!(
+            claimed.iter().map(|r| r.line).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(claimed[0].attempts, 1);
+        assert_eq!(claimed[0].api_key.as_deref(), Some("sk-creator"));
+
+        complete_request_with(&mut conn, "batch_a", 2, false, "{\"custom_id\":\"req-2\"}").unwrap();
+        complete_request_with(&mut conn, "batch_a", 1, true, "{\"custom_id\":\"req-1\"}").unwrap();
+        // Completing twice does not double count
+        complete_request_with(&mut conn, "batch_a", 1, true, "{\"custom_id\":\"req-1\"}").unwrap();
+        assert_eq!(query_unfinished_count(&conn, "batch_a").unwrap(), 1);
+
+        let batch = query_batch(&conn, "batch_a").unwrap().unwrap();
+        assert_eq!(
+            (
+                batch.status.as_str(),
+                batch.total,
+                batch.completed,
+                batch.failed
+            ),
+            ("in_progress", 3, 1, 1)
+        );
+        assert_eq!(batch.metadata, Some(json!({"job": "nightly"})));
+        assert_eq!(
+            query_request_results(&conn, "batch_a", true).unwrap(),
+            vec!["{\"custom_id\":\"req-1\"}"]
+        );
+
+        // A throttled request goes back to the queue without consuming an attempt
+        let claimed = claim_requests_with(&mut conn, 10, 130).unwrap();
+        assert_eq!((claimed[0].line, claimed[0].attempts), (3, 1));
+        retry_request_with(&conn, "batch_a", 3, 150, true).unwrap();
+        assert!(claim_requests_with(&mut conn, 10, 140).unwrap().is_empty());
+        assert_eq!(
+            query_batches_with_status(&conn, &["validating", "in_progress"])
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Finishing drops the checkpointed requests
+        let claimed = claim_requests_with(&mut conn, 10, 150).unwrap();
+        assert_eq!((claimed[0].line, claimed[0].attempts), (3, 1));
+        finish_batch_with(
+            &mut conn,
+            "batch_a",
+            "completed",
+            Some("file-out"),
+            None,
+            140,
+        )
+        .unwrap();
+        let batch = query_batch(&conn, "batch_a").unwrap().unwrap();
+        assert_eq!(
+            (batch.status.as_str(), batch.completed_at),
+            ("completed", Some(140))
+        );
+        assert!(query_request_results(&conn, "batch_a", true)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            query_batches_with_status(&conn, &["in_progress"])
+                .unwrap()
+                .len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_file_pagination() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        for (id, purpose) in [
+            ("file-1", "batch"),
+            ("file-2", "batch_output"),
+            ("file-3", "batch"),
+        ] {
+            insert_file_with(
+                &conn,
+                &BatchFile {
+                    id: id.to_string(),
+                    filename: format!("{}.jsonl", id),
+                    purpose: purpose.to_string(),
+                    bytes: 10,
+                    created_at: 100,
+                },
+            )
+            .unwrap();
+        }
+
+        let (page, has_more) = query_files(&conn, None, None, 2).unwrap();
+        assert_eq!(
+            page.iter().map(|f| f.id.as_str()).collect::<Vec<_>>(),
+            vec!["file-3", "file-2"]
+        );
+        assert!(has_more);
+        let (page, has_more) = query_files(&conn, None, Some("file-2"), 2).unwrap();
+        assert_eq!(page[0].id, "file-1");
+        assert!(!has_more);
+        let (page, _) = query_files(&conn, Some("batch"), None, 10).unwrap();
+        assert_eq!(page.len(), 2);
+    }
+}
@@ -8,10 +8,11 @@
 //! - POST /accounts/switch           Switch account (async execution)
 //! - POST /accounts/refresh          Refresh all quotas
 //! - POST /accounts/:id/bind-device  Bind device fingerprint
+//! - GET  /stats/usage               Usage report (CSV / JSON)
 
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, StatusCode},
     response::IntoResponse,
     routing::{get, post},
     Json, Router,
@@ -21,7 +22,7 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::modules::{account, logger, proxy_db};
+use crate::modules::{account, logger, proxy_db, token_stats, usage_report};
 
 /// Default port for HTTP API server
 pub const DEFAULT_PORT: u16 = 19527;
@@ -220,6 +221,47 @@ struct LogsRequest {
     errors_only: bool,
 }
 
+#[derive(Deserialize)]
+struct UsageReportRequest {
+    /// Unix seconds, RFC 3339 or YYYY-MM-DD (default: 24h before `end`)
+    start: Option<String>,
+    /// Unix seconds, RFC 3339 or YYYY-MM-DD (default: now)
+    end: Option<String>,
+    /// hour / day / week / month / total
+    bucket: Option<String>,
+    /// Comma separated: account, model, api_key, protocol
+    group_by: Option<String>,
+    /// csv (default) / json
+    format: Option<String>,
+}
+
+impl UsageReportRequest {
+    fn into_query(self) -> Result<(token_stats::UsageQuery, usage_report::UsageReportFormat), String> {
+        let end = match self.end.as_deref() {
+            Some(value) => usage_report::parse_time(value)?,
+            None => chrono::Utc::now().timestamp(),
+        };
+        let start = match self.start.as_deref() {
+            Some(value) => usage_report::parse_time(value)?,
+            None => end - 24 * 3600,
+        };
+        if end <= start {
+            return Err("invalid_time_range: end must be after start".to_string());
+        }
+        let query = token_stats::UsageQuery {
+            start,
+            end,
+            bucket: match self.bucket.as_deref() {
+                Some(value) => token_stats::UsageBucket::parse(value)?,
+                None => token_stats::UsageBucket::default(),
+            },
+            group_by: token_stats::UsageDimension::parse_list(self.group_by.as_deref().unwrap_or(""))?,
+        };
+        let format = usage_report::UsageReportFormat::parse(self.format.as_deref().unwrap_or("csv"))?;
+        Ok((query, format))
+    }
+}
+
 // ============================================================================
 // Handlers
 // ============================================================================
@@ -444,6 +486,23 @@ async fn get_logs(
     }))
 }
 
+/// GET /stats/usage - Usage report over an arbitrary range
+async fn get_usage_report(
+    Query(params): Query<UsageReportRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let (query, format) = params
+        .into_query()
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e })))?;
+
+    let body = tokio::task::spawn_blocking(move || usage_report::export(&query, format))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: e })))?;
+
+    Ok(([(header::CONTENT_TYPE, format.content_type())], body))
+}
+
 // ============================================================================
 // Server
 // ============================================================================
@@ -466,6 +525,7 @@ pub async fn start_server(port: u16) -> Result<(), String> {
         .route("/accounts/refresh", post(refresh_all_quotas))
         .route("/accounts/{id}/bind-device", post(bind_device))
         .route("/logs", get(get_logs))
+        .route("/stats/usage", get(get_usage_report))
         .layer(cors)
         .with_state(state);
 
@@ -63,7 +63,9 @@ pub fn init_logger() {
         .unwrap_or_else(|_| EnvFilter::new("info"));
 
     // 5. Initialize global subscriber (use try_init to avoid crash on repeated initialization)
+    // OTLP 导出层放在最内层 (直接挂在 Registry 上)，由反代配置热启用
     let _ = tracing_subscriber::registry()
+        .with(crate::proxy::telemetry::layer())
         .with(filter_layer)
         .with(console_layer)
         .with(file_layer)
@@ -13,8 +13,14 @@ pub mod proxy_db;
 pub mod device;
 pub mod update_checker;
 pub mod scheduler;
+pub mod warmup_schedule;
+pub mod warmup_db;
+pub mod account_health;
 pub mod http_api;
 pub mod token_stats;
+pub mod usage_report;
+pub mod response_cache;
+pub mod batch_db;
 pub mod cloudflared;
 
 use crate::models;
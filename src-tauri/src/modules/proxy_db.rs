@@ -1,6 +1,7 @@
 use rusqlite::{params, Connection};
 use std::path::PathBuf;
 use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::replay::LogReplay;
 
 pub fn get_proxy_db_path() -> Result<PathBuf, String> {
     let data_dir = crate::modules::account::get_data_dir()?;
@@ -49,6 +50,16 @@ pub fn init_db() -> Result<(), String> {
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN account_email TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN mapped_model TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN protocol TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN hedge TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN background_task TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN cache TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN api_key TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN cost REAL", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN cached_input_tokens INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN thinking_tokens INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN session_id TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN request_body_lossy INTEGER DEFAULT 0", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN request_headers TEXT", []);
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_timestamp ON request_logs (timestamp DESC)",
@@ -61,15 +72,70 @@ pub fn init_db() -> Result<(), String> {
         [],
     ).map_err(|e| e.to_string())?;
 
+    // Replays of logged requests, linked to the original log id
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS log_replays (
+            id TEXT PRIMARY KEY,
+            original_log_id TEXT NOT NULL,
+            replay_log_id TEXT,
+            timestamp INTEGER,
+            overrides TEXT,
+            status INTEGER,
+            duration INTEGER,
+            model TEXT,
+            mapped_model TEXT,
+            account_email TEXT,
+            response_body TEXT,
+            error TEXT
+        )",
+        [],
+    ).map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_replay_original ON log_replays (original_log_id)",
+        [],
+    ).map_err(|e| e.to_string())?;
+
+    // Background task hit counters, independent of request logging and retention
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS background_task_counts (
+            class TEXT PRIMARY KEY,
+            count INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    ).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Count one request classified as the given background task class
+pub fn increment_background_task(class: &str) -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute(
+        "INSERT INTO background_task_counts (class, count) VALUES (?1, 1)
+         ON CONFLICT(class) DO UPDATE SET count = count + 1",
+        [class],
+    ).map_err(|e| e.to_string())?;
     Ok(())
 }
 
-pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
+/// Check that the database is reachable and its schema is in place (used by /readyz)
+pub fn health_check() -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.query_row("SELECT COUNT(*) FROM (SELECT 1 FROM request_logs LIMIT 1)", [], |row| row.get::<_, i64>(0))
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// `request_body_lossy` marks request bodies the log policy altered (redacted, stripped or truncated),
+/// which therefore cannot be replayed faithfully. `request_headers` are the end-to-end request headers
+/// (JSON) kept for replay only; they are never returned with the log itself.
+pub fn save_log(log: &ProxyRequestLog, request_body_lossy: bool, request_headers: Option<&str>) -> Result<(), String> {
     let conn = connect_db()?;
 
     conn.execute(
-        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, protocol)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, protocol, hedge, background_task, cache, api_key, cost, cached_input_tokens, thinking_tokens, session_id, request_body_lossy, request_headers)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)",
         params![
             log.id,
             log.timestamp,
@@ -86,12 +152,45 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.account_email,
             log.mapped_model,
             log.protocol,
+            log.hedge,
+            log.background_task,
+            log.cache,
+            log.api_key,
+            log.cost,
+            log.cached_input_tokens,
+            log.thinking_tokens,
+            log.session_id,
+            request_body_lossy,
+            request_headers,
         ],
     ).map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
+/// Whether the stored request body was altered by the log policy
+pub fn is_request_body_lossy(log_id: &str) -> Result<bool, String> {
+    let conn = connect_db()?;
+    conn.query_row(
+        "SELECT COALESCE(request_body_lossy, 0) FROM request_logs WHERE id = ?1",
+        [log_id],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|v| v != 0)
+    .map_err(|e| e.to_string())
+}
+
+/// Stored request headers of a log (JSON list of name/value pairs), if any
+pub fn get_request_headers(log_id: &str) -> Result<Option<String>, String> {
+    let conn = connect_db()?;
+    conn.query_row(
+        "SELECT request_headers FROM request_logs WHERE id = ?1",
+        [log_id],
+        |row| row.get::<_, Option<String>>(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
 /// Get logs summary (without large request_body and response_body fields) with pagination
 pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestLog>, String> {
     let conn = connect_db()?;
@@ -99,7 +198,8 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model, protocol
+                input_tokens, output_tokens, account_email, mapped_model, protocol, hedge, background_task, cache, api_key, cost,
+                cached_input_tokens, thinking_tokens, session_id
          FROM request_logs 
          ORDER BY timestamp DESC 
          LIMIT ?1 OFFSET ?2"
@@ -122,6 +222,14 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
             protocol: row.get(14).unwrap_or(None),
+            hedge: row.get(15).unwrap_or(None),
+            background_task: row.get(16).unwrap_or(None),
+            cache: row.get(17).unwrap_or(None),
+            api_key: row.get(18).unwrap_or(None),
+            cost: row.get(19).unwrap_or(None),
+            cached_input_tokens: row.get(20).unwrap_or(None),
+            thinking_tokens: row.get(21).unwrap_or(None),
+            session_id: row.get(22).unwrap_or(None),
         })
     }).map_err(|e| e.to_string())?;
 
@@ -151,13 +259,43 @@ pub fn get_stats() -> Result<crate::proxy::monitor::ProxyStats, String> {
         |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
     ).map_err(|e| e.to_string())?;
 
+    let mut stmt = conn.prepare(
+        "SELECT class, count FROM background_task_counts"
+    ).map_err(|e| e.to_string())?;
+    let background_tasks = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
     Ok(crate::proxy::monitor::ProxyStats {
         total_requests,
         success_count,
         error_count,
+        background_tasks,
     })
 }
 
+/// Get the most recent request bodies for a URL prefix, newest first (used by the background task dry-run)
+pub fn get_recent_request_bodies(url_prefix: &str, limit: usize) -> Result<Vec<(String, String)>, String> {
+    let conn = connect_db()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, request_body FROM request_logs
+         WHERE url LIKE ?1 AND request_body IS NOT NULL
+         ORDER BY timestamp DESC
+         LIMIT ?2"
+    ).map_err(|e| e.to_string())?;
+
+    let pattern = format!("{}%", url_prefix);
+    let rows = stmt
+        .query_map(params![pattern, limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(rows)
+}
+
 /// Get single log detail (with request_body and response_body)
 pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
     let conn = connect_db()?;
@@ -165,7 +303,8 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 request_body, response_body, input_tokens, output_tokens, 
-                account_email, mapped_model, protocol
+                account_email, mapped_model, protocol, hedge, background_task, cache, api_key, cost,
+                cached_input_tokens, thinking_tokens, session_id
          FROM request_logs 
          WHERE id = ?1"
     ).map_err(|e| e.to_string())?;
@@ -187,25 +326,110 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
             protocol: row.get(14).unwrap_or(None),
+            hedge: row.get(15).unwrap_or(None),
+            background_task: row.get(16).unwrap_or(None),
+            cache: row.get(17).unwrap_or(None),
+            api_key: row.get(18).unwrap_or(None),
+            cost: row.get(19).unwrap_or(None),
+            cached_input_tokens: row.get(20).unwrap_or(None),
+            thinking_tokens: row.get(21).unwrap_or(None),
+            session_id: row.get(22).unwrap_or(None),
         })
     }).map_err(|e| e.to_string())
 }
 
-/// Cleanup old logs (keep last N days)
-pub fn cleanup_old_logs(days: i64) -> Result<usize, String> {
+/// Apply retention periods: clear bodies older than `body_days` and delete rows older than
+/// `metadata_days` (0 disables the respective step). Returns (bodies cleared, rows deleted).
+pub fn apply_retention(body_days: u32, metadata_days: u32) -> Result<(usize, usize), String> {
     let conn = connect_db()?;
-    
-    let cutoff_timestamp = chrono::Utc::now().timestamp() - (days * 24 * 3600);
-    
-    let deleted = conn.execute(
-        "DELETE FROM request_logs WHERE timestamp < ?1",
-        [cutoff_timestamp],
-    ).map_err(|e| e.to_string())?;
-    
+
+    // timestamp is stored in milliseconds
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    let cutoff = |days: u32| now_ms - days as i64 * 24 * 3600 * 1000;
+
+    let cleared = if body_days > 0 {
+        conn.execute(
+            "UPDATE request_logs SET request_body = NULL, response_body = NULL, request_headers = NULL
+             WHERE timestamp < ?1 AND (request_body IS NOT NULL OR response_body IS NOT NULL OR request_headers IS NOT NULL)",
+            [cutoff(body_days)],
+        ).map_err(|e| e.to_string())?
+    } else {
+        0
+    };
+
+    let deleted = if metadata_days > 0 {
+        conn.execute(
+            "DELETE FROM log_replays WHERE timestamp < ?1",
+            [cutoff(metadata_days)],
+        ).map_err(|e| e.to_string())?;
+        conn.execute(
+            "DELETE FROM request_logs WHERE timestamp < ?1",
+            [cutoff(metadata_days)],
+        ).map_err(|e| e.to_string())?
+    } else {
+        0
+    };
+
     // Execute VACUUM to reclaim disk space
-    conn.execute("VACUUM", []).map_err(|e| e.to_string())?;
-    
-    Ok(deleted)
+    if cleared > 0 || deleted > 0 {
+        conn.execute("VACUUM", []).map_err(|e| e.to_string())?;
+    }
+
+    Ok((cleared, deleted))
+}
+
+/// A stored row's policy-relevant text: (rowid, request_body, response_body, error)
+type PolicyRow = (i64, Option<String>, Option<String>, Option<String>);
+
+/// Re-apply a log policy to every stored row (bodies and error text). Returns the number of rows rewritten.
+pub fn reapply_log_policy(policy: &crate::proxy::log_policy::LogPolicy) -> Result<usize, String> {
+    const BATCH_SIZE: i64 = 500;
+
+    let mut conn = connect_db()?;
+    let mut last_rowid: i64 = 0;
+    let mut rewritten = 0;
+
+    loop {
+        let mut stmt = conn.prepare(
+            "SELECT rowid, request_body, response_body, error FROM request_logs
+             WHERE rowid > ?1 ORDER BY rowid LIMIT ?2"
+        ).map_err(|e| e.to_string())?;
+        let batch: Vec<PolicyRow> = stmt
+            .query_map(params![last_rowid, BATCH_SIZE], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        drop(stmt);
+        let Some(&(max_rowid, ..)) = batch.last() else {
+            break;
+        };
+        last_rowid = max_rowid;
+
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        for (rowid, request_body, response_body, error) in batch {
+            let new_request = policy.apply_opt(request_body.as_deref());
+            let new_response = policy.apply_opt(response_body.as_deref());
+            let new_error = error.as_deref().map(|e| policy.apply(e));
+            if new_request != request_body || new_response != response_body || new_error != error {
+                let request_lossy = new_request.is_some() && new_request != request_body;
+                tx.execute(
+                    "UPDATE request_logs SET request_body = ?1, response_body = ?2, error = ?3,
+                         request_body_lossy = COALESCE(request_body_lossy, 0) OR ?4
+                     WHERE rowid = ?5",
+                    params![new_request, new_response, new_error, request_lossy, rowid],
+                ).map_err(|e| e.to_string())?;
+                rewritten += 1;
+            }
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+    }
+
+    if rewritten > 0 {
+        conn.execute("VACUUM", []).map_err(|e| e.to_string())?;
+    }
+    Ok(rewritten)
 }
 
 /// Limit maximum log count (keep newest N records)
@@ -228,9 +452,70 @@ pub fn limit_max_logs(max_count: usize) -> Result<usize, String> {
 pub fn clear_logs() -> Result<(), String> {
     let conn = connect_db()?;
     conn.execute("DELETE FROM request_logs", []).map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM log_replays", []).map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM background_task_counts", []).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Store the result of replaying a logged request
+pub fn save_replay(replay: &LogReplay) -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute(
+        "INSERT INTO log_replays (id, original_log_id, replay_log_id, timestamp, overrides, status, duration,
+                                  model, mapped_model, account_email, response_body, error)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            replay.id,
+            replay.original_log_id,
+            replay.replay_log_id,
+            replay.timestamp,
+            replay.overrides.to_string(),
+            replay.status,
+            replay.duration,
+            replay.model,
+            replay.mapped_model,
+            replay.account_email,
+            replay.response_body,
+            replay.error,
+        ],
+    ).map_err(|e| e.to_string())?;
     Ok(())
 }
 
+/// Get all replays of a logged request, newest first
+pub fn get_replays(original_log_id: &str) -> Result<Vec<LogReplay>, String> {
+    let conn = connect_db()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, original_log_id, replay_log_id, timestamp, overrides, status, duration,
+                model, mapped_model, account_email, response_body, error
+         FROM log_replays
+         WHERE original_log_id = ?1
+         ORDER BY timestamp DESC"
+    ).map_err(|e| e.to_string())?;
+
+    let replays = stmt.query_map([original_log_id], |row| {
+        let overrides: Option<String> = row.get(4)?;
+        Ok(LogReplay {
+            id: row.get(0)?,
+            original_log_id: row.get(1)?,
+            replay_log_id: row.get(2)?,
+            timestamp: row.get(3)?,
+            overrides: overrides
+                .and_then(|o| serde_json::from_str(&o).ok())
+                .unwrap_or(serde_json::Value::Null),
+            status: row.get(5)?,
+            duration: row.get(6)?,
+            model: row.get(7)?,
+            mapped_model: row.get(8)?,
+            account_email: row.get(9)?,
+            response_body: row.get(10)?,
+            error: row.get(11)?,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    replays.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
 /// Get total count of logs in database
 pub fn get_logs_count() -> Result<u64, String> {
     let conn = connect_db()?;
@@ -283,7 +568,8 @@ pub fn get_logs_filtered(filter: &str, errors_only: bool, limit: usize, offset:
     let sql = if errors_only {
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model, protocol
+                input_tokens, output_tokens, account_email, mapped_model, protocol, hedge, background_task, cache, api_key, cost,
+                cached_input_tokens, thinking_tokens, session_id
          FROM request_logs 
          WHERE (status < 200 OR status >= 400)
          ORDER BY timestamp DESC 
@@ -291,14 +577,16 @@ pub fn get_logs_filtered(filter: &str, errors_only: bool, limit: usize, offset:
     } else if filter.is_empty() {
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model, protocol
+                input_tokens, output_tokens, account_email, mapped_model, protocol, hedge, background_task, cache, api_key, cost,
+                cached_input_tokens, thinking_tokens, session_id
          FROM request_logs 
          ORDER BY timestamp DESC 
          LIMIT ?1 OFFSET ?2"
     } else {
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model, protocol
+                input_tokens, output_tokens, account_email, mapped_model, protocol, hedge, background_task, cache, api_key, cost,
+                cached_input_tokens, thinking_tokens, session_id
          FROM request_logs 
          WHERE (url LIKE ?3 OR method LIKE ?3 OR model LIKE ?3 OR CAST(status AS TEXT) LIKE ?3 OR account_email LIKE ?3)
          ORDER BY timestamp DESC 
@@ -324,6 +612,14 @@ pub fn get_logs_filtered(filter: &str, errors_only: bool, limit: usize, offset:
                 input_tokens: row.get(10).unwrap_or(None),
                 output_tokens: row.get(11).unwrap_or(None),
                 protocol: row.get(14).unwrap_or(None),
+                hedge: row.get(15).unwrap_or(None),
+                background_task: row.get(16).unwrap_or(None),
+                cache: row.get(17).unwrap_or(None),
+                api_key: row.get(18).unwrap_or(None),
+                cost: row.get(19).unwrap_or(None),
+                cached_input_tokens: row.get(20).unwrap_or(None),
+                thinking_tokens: row.get(21).unwrap_or(None),
+                session_id: row.get(22).unwrap_or(None),
             })
         }).map_err(|e| e.to_string())?;
         logs_iter.filter_map(|r| r.ok()).collect()
@@ -346,6 +642,14 @@ pub fn get_logs_filtered(filter: &str, errors_only: bool, limit: usize, offset:
                 input_tokens: row.get(10).unwrap_or(None),
                 output_tokens: row.get(11).unwrap_or(None),
                 protocol: row.get(14).unwrap_or(None),
+                hedge: row.get(15).unwrap_or(None),
+                background_task: row.get(16).unwrap_or(None),
+                cache: row.get(17).unwrap_or(None),
+                api_key: row.get(18).unwrap_or(None),
+                cost: row.get(19).unwrap_or(None),
+                cached_input_tokens: row.get(20).unwrap_or(None),
+                thinking_tokens: row.get(21).unwrap_or(None),
+                session_id: row.get(22).unwrap_or(None),
             })
         }).map_err(|e| e.to_string())?;
         logs_iter.filter_map(|r| r.ok()).collect()
@@ -368,6 +672,14 @@ pub fn get_logs_filtered(filter: &str, errors_only: bool, limit: usize, offset:
                 input_tokens: row.get(10).unwrap_or(None),
                 output_tokens: row.get(11).unwrap_or(None),
                 protocol: row.get(14).unwrap_or(None),
+                hedge: row.get(15).unwrap_or(None),
+                background_task: row.get(16).unwrap_or(None),
+                cache: row.get(17).unwrap_or(None),
+                api_key: row.get(18).unwrap_or(None),
+                cost: row.get(19).unwrap_or(None),
+                cached_input_tokens: row.get(20).unwrap_or(None),
+                thinking_tokens: row.get(21).unwrap_or(None),
+                session_id: row.get(22).unwrap_or(None),
             })
         }).map_err(|e| e.to_string())?;
         logs_iter.filter_map(|r| r.ok()).collect()
@@ -383,7 +695,8 @@ pub fn get_all_logs_for_export() -> Result<Vec<ProxyRequestLog>, String> {
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 request_body, response_body, input_tokens, output_tokens, 
-                account_email, mapped_model, protocol
+                account_email, mapped_model, protocol, hedge, background_task, cache, api_key, cost,
+                cached_input_tokens, thinking_tokens, session_id
          FROM request_logs 
          ORDER BY timestamp DESC"
     ).map_err(|e| e.to_string())?;
@@ -405,6 +718,14 @@ pub fn get_all_logs_for_export() -> Result<Vec<ProxyRequestLog>, String> {
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
             protocol: row.get(14).unwrap_or(None),
+            hedge: row.get(15).unwrap_or(None),
+            background_task: row.get(16).unwrap_or(None),
+            cache: row.get(17).unwrap_or(None),
+            api_key: row.get(18).unwrap_or(None),
+            cost: row.get(19).unwrap_or(None),
+            cached_input_tokens: row.get(20).unwrap_or(None),
+            thinking_tokens: row.get(21).unwrap_or(None),
+            session_id: row.get(22).unwrap_or(None),
         })
     }).map_err(|e| e.to_string())?;
 
@@ -429,7 +750,8 @@ pub fn get_logs_by_ids(ids: &[String]) -> Result<Vec<ProxyRequestLog>, String> {
     let sql = format!(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 request_body, response_body, input_tokens, output_tokens, 
-                account_email, mapped_model, protocol
+                account_email, mapped_model, protocol, hedge, background_task, cache, api_key, cost,
+                cached_input_tokens, thinking_tokens, session_id
          FROM request_logs 
          WHERE id IN ({})
          ORDER BY timestamp DESC",
@@ -458,6 +780,14 @@ pub fn get_logs_by_ids(ids: &[String]) -> Result<Vec<ProxyRequestLog>, String> {
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
             protocol: row.get(14).unwrap_or(None),
+            hedge: row.get(15).unwrap_or(None),
+            background_task: row.get(16).unwrap_or(None),
+            cache: row.get(17).unwrap_or(None),
+            api_key: row.get(18).unwrap_or(None),
+            cost: row.get(19).unwrap_or(None),
+            cached_input_tokens: row.get(20).unwrap_or(None),
+            thinking_tokens: row.get(21).unwrap_or(None),
+            session_id: row.get(22).unwrap_or(None),
         })
     }).map_err(|e| e.to_string())?;
 
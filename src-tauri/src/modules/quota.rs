@@ -376,16 +376,17 @@ pub async fn warm_up_all_accounts() -> Result<String, String> {
         if !warmup_items.is_empty() {
             let total_before = warmup_items.len();
             
-            // Filter out models warmed up within 4 hours
+            // Filter out models warmed up within the cooldown
+            let cooldown = crate::modules::scheduler::cooldown_seconds();
             warmup_items.retain(|(email, model, _, _, _)| {
                 let history_key = format!("{}:{}:100", email, model);
-                !crate::modules::scheduler::check_cooldown(&history_key, 14400)
+                !crate::modules::scheduler::check_cooldown(&history_key, cooldown)
             });
             
             if warmup_items.is_empty() {
                 let skipped = total_before;
                 crate::modules::logger::log_info(&format!("[Warmup] Returning to frontend: All models in cooldown, skipped {}", skipped));
-                return Ok(format!("All models are in 4-hour cooldown, skipped {} items", skipped));
+                return Ok(format!("All models are in cooldown, skipped {} items", skipped));
             }
             
             let total = warmup_items.len();
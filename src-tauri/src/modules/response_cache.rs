@@ -0,0 +1,200 @@
+use rusqlite::{params, Connection};
+use std::path::PathBuf;
+
+fn get_db_path() -> Result<PathBuf, String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    Ok(data_dir.join("response_cache.db"))
+}
+
+fn connect_db() -> Result<Connection, String> {
+    let db_path = get_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    // Enable WAL mode for better concurrency
+    conn.pragma_update(None, "journal_mode", "WAL")
+        .map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "busy_timeout", 5000)
+        .map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "synchronous", "NORMAL")
+        .map_err(|e| e.to_string())?;
+
+    Ok(conn)
+}
+
+/// Initialize the response cache database
+pub fn init_db() -> Result<(), String> {
+    let conn = connect_db()?;
+    create_schema(&conn)
+}
+
+fn create_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS response_cache (
+            key TEXT PRIMARY KEY,
+            model TEXT,
+            body TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            hits INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_cache_expires ON response_cache (expires_at)",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_cache_created ON response_cache (created_at)",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Look up a non-expired entry and bump its hit counter
+pub fn get(key: &str) -> Result<Option<String>, String> {
+    let conn = connect_db()?;
+    get_entry(&conn, key, chrono::Utc::now().timestamp())
+}
+
+fn get_entry(conn: &Connection, key: &str, now: i64) -> Result<Option<String>, String> {
+    let body = conn
+        .query_row(
+            "SELECT body FROM response_cache WHERE key = ?1 AND expires_at > ?2",
+            params![key, now],
+            |row| row.get::<_, String>(0),
+        )
+        .map(Some)
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            e => Err(e.to_string()),
+        })?;
+
+    if body.is_some() {
+        conn.execute("UPDATE response_cache SET hits = hits + 1 WHERE key = ?1", [key])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(body)
+}
+
+/// Store an entry, then drop expired entries and evict the oldest ones until the
+/// total body size fits in `max_total_bytes`
+pub fn put(key: &str, model: &str, body: &str, ttl_secs: u64, max_total_bytes: u64) -> Result<(), String> {
+    let conn = connect_db()?;
+    put_entry(&conn, key, model, body, ttl_secs, max_total_bytes, chrono::Utc::now().timestamp())
+}
+
+fn put_entry(
+    conn: &Connection,
+    key: &str,
+    model: &str,
+    body: &str,
+    ttl_secs: u64,
+    max_total_bytes: u64,
+    now: i64,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT OR REPLACE INTO response_cache (key, model, body, size, created_at, expires_at, hits)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0)",
+        params![key, model, body, body.len() as i64, now, now + ttl_secs as i64],
+    )
+    .map_err(|e| e.to_string())?;
+
+    conn.execute("DELETE FROM response_cache WHERE expires_at <= ?1", [now])
+        .map_err(|e| e.to_string())?;
+
+    let total: i64 = conn
+        .query_row("SELECT COALESCE(SUM(size), 0) FROM response_cache", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let mut excess = total - max_total_bytes as i64;
+    if excess <= 0 {
+        return Ok(());
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT key, size FROM response_cache ORDER BY created_at ASC")
+        .map_err(|e| e.to_string())?;
+    let oldest = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))
+        .map_err(|e| e.to_string())?;
+
+    let mut evict = Vec::new();
+    for row in oldest {
+        let (key, size) = row.map_err(|e| e.to_string())?;
+        evict.push(key);
+        excess -= size;
+        if excess <= 0 {
+            break;
+        }
+    }
+    for key in &evict {
+        conn.execute("DELETE FROM response_cache WHERE key = ?1", [key])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        conn
+    }
+
+    fn hits(conn: &Connection, key: &str) -> i64 {
+        conn.query_row("SELECT hits FROM response_cache WHERE key = ?1", [key], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_store_and_lookup() {
+        let conn = memory_db();
+        assert_eq!(get_entry(&conn, "k1", 100).unwrap(), None);
+
+        put_entry(&conn, "k1", "gemini-2.5-flash", "{\"a\":1}", 60, 1024, 100).unwrap();
+        assert_eq!(get_entry(&conn, "k1", 101).unwrap().as_deref(), Some("{\"a\":1}"));
+        assert_eq!(get_entry(&conn, "k1", 102).unwrap().as_deref(), Some("{\"a\":1}"));
+        assert_eq!(hits(&conn, "k1"), 2);
+
+        // 同键重复写入覆盖旧内容并重置命中数
+        put_entry(&conn, "k1", "gemini-2.5-flash", "{\"a\":2}", 60, 1024, 103).unwrap();
+        assert_eq!(hits(&conn, "k1"), 0);
+        assert_eq!(get_entry(&conn, "k1", 104).unwrap().as_deref(), Some("{\"a\":2}"));
+    }
+
+    #[test]
+    fn test_expiry() {
+        let conn = memory_db();
+        put_entry(&conn, "old", "m", "old-body", 10, 1024, 100).unwrap();
+        assert!(get_entry(&conn, "old", 109).unwrap().is_some());
+        assert_eq!(get_entry(&conn, "old", 110).unwrap(), None);
+
+        // 写入新条目时清理已过期的条目
+        put_entry(&conn, "new", "m", "new-body", 10, 1024, 200).unwrap();
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM response_cache", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_evicts_oldest_over_size_limit() {
+        let conn = memory_db();
+        put_entry(&conn, "a", "m", "0123456789", 60, 25, 100).unwrap();
+        put_entry(&conn, "b", "m", "0123456789", 60, 25, 101).unwrap();
+        put_entry(&conn, "c", "m", "0123456789", 60, 25, 102).unwrap();
+
+        assert_eq!(get_entry(&conn, "a", 103).unwrap(), None);
+        assert!(get_entry(&conn, "b", 103).unwrap().is_some());
+        assert!(get_entry(&conn, "c", 103).unwrap().is_some());
+    }
+}
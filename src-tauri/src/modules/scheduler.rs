@@ -1,263 +1,430 @@
 use chrono::Utc;
-use once_cell::sync::Lazy;
-use std::collections::HashMap;
-use std::sync::Mutex;
 use tokio::time::{self, Duration};
 use tauri::Manager;
-use crate::modules::{config, logger, quota, account};
-use crate::models::Account;
-use std::path::PathBuf;
+use crate::modules::{config, logger, quota, account, warmup_db};
+use crate::modules::warmup_db::WarmupRunResult;
+use crate::modules::warmup_schedule::WarmupSchedule;
+use crate::models::{Account, config::ScheduledWarmupConfig};
+
+/// Scheduler tick (cron schedules have minute granularity)
+const TICK_SECS: u64 = 60;
+/// The 100% quota scan runs every N ticks (10 minutes)
+const QUOTA_SCAN_EVERY_TICKS: u64 = 10;
+/// Cooldown history retention (must exceed any configured cooldown)
+const HISTORY_RETENTION_SECS: i64 = 7 * 86400;
+/// Run log retention
+const RUN_LOG_RETENTION_SECS: i64 = 30 * 86400;
+/// Built-in run name for the 100% quota scan
+const QUOTA_FULL_RUN: &str = "quota_full";
+
+/// A single account + model warmup to execute
+struct WarmupTask {
+    email: String,
+    model: String,
+    token: String,
+    pid: String,
+    percentage: i32,
+    history_key: String,
+}
 
-// Warmup history: key = "email:model_name:100", value = warmup timestamp
-static WARMUP_HISTORY: Lazy<Mutex<HashMap<String, i64>>> = Lazy::new(|| Mutex::new(load_warmup_history()));
+fn history_key(email: &str, model: &str) -> String {
+    format!("{}:{}:100", email, model)
+}
 
-fn get_warmup_history_path() -> Result<PathBuf, String> {
-    let data_dir = account::get_data_dir()?;
-    Ok(data_dir.join("warmup_history.json"))
+/// Cooldown between two warmups of the same account + model (configurable, default 4h)
+pub fn cooldown_seconds() -> i64 {
+    config::load_app_config()
+        .map(|c| c.scheduled_warmup.cooldown_minutes)
+        .unwrap_or(240) as i64
+        * 60
 }
 
-fn load_warmup_history() -> HashMap<String, i64> {
-    match get_warmup_history_path() {
-        Ok(path) if path.exists() => {
-            match std::fs::read_to_string(&path) {
-                Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
-                Err(_) => HashMap::new(),
-            }
-        }
-        _ => HashMap::new(),
+pub fn record_warmup_history(key: &str, timestamp: i64) {
+    if let Err(e) = warmup_db::record_warmup(key, timestamp) {
+        logger::log_warn(&format!("[Scheduler] Failed to record warmup history: {}", e));
     }
 }
 
-fn save_warmup_history(history: &HashMap<String, i64>) {
-    if let Ok(path) = get_warmup_history_path() {
-        if let Ok(content) = serde_json::to_string_pretty(history) {
-            let _ = std::fs::write(&path, content);
-        }
+pub fn check_cooldown(key: &str, cooldown_seconds: i64) -> bool {
+    match warmup_db::get_last_warmup(key) {
+        Ok(Some(last_ts)) => Utc::now().timestamp() - last_ts < cooldown_seconds,
+        _ => false,
     }
 }
 
-pub fn record_warmup_history(key: &str, timestamp: i64) {
-    let mut history = WARMUP_HISTORY.lock().unwrap();
-    history.insert(key.to_string(), timestamp);
-    save_warmup_history(&history);
+fn record_result(run_id: i64, email: &str, model: &str, status: &str, percentage: Option<i32>, message: Option<String>) {
+    let result = WarmupRunResult {
+        run_id,
+        account_email: email.to_string(),
+        model: model.to_string(),
+        status: status.to_string(),
+        percentage,
+        message,
+        timestamp: Utc::now().timestamp(),
+    };
+    if let Err(e) = warmup_db::record_result(&result) {
+        logger::log_warn(&format!("[Scheduler] Failed to record warmup result: {}", e));
+    }
 }
 
-pub fn check_cooldown(key: &str, cooldown_seconds: i64) -> bool {
-    let history = WARMUP_HISTORY.lock().unwrap();
-    if let Some(&last_ts) = history.get(key) {
-        let now = chrono::Utc::now().timestamp();
-        now - last_ts < cooldown_seconds
-    } else {
-        false
+/// Execute warmup tasks in batches of 3, recording per-account results into the run log
+async fn execute_warmups(run_id: i64, tasks: Vec<WarmupTask>) -> usize {
+    let total = tasks.len();
+    let batch_size = 3;
+    let batch_count = total.div_ceil(batch_size);
+    let mut success = 0;
+
+    for (batch_idx, batch) in tasks.chunks(batch_size).enumerate() {
+        let mut handles = Vec::new();
+
+        for (task_idx, task) in batch.iter().enumerate() {
+            let global_idx = batch_idx * batch_size + task_idx + 1;
+            logger::log_info(&format!(
+                "[Warmup {}/{}] {} @ {} ({}%)",
+                global_idx, total, task.model, task.email, task.percentage
+            ));
+
+            let (token, model, pid, email, pct) = (
+                task.token.clone(),
+                task.model.clone(),
+                task.pid.clone(),
+                task.email.clone(),
+                task.percentage,
+            );
+            handles.push(tokio::spawn(async move {
+                quota::warmup_model_directly(&token, &model, &pid, &email, pct).await
+            }));
+        }
+
+        for (task, handle) in batch.iter().zip(handles) {
+            let ok = matches!(handle.await, Ok(true));
+            if ok {
+                success += 1;
+                record_warmup_history(&task.history_key, Utc::now().timestamp());
+            }
+            record_result(
+                run_id,
+                &task.email,
+                &task.model,
+                if ok { "success" } else { "failed" },
+                Some(task.percentage),
+                None,
+            );
+        }
+
+        if batch_idx + 1 < batch_count {
+            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        }
+    }
+
+    if let Err(e) = warmup_db::finish_run(run_id) {
+        logger::log_warn(&format!("[Scheduler] Failed to finish warmup run: {}", e));
     }
+    success
+}
+
+fn refresh_quotas_later(app_handle: &tauri::AppHandle) {
+    let handle_inner = app_handle.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        let state = handle_inner.state::<crate::commands::proxy::ProxyServiceState>();
+        let _ = crate::commands::refresh_all_quotas(state).await;
+    });
 }
 
 pub fn start_scheduler(app_handle: tauri::AppHandle) {
     tauri::async_runtime::spawn(async move {
-        logger::log_info("Smart Warmup Scheduler started. Monitoring quota at 100%...");
-        
-        // Scan every 10 minutes
-        let mut interval = time::interval(Duration::from_secs(600));
+        logger::log_info("Smart Warmup Scheduler started. Monitoring quota at 100% and warmup schedules...");
+
+        let mut interval = time::interval(Duration::from_secs(TICK_SECS));
+        // After sleep, fire once instead of bursting the missed ticks
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        let mut tick: u64 = 0;
 
         loop {
             interval.tick().await;
+            tick += 1;
 
             // Load configuration
             let Ok(app_config) = config::load_app_config() else {
                 continue;
             };
+            let warmup_config = app_config.scheduled_warmup;
 
-            if !app_config.scheduled_warmup.enabled {
-                continue;
-            }
-            
-            // Get all accounts (no longer filtering by level)
-            let Ok(accounts) = account::list_accounts() else {
-                continue;
-            };
+            run_due_schedules(&app_handle, &warmup_config).await;
 
-            if accounts.is_empty() {
-                continue;
+            if tick % QUOTA_SCAN_EVERY_TICKS == 1 {
+                if warmup_config.enabled {
+                    scan_full_quota(&app_handle, &warmup_config).await;
+                }
+
+                let now_ts = Utc::now().timestamp();
+                if let Err(e) = warmup_db::prune(now_ts - HISTORY_RETENTION_SECS, now_ts - RUN_LOG_RETENTION_SECS) {
+                    logger::log_warn(&format!("[Scheduler] Failed to prune warmup history: {}", e));
+                }
             }
+        }
+    });
+}
 
-            logger::log_info(&format!(
-                "[Scheduler] Scanning {} accounts for 100% quota models...",
-                accounts.len()
-            ));
+/// Built-in trigger: warm monitored models that are back at 100%
+async fn scan_full_quota(app_handle: &tauri::AppHandle, warmup_config: &ScheduledWarmupConfig) {
+    // Get all accounts (no longer filtering by level)
+    let Ok(accounts) = account::list_accounts() else {
+        return;
+    };
 
-            let mut warmup_tasks = Vec::new();
-            let mut skipped_cooldown = 0;
+    if accounts.is_empty() {
+        return;
+    }
 
-            // Scan each model for each account
-            for account in &accounts {
-                // Skip disabled accounts
-                if account.proxy_disabled {
-                    continue;
-                }
+    logger::log_info(&format!(
+        "[Scheduler] Scanning {} accounts for 100% quota models...",
+        accounts.len()
+    ));
 
-                // Get valid token
-                let Ok((token, pid)) = quota::get_valid_token_for_warmup(account).await else {
-                    continue;
-                };
+    let cooldown = warmup_config.cooldown_minutes as i64 * 60;
+    let mut warmup_tasks = Vec::new();
+    let mut skipped_cooldown = Vec::new();
 
-                // Get fresh quota
-                let Ok((fresh_quota, _)) = quota::fetch_quota_with_cache(&token, &account.email, Some(&pid)).await else {
-                    continue;
-                };
+    // Scan each model for each account
+    for account in &accounts {
+        // Skip disabled accounts
+        if account.disabled || account.proxy_disabled {
+            continue;
+        }
 
-                let now_ts = Utc::now().timestamp();
+        // Get valid token
+        let Ok((token, pid)) = quota::get_valid_token_for_warmup(account).await else {
+            continue;
+        };
+
+        // Get fresh quota
+        let Ok((fresh_quota, _)) = quota::fetch_quota_with_cache(&token, &account.email, Some(&pid)).await else {
+            continue;
+        };
+
+        for model in fresh_quota.models {
+            let key = history_key(&account.email, &model.name);
 
-                for model in fresh_quota.models {
-                    // Core logic: detect 100% quota
-                    if model.percentage == 100 {
-                        let model_to_ping = model.name.clone();
-
-                        // Only warmup models configured by user (allowlist)
-                        if !app_config.scheduled_warmup.monitored_models.contains(&model_to_ping) {
-                            continue;
-                        }
-
-                        // Use mapped name as key
-                        let history_key = format!("{}:{}:100", account.email, model_to_ping);
-                        
-                        // Check cooldown: do not repeat warmup within 4 hours
-                        {
-                            let history = WARMUP_HISTORY.lock().unwrap();
-                            if let Some(&last_warmup_ts) = history.get(&history_key) {
-                                let cooldown_seconds = 14400;
-                                if now_ts - last_warmup_ts < cooldown_seconds {
-                                    skipped_cooldown += 1;
-                                    continue;
-                                }
-                            }
-                        }
-
-                        warmup_tasks.push((
-                            account.email.clone(),
-                            model_to_ping.clone(),
-                            token.clone(),
-                            pid.clone(),
-                            model.percentage,
-                            history_key.clone(),
-                        ));
-
-                        logger::log_info(&format!(
-                            "[Scheduler] ✓ Scheduled warmup: {} @ {} (quota at 100%)",
-                            model_to_ping, account.email
-                        ));
-                    } else if model.percentage < 100 {
-                        // Quota not full, clear history, need to map name first
-                        let model_to_ping = model.name.clone();
-                        let history_key = format!("{}:{}:100", account.email, model_to_ping);
-                        
-                        let mut history = WARMUP_HISTORY.lock().unwrap();
-                        if history.remove(&history_key).is_some() {
-                            save_warmup_history(&history);
-                            logger::log_info(&format!(
-                                "[Scheduler] Cleared history for {} @ {} (quota: {}%)",
-                                model_to_ping, account.email, model.percentage
-                            ));
-                        }
-                    }
+            // Core logic: detect 100% quota
+            if model.percentage == 100 {
+                // Only warmup models configured by user (allowlist)
+                if !warmup_config.monitored_models.contains(&model.name) {
+                    continue;
                 }
-            }
 
-            // Execute warmup tasks
-            if !warmup_tasks.is_empty() {
-                let total = warmup_tasks.len();
-                if skipped_cooldown > 0 {
-                    logger::log_info(&format!(
-                        "[Scheduler] Skipped {} models in cooldown, will warmup {}",
-                        skipped_cooldown, total
-                    ));
+                // Check cooldown: do not repeat warmup within the cooldown
+                if check_cooldown(&key, cooldown) {
+                    skipped_cooldown.push((account.email.clone(), model.name.clone()));
+                    continue;
                 }
+
                 logger::log_info(&format!(
-                    "[Scheduler] 🔥 Triggering {} warmup tasks...",
-                    total
+                    "[Scheduler] ✓ Scheduled warmup: {} @ {} (quota at 100%)",
+                    model.name, account.email
                 ));
-
-                let handle_for_warmup = app_handle.clone();
-                tokio::spawn(async move {
-                    let mut success = 0;
-                    let batch_size = 3;
-                    let now_ts = chrono::Utc::now().timestamp();
-                    
-                    for (batch_idx, batch) in warmup_tasks.chunks(batch_size).enumerate() {
-                        let mut handles = Vec::new();
-                        
-                        for (task_idx, (email, model, token, pid, pct, history_key)) in batch.iter().enumerate() {
-                            let global_idx = batch_idx * batch_size + task_idx + 1;
-                            let email = email.clone();
-                            let model = model.clone();
-                            let token = token.clone();
-                            let pid = pid.clone();
-                            let pct = *pct;
-                            let history_key = history_key.clone();
-                            
-                            logger::log_info(&format!(
-                                "[Warmup {}/{}] {} @ {} ({}%)",
-                                global_idx, total, model, email, pct
-                            ));
-                            
-                            let handle = tokio::spawn(async move {
-                                let result = quota::warmup_model_directly(&token, &model, &pid, &email, pct).await;
-                                (result, history_key)
-                            });
-                            handles.push(handle);
-                        }
-                        
-                        for handle in handles {
-                            match handle.await {
-                                Ok((true, history_key)) => {
-                                    success += 1;
-                                    record_warmup_history(&history_key, now_ts);
-                                }
-                                _ => {}
-                            }
-                        }
-                        
-                        if batch_idx < (warmup_tasks.len() + batch_size - 1) / batch_size - 1 {
-                            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                        }
-                    }
-
+                warmup_tasks.push(WarmupTask {
+                    email: account.email.clone(),
+                    model: model.name,
+                    token: token.clone(),
+                    pid: pid.clone(),
+                    percentage: model.percentage,
+                    history_key: key,
+                });
+            } else if model.percentage < 100 {
+                // Quota not full, clear history so the next 100% is warmed again
+                if let Ok(true) = warmup_db::clear_warmup(&key) {
                     logger::log_info(&format!(
-                        "[Scheduler] ✅ Warmup completed: {}/{} successful",
-                        success, total
+                        "[Scheduler] Cleared history for {} @ {} (quota: {}%)",
+                        model.name, account.email, model.percentage
                     ));
+                }
+            }
+        }
+    }
 
-                    // Refresh quota, sync to frontend
-                    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                    let state = handle_for_warmup.state::<crate::commands::proxy::ProxyServiceState>();
-                    let _ = crate::commands::refresh_all_quotas(state).await;
-                });
-            } else if skipped_cooldown > 0 {
-                logger::log_info(&format!(
-                    "[Scheduler] Scan completed, all 100% models are in cooldown, skipped {}",
-                    skipped_cooldown
-                ));
-            } else {
-                logger::log_info("[Scheduler] Scan completed, no models with 100% quota need warmup");
+    if warmup_tasks.is_empty() {
+        if !skipped_cooldown.is_empty() {
+            logger::log_info(&format!(
+                "[Scheduler] Scan completed, all 100% models are in cooldown, skipped {}",
+                skipped_cooldown.len()
+            ));
+        } else {
+            logger::log_info("[Scheduler] Scan completed, no models with 100% quota need warmup");
+        }
+        // Refresh frontend display after scan (ensure UI has latest data)
+        refresh_quotas_later(app_handle);
+        return;
+    }
+
+    let total = warmup_tasks.len();
+    if !skipped_cooldown.is_empty() {
+        logger::log_info(&format!(
+            "[Scheduler] Skipped {} models in cooldown, will warmup {}",
+            skipped_cooldown.len(),
+            total
+        ));
+    }
+    logger::log_info(&format!("[Scheduler] 🔥 Triggering {} warmup tasks...", total));
+
+    let run_id = match warmup_db::start_run(QUOTA_FULL_RUN, QUOTA_FULL_RUN) {
+        Ok(id) => id,
+        Err(e) => {
+            logger::log_error(&format!("[Scheduler] Failed to start warmup run: {}", e));
+            return;
+        }
+    };
+    for (email, model) in &skipped_cooldown {
+        record_result(run_id, email, model, "skipped_cooldown", Some(100), None);
+    }
+
+    let handle_for_warmup = app_handle.clone();
+    tokio::spawn(async move {
+        let success = execute_warmups(run_id, warmup_tasks).await;
+        logger::log_info(&format!(
+            "[Scheduler] ✅ Warmup completed: {}/{} successful",
+            success, total
+        ));
+
+        // Refresh quota, sync to frontend
+        refresh_quotas_later(&handle_for_warmup);
+    });
+}
+
+/// Evaluate cron / window schedules; missed occurrences (sleep, restart) are caught up once or skipped
+async fn run_due_schedules(app_handle: &tauri::AppHandle, warmup_config: &ScheduledWarmupConfig) {
+    let now = chrono::Local::now();
+    for schedule in warmup_config.schedules.iter().filter(|s| s.enabled) {
+        let last_run = match warmup_db::get_schedule_last_run(&schedule.name) {
+            Ok(Some(ts)) => ts,
+            Ok(None) => {
+                // First sight of this schedule: start counting from now
+                let _ = warmup_db::set_schedule_last_run(&schedule.name, now.timestamp());
+                continue;
             }
+            Err(e) => {
+                logger::log_warn(&format!("[Scheduler] Failed to load schedule state: {}", e));
+                continue;
+            }
+        };
+        let Some(last_run_local) = chrono::DateTime::from_timestamp(last_run, 0)
+            .map(|t| t.with_timezone(&chrono::Local).naive_local())
+        else {
+            continue;
+        };
+
+        let due = match schedule.due(last_run_local, now.naive_local()) {
+            Ok(Some(due)) => due,
+            Ok(None) => continue,
+            Err(e) => {
+                logger::log_warn(&format!("[Scheduler] Invalid schedule '{}': {}", schedule.name, e));
+                continue;
+            }
+        };
+        let _ = warmup_db::set_schedule_last_run(&schedule.name, now.timestamp());
 
-            // Refresh frontend display after scan (ensure UI has latest data)
-            let handle_inner = app_handle.clone();
-            tokio::spawn(async move {
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                let state = handle_inner.state::<crate::commands::proxy::ProxyServiceState>();
-                let _ = crate::commands::refresh_all_quotas(state).await;
-                logger::log_info("[Scheduler] Quota data synced to frontend");
-            });
+        if due.missed && !schedule.catch_up {
+            logger::log_info(&format!(
+                "[Scheduler] Schedule '{}' missed its run at {}, catch-up disabled",
+                schedule.name, due.at
+            ));
+            if let Ok(run_id) = warmup_db::start_run(&schedule.name, "missed") {
+                let _ = warmup_db::finish_run(run_id);
+            }
+            continue;
+        }
 
-            // Regularly clean up history (keep last 24 hours)
-            {
-                let now_ts = Utc::now().timestamp();
-                let mut history = WARMUP_HISTORY.lock().unwrap();
-                let cutoff = now_ts - 86400; // 24 hours ago
-                history.retain(|_, &mut ts| ts > cutoff);
+        let trigger = if due.missed { "catch_up" } else { "schedule" };
+        logger::log_info(&format!(
+            "[Scheduler] ⏰ Schedule '{}' due at {} ({})",
+            schedule.name, due.at, trigger
+        ));
+        let schedule = schedule.clone();
+        let warmup_config = warmup_config.clone();
+        let handle = app_handle.clone();
+        tokio::spawn(async move {
+            match run_schedule(&schedule, &warmup_config, trigger).await {
+                Ok(_) => refresh_quotas_later(&handle),
+                Err(e) => logger::log_error(&format!("[Scheduler] Schedule '{}' failed: {}", schedule.name, e)),
             }
+        });
+    }
+}
+
+/// Run one schedule now; returns the run id
+pub async fn run_schedule(
+    schedule: &WarmupSchedule,
+    warmup_config: &ScheduledWarmupConfig,
+    trigger: &str,
+) -> Result<i64, String> {
+    let accounts: Vec<Account> = account::list_accounts()?
+        .into_iter()
+        .filter(|a| !a.disabled && !a.proxy_disabled)
+        .filter(|a| schedule.targets(&a.email, &warmup_config.account_tags))
+        .collect();
+    let models = if schedule.models.is_empty() {
+        &warmup_config.monitored_models
+    } else {
+        &schedule.models
+    };
+    let cooldown = schedule.cooldown_minutes.unwrap_or(warmup_config.cooldown_minutes) as i64 * 60;
+
+    let run_id = warmup_db::start_run(&schedule.name, trigger)?;
+    let mut tasks = Vec::new();
+
+    for account in &accounts {
+        let (token, pid) = match quota::get_valid_token_for_warmup(account).await {
+            Ok(t) => t,
+            Err(e) => {
+                for model in models {
+                    record_result(run_id, &account.email, model, "failed", None, Some(e.clone()));
+                }
+                continue;
+            }
+        };
+        let fresh_quota = match quota::fetch_quota_with_cache(&token, &account.email, Some(&pid)).await {
+            Ok((q, _)) => q,
+            Err(e) => {
+                for model in models {
+                    record_result(run_id, &account.email, model, "failed", None, Some(e.to_string()));
+                }
+                continue;
+            }
+        };
+
+        for model in models {
+            let percentage = fresh_quota.models.iter().find(|m| &m.name == model).map(|m| m.percentage);
+            if schedule.require_full_quota && percentage != Some(100) {
+                record_result(run_id, &account.email, model, "skipped_quota", percentage, None);
+                continue;
+            }
+            let key = history_key(&account.email, model);
+            if check_cooldown(&key, cooldown) {
+                record_result(run_id, &account.email, model, "skipped_cooldown", percentage, None);
+                continue;
+            }
+            tasks.push(WarmupTask {
+                email: account.email.clone(),
+                model: model.clone(),
+                token: token.clone(),
+                pid: pid.clone(),
+                percentage: percentage.unwrap_or(0),
+                history_key: key,
+            });
         }
-    });
+    }
+
+    let total = tasks.len();
+    let success = execute_warmups(run_id, tasks).await;
+    logger::log_info(&format!(
+        "[Scheduler] ✅ Schedule '{}' completed: {}/{} warmed ({} accounts)",
+        schedule.name,
+        success,
+        total,
+        accounts.len()
+    ));
+    Ok(run_id)
 }
 
 /// Trigger immediate smart warmup check for a single account
@@ -273,55 +440,40 @@ pub async fn trigger_warmup_for_account(account: &Account) {
         return;
     };
 
+    // Only warmup models selected by user
+    let Ok(app_config) = config::load_app_config() else {
+        return;
+    };
+    let cooldown = app_config.scheduled_warmup.cooldown_minutes as i64 * 60;
+
     let now_ts = Utc::now().timestamp();
     let mut tasks_to_run = Vec::new();
 
     for model in fresh_quota.models {
-        let history_key = format!("{}:{}:100", account.email, model.name);
-        
+        let key = history_key(&account.email, &model.name);
+
         if model.percentage == 100 {
             // Check history to avoid repeated warmup (with cooldown)
-            {
-                let mut history = WARMUP_HISTORY.lock().unwrap();
-                
-                // 4 hour cooldown (Pro account resets every 5h, 1h margin)
-                if let Some(&last_warmup_ts) = history.get(&history_key) {
-                    let cooldown_seconds = 14400;
-                    if now_ts - last_warmup_ts < cooldown_seconds {
-                        // Still in cooldown, skip
-                        continue;
-                    }
-                }
-                
-                history.insert(history_key, now_ts);
-                save_warmup_history(&history);
-            }
-
-            let model_to_ping = model.name.clone();
-
-            // Only warmup models selected by user
-            let Ok(app_config) = config::load_app_config() else {
+            if check_cooldown(&key, cooldown) {
                 continue;
-            };
+            }
+            record_warmup_history(&key, now_ts);
 
-            if app_config.scheduled_warmup.monitored_models.contains(&model_to_ping) {
-                tasks_to_run.push((model_to_ping, model.percentage));
+            if app_config.scheduled_warmup.monitored_models.contains(&model.name) {
+                tasks_to_run.push((model.name, model.percentage));
             }
         } else if model.percentage < 100 {
             // Quota not full, clear history, allow warmup next time it's 100%
-            let mut history = WARMUP_HISTORY.lock().unwrap();
-            history.remove(&history_key);
+            let _ = warmup_db::clear_warmup(&key);
         }
     }
 
     // Execute warmup
-    if !tasks_to_run.is_empty() {
-        for (model, pct) in tasks_to_run {
-            logger::log_info(&format!(
-                "[Scheduler] 🔥 Triggering individual warmup: {} @ {} (Sync)",
-                model, account.email
-            ));
-            quota::warmup_model_directly(&token, &model, &pid, &account.email, pct).await;
-        }
+    for (model, pct) in tasks_to_run {
+        logger::log_info(&format!(
+            "[Scheduler] 🔥 Triggering individual warmup: {} @ {} (Sync)",
+            model, account.email
+        ));
+        quota::warmup_model_directly(&token, &model, &pid, &account.email, pct).await;
     }
 }
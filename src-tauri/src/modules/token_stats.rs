@@ -1,5 +1,6 @@
 use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 /// Aggregated token statistics
@@ -10,6 +11,15 @@ pub struct TokenStatsAggregated {
     pub total_output_tokens: u64,
     pub total_tokens: u64,
     pub request_count: u64,
+    /// Estimated cost in USD (from the configured price table)
+    #[serde(default)]
+    pub total_cost: f64,
+    /// Input tokens served from the context cache (included in `total_input_tokens`)
+    #[serde(default)]
+    pub total_cached_input_tokens: u64,
+    /// Thinking tokens (not included in `total_output_tokens`)
+    #[serde(default)]
+    pub total_thinking_tokens: u64,
 }
 
 /// Per-account token statistics
@@ -20,6 +30,21 @@ pub struct AccountTokenStats {
     pub total_output_tokens: u64,
     pub total_tokens: u64,
     pub request_count: u64,
+    /// Estimated cost in USD (from the configured price table)
+    #[serde(default)]
+    pub total_cost: f64,
+    /// Input tokens served from the context cache (included in `total_input_tokens`)
+    #[serde(default)]
+    pub total_cached_input_tokens: u64,
+    /// Thinking tokens (not included in `total_output_tokens`)
+    #[serde(default)]
+    pub total_thinking_tokens: u64,
+    /// Cached share of input tokens (0.0 - 1.0)
+    #[serde(default)]
+    pub cache_hit_rate: f64,
+    /// Request count per HTTP status (including failed requests without token usage)
+    #[serde(default)]
+    pub request_count_by_status: BTreeMap<u16, u64>,
 }
 
 /// Summary statistics
@@ -30,6 +55,15 @@ pub struct TokenStatsSummary {
     pub total_tokens: u64,
     pub total_requests: u64,
     pub unique_accounts: u64,
+    #[serde(default)]
+    pub total_cost: f64,
+    #[serde(default)]
+    pub total_cached_input_tokens: u64,
+    #[serde(default)]
+    pub total_thinking_tokens: u64,
+    /// Request count per HTTP status (including failed requests without token usage)
+    #[serde(default)]
+    pub request_count_by_status: BTreeMap<u16, u64>,
 }
 
 /// Per-model token statistics
@@ -40,12 +74,42 @@ pub struct ModelTokenStats {
     pub total_output_tokens: u64,
     pub total_tokens: u64,
     pub request_count: u64,
+    /// Estimated cost in USD (from the configured price table)
+    #[serde(default)]
+    pub total_cost: f64,
+    /// Input tokens served from the context cache (included in `total_input_tokens`)
+    #[serde(default)]
+    pub total_cached_input_tokens: u64,
+    /// Thinking tokens (not included in `total_output_tokens`)
+    #[serde(default)]
+    pub total_thinking_tokens: u64,
+    /// Cached share of input tokens (0.0 - 1.0)
+    #[serde(default)]
+    pub cache_hit_rate: f64,
+}
+
+/// Per-session token statistics (session = sticky-scheduling fingerprint)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTokenStats {
+    pub session_id: String,
+    /// Account that served the most recent request of the session
+    pub account_email: String,
+    pub total_input_tokens: u64,
+    pub total_cached_input_tokens: u64,
+    pub total_output_tokens: u64,
+    pub total_thinking_tokens: u64,
+    pub request_count: u64,
+    pub cache_hit_rate: f64,
+    pub last_seen: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelTrendPoint {
     pub period: String,
     pub model_data: std::collections::HashMap<String, u64>,
+    /// Cached share of input tokens per model
+    #[serde(default)]
+    pub cache_hit_rate: std::collections::HashMap<String, f64>,
 }
 
 /// Account trend data point (for stacked area chart)
@@ -53,6 +117,9 @@ pub struct ModelTrendPoint {
 pub struct AccountTrendPoint {
     pub period: String,
     pub account_data: std::collections::HashMap<String, u64>,
+    /// Cached share of input tokens per account
+    #[serde(default)]
+    pub cache_hit_rate: std::collections::HashMap<String, f64>,
 }
 
 fn get_db_path() -> Result<PathBuf, String> {
@@ -78,7 +145,10 @@ fn connect_db() -> Result<Connection, String> {
 /// Initialize the token stats database
 pub fn init_db() -> Result<(), String> {
     let conn = connect_db()?;
+    create_schema(&conn)
+}
 
+fn create_schema(conn: &Connection) -> Result<(), String> {
     // Create main usage table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS token_usage (
@@ -94,6 +164,16 @@ pub fn init_db() -> Result<(), String> {
     )
     .map_err(|e| e.to_string())?;
 
+    // Migrations: cost accounting columns
+    let _ = conn.execute("ALTER TABLE token_usage ADD COLUMN cost REAL NOT NULL DEFAULT 0", []);
+    let _ = conn.execute("ALTER TABLE token_usage ADD COLUMN api_key TEXT", []);
+    // Migrations: cache / thinking breakdown
+    let _ = conn.execute("ALTER TABLE token_usage ADD COLUMN cached_input_tokens INTEGER NOT NULL DEFAULT 0", []);
+    let _ = conn.execute("ALTER TABLE token_usage ADD COLUMN thinking_tokens INTEGER NOT NULL DEFAULT 0", []);
+    let _ = conn.execute("ALTER TABLE token_usage ADD COLUMN session_id TEXT", []);
+    // Migration: client protocol (openai / anthropic / gemini) for usage reports
+    let _ = conn.execute("ALTER TABLE token_usage ADD COLUMN protocol TEXT", []);
+
     // Create indexes for efficient queries
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_token_timestamp ON token_usage (timestamp DESC)",
@@ -121,43 +201,208 @@ pub fn init_db() -> Result<(), String> {
         [],
     )
     .map_err(|e| e.to_string())?;
+    let _ = conn.execute(
+        "ALTER TABLE token_stats_hourly ADD COLUMN total_cost REAL NOT NULL DEFAULT 0",
+        [],
+    );
+    let _ = conn.execute(
+        "ALTER TABLE token_stats_hourly ADD COLUMN total_cached_input_tokens INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+    let _ = conn.execute(
+        "ALTER TABLE token_stats_hourly ADD COLUMN total_thinking_tokens INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_token_session ON token_usage (session_id)",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
+    // Request count per HTTP status, recorded for every proxied API request
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS request_status_hourly (
+            hour_bucket TEXT NOT NULL,
+            account_email TEXT NOT NULL,
+            status INTEGER NOT NULL,
+            request_count INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (hour_bucket, account_email, status)
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
+/// Check that the database is reachable and its schema is in place (used by /readyz)
+pub fn health_check() -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.query_row("SELECT COUNT(*) FROM (SELECT 1 FROM token_usage LIMIT 1)", [], |row| row.get::<_, i64>(0))
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// A single request's usage, as recorded by the monitor
+///
+/// `input_tokens` includes `cached_input_tokens`; `output_tokens` excludes `thinking_tokens`
+#[derive(Debug, Clone, Default)]
+pub struct UsageRecord {
+    pub account_email: String,
+    pub model: String,
+    /// Hashed API key id (`key-xxxx`), see `request_queue::api_key_id`
+    pub api_key: Option<String>,
+    /// Session fingerprint, see `SessionManager`
+    pub session_id: Option<String>,
+    /// Client protocol: "openai", "anthropic", "gemini"
+    pub protocol: Option<String>,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub cached_input_tokens: u32,
+    pub thinking_tokens: u32,
+    /// Cost in USD, 0 when the model has no configured price
+    pub cost: f64,
+}
+
 /// Record token usage from a request
-pub fn record_usage(
-    account_email: &str,
-    model: &str,
-    input_tokens: u32,
-    output_tokens: u32,
-) -> Result<(), String> {
+pub fn record_usage(record: &UsageRecord) -> Result<(), String> {
     let conn = connect_db()?;
-    let timestamp = chrono::Utc::now().timestamp();
-    let total_tokens = input_tokens + output_tokens;
+    insert_usage(&conn, record, chrono::Utc::now())
+}
+
+fn insert_usage(conn: &Connection, record: &UsageRecord, now: chrono::DateTime<chrono::Utc>) -> Result<(), String> {
+    let timestamp = now.timestamp();
+    let total_tokens = record.input_tokens + record.output_tokens + record.thinking_tokens;
 
     // Insert into raw usage table
     conn.execute(
-        "INSERT INTO token_usage (timestamp, account_email, model, input_tokens, output_tokens, total_tokens)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![timestamp, account_email, model, input_tokens, output_tokens, total_tokens],
+        "INSERT INTO token_usage (timestamp, account_email, model, input_tokens, output_tokens, total_tokens, cost, api_key,
+                                  cached_input_tokens, thinking_tokens, session_id, protocol)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            timestamp,
+            record.account_email,
+            record.model,
+            record.input_tokens,
+            record.output_tokens,
+            total_tokens,
+            record.cost,
+            record.api_key,
+            record.cached_input_tokens,
+            record.thinking_tokens,
+            record.session_id,
+            record.protocol
+        ],
     ).map_err(|e| e.to_string())?;
 
-    let hour_bucket = chrono::Utc::now().format("%Y-%m-%d %H:00").to_string();
+    let hour_bucket = now.format("%Y-%m-%d %H:00").to_string();
     conn.execute(
-        "INSERT INTO token_stats_hourly (hour_bucket, account_email, total_input_tokens, total_output_tokens, total_tokens, request_count)
-         VALUES (?1, ?2, ?3, ?4, ?5, 1)
+        "INSERT INTO token_stats_hourly (hour_bucket, account_email, total_input_tokens, total_output_tokens, total_tokens, request_count, total_cost,
+                                         total_cached_input_tokens, total_thinking_tokens)
+         VALUES (?1, ?2, ?3, ?4, ?5, 1, ?6, ?7, ?8)
          ON CONFLICT(hour_bucket, account_email) DO UPDATE SET
             total_input_tokens = total_input_tokens + ?3,
             total_output_tokens = total_output_tokens + ?4,
             total_tokens = total_tokens + ?5,
-            request_count = request_count + 1",
-        params![hour_bucket, account_email, input_tokens, output_tokens, total_tokens],
+            request_count = request_count + 1,
+            total_cost = total_cost + ?6,
+            total_cached_input_tokens = total_cached_input_tokens + ?7,
+            total_thinking_tokens = total_thinking_tokens + ?8",
+        params![
+            hour_bucket,
+            record.account_email,
+            record.input_tokens,
+            record.output_tokens,
+            total_tokens,
+            record.cost,
+            record.cached_input_tokens,
+            record.thinking_tokens
+        ],
     ).map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
+/// Count a finished API request by HTTP status (empty account = failed before an account was assigned)
+pub fn record_request_status(account_email: &str, status: u16) -> Result<(), String> {
+    let conn = connect_db()?;
+    insert_request_status(&conn, account_email, status, chrono::Utc::now())
+}
+
+fn insert_request_status(
+    conn: &Connection,
+    account_email: &str,
+    status: u16,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<(), String> {
+    let hour_bucket = now.format("%Y-%m-%d %H:00").to_string();
+    conn.execute(
+        "INSERT INTO request_status_hourly (hour_bucket, account_email, status, request_count)
+         VALUES (?1, ?2, ?3, 1)
+         ON CONFLICT(hour_bucket, account_email, status) DO UPDATE SET request_count = request_count + 1",
+        params![hour_bucket, account_email, status],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Request counts per (account, status) since the given hour bucket
+fn query_status_counts(conn: &Connection, cutoff_bucket: &str) -> Result<Vec<(String, u16, u64)>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT account_email, status, SUM(request_count)
+         FROM request_status_hourly
+         WHERE hour_bucket >= ?1
+         GROUP BY account_email, status",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([cutoff_bucket], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
+fn cache_hit_rate(cached: u64, input: u64) -> f64 {
+    if input == 0 {
+        0.0
+    } else {
+        cached as f64 / input as f64
+    }
+}
+
+/// Total cost (USD) recorded since `since` (unix seconds), used by budget rules
+pub fn get_cost_since(since: i64, filter: &crate::proxy::budget::SpendFilter) -> Result<f64, String> {
+    let conn = connect_db()?;
+    query_cost_since(&conn, since, filter)
+}
+
+fn query_cost_since(conn: &Connection, since: i64, filter: &crate::proxy::budget::SpendFilter) -> Result<f64, String> {
+    use crate::proxy::budget::SpendFilter;
+    use rusqlite::types::Value as SqlValue;
+
+    let mut sql = "SELECT COALESCE(SUM(cost), 0) FROM token_usage WHERE timestamp >= ?1".to_string();
+    let mut values = vec![SqlValue::Integer(since)];
+    match filter {
+        SpendFilter::All => {}
+        SpendFilter::ApiKey(key) => {
+            sql.push_str(" AND api_key = ?2");
+            values.push(SqlValue::Text(key.clone()));
+        }
+        SpendFilter::Accounts(accounts) => {
+            if accounts.is_empty() {
+                return Ok(0.0);
+            }
+            let placeholders: Vec<String> = (0..accounts.len()).map(|i| format!("?{}", i + 2)).collect();
+            sql.push_str(&format!(" AND account_email IN ({})", placeholders.join(", ")));
+            values.extend(accounts.iter().map(|a| SqlValue::Text(a.clone())));
+        }
+    }
+
+    conn.query_row(&sql, rusqlite::params_from_iter(values), |row| row.get(0))
+        .map_err(|e| e.to_string())
+}
+
 /// Get hourly aggregated stats for a time range
 pub fn get_hourly_stats(hours: i64) -> Result<Vec<TokenStatsAggregated>, String> {
     let conn = connect_db()?;
@@ -170,7 +415,10 @@ pub fn get_hourly_stats(hours: i64) -> Result<Vec<TokenStatsAggregated>, String>
                 SUM(total_input_tokens) as input, 
                 SUM(total_output_tokens) as output,
                 SUM(total_tokens) as total,
-                SUM(request_count) as count
+                SUM(request_count) as count,
+                SUM(total_cost) as cost,
+                SUM(total_cached_input_tokens) as cached,
+                SUM(total_thinking_tokens) as thinking
          FROM token_stats_hourly 
          WHERE hour_bucket >= ?1
          GROUP BY hour_bucket
@@ -186,6 +434,9 @@ pub fn get_hourly_stats(hours: i64) -> Result<Vec<TokenStatsAggregated>, String>
                 total_output_tokens: row.get(2)?,
                 total_tokens: row.get(3)?,
                 request_count: row.get(4)?,
+                total_cost: row.get(5)?,
+                total_cached_input_tokens: row.get(6)?,
+                total_thinking_tokens: row.get(7)?,
             })
         })
         .map_err(|e| e.to_string())?;
@@ -209,7 +460,10 @@ pub fn get_daily_stats(days: i64) -> Result<Vec<TokenStatsAggregated>, String> {
                 SUM(total_input_tokens) as input, 
                 SUM(total_output_tokens) as output,
                 SUM(total_tokens) as total,
-                SUM(request_count) as count
+                SUM(request_count) as count,
+                SUM(total_cost) as cost,
+                SUM(total_cached_input_tokens) as cached,
+                SUM(total_thinking_tokens) as thinking
          FROM token_stats_hourly 
          WHERE substr(hour_bucket, 1, 10) >= ?1
          GROUP BY day_bucket
@@ -225,6 +479,9 @@ pub fn get_daily_stats(days: i64) -> Result<Vec<TokenStatsAggregated>, String> {
                 total_output_tokens: row.get(2)?,
                 total_tokens: row.get(3)?,
                 request_count: row.get(4)?,
+                total_cost: row.get(5)?,
+                total_cached_input_tokens: row.get(6)?,
+                total_thinking_tokens: row.get(7)?,
             })
         })
         .map_err(|e| e.to_string())?;
@@ -248,7 +505,10 @@ pub fn get_weekly_stats(weeks: i64) -> Result<Vec<TokenStatsAggregated>, String>
                 SUM(input_tokens) as input, 
                 SUM(output_tokens) as output,
                 SUM(total_tokens) as total,
-                COUNT(*) as count
+                COUNT(*) as count,
+                SUM(cost) as cost,
+                SUM(cached_input_tokens) as cached,
+                SUM(thinking_tokens) as thinking
          FROM token_usage 
          WHERE timestamp >= ?1
          GROUP BY week_bucket
@@ -264,6 +524,9 @@ pub fn get_weekly_stats(weeks: i64) -> Result<Vec<TokenStatsAggregated>, String>
                 total_output_tokens: row.get(2)?,
                 total_tokens: row.get(3)?,
                 request_count: row.get(4)?,
+                total_cost: row.get(5)?,
+                total_cached_input_tokens: row.get(6)?,
+                total_thinking_tokens: row.get(7)?,
             })
         })
         .map_err(|e| e.to_string())?;
@@ -279,7 +542,10 @@ pub fn get_weekly_stats(weeks: i64) -> Result<Vec<TokenStatsAggregated>, String>
 pub fn get_account_stats(hours: i64) -> Result<Vec<AccountTokenStats>, String> {
     let conn = connect_db()?;
     let cutoff = chrono::Utc::now() - chrono::Duration::hours(hours);
-    let cutoff_bucket = cutoff.format("%Y-%m-%d %H:00").to_string();
+    query_account_stats(&conn, &cutoff.format("%Y-%m-%d %H:00").to_string())
+}
+
+fn query_account_stats(conn: &Connection, cutoff_bucket: &str) -> Result<Vec<AccountTokenStats>, String> {
 
     let mut stmt = conn
         .prepare(
@@ -287,7 +553,10 @@ pub fn get_account_stats(hours: i64) -> Result<Vec<AccountTokenStats>, String> {
                 SUM(total_input_tokens) as input, 
                 SUM(total_output_tokens) as output,
                 SUM(total_tokens) as total,
-                SUM(request_count) as count
+                SUM(request_count) as count,
+                SUM(total_cost) as cost,
+                SUM(total_cached_input_tokens) as cached,
+                SUM(total_thinking_tokens) as thinking
          FROM token_stats_hourly 
          WHERE hour_bucket >= ?1
          GROUP BY account_email
@@ -303,6 +572,11 @@ pub fn get_account_stats(hours: i64) -> Result<Vec<AccountTokenStats>, String> {
                 total_output_tokens: row.get(2)?,
                 total_tokens: row.get(3)?,
                 request_count: row.get(4)?,
+                total_cost: row.get(5)?,
+                total_cached_input_tokens: row.get(6)?,
+                total_thinking_tokens: row.get(7)?,
+                cache_hit_rate: cache_hit_rate(row.get(6)?, row.get(1)?),
+                request_count_by_status: BTreeMap::new(),
             })
         })
         .map_err(|e| e.to_string())?;
@@ -311,6 +585,12 @@ pub fn get_account_stats(hours: i64) -> Result<Vec<AccountTokenStats>, String> {
     for row in rows {
         result.push(row.map_err(|e| e.to_string())?);
     }
+
+    for (account, status, count) in query_status_counts(conn, cutoff_bucket)? {
+        if let Some(stats) = result.iter_mut().find(|s| s.account_email == account) {
+            stats.request_count_by_status.insert(status, count);
+        }
+    }
     Ok(result)
 }
 
@@ -320,19 +600,27 @@ pub fn get_summary_stats(hours: i64) -> Result<TokenStatsSummary, String> {
     let cutoff = chrono::Utc::now() - chrono::Duration::hours(hours);
     let cutoff_bucket = cutoff.format("%Y-%m-%d %H:00").to_string();
 
-    let (total_input, total_output, total, requests): (u64, u64, u64, u64) = conn
+    let (total_input, total_output, total, requests, total_cost, total_cached, total_thinking): (u64, u64, u64, u64, f64, u64, u64) = conn
         .query_row(
             "SELECT COALESCE(SUM(total_input_tokens), 0),
                 COALESCE(SUM(total_output_tokens), 0),
                 COALESCE(SUM(total_tokens), 0),
-                COALESCE(SUM(request_count), 0)
+                COALESCE(SUM(request_count), 0),
+                COALESCE(SUM(total_cost), 0),
+                COALESCE(SUM(total_cached_input_tokens), 0),
+                COALESCE(SUM(total_thinking_tokens), 0)
          FROM token_stats_hourly 
          WHERE hour_bucket >= ?1",
             [&cutoff_bucket],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?)),
         )
         .map_err(|e| e.to_string())?;
 
+    let mut request_count_by_status = BTreeMap::new();
+    for (_, status, count) in query_status_counts(&conn, &cutoff_bucket)? {
+        *request_count_by_status.entry(status).or_insert(0) += count;
+    }
+
     let unique_accounts: u64 = conn
         .query_row(
             "SELECT COUNT(DISTINCT account_email) FROM token_stats_hourly WHERE hour_bucket >= ?1",
@@ -347,6 +635,10 @@ pub fn get_summary_stats(hours: i64) -> Result<TokenStatsSummary, String> {
         total_tokens: total,
         total_requests: requests,
         unique_accounts,
+        total_cost,
+        total_cached_input_tokens: total_cached,
+        total_thinking_tokens: total_thinking,
+        request_count_by_status,
     })
 }
 
@@ -360,7 +652,10 @@ pub fn get_model_stats(hours: i64) -> Result<Vec<ModelTokenStats>, String> {
                 SUM(input_tokens) as input,
                 SUM(output_tokens) as output,
                 SUM(total_tokens) as total,
-                COUNT(*) as count
+                COUNT(*) as count,
+                SUM(cost) as cost,
+                SUM(cached_input_tokens) as cached,
+                SUM(thinking_tokens) as thinking
          FROM token_usage
          WHERE timestamp >= ?1
          GROUP BY model
@@ -376,6 +671,10 @@ pub fn get_model_stats(hours: i64) -> Result<Vec<ModelTokenStats>, String> {
                 total_output_tokens: row.get(2)?,
                 total_tokens: row.get(3)?,
                 request_count: row.get(4)?,
+                total_cost: row.get(5)?,
+                total_cached_input_tokens: row.get(6)?,
+                total_thinking_tokens: row.get(7)?,
+                cache_hit_rate: cache_hit_rate(row.get(6)?, row.get(1)?),
             })
         })
         .map_err(|e| e.to_string())?;
@@ -387,24 +686,28 @@ pub fn get_model_stats(hours: i64) -> Result<Vec<ModelTokenStats>, String> {
     Ok(result)
 }
 
-pub fn get_model_trend_hourly(hours: i64) -> Result<Vec<ModelTrendPoint>, String> {
-    let conn = connect_db()?;
-    let cutoff = chrono::Utc::now().timestamp() - (hours * 3600);
+/// (total tokens per key, cache hit rate per key)
+type TrendData = (std::collections::HashMap<String, u64>, std::collections::HashMap<String, f64>);
+/// (period, total tokens per key, cache hit rate per key)
+type TrendRow = (String, std::collections::HashMap<String, u64>, std::collections::HashMap<String, f64>);
 
-    let mut stmt = conn
-        .prepare(
-            "SELECT strftime('%Y-%m-%d %H:00', datetime(timestamp, 'unixepoch')) as hour_bucket,
-                model,
-                SUM(total_tokens) as total
+/// Trend over `token_usage` bucketed by `bucket_format` (strftime) and grouped by `dimension` column
+fn query_trend(conn: &Connection, bucket_format: &str, dimension: &str, cutoff: i64) -> Result<Vec<TrendRow>, String> {
+    let sql = format!(
+        "SELECT strftime('{}', datetime(timestamp, 'unixepoch')) as bucket,
+                {} as dimension,
+                SUM(total_tokens) as total,
+                SUM(input_tokens) as input,
+                SUM(cached_input_tokens) as cached
          FROM token_usage
          WHERE timestamp >= ?1
-         GROUP BY hour_bucket, model
-         ORDER BY hour_bucket ASC",
-        )
-        .map_err(|e| e.to_string())?;
+         GROUP BY bucket, dimension
+         ORDER BY bucket ASC",
+        bucket_format, dimension
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
 
-    let mut trend_map: std::collections::BTreeMap<String, std::collections::HashMap<String, u64>> =
-        std::collections::BTreeMap::new();
+    let mut trend_map: BTreeMap<String, TrendData> = BTreeMap::new();
 
     let rows = stmt
         .query_map([cutoff], |row| {
@@ -412,145 +715,316 @@ pub fn get_model_trend_hourly(hours: i64) -> Result<Vec<ModelTrendPoint>, String
                 row.get::<_, String>(0)?,
                 row.get::<_, String>(1)?,
                 row.get::<_, u64>(2)?,
+                row.get::<_, u64>(3)?,
+                row.get::<_, u64>(4)?,
             ))
         })
         .map_err(|e| e.to_string())?;
 
     for row in rows {
-        let (period, model, total) = row.map_err(|e| e.to_string())?;
-        trend_map.entry(period).or_default().insert(model, total);
+        let (period, key, total, input, cached) = row.map_err(|e| e.to_string())?;
+        let entry = trend_map.entry(period).or_default();
+        entry.0.insert(key.clone(), total);
+        entry.1.insert(key, cache_hit_rate(cached, input));
     }
 
     Ok(trend_map
         .into_iter()
-        .map(|(period, model_data)| ModelTrendPoint { period, model_data })
+        .map(|(period, (totals, hit_rates))| (period, totals, hit_rates))
         .collect())
 }
 
+fn model_trend(rows: Vec<TrendRow>) -> Vec<ModelTrendPoint> {
+    rows.into_iter()
+        .map(|(period, model_data, cache_hit_rate)| ModelTrendPoint {
+            period,
+            model_data,
+            cache_hit_rate,
+        })
+        .collect()
+}
+
+fn account_trend(rows: Vec<TrendRow>) -> Vec<AccountTrendPoint> {
+    rows.into_iter()
+        .map(|(period, account_data, cache_hit_rate)| AccountTrendPoint {
+            period,
+            account_data,
+            cache_hit_rate,
+        })
+        .collect()
+}
+
+pub fn get_model_trend_hourly(hours: i64) -> Result<Vec<ModelTrendPoint>, String> {
+    let conn = connect_db()?;
+    let cutoff = chrono::Utc::now().timestamp() - (hours * 3600);
+    query_trend(&conn, "%Y-%m-%d %H:00", "model", cutoff).map(model_trend)
+}
+
 pub fn get_model_trend_daily(days: i64) -> Result<Vec<ModelTrendPoint>, String> {
     let conn = connect_db()?;
     let cutoff = chrono::Utc::now().timestamp() - (days * 24 * 3600);
+    query_trend(&conn, "%Y-%m-%d", "model", cutoff).map(model_trend)
+}
 
+pub fn get_account_trend_hourly(hours: i64) -> Result<Vec<AccountTrendPoint>, String> {
+    let conn = connect_db()?;
+    let cutoff = chrono::Utc::now().timestamp() - (hours * 3600);
+    query_trend(&conn, "%Y-%m-%d %H:00", "account_email", cutoff).map(account_trend)
+}
+
+pub fn get_account_trend_daily(days: i64) -> Result<Vec<AccountTrendPoint>, String> {
+    let conn = connect_db()?;
+    let cutoff = chrono::Utc::now().timestamp() - (days * 24 * 3600);
+    query_trend(&conn, "%Y-%m-%d", "account_email", cutoff).map(account_trend)
+}
+
+/// Per-session statistics (most recently active first), for cache hit rates per conversation
+pub fn get_session_stats(hours: i64, limit: usize) -> Result<Vec<SessionTokenStats>, String> {
+    let conn = connect_db()?;
+    let cutoff = chrono::Utc::now().timestamp() - (hours * 3600);
+    query_session_stats(&conn, cutoff, limit)
+}
+
+fn query_session_stats(conn: &Connection, cutoff: i64, limit: usize) -> Result<Vec<SessionTokenStats>, String> {
     let mut stmt = conn
         .prepare(
-            "SELECT strftime('%Y-%m-%d', datetime(timestamp, 'unixepoch')) as day_bucket,
-                model,
-                SUM(total_tokens) as total
-         FROM token_usage
-         WHERE timestamp >= ?1
-         GROUP BY day_bucket, model
-         ORDER BY day_bucket ASC",
+            "SELECT session_id,
+                (SELECT t2.account_email FROM token_usage t2
+                 WHERE t2.session_id = t.session_id ORDER BY t2.timestamp DESC, t2.id DESC LIMIT 1) as account,
+                SUM(input_tokens) as input,
+                SUM(cached_input_tokens) as cached,
+                SUM(output_tokens) as output,
+                SUM(thinking_tokens) as thinking,
+                COUNT(*) as count,
+                MAX(timestamp) as last_seen
+         FROM token_usage t
+         WHERE timestamp >= ?1 AND session_id IS NOT NULL
+         GROUP BY session_id
+         ORDER BY last_seen DESC
+         LIMIT ?2",
         )
         .map_err(|e| e.to_string())?;
 
-    let mut trend_map: std::collections::BTreeMap<String, std::collections::HashMap<String, u64>> =
-        std::collections::BTreeMap::new();
-
     let rows = stmt
-        .query_map([cutoff], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, u64>(2)?,
-            ))
+        .query_map(params![cutoff, limit as i64], |row| {
+            let input: u64 = row.get(2)?;
+            let cached: u64 = row.get(3)?;
+            Ok(SessionTokenStats {
+                session_id: row.get(0)?,
+                account_email: row.get(1)?,
+                total_input_tokens: input,
+                total_cached_input_tokens: cached,
+                total_output_tokens: row.get(4)?,
+                total_thinking_tokens: row.get(5)?,
+                request_count: row.get(6)?,
+                cache_hit_rate: cache_hit_rate(cached, input),
+                last_seen: row.get(7)?,
+            })
         })
         .map_err(|e| e.to_string())?;
 
-    for row in rows {
-        let (period, model, total) = row.map_err(|e| e.to_string())?;
-        trend_map.entry(period).or_default().insert(model, total);
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
+/// Time bucket of a usage query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageBucket {
+    Hour,
+    #[default]
+    Day,
+    Week,
+    Month,
+    /// One bucket for the whole range
+    Total,
+}
+
+impl UsageBucket {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "hour" | "hourly" => Ok(Self::Hour),
+            "day" | "daily" => Ok(Self::Day),
+            "week" | "weekly" => Ok(Self::Week),
+            "month" | "monthly" => Ok(Self::Month),
+            "total" | "none" | "" => Ok(Self::Total),
+            other => Err(format!("invalid_bucket: {}", other)),
+        }
     }
 
-    Ok(trend_map
-        .into_iter()
-        .map(|(period, model_data)| ModelTrendPoint { period, model_data })
-        .collect())
+    /// strftime format of the bucket label (UTC)
+    fn strftime(self) -> Option<&'static str> {
+        match self {
+            Self::Hour => Some("%Y-%m-%d %H:00"),
+            Self::Day => Some("%Y-%m-%d"),
+            Self::Week => Some("%Y-W%W"),
+            Self::Month => Some("%Y-%m"),
+            Self::Total => None,
+        }
+    }
 }
 
-pub fn get_account_trend_hourly(hours: i64) -> Result<Vec<AccountTrendPoint>, String> {
-    let conn = connect_db()?;
-    let cutoff = chrono::Utc::now().timestamp() - (hours * 3600);
+/// Group-by dimension of a usage query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageDimension {
+    Account,
+    Model,
+    ApiKey,
+    Protocol,
+}
 
-    let mut stmt = conn
-        .prepare(
-            "SELECT strftime('%Y-%m-%d %H:00', datetime(timestamp, 'unixepoch')) as hour_bucket,
-                account_email,
-                SUM(total_tokens) as total
-         FROM token_usage
-         WHERE timestamp >= ?1
-         GROUP BY hour_bucket, account_email
-         ORDER BY hour_bucket ASC",
-        )
-        .map_err(|e| e.to_string())?;
+impl UsageDimension {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "account" | "account_email" => Ok(Self::Account),
+            "model" => Ok(Self::Model),
+            "api_key" | "key" => Ok(Self::ApiKey),
+            "protocol" => Ok(Self::Protocol),
+            other => Err(format!("invalid_group_by: {}", other)),
+        }
+    }
 
-    let mut trend_map: std::collections::BTreeMap<String, std::collections::HashMap<String, u64>> =
-        std::collections::BTreeMap::new();
+    /// Parse a comma separated list, e.g. "account,model"
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        value
+            .split(',')
+            .filter(|part| !part.trim().is_empty())
+            .map(Self::parse)
+            .collect()
+    }
 
-    let rows = stmt
-        .query_map([cutoff], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, u64>(2)?,
-            ))
-        })
-        .map_err(|e| e.to_string())?;
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Account => "account",
+            Self::Model => "model",
+            Self::ApiKey => "api_key",
+            Self::Protocol => "protocol",
+        }
+    }
 
-    for row in rows {
-        let (period, account, total) = row.map_err(|e| e.to_string())?;
-        trend_map.entry(period).or_default().insert(account, total);
+    fn column(self) -> &'static str {
+        match self {
+            Self::Account => "account_email",
+            Self::Model => "model",
+            Self::ApiKey => "COALESCE(api_key, '')",
+            Self::Protocol => "COALESCE(protocol, '')",
+        }
     }
+}
 
-    Ok(trend_map
-        .into_iter()
-        .map(|(period, account_data)| AccountTrendPoint {
-            period,
-            account_data,
-        })
-        .collect())
+/// Usage query over an arbitrary time range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageQuery {
+    /// Range start, unix seconds (inclusive)
+    pub start: i64,
+    /// Range end, unix seconds (exclusive)
+    pub end: i64,
+    #[serde(default)]
+    pub bucket: UsageBucket,
+    #[serde(default)]
+    pub group_by: Vec<UsageDimension>,
 }
 
-pub fn get_account_trend_daily(days: i64) -> Result<Vec<AccountTrendPoint>, String> {
+/// One row of a usage query; dimension fields are only set when grouped by them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageReportRow {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    pub request_count: u64,
+    pub total_input_tokens: u64,
+    pub total_cached_input_tokens: u64,
+    pub total_output_tokens: u64,
+    pub total_thinking_tokens: u64,
+    pub total_tokens: u64,
+    pub total_cost: f64,
+}
+
+/// Aggregate `token_usage` over `query.start..query.end` by bucket and the requested dimensions
+pub fn query_usage(query: &UsageQuery) -> Result<Vec<UsageReportRow>, String> {
     let conn = connect_db()?;
-    let cutoff = chrono::Utc::now().timestamp() - (days * 24 * 3600);
+    query_usage_rows(&conn, query)
+}
 
-    let mut stmt = conn
-        .prepare(
-            "SELECT strftime('%Y-%m-%d', datetime(timestamp, 'unixepoch')) as day_bucket,
-                account_email,
-                SUM(total_tokens) as total
-         FROM token_usage
-         WHERE timestamp >= ?1
-         GROUP BY day_bucket, account_email
-         ORDER BY day_bucket ASC",
-        )
-        .map_err(|e| e.to_string())?;
+fn query_usage_rows(conn: &Connection, query: &UsageQuery) -> Result<Vec<UsageReportRow>, String> {
+    if query.end <= query.start {
+        return Err("invalid_time_range: end must be after start".to_string());
+    }
 
-    let mut trend_map: std::collections::BTreeMap<String, std::collections::HashMap<String, u64>> =
-        std::collections::BTreeMap::new();
+    // Fixed column layout: period, account, model, api_key, protocol; unused ones are NULL
+    let period = query
+        .bucket
+        .strftime()
+        .map(|fmt| format!("strftime('{}', timestamp, 'unixepoch')", fmt));
+    let dimensions = [
+        UsageDimension::Account,
+        UsageDimension::Model,
+        UsageDimension::ApiKey,
+        UsageDimension::Protocol,
+    ];
+    let mut select = vec![period.clone().unwrap_or_else(|| "NULL".to_string())];
+    let mut group_positions = Vec::new();
+    if period.is_some() {
+        group_positions.push("1".to_string());
+    }
+    for (i, dimension) in dimensions.iter().enumerate() {
+        if query.group_by.contains(dimension) {
+            select.push(dimension.column().to_string());
+            group_positions.push((i + 2).to_string());
+        } else {
+            select.push("NULL".to_string());
+        }
+    }
+    let grouping = if group_positions.is_empty() {
+        String::new()
+    } else {
+        format!("GROUP BY {0} ORDER BY {0}", group_positions.join(", "))
+    };
+
+    let sql = format!(
+        "SELECT {},
+                COUNT(*),
+                COALESCE(SUM(input_tokens), 0),
+                COALESCE(SUM(cached_input_tokens), 0),
+                COALESCE(SUM(output_tokens), 0),
+                COALESCE(SUM(thinking_tokens), 0),
+                COALESCE(SUM(total_tokens), 0),
+                COALESCE(SUM(cost), 0)
+         FROM token_usage
+         WHERE timestamp >= ?1 AND timestamp < ?2
+         {}",
+        select.join(", "),
+        grouping
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
 
     let rows = stmt
-        .query_map([cutoff], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, u64>(2)?,
-            ))
+        .query_map(params![query.start, query.end], |row| {
+            Ok(UsageReportRow {
+                period: row.get(0)?,
+                account_email: row.get(1)?,
+                model: row.get(2)?,
+                api_key: row.get(3)?,
+                protocol: row.get(4)?,
+                request_count: row.get(5)?,
+                total_input_tokens: row.get(6)?,
+                total_cached_input_tokens: row.get(7)?,
+                total_output_tokens: row.get(8)?,
+                total_thinking_tokens: row.get(9)?,
+                total_tokens: row.get(10)?,
+                total_cost: row.get(11)?,
+            })
         })
         .map_err(|e| e.to_string())?;
 
-    for row in rows {
-        let (period, account, total) = row.map_err(|e| e.to_string())?;
-        trend_map.entry(period).or_default().insert(account, total);
-    }
-
-    Ok(trend_map
-        .into_iter()
-        .map(|(period, account_data)| AccountTrendPoint {
-            period,
-            account_data,
-        })
-        .collect())
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
 }
 
 #[cfg(test)]
@@ -563,4 +1037,150 @@ mod tests {
         // For now, just verify the module compiles
         assert!(true);
     }
+
+    #[test]
+    fn test_cost_columns_and_budget_filters() {
+        use crate::proxy::budget::SpendFilter;
+
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        let now = chrono::Utc::now();
+        let record = |account: &str, key: Option<&str>, cost: f64| UsageRecord {
+            account_email: account.to_string(),
+            model: "gemini-2.5-pro".to_string(),
+            api_key: key.map(|k| k.to_string()),
+            input_tokens: 100,
+            output_tokens: 50,
+            cost,
+            ..Default::default()
+        };
+        insert_usage(&conn, &record("a@example.com", Some("key-1"), 0.25), now).unwrap();
+        insert_usage(&conn, &record("a@example.com", Some("key-2"), 0.5), now).unwrap();
+        insert_usage(&conn, &record("b@example.com", None, 1.0), now).unwrap();
+        insert_usage(&conn, &record("b@example.com", Some("key-1"), 8.0), now - chrono::Duration::days(2)).unwrap();
+
+        let since = (now - chrono::Duration::hours(1)).timestamp();
+        let cost = |filter: SpendFilter| query_cost_since(&conn, since, &filter).unwrap();
+        assert!((cost(SpendFilter::All) - 1.75).abs() < 1e-9);
+        assert!((cost(SpendFilter::ApiKey("key-1".to_string())) - 0.25).abs() < 1e-9);
+        assert!((cost(SpendFilter::Accounts(vec!["b@example.com".to_string()])) - 1.0).abs() < 1e-9);
+        assert_eq!(cost(SpendFilter::Accounts(vec![])), 0.0);
+
+        let hourly_cost: f64 = conn
+            .query_row(
+                "SELECT total_cost FROM token_stats_hourly WHERE hour_bucket = ?1 AND account_email = 'a@example.com'",
+                [now.format("%Y-%m-%d %H:00").to_string()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!((hourly_cost - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cache_thinking_and_status_breakdown() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        let now = chrono::Utc::now();
+        let record = |account: &str, session: &str, cached: u32| UsageRecord {
+            account_email: account.to_string(),
+            model: "claude-sonnet-4-5".to_string(),
+            session_id: Some(session.to_string()),
+            input_tokens: 1000,
+            output_tokens: 100,
+            cached_input_tokens: cached,
+            thinking_tokens: 50,
+            ..Default::default()
+        };
+        insert_usage(&conn, &record("a@example.com", "s1", 0), now).unwrap();
+        insert_usage(&conn, &record("a@example.com", "s1", 800), now).unwrap();
+        insert_usage(&conn, &record("b@example.com", "s2", 500), now).unwrap();
+        insert_request_status(&conn, "a@example.com", 200, now).unwrap();
+        insert_request_status(&conn, "a@example.com", 200, now).unwrap();
+        insert_request_status(&conn, "a@example.com", 429, now).unwrap();
+
+        let cutoff_bucket = (now - chrono::Duration::hours(1)).format("%Y-%m-%d %H:00").to_string();
+        let accounts = query_account_stats(&conn, &cutoff_bucket).unwrap();
+        let a = accounts.iter().find(|s| s.account_email == "a@example.com").unwrap();
+        assert_eq!(a.total_cached_input_tokens, 800);
+        assert_eq!(a.total_thinking_tokens, 100);
+        assert_eq!(a.total_tokens, 2 * 1150);
+        assert!((a.cache_hit_rate - 0.4).abs() < 1e-9);
+        assert_eq!(a.request_count_by_status.get(&200), Some(&2));
+        assert_eq!(a.request_count_by_status.get(&429), Some(&1));
+
+        let cutoff = now.timestamp() - 3600;
+        let sessions = query_session_stats(&conn, cutoff, 10).unwrap();
+        let s1 = sessions.iter().find(|s| s.session_id == "s1").unwrap();
+        assert_eq!((s1.request_count, s1.total_cached_input_tokens), (2, 800));
+        assert_eq!(s1.account_email, "a@example.com");
+
+        let trend = model_trend(query_trend(&conn, "%Y-%m-%d", "model", cutoff).unwrap());
+        let rate = trend[0].cache_hit_rate["claude-sonnet-4-5"];
+        assert!((rate - 1300.0 / 3000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_query_usage_buckets_and_group_by() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        let day = chrono::NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+        let at = |d: i64, h: u32| (day + chrono::Duration::days(d)).and_hms_opt(h, 0, 0).unwrap().and_utc();
+        let record = |model: &str, protocol: &str, cost: f64| UsageRecord {
+            account_email: "a@example.com".to_string(),
+            model: model.to_string(),
+            protocol: Some(protocol.to_string()),
+            input_tokens: 100,
+            output_tokens: 10,
+            cost,
+            ..Default::default()
+        };
+        insert_usage(&conn, &record("gemini-2.5-pro", "gemini", 0.5), at(0, 1)).unwrap();
+        insert_usage(&conn, &record("gemini-2.5-pro", "openai", 0.5), at(0, 2)).unwrap();
+        insert_usage(&conn, &record("claude-sonnet-4-5", "anthropic", 1.0), at(1, 3)).unwrap();
+        insert_usage(&conn, &record("claude-sonnet-4-5", "anthropic", 9.0), at(3, 0)).unwrap();
+
+        let query = |bucket, group_by| UsageQuery {
+            start: at(0, 0).timestamp(),
+            end: at(2, 0).timestamp(),
+            bucket,
+            group_by,
+        };
+
+        let total = query_usage_rows(&conn, &query(UsageBucket::Total, vec![])).unwrap();
+        assert_eq!(total.len(), 1);
+        assert_eq!((total[0].request_count, total[0].total_tokens), (3, 330));
+        assert!((total[0].total_cost - 2.0).abs() < 1e-9);
+        assert!(total[0].period.is_none() && total[0].model.is_none());
+
+        let daily = query_usage_rows(&conn, &query(UsageBucket::Day, vec![UsageDimension::Model])).unwrap();
+        let labels: Vec<_> = daily
+            .iter()
+            .map(|r| (r.period.clone().unwrap(), r.model.clone().unwrap(), r.request_count))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("2025-03-10".to_string(), "gemini-2.5-pro".to_string(), 2),
+                ("2025-03-11".to_string(), "claude-sonnet-4-5".to_string(), 1),
+            ]
+        );
+
+        let by_protocol = query_usage_rows(
+            &conn,
+            &query(UsageBucket::Total, vec![UsageDimension::Protocol, UsageDimension::ApiKey]),
+        )
+        .unwrap();
+        assert_eq!(by_protocol.len(), 3);
+        assert_eq!(by_protocol[0].protocol.as_deref(), Some("anthropic"));
+        assert_eq!(by_protocol[0].api_key.as_deref(), Some(""));
+        assert!(by_protocol[0].account_email.is_none());
+
+        let mut invalid = query(UsageBucket::Day, vec![]);
+        invalid.end = invalid.start;
+        assert!(query_usage_rows(&conn, &invalid).is_err());
+        assert_eq!(
+            UsageDimension::parse_list("account, model").unwrap(),
+            vec![UsageDimension::Account, UsageDimension::Model]
+        );
+    }
 }
@@ -0,0 +1,266 @@
+//! Usage report export
+//! Renders `token_stats::query_usage` results as CSV / JSON, and writes a daily report
+//! file (previous UTC day) into the configured directory.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::time::{self, Duration};
+
+use crate::modules::token_stats::{self, UsageDimension, UsageQuery, UsageReportRow};
+use crate::modules::{account, config, logger};
+
+/// How often the daily report job checks whether yesterday's report is missing
+const DAILY_REPORT_CHECK_SECS: u64 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageReportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl UsageReportFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "csv" | "" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            other => Err(format!("invalid_format: {}", other)),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Json => "application/json",
+        }
+    }
+}
+
+/// JSON export envelope
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub generated_at: i64,
+    pub query: UsageQuery,
+    pub rows: Vec<UsageReportRow>,
+}
+
+/// Run the query and render it in the requested format
+pub fn export(query: &UsageQuery, format: UsageReportFormat) -> Result<String, String> {
+    let rows = token_stats::query_usage(query)?;
+    render(query, rows, format)
+}
+
+pub fn render(query: &UsageQuery, rows: Vec<UsageReportRow>, format: UsageReportFormat) -> Result<String, String> {
+    match format {
+        UsageReportFormat::Csv => Ok(to_csv(query, &rows)),
+        UsageReportFormat::Json => serde_json::to_string_pretty(&UsageReport {
+            generated_at: chrono::Utc::now().timestamp(),
+            query: query.clone(),
+            rows,
+        })
+        .map_err(|e| e.to_string()),
+    }
+}
+
+/// CSV columns: period (unless the bucket is `total`), the group-by dimensions in request order, then the metrics
+fn to_csv(query: &UsageQuery, rows: &[UsageReportRow]) -> String {
+    let with_period = query.bucket != token_stats::UsageBucket::Total;
+    let mut dimensions: Vec<UsageDimension> = Vec::new();
+    for dimension in &query.group_by {
+        if !dimensions.contains(dimension) {
+            dimensions.push(*dimension);
+        }
+    }
+
+    let mut header: Vec<&str> = Vec::new();
+    if with_period {
+        header.push("period");
+    }
+    header.extend(dimensions.iter().map(|d| d.name()));
+    header.extend([
+        "request_count",
+        "input_tokens",
+        "cached_input_tokens",
+        "output_tokens",
+        "thinking_tokens",
+        "total_tokens",
+        "cost_usd",
+    ]);
+
+    let mut out = header.join(",");
+    out.push('\n');
+    for row in rows {
+        let mut fields: Vec<String> = Vec::new();
+        if with_period {
+            fields.push(csv_field(row.period.as_deref().unwrap_or("")));
+        }
+        for dimension in &dimensions {
+            let value = match dimension {
+                UsageDimension::Account => row.account_email.as_deref(),
+                UsageDimension::Model => row.model.as_deref(),
+                UsageDimension::ApiKey => row.api_key.as_deref(),
+                UsageDimension::Protocol => row.protocol.as_deref(),
+            };
+            fields.push(csv_field(value.unwrap_or("")));
+        }
+        fields.extend([
+            row.request_count.to_string(),
+            row.total_input_tokens.to_string(),
+            row.total_cached_input_tokens.to_string(),
+            row.total_output_tokens.to_string(),
+            row.total_thinking_tokens.to_string(),
+            row.total_tokens.to_string(),
+            format!("{:.6}", row.total_cost),
+        ]);
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Parse a time parameter: unix seconds, RFC 3339, or a UTC date (YYYY-MM-DD)
+pub fn parse_time(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    if let Ok(ts) = value.parse::<i64>() {
+        return Ok(ts);
+    }
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(dt.timestamp());
+    }
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp())
+        .map_err(|_| format!("invalid_time: {}", value))
+}
+
+// ============================================================================
+// Daily report
+// ============================================================================
+
+fn report_dir(report_config: &crate::models::config::UsageReportConfig) -> Result<PathBuf, String> {
+    match report_config.directory.as_deref().map(str::trim) {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => Ok(account::get_data_dir()?.join("reports")),
+    }
+}
+
+/// Query covering one UTC day
+pub fn daily_query(report_config: &crate::models::config::UsageReportConfig, day: chrono::NaiveDate) -> UsageQuery {
+    let start = day.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    UsageQuery {
+        start,
+        end: start + 24 * 3600,
+        bucket: report_config.bucket,
+        group_by: report_config.group_by.clone(),
+    }
+}
+
+/// Write the report for `day`, unless it already exists. Returns the path when a file was written
+pub fn write_daily_report(
+    report_config: &crate::models::config::UsageReportConfig,
+    day: chrono::NaiveDate,
+) -> Result<Option<PathBuf>, String> {
+    let dir = report_dir(report_config)?;
+    let path = dir.join(format!(
+        "usage-{}.{}",
+        day.format("%Y-%m-%d"),
+        report_config.format.extension()
+    ));
+    if path.exists() {
+        return Ok(None);
+    }
+
+    let content = export(&daily_query(report_config, day), report_config.format)?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("failed_to_create_report_dir: {}", e))?;
+    std::fs::write(&path, content).map_err(|e| format!("failed_to_write_report: {}", e))?;
+    Ok(Some(path))
+}
+
+/// Background job: once the UTC day rolls over, write the previous day's report
+pub fn start_daily_report_job() {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(DAILY_REPORT_CHECK_SECS));
+        loop {
+            interval.tick().await;
+
+            let Ok(app_config) = config::load_app_config() else {
+                continue;
+            };
+            if !app_config.usage_report.enabled {
+                continue;
+            }
+
+            let yesterday = chrono::Utc::now().date_naive() - chrono::Duration::days(1);
+            let report_config = app_config.usage_report;
+            let result = tokio::task::spawn_blocking(move || write_daily_report(&report_config, yesterday)).await;
+            match result {
+                Ok(Ok(Some(path))) => {
+                    logger::log_info(&format!("[UsageReport] Daily report written: {}", path.display()));
+                }
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => logger::log_error(&format!("[UsageReport] Failed to write daily report: {}", e)),
+                Err(e) => logger::log_error(&format!("[UsageReport] Daily report task failed: {}", e)),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::token_stats::UsageBucket;
+
+    #[test]
+    fn test_csv_columns_follow_query() {
+        let query = UsageQuery {
+            start: 0,
+            end: 86400,
+            bucket: UsageBucket::Day,
+            group_by: vec![UsageDimension::Model, UsageDimension::Account],
+        };
+        let rows = vec![UsageReportRow {
+            period: Some("1970-01-01".to_string()),
+            account_email: Some("a@example.com".to_string()),
+            model: Some("model,\"quoted\"".to_string()),
+            request_count: 2,
+            total_input_tokens: 100,
+            total_output_tokens: 20,
+            total_tokens: 120,
+            total_cost: 0.25,
+            ..Default::default()
+        }];
+        let csv = to_csv(&query, &rows);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "period,model,account,request_count,input_tokens,cached_input_tokens,output_tokens,thinking_tokens,total_tokens,cost_usd"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            "1970-01-01,\"model,\"\"quoted\"\"\",a@example.com,2,100,0,20,0,120,0.250000"
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("86400").unwrap(), 86400);
+        assert_eq!(parse_time("1970-01-02").unwrap(), 86400);
+        assert_eq!(parse_time("1970-01-02T01:00:00+01:00").unwrap(), 86400);
+        assert!(parse_time("yesterday").is_err());
+    }
+}
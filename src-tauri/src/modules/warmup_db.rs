@@ -0,0 +1,346 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// One warmup run (a schedule firing, the 100% quota scan, or a manual trigger)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarmupRun {
+    pub id: i64,
+    /// Schedule name, or "quota_full" for the built-in 100% scan
+    pub schedule: String,
+    /// "schedule", "catch_up", "missed", "quota_full", "manual"
+    pub trigger: String,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub success_count: u32,
+    pub failed_count: u32,
+    pub skipped_count: u32,
+}
+
+/// Per-account / per-model result of a run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarmupRunResult {
+    pub run_id: i64,
+    pub account_email: String,
+    pub model: String,
+    /// "success", "failed", "skipped_cooldown", "skipped_quota"
+    pub status: String,
+    pub percentage: Option<i32>,
+    pub message: Option<String>,
+    pub timestamp: i64,
+}
+
+fn get_db_path() -> Result<PathBuf, String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    Ok(data_dir.join("warmup.db"))
+}
+
+fn connect_db() -> Result<Connection, String> {
+    let db_path = get_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    // Enable WAL mode for better concurrency
+    conn.pragma_update(None, "journal_mode", "WAL")
+        .map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "busy_timeout", 5000)
+        .map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "synchronous", "NORMAL")
+        .map_err(|e| e.to_string())?;
+
+    Ok(conn)
+}
+
+/// Initialize the warmup database and import the legacy `warmup_history.json`
+pub fn init_db() -> Result<(), String> {
+    let conn = connect_db()?;
+    create_schema(&conn)?;
+
+    let legacy_path = crate::modules::account::get_data_dir()?.join("warmup_history.json");
+    if legacy_path.exists() {
+        let history: std::collections::HashMap<String, i64> = std::fs::read_to_string(&legacy_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        for (key, timestamp) in &history {
+            conn.execute(
+                "INSERT OR IGNORE INTO warmup_history (history_key, last_warmup) VALUES (?1, ?2)",
+                params![key, timestamp],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        let _ = std::fs::rename(&legacy_path, legacy_path.with_extension("json.migrated"));
+    }
+    Ok(())
+}
+
+fn create_schema(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS warmup_history (
+            history_key TEXT PRIMARY KEY,
+            last_warmup INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS warmup_schedule_state (
+            schedule TEXT PRIMARY KEY,
+            last_run INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS warmup_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            schedule TEXT NOT NULL,
+            trigger TEXT NOT NULL,
+            started_at INTEGER NOT NULL,
+            finished_at INTEGER,
+            success_count INTEGER NOT NULL DEFAULT 0,
+            failed_count INTEGER NOT NULL DEFAULT 0,
+            skipped_count INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_warmup_runs_started ON warmup_runs (started_at DESC);
+        CREATE TABLE IF NOT EXISTS warmup_run_results (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            run_id INTEGER NOT NULL,
+            account_email TEXT NOT NULL,
+            model TEXT NOT NULL,
+            status TEXT NOT NULL,
+            percentage INTEGER,
+            message TEXT,
+            timestamp INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_warmup_results_run ON warmup_run_results (run_id);",
+    )
+    .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Cooldown history
+// ============================================================================
+
+pub fn get_last_warmup(history_key: &str) -> Result<Option<i64>, String> {
+    let conn = connect_db()?;
+    query_last_warmup(&conn, history_key)
+}
+
+fn query_last_warmup(conn: &Connection, history_key: &str) -> Result<Option<i64>, String> {
+    conn.query_row(
+        "SELECT last_warmup FROM warmup_history WHERE history_key = ?1",
+        [history_key],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+pub fn record_warmup(history_key: &str, timestamp: i64) -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute(
+        "INSERT INTO warmup_history (history_key, last_warmup) VALUES (?1, ?2)
+         ON CONFLICT(history_key) DO UPDATE SET last_warmup = ?2",
+        params![history_key, timestamp],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Returns true when an entry was removed
+pub fn clear_warmup(history_key: &str) -> Result<bool, String> {
+    let conn = connect_db()?;
+    conn.execute("DELETE FROM warmup_history WHERE history_key = ?1", [history_key])
+        .map(|n| n > 0)
+        .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Schedule state (for missed-run detection across restarts)
+// ============================================================================
+
+pub fn get_schedule_last_run(schedule: &str) -> Result<Option<i64>, String> {
+    let conn = connect_db()?;
+    conn.query_row(
+        "SELECT last_run FROM warmup_schedule_state WHERE schedule = ?1",
+        [schedule],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+pub fn set_schedule_last_run(schedule: &str, timestamp: i64) -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute(
+        "INSERT INTO warmup_schedule_state (schedule, last_run) VALUES (?1, ?2)
+         ON CONFLICT(schedule) DO UPDATE SET last_run = ?2",
+        params![schedule, timestamp],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Run log
+// ============================================================================
+
+pub fn start_run(schedule: &str, trigger: &str) -> Result<i64, String> {
+    let conn = connect_db()?;
+    insert_run(&conn, schedule, trigger, chrono::Utc::now().timestamp())
+}
+
+fn insert_run(conn: &Connection, schedule: &str, trigger: &str, started_at: i64) -> Result<i64, String> {
+    conn.execute(
+        "INSERT INTO warmup_runs (schedule, trigger, started_at) VALUES (?1, ?2, ?3)",
+        params![schedule, trigger, started_at],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn record_result(result: &WarmupRunResult) -> Result<(), String> {
+    let conn = connect_db()?;
+    insert_result(&conn, result)
+}
+
+fn insert_result(conn: &Connection, result: &WarmupRunResult) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO warmup_run_results (run_id, account_email, model, status, percentage, message, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            result.run_id,
+            result.account_email,
+            result.model,
+            result.status,
+            result.percentage,
+            result.message,
+            result.timestamp
+        ],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Close a run, deriving its counters from the recorded results
+pub fn finish_run(run_id: i64) -> Result<(), String> {
+    let conn = connect_db()?;
+    update_run_counts(&conn, run_id, chrono::Utc::now().timestamp())
+}
+
+fn update_run_counts(conn: &Connection, run_id: i64, finished_at: i64) -> Result<(), String> {
+    conn.execute(
+        "UPDATE warmup_runs SET
+            finished_at = ?2,
+            success_count = (SELECT COUNT(*) FROM warmup_run_results WHERE run_id = ?1 AND status = 'success'),
+            failed_count = (SELECT COUNT(*) FROM warmup_run_results WHERE run_id = ?1 AND status = 'failed'),
+            skipped_count = (SELECT COUNT(*) FROM warmup_run_results WHERE run_id = ?1 AND status LIKE 'skipped%')
+         WHERE id = ?1",
+        params![run_id, finished_at],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+pub fn get_runs(limit: usize) -> Result<Vec<WarmupRun>, String> {
+    let conn = connect_db()?;
+    query_runs(&conn, limit)
+}
+
+fn query_runs(conn: &Connection, limit: usize) -> Result<Vec<WarmupRun>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, schedule, trigger, started_at, finished_at, success_count, failed_count, skipped_count
+             FROM warmup_runs ORDER BY started_at DESC, id DESC LIMIT ?1",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([limit as i64], |row| {
+            Ok(WarmupRun {
+                id: row.get(0)?,
+                schedule: row.get(1)?,
+                trigger: row.get(2)?,
+                started_at: row.get(3)?,
+                finished_at: row.get(4)?,
+                success_count: row.get(5)?,
+                failed_count: row.get(6)?,
+                skipped_count: row.get(7)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
+pub fn get_run_results(run_id: i64) -> Result<Vec<WarmupRunResult>, String> {
+    let conn = connect_db()?;
+    query_run_results(&conn, run_id)
+}
+
+fn query_run_results(conn: &Connection, run_id: i64) -> Result<Vec<WarmupRunResult>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT run_id, account_email, model, status, percentage, message, timestamp
+             FROM warmup_run_results WHERE run_id = ?1 ORDER BY id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([run_id], |row| {
+            Ok(WarmupRunResult {
+                run_id: row.get(0)?,
+                account_email: row.get(1)?,
+                model: row.get(2)?,
+                status: row.get(3)?,
+                percentage: row.get(4)?,
+                message: row.get(5)?,
+                timestamp: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
+/// Drop cooldown entries older than `history_cutoff` and runs older than `runs_cutoff`
+pub fn prune(history_cutoff: i64, runs_cutoff: i64) -> Result<(), String> {
+    let conn = connect_db()?;
+    conn.execute("DELETE FROM warmup_history WHERE last_warmup < ?1", [history_cutoff])
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "DELETE FROM warmup_run_results WHERE run_id IN (SELECT id FROM warmup_runs WHERE started_at < ?1)",
+        [runs_cutoff],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM warmup_runs WHERE started_at < ?1", [runs_cutoff])
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_log_counts() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+
+        let run_id = insert_run(&conn, "morning", "catch_up", 100).unwrap();
+        for (email, status) in [("a@example.com", "success"), ("b@example.com", "failed"), ("c@example.com", "skipped_cooldown")] {
+            insert_result(
+                &conn,
+                &WarmupRunResult {
+                    run_id,
+                    account_email: email.to_string(),
+                    model: "gemini-3-flash".to_string(),
+                    status: status.to_string(),
+                    percentage: Some(100),
+                    message: None,
+                    timestamp: 101,
+                },
+            )
+            .unwrap();
+        }
+        update_run_counts(&conn, run_id, 120).unwrap();
+
+        let runs = query_runs(&conn, 10).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].trigger, "catch_up");
+        assert_eq!((runs[0].success_count, runs[0].failed_count, runs[0].skipped_count), (1, 1, 1));
+        assert_eq!(runs[0].finished_at, Some(120));
+
+        let results = query_run_results(&conn, run_id).unwrap();
+        assert_eq!(results[1].account_email, "b@example.com");
+        assert_eq!(query_last_warmup(&conn, "a@example.com:gemini-3-flash:100").unwrap(), None);
+    }
+}
@@ -0,0 +1,341 @@
+//! Warmup schedule definitions
+//! A schedule fires either on a 5-field cron expression or once per time window (local time),
+//! and warms a model list on a set of accounts (by email or by tag).
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+/// A cron occurrence counts as missed when the scheduler sees it later than this
+const CRON_GRACE_SECS: i64 = 300;
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WarmupTrigger {
+    /// "minute hour day-of-month month day-of-week", e.g. "0 8 * * 1-5"
+    Cron { expr: String },
+    /// Fires once per window, e.g. 08:00-09:00 on weekdays
+    Window {
+        start: String,
+        end: String,
+        /// 0 = Sunday .. 6 = Saturday, empty = every day
+        #[serde(default)]
+        days: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarmupSchedule {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub trigger: WarmupTrigger,
+    /// Account emails; together with `tags` empty = all accounts
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    /// Tags defined in `ScheduledWarmupConfig::account_tags`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Models to warm, empty = `ScheduledWarmupConfig::monitored_models`
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Only warm models whose quota is at 100%
+    #[serde(default = "default_true")]
+    pub require_full_quota: bool,
+    /// Overrides the global cooldown
+    #[serde(default)]
+    pub cooldown_minutes: Option<u64>,
+    /// Run once after sleep / restart when an occurrence was missed
+    #[serde(default = "default_true")]
+    pub catch_up: bool,
+}
+
+/// The occurrence a schedule should act on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DueOccurrence {
+    pub at: NaiveDateTime,
+    /// Seen after its deadline (cron grace / window end)
+    pub missed: bool,
+}
+
+impl WarmupSchedule {
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.trigger {
+            WarmupTrigger::Cron { expr } => CronExpr::parse(expr).map(|_| ()),
+            WarmupTrigger::Window { start, end, days } => {
+                parse_time_of_day(start)?;
+                parse_time_of_day(end)?;
+                match days.iter().find(|d| **d > 6) {
+                    Some(d) => Err(format!("invalid_window_day: {}", d)),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Latest occurrence in `(last_run, now]`, if any
+    pub fn due(&self, last_run: NaiveDateTime, now: NaiveDateTime) -> Result<Option<DueOccurrence>, String> {
+        match &self.trigger {
+            WarmupTrigger::Cron { expr } => {
+                let cron = CronExpr::parse(expr)?;
+                let Some(mut latest) = cron.next_after(last_run).filter(|t| *t <= now) else {
+                    return Ok(None);
+                };
+                while let Some(next) = cron.next_after(latest).filter(|t| *t <= now) {
+                    latest = next;
+                }
+                Ok(Some(DueOccurrence {
+                    at: latest,
+                    missed: now - latest > Duration::seconds(CRON_GRACE_SECS),
+                }))
+            }
+            WarmupTrigger::Window { start, end, days } => {
+                let start = parse_time_of_day(start)?;
+                let end = parse_time_of_day(end)?;
+                // Latest window start <= now (a week back covers any day filter)
+                for back in 0..8 {
+                    let date = now.date() - Duration::days(back);
+                    if !days.is_empty() && !days.contains(&(date.weekday().num_days_from_sunday() as u8)) {
+                        continue;
+                    }
+                    let window_start = date.and_time(start);
+                    if window_start > now {
+                        continue;
+                    }
+                    if window_start <= last_run {
+                        return Ok(None);
+                    }
+                    let mut window_end = date.and_time(end);
+                    if window_end <= window_start {
+                        window_end += Duration::days(1);
+                    }
+                    return Ok(Some(DueOccurrence {
+                        at: window_start,
+                        missed: now >= window_end,
+                    }));
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn targets(&self, email: &str, account_tags: &std::collections::HashMap<String, Vec<String>>) -> bool {
+        if self.accounts.is_empty() && self.tags.is_empty() {
+            return true;
+        }
+        self.accounts.iter().any(|a| a.eq_ignore_ascii_case(email))
+            || self.tags.iter().any(|tag| {
+                account_tags
+                    .get(tag)
+                    .is_some_and(|emails| emails.iter().any(|e| e.eq_ignore_ascii_case(email)))
+            })
+    }
+}
+
+fn parse_time_of_day(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M").map_err(|_| format!("invalid_time_of_day: {}", value))
+}
+
+/// Standard 5-field cron expression (no seconds), plus @hourly / @daily / @weekly / @monthly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+impl CronExpr {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expr = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("invalid_cron: expected 5 fields, got {}", fields.len()));
+        }
+
+        let mut days_of_week = parse_cron_field(fields[4], 0, 7)?;
+        // 7 is an alias for Sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            minutes: parse_cron_field(fields[0], 0, 59)?,
+            hours: parse_cron_field(fields[1], 0, 23)?,
+            days_of_month: parse_cron_field(fields[2], 1, 31)?,
+            months: parse_cron_field(fields[3], 1, 12)?,
+            days_of_week,
+            dom_restricted: fields[2] != "*",
+            dow_restricted: fields[4] != "*",
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let dom = self.days_of_month & (1 << date.day()) != 0;
+        let dow = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+        // Like cron: when both day fields are restricted, either one matching is enough
+        match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom || dow,
+            (true, false) => dom,
+            (false, true) => dow,
+            (false, false) => true,
+        }
+    }
+
+    /// First fire time strictly after `after`
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        // Eight years is enough for any satisfiable expression (e.g. Feb 29)
+        for offset in 0..(366 * 8) {
+            let date = start.date() + Duration::days(offset);
+            if !self.matches_day(date) {
+                continue;
+            }
+            for hour in (0..24u32).filter(|h| self.hours & (1 << h) != 0) {
+                for minute in (0..60u32).filter(|m| self.minutes & (1 << m) != 0) {
+                    let candidate = date.and_hms_opt(hour, minute, 0)?;
+                    if candidate >= start {
+                        return Some(candidate);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Bit mask of the values a cron field allows: "*", "5", "1-5", "*/15", "0-30/10", "1,3,5"
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let invalid = || format!("invalid_cron_field: {}", field);
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+        let (low, high) = if range == "*" {
+            (min, max)
+        } else if let Some((low, high)) = range.split_once('-') {
+            (low.parse().map_err(|_| invalid())?, high.parse().map_err(|_| invalid())?)
+        } else {
+            let value: u32 = range.parse().map_err(|_| invalid())?;
+            // "5/15" means from 5 to the end of the range
+            (value, if part.contains('/') { max } else { value })
+        };
+        if low < min || high > max || low > high {
+            return Err(invalid());
+        }
+        for value in (low..=high).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn schedule(trigger: WarmupTrigger) -> WarmupSchedule {
+        WarmupSchedule {
+            name: "test".to_string(),
+            enabled: true,
+            trigger,
+            accounts: vec![],
+            tags: vec![],
+            models: vec![],
+            require_full_quota: true,
+            cooldown_minutes: None,
+            catch_up: true,
+        }
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        // 2025-03-14 is a Friday
+        let weekdays = CronExpr::parse("30 8 * * 1-5").unwrap();
+        assert_eq!(weekdays.next_after(at("2025-03-14", "08:00")), Some(at("2025-03-14", "08:30")));
+        assert_eq!(weekdays.next_after(at("2025-03-14", "08:30")), Some(at("2025-03-17", "08:30")));
+
+        let every_quarter = CronExpr::parse("*/15 * * * *").unwrap();
+        assert_eq!(every_quarter.next_after(at("2025-03-14", "23:50")), Some(at("2025-03-15", "00:00")));
+
+        let sunday = CronExpr::parse("0 0 * * 7").unwrap();
+        assert_eq!(sunday.next_after(at("2025-03-14", "12:00")), Some(at("2025-03-16", "00:00")));
+
+        // Both day fields restricted: 1st of the month OR Monday
+        let either = CronExpr::parse("0 6 1 * 1").unwrap();
+        assert_eq!(either.next_after(at("2025-03-14", "12:00")), Some(at("2025-03-17", "06:00")));
+
+        assert!(CronExpr::parse("61 * * * *").is_err());
+        assert!(CronExpr::parse("0 8 * *").is_err());
+        assert!(CronExpr::parse("*/0 * * * *").is_err());
+    }
+
+    #[test]
+    fn test_cron_due_and_missed() {
+        let s = schedule(WarmupTrigger::Cron { expr: "0 */5 * * *".to_string() });
+        let last = at("2025-03-14", "05:00");
+        assert_eq!(s.due(last, at("2025-03-14", "09:59")).unwrap(), None);
+
+        let on_time = s.due(last, at("2025-03-14", "10:01")).unwrap().unwrap();
+        assert_eq!(on_time, DueOccurrence { at: at("2025-03-14", "10:00"), missed: false });
+
+        // Asleep overnight: collapse to the latest occurrence, flagged as missed
+        let after_sleep = s.due(last, at("2025-03-15", "07:30")).unwrap().unwrap();
+        assert_eq!(after_sleep, DueOccurrence { at: at("2025-03-15", "05:00"), missed: true });
+    }
+
+    #[test]
+    fn test_window_due_once_per_window() {
+        let s = schedule(WarmupTrigger::Window {
+            start: "22:00".to_string(),
+            end: "02:00".to_string(),
+            days: vec![5], // Friday
+        });
+        let friday = "2025-03-14";
+        let due = s.due(at("2025-03-14", "12:00"), at("2025-03-14", "23:00")).unwrap().unwrap();
+        assert_eq!(due, DueOccurrence { at: at(friday, "22:00"), missed: false });
+        // Already ran in this window
+        assert_eq!(s.due(at(friday, "23:00"), at("2025-03-15", "01:00")).unwrap(), None);
+        // Overnight window still open on Saturday 01:00, closed at 03:00
+        let open = s.due(at(friday, "12:00"), at("2025-03-15", "01:00")).unwrap().unwrap();
+        assert!(!open.missed);
+        let missed = s.due(at(friday, "12:00"), at("2025-03-15", "03:00")).unwrap().unwrap();
+        assert!(missed.missed);
+    }
+
+    #[test]
+    fn test_targets_accounts_and_tags() {
+        let mut s = schedule(WarmupTrigger::Cron { expr: "@daily".to_string() });
+        let tags = std::collections::HashMap::from([("pro".to_string(), vec!["b@example.com".to_string()])]);
+        assert!(s.targets("anyone@example.com", &tags));
+
+        s.accounts = vec!["A@example.com".to_string()];
+        s.tags = vec!["pro".to_string()];
+        assert!(s.targets("a@example.com", &tags));
+        assert!(s.targets("b@example.com", &tags));
+        assert!(!s.targets("c@example.com", &tags));
+    }
+}
@@ -0,0 +1,740 @@
+// 大音频分片: 按容器格式 (WAV / MP3 / FLAC) 在帧边界切分为带重叠的时间片段，
+// 各分片独立转录后按时间偏移拼接，并去除重叠区域的重复内容
+
+use std::ops::Range;
+
+use super::format::{Transcript, TranscriptSegment, TranscriptWord};
+
+/// 单个分片的目标时长 (秒)
+pub const CHUNK_SECONDS: f64 = 600.0;
+/// 相邻分片的重叠时长 (秒)
+pub const OVERLAP_SECONDS: f64 = 5.0;
+
+/// 切分后的音频分片 (可独立解码的完整文件)
+#[derive(Debug, Clone)]
+pub struct AudioChunk {
+    pub index: usize,
+    /// 分片在原音频中的起始时间 (秒)
+    pub offset: f64,
+    pub duration: f64,
+    pub data: Vec<u8>,
+}
+
+/// 单个分片的转录结果 (时间戳相对于分片起点)
+#[derive(Debug, Clone)]
+pub struct ChunkTranscript {
+    pub offset: f64,
+    pub duration: f64,
+    pub transcript: Transcript,
+}
+
+/// 是否支持按容器切分
+pub fn supports_chunking(mime_type: &str) -> bool {
+    matches!(mime_type, "audio/wav" | "audio/mp3" | "audio/flac")
+}
+
+/// 将音频切分为不超过 `max_chunk_bytes` 且时长约为 `chunk_seconds` 的重叠分片
+pub fn split_audio(
+    data: &[u8],
+    mime_type: &str,
+    chunk_seconds: f64,
+    overlap_seconds: f64,
+    max_chunk_bytes: usize,
+) -> Result<Vec<AudioChunk>, String> {
+    let parsed = match mime_type {
+        "audio/wav" => parse_wav(data)?,
+        "audio/mp3" => parse_mp3(data)?,
+        "audio/flac" => parse_flac(data)?,
+        other => return Err(format!("不支持分片的音频格式: {}", other)),
+    };
+
+    let ranges = plan_chunks(
+        &parsed.frames,
+        parsed.header_len(),
+        chunk_seconds,
+        overlap_seconds,
+        max_chunk_bytes,
+    )?;
+
+    Ok(ranges
+        .into_iter()
+        .enumerate()
+        .map(|(index, range)| {
+            let frames = &parsed.frames[range];
+            let first = &frames[0];
+            let last = &frames[frames.len() - 1];
+            AudioChunk {
+                index,
+                offset: first.time,
+                duration: last.time + last.duration - first.time,
+                data: parsed.assemble(data, frames),
+            }
+        })
+        .collect())
+}
+
+/// 合并分片转录结果: 时间戳加上分片偏移，重叠区域以中点为界各取一半，
+/// 并丢弃与上一条内容重复或大部分已被覆盖的分段
+pub fn merge_transcripts(parts: Vec<ChunkTranscript>) -> Transcript {
+    let cuts: Vec<f64> = (1..parts.len())
+        .map(|k| {
+            let prev_end = parts[k - 1].offset + parts[k - 1].duration;
+            (parts[k].offset + prev_end) / 2.0
+        })
+        .collect();
+
+    let mut language = None;
+    let mut segments: Vec<TranscriptSegment> = Vec::new();
+    let mut words: Vec<TranscriptWord> = Vec::new();
+
+    for (k, part) in parts.into_iter().enumerate() {
+        let lower = if k == 0 { f64::NEG_INFINITY } else { cuts[k - 1] };
+        let upper = cuts.get(k).copied().unwrap_or(f64::INFINITY);
+        if language.is_none() {
+            language = part.transcript.language.clone();
+        }
+
+        for segment in part.transcript.segments {
+            let start = segment.start + part.offset;
+            let end = segment.end + part.offset;
+            if start < lower || start >= upper {
+                continue;
+            }
+            if let Some(last) = segments.last() {
+                let duplicate = normalize(&last.text) == normalize(&segment.text)
+                    && start <= last.end + 1.0;
+                if duplicate || (start + end) / 2.0 < last.end {
+                    continue;
+                }
+            }
+            segments.push(TranscriptSegment {
+                start,
+                end,
+                text: segment.text,
+            });
+        }
+
+        for word in part.transcript.words {
+            let start = word.start + part.offset;
+            let end = word.end + part.offset;
+            if start < lower || start >= upper {
+                continue;
+            }
+            if words.last().is_some_and(|last| (start + end) / 2.0 < last.end) {
+                continue;
+            }
+            words.push(TranscriptWord {
+                word: word.word,
+                start,
+                end,
+            });
+        }
+    }
+
+    let text = segments
+        .iter()
+        .map(|s| s.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Transcript {
+        language,
+        text,
+        segments,
+        words,
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// ===== 分片规划 =====
+
+/// 可独立切分的最小单元 (MP3/FLAC 帧，或 WAV 的 0.1 秒采样块)
+#[derive(Debug, Clone)]
+struct Frame {
+    offset: usize,
+    len: usize,
+    time: f64,
+    duration: f64,
+}
+
+enum Container {
+    /// 原始 fmt 块 (含块头)
+    Wav { fmt_chunk: Vec<u8> },
+    Mp3,
+    /// fLaC 标识 + 仅保留 STREAMINFO 的元数据
+    Flac { header: Vec<u8> },
+}
+
+struct ParsedAudio {
+    container: Container,
+    frames: Vec<Frame>,
+}
+
+impl ParsedAudio {
+    fn header_len(&self) -> usize {
+        match &self.container {
+            Container::Wav { fmt_chunk } => 12 + fmt_chunk.len() + 8,
+            Container::Mp3 => 0,
+            Container::Flac { header } => header.len(),
+        }
+    }
+
+    /// 将连续帧重新封装为完整文件
+    fn assemble(&self, data: &[u8], frames: &[Frame]) -> Vec<u8> {
+        let last = &frames[frames.len() - 1];
+        let body = &data[frames[0].offset..last.offset + last.len];
+        let mut out = Vec::with_capacity(self.header_len() + body.len() + 1);
+
+        match &self.container {
+            Container::Wav { fmt_chunk } => {
+                let padding = body.len() % 2;
+                let riff_size = 4 + fmt_chunk.len() + 8 + body.len() + padding;
+                out.extend_from_slice(b"RIFF");
+                out.extend_from_slice(&(riff_size as u32).to_le_bytes());
+                out.extend_from_slice(b"WAVE");
+                out.extend_from_slice(fmt_chunk);
+                out.extend_from_slice(b"data");
+                out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+                out.extend_from_slice(body);
+                if padding == 1 {
+                    out.push(0);
+                }
+            }
+            Container::Mp3 => out.extend_from_slice(body),
+            Container::Flac { header } => {
+                out.extend_from_slice(header);
+                out.extend_from_slice(body);
+            }
+        }
+        out
+    }
+}
+
+/// 贪心规划: 每片累积帧直到达到目标时长或字节上限，下一片从结束前 `overlap` 秒处开始
+fn plan_chunks(
+    frames: &[Frame],
+    header_len: usize,
+    chunk_seconds: f64,
+    overlap_seconds: f64,
+    max_chunk_bytes: usize,
+) -> Result<Vec<Range<usize>>, String> {
+    if frames.is_empty() {
+        return Err("音频中没有可识别的数据帧".to_string());
+    }
+
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    loop {
+        let begin_offset = frames[start].offset;
+        let begin_time = frames[start].time;
+        let mut end = start;
+        while end < frames.len() {
+            let frame = &frames[end];
+            let bytes = header_len + frame.offset + frame.len - begin_offset;
+            let elapsed = frame.time + frame.duration - begin_time;
+            if bytes > max_chunk_bytes || (end > start && elapsed > chunk_seconds + 1e-6) {
+                if end == start {
+                    return Err(format!("单个音频帧超过分片大小上限 ({} bytes)", frame.len));
+                }
+                break;
+            }
+            end += 1;
+        }
+
+        ranges.push(start..end);
+        if end >= frames.len() {
+            break;
+        }
+
+        // 重叠不超过半个分片，保证向前推进
+        let cut = frames[end].time - overlap_seconds;
+        let min_next = start + ((end - start) / 2).max(1);
+        start = (min_next..end)
+            .find(|&i| frames[i].time >= cut)
+            .unwrap_or(end);
+    }
+    Ok(ranges)
+}
+
+// ===== WAV =====
+
+fn parse_wav(data: &[u8]) -> Result<ParsedAudio, String> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err("不是有效的 WAV 文件".to_string());
+    }
+
+    let mut pos = 12;
+    let mut fmt_chunk: Option<Vec<u8>> = None;
+    let mut data_range: Option<Range<usize>> = None;
+    while pos + 8 <= data.len() {
+        let size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        let body_start = pos + 8;
+        // 流式录制的 WAV 可能声明错误的长度，以实际文件长度为准
+        let body_end = body_start.saturating_add(size).min(data.len());
+        match &data[pos..pos + 4] {
+            b"fmt " => {
+                let mut chunk = data[pos..body_end].to_vec();
+                if chunk.len() % 2 == 1 {
+                    chunk.push(0);
+                }
+                fmt_chunk = Some(chunk);
+            }
+            b"data" => {
+                data_range = Some(body_start..body_end);
+                break;
+            }
+            _ => {}
+        }
+        pos = body_end + (size & 1);
+    }
+
+    let fmt_chunk = fmt_chunk.ok_or("WAV 文件缺少 fmt 块")?;
+    let data_range = data_range.ok_or("WAV 文件缺少 data 块")?;
+    if fmt_chunk.len() < 8 + 16 {
+        return Err("WAV fmt 块长度无效".to_string());
+    }
+    let fmt = &fmt_chunk[8..];
+    let byte_rate = u32::from_le_bytes([fmt[8], fmt[9], fmt[10], fmt[11]]) as usize;
+    let block_align = u16::from_le_bytes([fmt[12], fmt[13]]) as usize;
+    if byte_rate == 0 || block_align == 0 {
+        return Err("WAV 文件的码率或块对齐无效".to_string());
+    }
+
+    // 以约 0.1 秒为单位切分，保持块对齐
+    let unit = (byte_rate / 10 / block_align).max(1) * block_align;
+    let mut frames = Vec::new();
+    let mut offset = data_range.start;
+    while offset < data_range.end {
+        let len = (unit.min(data_range.end - offset) / block_align) * block_align;
+        if len == 0 {
+            break;
+        }
+        frames.push(Frame {
+            offset,
+            len,
+            time: (offset - data_range.start) as f64 / byte_rate as f64,
+            duration: len as f64 / byte_rate as f64,
+        });
+        offset += len;
+    }
+
+    Ok(ParsedAudio {
+        container: Container::Wav { fmt_chunk },
+        frames,
+    })
+}
+
+// ===== MP3 =====
+
+const MP3_BITRATES_V1_L1: [u32; 14] = [32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448];
+const MP3_BITRATES_V1_L2: [u32; 14] = [32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384];
+const MP3_BITRATES_V1_L3: [u32; 14] = [32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+const MP3_BITRATES_V2_L1: [u32; 14] = [32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256];
+const MP3_BITRATES_V2_L23: [u32; 14] = [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+struct Mp3FrameHeader {
+    len: usize,
+    samples: u32,
+    sample_rate: u32,
+}
+
+fn parse_mp3_header(b: &[u8]) -> Option<Mp3FrameHeader> {
+    if b.len() < 4 || b[0] != 0xFF || b[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    // version: 0 = MPEG 2.5, 1 = 保留, 2 = MPEG 2, 3 = MPEG 1
+    let version = (b[1] >> 3) & 0x03;
+    // layer: 1 = III, 2 = II, 3 = I
+    let layer = (b[1] >> 1) & 0x03;
+    let bitrate_index = (b[2] >> 4) as usize;
+    let sample_rate_index = ((b[2] >> 2) & 0x03) as usize;
+    let padding = ((b[2] >> 1) & 0x01) as u32;
+    if version == 1 || layer == 0 || bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+        return None;
+    }
+
+    let v1 = version == 3;
+    let table = match (v1, layer) {
+        (true, 3) => &MP3_BITRATES_V1_L1,
+        (true, 2) => &MP3_BITRATES_V1_L2,
+        (true, _) => &MP3_BITRATES_V1_L3,
+        (false, 3) => &MP3_BITRATES_V2_L1,
+        (false, _) => &MP3_BITRATES_V2_L23,
+    };
+    let bitrate = table[bitrate_index - 1] * 1000;
+    let sample_rate = match version {
+        3 => [44100, 48000, 32000][sample_rate_index],
+        2 => [22050, 24000, 16000][sample_rate_index],
+        _ => [11025, 12000, 8000][sample_rate_index],
+    };
+
+    let (len, samples) = match layer {
+        3 => ((12 * bitrate / sample_rate + padding) * 4, 384),
+        2 => (144 * bitrate / sample_rate + padding, 1152),
+        _ if v1 => (144 * bitrate / sample_rate + padding, 1152),
+        _ => (72 * bitrate / sample_rate + padding, 576),
+    };
+    Some(Mp3FrameHeader {
+        len: len as usize,
+        samples,
+        sample_rate,
+    })
+}
+
+fn parse_mp3(data: &[u8]) -> Result<ParsedAudio, String> {
+    let mut pos = 0;
+
+    // 跳过 ID3v2 标签
+    if data.len() >= 10 && &data[0..3] == b"ID3" {
+        let size = data[6..10]
+            .iter()
+            .fold(0usize, |acc, b| (acc << 7) | (*b & 0x7F) as usize);
+        let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+        pos = 10 + size + footer;
+    }
+
+    let mut frames = Vec::new();
+    let mut time = 0.0;
+    while pos + 4 <= data.len() {
+        let header = match parse_mp3_header(&data[pos..]) {
+            Some(h) if h.len > 4 && pos + h.len <= data.len() => h,
+            _ => {
+                pos += 1;
+                continue;
+            }
+        };
+
+        // 要求下一帧也能同步 (或到达文件末尾 / ID3v1 标签)，避免把数据误判为帧头
+        let next = pos + header.len;
+        let confirmed = next + 4 > data.len()
+            || parse_mp3_header(&data[next..]).is_some()
+            || &data[next..next + 3] == b"TAG";
+        if !confirmed {
+            pos += 1;
+            continue;
+        }
+
+        // 首帧可能是 Xing/Info/VBRI 信息帧，记录的是整个文件的帧数，分片后会误导解码器
+        let is_info_frame = frames.is_empty()
+            && data[pos..next]
+                .windows(4)
+                .take(48)
+                .any(|w| w == b"Xing" || w == b"Info" || w == b"VBRI");
+        if !is_info_frame {
+            let duration = header.samples as f64 / header.sample_rate as f64;
+            frames.push(Frame {
+                offset: pos,
+                len: header.len,
+                time,
+                duration,
+            });
+            time += duration;
+        }
+        pos = next;
+    }
+
+    if frames.is_empty() {
+        return Err("不是有效的 MP3 文件".to_string());
+    }
+    Ok(ParsedAudio {
+        container: Container::Mp3,
+        frames,
+    })
+}
+
+// ===== FLAC =====
+
+struct FlacFrameHeader {
+    variable: bool,
+    /// 固定块大小时为帧序号，可变块大小时为首个采样序号
+    number: u64,
+    block_size: u64,
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |mut crc, b| {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// 解析 FLAC 帧头中的 UTF-8 编码数字，返回 (数值, 结束位置)
+fn read_utf8_number(b: &[u8], pos: usize) -> Option<(u64, usize)> {
+    let first = *b.get(pos)?;
+    let extra = match first.leading_ones() {
+        0 => return Some((first as u64, pos + 1)),
+        n @ 2..=7 => n as usize - 1,
+        _ => return None,
+    };
+    let mut value = (first & (0xFF >> (extra + 2))) as u64;
+    for i in 1..=extra {
+        let byte = *b.get(pos + i)?;
+        if byte & 0xC0 != 0x80 {
+            return None;
+        }
+        value = (value << 6) | (byte & 0x3F) as u64;
+    }
+    Some((value, pos + extra + 1))
+}
+
+fn parse_flac_header(b: &[u8]) -> Option<FlacFrameHeader> {
+    if b.len() < 6 || b[0] != 0xFF || b[1] & 0xFE != 0xF8 {
+        return None;
+    }
+    let variable = b[1] & 0x01 == 1;
+    let block_code = b[2] >> 4;
+    let rate_code = b[2] & 0x0F;
+    let channels = b[3] >> 4;
+    let sample_size = (b[3] >> 1) & 0x07;
+    if block_code == 0 || rate_code == 15 || channels > 10 || sample_size == 3 || b[3] & 0x01 != 0 {
+        return None;
+    }
+
+    let (number, mut i) = read_utf8_number(b, 4)?;
+    let block_size = match block_code {
+        1 => 192,
+        2..=5 => 576u64 << (block_code - 2),
+        6 => {
+            i += 1;
+            *b.get(i - 1)? as u64 + 1
+        }
+        7 => {
+            i += 2;
+            u16::from_be_bytes([*b.get(i - 2)?, *b.get(i - 1)?]) as u64 + 1
+        }
+        _ => 256u64 << (block_code - 8),
+    };
+    match rate_code {
+        12 => i += 1,
+        13 | 14 => i += 2,
+        _ => {}
+    }
+
+    if crc8(b.get(..i)?) != *b.get(i)? {
+        return None;
+    }
+    Some(FlacFrameHeader {
+        variable,
+        number,
+        block_size,
+    })
+}
+
+fn parse_flac(data: &[u8]) -> Result<ParsedAudio, String> {
+    if !data.starts_with(b"fLaC") {
+        return Err("不是有效的 FLAC 文件".to_string());
+    }
+
+    // 读取元数据块，只保留 STREAMINFO
+    let mut pos = 4;
+    let mut stream_info: Option<Vec<u8>> = None;
+    loop {
+        if pos + 4 > data.len() {
+            return Err("FLAC 元数据不完整".to_string());
+        }
+        let last = data[pos] & 0x80 != 0;
+        let kind = data[pos] & 0x7F;
+        let size = u32::from_be_bytes([0, data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let body_end = pos + 4 + size;
+        if body_end > data.len() {
+            return Err("FLAC 元数据不完整".to_string());
+        }
+        if kind == 0 {
+            stream_info = Some(data[pos + 4..body_end].to_vec());
+        }
+        pos = body_end;
+        if last {
+            break;
+        }
+    }
+
+    let mut stream_info = stream_info.ok_or("FLAC 文件缺少 STREAMINFO")?;
+    if stream_info.len() < 34 {
+        return Err("FLAC STREAMINFO 长度无效".to_string());
+    }
+    let sample_rate = ((stream_info[10] as u32) << 12)
+        | ((stream_info[11] as u32) << 4)
+        | ((stream_info[12] as u32) >> 4);
+    if sample_rate == 0 {
+        return Err("FLAC 采样率无效".to_string());
+    }
+
+    // 分片后总采样数与 MD5 不再成立，置 0 表示未知
+    stream_info[13] &= 0xF0;
+    stream_info[14..34].fill(0);
+    let mut header = b"fLaC".to_vec();
+    header.push(0x80);
+    header.extend_from_slice(&(stream_info.len() as u32).to_be_bytes()[1..]);
+    header.extend_from_slice(&stream_info);
+
+    // 帧同步: 同步码 + CRC-8 + 帧序号连续，三重校验避免误判
+    let first = parse_flac_header(&data[pos..]).ok_or("FLAC 帧头无效")?;
+    let variable = first.variable;
+    let mut starts = vec![(pos, first.block_size)];
+    let mut expected = if variable { first.number + first.block_size } else { first.number + 1 };
+    let mut p = pos + 2;
+    while p + 2 <= data.len() {
+        if data[p] == 0xFF && data[p + 1] & 0xFE == 0xF8 {
+            if let Some(h) = parse_flac_header(&data[p..]) {
+                if h.variable == variable && h.number == expected {
+                    expected = if variable { h.number + h.block_size } else { h.number + 1 };
+                    starts.push((p, h.block_size));
+                    p += 2;
+                    continue;
+                }
+            }
+        }
+        p += 1;
+    }
+
+    let mut frames = Vec::with_capacity(starts.len());
+    let mut samples = 0u64;
+    for (i, &(offset, block_size)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map(|s| s.0).unwrap_or(data.len());
+        frames.push(Frame {
+            offset,
+            len: end - offset,
+            time: samples as f64 / sample_rate as f64,
+            duration: block_size as f64 / sample_rate as f64,
+        });
+        samples += block_size;
+    }
+
+    Ok(ParsedAudio {
+        container: Container::Flac { header },
+        frames,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(seconds: usize, byte_rate: u32) -> Vec<u8> {
+        let body = vec![0x11u8; seconds * byte_rate as usize];
+        let mut out = Vec::new();
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&((4 + 24 + 8 + body.len()) as u32).to_le_bytes());
+        out.extend_from_slice(b"WAVE");
+        out.extend_from_slice(b"fmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        out.extend_from_slice(&1u16.to_le_bytes()); // 单声道
+        out.extend_from_slice(&(byte_rate / 2).to_le_bytes());
+        out.extend_from_slice(&byte_rate.to_le_bytes());
+        out.extend_from_slice(&2u16.to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(&body);
+        out
+    }
+
+    #[test]
+    fn test_split_wav_with_overlap() {
+        let data = wav(25, 32000);
+        let chunks = split_audio(&data, "audio/wav", 10.0, 2.0, usize::MAX).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].offset, 0.0);
+        assert!((chunks[0].duration - 10.0).abs() < 1e-9);
+        assert!((chunks[1].offset - 8.0).abs() < 1e-9);
+        assert!((chunks[2].offset - 16.0).abs() < 1e-9);
+        let last = &chunks[2];
+        assert!((last.offset + last.duration - 25.0).abs() < 1e-9);
+
+        // 每个分片都是完整的 WAV
+        for chunk in &chunks {
+            let parsed = parse_wav(&chunk.data).unwrap();
+            let total: f64 = parsed.frames.iter().map(|f| f.duration).sum();
+            assert!((total - chunk.duration).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_split_respects_byte_limit() {
+        let data = wav(20, 32000);
+        let chunks = split_audio(&data, "audio/wav", 600.0, 1.0, 5 * 32000 + 44).unwrap();
+        assert!(chunks.len() >= 4);
+        assert!(chunks.iter().all(|c| c.data.len() <= 5 * 32000 + 44));
+    }
+
+    #[test]
+    fn test_split_mp3_frames() {
+        // MPEG1 Layer III 128kbps 44.1kHz: 417 字节/帧, 1152 采样
+        let mut frame = vec![0u8; 417];
+        frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+        let mut data = b"ID3\x03\x00\x00\x00\x00\x00\x04abcd".to_vec();
+        for _ in 0..400 {
+            data.extend_from_slice(&frame);
+        }
+
+        let chunks = split_audio(&data, "audio/mp3", 5.0, 1.0, usize::MAX).unwrap();
+        let frame_secs = 1152.0 / 44100.0;
+        assert!(chunks.len() >= 2);
+        assert!(chunks[0].data.starts_with(&[0xFF, 0xFB]));
+        assert_eq!(chunks[0].data.len() % 417, 0);
+        assert!(chunks[0].duration <= 5.0 + frame_secs);
+        let last = chunks.last().unwrap();
+        assert!((last.offset + last.duration - 400.0 * frame_secs).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_flac_frame_header() {
+        // 固定块大小 4096, 44.1kHz, 单声道 16bit, 帧序号 5
+        let mut header = vec![0xFF, 0xF8, 0xC9, 0x08, 0x05];
+        header.push(crc8(&header));
+        let parsed = parse_flac_header(&header).unwrap();
+        assert!(!parsed.variable);
+        assert_eq!(parsed.number, 5);
+        assert_eq!(parsed.block_size, 4096);
+
+        header[5] ^= 0xFF;
+        assert!(parse_flac_header(&header).is_none());
+    }
+
+    #[test]
+    fn test_merge_deduplicates_overlap() {
+        let segment = |start: f64, end: f64, text: &str| TranscriptSegment {
+            start,
+            end,
+            text: text.to_string(),
+        };
+        let parts = vec![
+            ChunkTranscript {
+                offset: 0.0,
+                duration: 10.0,
+                transcript: Transcript {
+                    language: Some("english".to_string()),
+                    segments: vec![segment(0.0, 4.0, "Hello there."), segment(4.0, 8.5, "How are you?")],
+                    ..Default::default()
+                },
+            },
+            ChunkTranscript {
+                offset: 8.0,
+                duration: 10.0,
+                transcript: Transcript {
+                    segments: vec![
+                        segment(0.0, 0.5, "you?"),
+                        segment(0.2, 1.0, "How are you"),
+                        segment(1.0, 5.0, "Fine, thanks."),
+                    ],
+                    ..Default::default()
+                },
+            },
+        ];
+
+        let merged = merge_transcripts(parts);
+        assert_eq!(merged.language.as_deref(), Some("english"));
+        assert_eq!(merged.text, "Hello there. How are you? Fine, thanks.");
+        assert_eq!(merged.segments[2].start, 9.0);
+        assert_eq!(merged.segments[2].end, 13.0);
+    }
+}
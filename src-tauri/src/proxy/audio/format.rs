@@ -0,0 +1,405 @@
+// Whisper 兼容的转录输出格式
+// json / text / srt / vtt / verbose_json，带时间戳的格式由结构化输出 (responseSchema) 生成分段
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// 转录 / 翻译任务
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioTask {
+    Transcribe,
+    /// 翻译为英文 (/v1/audio/translations)
+    Translate,
+}
+
+impl AudioTask {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Transcribe => "transcribe",
+            Self::Translate => "translate",
+        }
+    }
+}
+
+/// `response_format` 参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioResponseFormat {
+    #[default]
+    Json,
+    Text,
+    Srt,
+    Vtt,
+    VerboseJson,
+}
+
+impl AudioResponseFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim() {
+            "" | "json" => Ok(Self::Json),
+            "text" => Ok(Self::Text),
+            "srt" => Ok(Self::Srt),
+            "vtt" => Ok(Self::Vtt),
+            "verbose_json" => Ok(Self::VerboseJson),
+            other => Err(format!(
+                "不支持的 response_format: {} (可选 json, text, srt, vtt, verbose_json)",
+                other
+            )),
+        }
+    }
+
+    /// 是否需要分段时间戳 (走结构化输出)
+    pub fn needs_segments(self) -> bool {
+        matches!(self, Self::Srt | Self::Vtt | Self::VerboseJson)
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json | Self::VerboseJson => "application/json",
+            Self::Text | Self::Srt => "text/plain; charset=utf-8",
+            Self::Vtt => "text/vtt; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptSegment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptWord {
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// 模型返回的转录结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transcript {
+    /// 语言的英文小写全称 (如 "english")，与 Whisper 一致
+    pub language: Option<String>,
+    pub text: String,
+    pub segments: Vec<TranscriptSegment>,
+    pub words: Vec<TranscriptWord>,
+}
+
+impl Transcript {
+    /// 纯文本结果 (json / text 格式)
+    pub fn from_text(text: &str) -> Self {
+        Self {
+            text: text.trim().to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// 解析结构化输出: {"language": "...", "segments": [{start, end, text}], "words": [...]}
+    pub fn from_structured(raw: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(strip_code_fence(raw))
+            .map_err(|e| format!("转录结果不是合法 JSON: {}", e))?;
+
+        let mut segments: Vec<TranscriptSegment> = value
+            .get("segments")
+            .cloned()
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|e| format!("转录分段格式错误: {}", e))?
+            .unwrap_or_default();
+        let mut words: Vec<TranscriptWord> = value
+            .get("words")
+            .cloned()
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|e| format!("转录单词格式错误: {}", e))?
+            .unwrap_or_default();
+
+        // 清理: 去空白、丢弃空段、修正倒置时间、按开始时间排序
+        segments.retain(|s| !s.text.trim().is_empty());
+        for segment in &mut segments {
+            segment.text = segment.text.trim().to_string();
+            segment.start = segment.start.max(0.0);
+            segment.end = segment.end.max(segment.start);
+        }
+        segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+        words.retain(|w| !w.word.trim().is_empty());
+        for word in &mut words {
+            word.word = word.word.trim().to_string();
+            word.start = word.start.max(0.0);
+            word.end = word.end.max(word.start);
+        }
+        words.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+        let text = segments
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(Self {
+            language: value
+                .get("language")
+                .and_then(|l| l.as_str())
+                .map(|l| l.trim().to_lowercase())
+                .filter(|l| !l.is_empty()),
+            text,
+            segments,
+            words,
+        })
+    }
+
+    /// 总时长 (最后一段的结束时间)
+    pub fn duration(&self) -> f64 {
+        self.segments
+            .iter()
+            .map(|s| s.end)
+            .chain(self.words.iter().map(|w| w.end))
+            .fold(0.0, f64::max)
+    }
+}
+
+fn strip_code_fence(raw: &str) -> &str {
+    let trimmed = raw.trim();
+    trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|s| s.strip_suffix("```"))
+        .map(str::trim)
+        .unwrap_or(trimmed)
+}
+
+/// 结构化输出的 responseSchema
+pub fn transcript_schema(with_words: bool) -> Value {
+    let mut properties = json!({
+        "language": { "type": "STRING" },
+        "segments": {
+            "type": "ARRAY",
+            "items": {
+                "type": "OBJECT",
+                "properties": {
+                    "start": { "type": "NUMBER" },
+                    "end": { "type": "NUMBER" },
+                    "text": { "type": "STRING" }
+                },
+                "required": ["start", "end", "text"]
+            }
+        }
+    });
+    if with_words {
+        properties["words"] = json!({
+            "type": "ARRAY",
+            "items": {
+                "type": "OBJECT",
+                "properties": {
+                    "word": { "type": "STRING" },
+                    "start": { "type": "NUMBER" },
+                    "end": { "type": "NUMBER" }
+                },
+                "required": ["word", "start", "end"]
+            }
+        });
+    }
+    json!({
+        "type": "OBJECT",
+        "properties": properties,
+        "required": ["language", "segments"]
+    })
+}
+
+/// 构造发给模型的指令，`structured` 时要求返回带时间戳的 JSON 分段
+pub fn build_instruction(
+    task: AudioTask,
+    structured: bool,
+    language: Option<&str>,
+    prompt: Option<&str>,
+    with_words: bool,
+) -> String {
+    let mut instruction = match task {
+        AudioTask::Transcribe => "Generate a transcript of the speech.".to_string(),
+        AudioTask::Translate => {
+            "Translate the speech into English. Output only the English translation.".to_string()
+        }
+    };
+    if let (AudioTask::Transcribe, Some(language)) = (task, language) {
+        instruction.push_str(&format!(" The spoken language is '{}'; transcribe in that language.", language));
+    }
+    if structured {
+        instruction.push_str(
+            " Return JSON with `language` (the detected spoken language as a lowercase English name, e.g. \"english\") \
+             and `segments`: consecutive sentence-level segments with `start` and `end` in seconds from the beginning \
+             of the audio and their `text`.",
+        );
+        if with_words {
+            instruction.push_str(" Also return `words`: every word with its `start` and `end` in seconds.");
+        }
+    }
+    if let Some(prompt) = prompt.filter(|p| !p.trim().is_empty()) {
+        // 与 Whisper 一致: prompt 作为上下文/拼写提示
+        instruction.push_str(&format!("\n\nContext and spelling hints: {}", prompt.trim()));
+    }
+    instruction
+}
+
+/// 秒数格式化为时间戳: SRT 用 "00:01:02,345"，VTT 用 "00:01:02.345"
+pub fn format_timestamp(seconds: f64, decimal_separator: char) -> String {
+    let total_ms = (seconds.max(0.0) * 1000.0).round() as u64;
+    let hours = total_ms / 3_600_000;
+    let minutes = (total_ms % 3_600_000) / 60_000;
+    let secs = (total_ms % 60_000) / 1000;
+    let millis = total_ms % 1000;
+    format!("{:02}:{:02}:{:02}{}{:03}", hours, minutes, secs, decimal_separator, millis)
+}
+
+pub fn to_srt(segments: &[TranscriptSegment]) -> String {
+    let mut out = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        out.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            format_timestamp(segment.start, ','),
+            format_timestamp(segment.end, ','),
+            segment.text
+        ));
+    }
+    out
+}
+
+pub fn to_vtt(segments: &[TranscriptSegment]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for segment in segments {
+        out.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            format_timestamp(segment.start, '.'),
+            format_timestamp(segment.end, '.'),
+            segment.text
+        ));
+    }
+    out
+}
+
+/// Whisper `verbose_json` 响应
+pub fn to_verbose_json(transcript: &Transcript, task: AudioTask, temperature: f64, with_segments: bool) -> Value {
+    let mut body = json!({
+        "task": task.as_str(),
+        "language": transcript.language.clone().unwrap_or_else(|| "english".to_string()),
+        "duration": round_ms(transcript.duration()),
+        "text": transcript.text,
+    });
+    if with_segments {
+        body["segments"] = Value::Array(
+            transcript
+                .segments
+                .iter()
+                .enumerate()
+                .map(|(id, s)| {
+                    json!({
+                        "id": id,
+                        "seek": 0,
+                        "start": round_ms(s.start),
+                        "end": round_ms(s.end),
+                        "text": s.text,
+                        "tokens": [],
+                        "temperature": temperature,
+                        "avg_logprob": 0.0,
+                        "compression_ratio": 0.0,
+                        "no_speech_prob": 0.0
+                    })
+                })
+                .collect(),
+        );
+    }
+    if !transcript.words.is_empty() {
+        body["words"] = Value::Array(
+            transcript
+                .words
+                .iter()
+                .map(|w| json!({ "word": w.word, "start": round_ms(w.start), "end": round_ms(w.end) }))
+                .collect(),
+        );
+    }
+    body
+}
+
+fn round_ms(seconds: f64) -> f64 {
+    (seconds * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments() -> Vec<TranscriptSegment> {
+        vec![
+            TranscriptSegment { start: 0.0, end: 2.5, text: "Hello there.".to_string() },
+            TranscriptSegment { start: 2.5, end: 3723.0456, text: "General Kenobi!".to_string() },
+        ]
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0.0, ','), "00:00:00,000");
+        assert_eq!(format_timestamp(61.5, ','), "00:01:01,500");
+        assert_eq!(format_timestamp(3723.0456, '.'), "01:02:03.046");
+        assert_eq!(format_timestamp(-1.0, '.'), "00:00:00.000");
+    }
+
+    #[test]
+    fn test_srt_output() {
+        assert_eq!(
+            to_srt(&segments()),
+            "1\n00:00:00,000 --> 00:00:02,500\nHello there.\n\n2\n00:00:02,500 --> 01:02:03,046\nGeneral Kenobi!\n\n"
+        );
+        assert_eq!(to_srt(&[]), "");
+    }
+
+    #[test]
+    fn test_vtt_output() {
+        assert_eq!(
+            to_vtt(&segments()),
+            "WEBVTT\n\n00:00:00.000 --> 00:00:02.500\nHello there.\n\n00:00:02.500 --> 01:02:03.046\nGeneral Kenobi!\n\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_structured_transcript() {
+        let raw = "```json\n{\"language\": \"English\", \"segments\": [
+            {\"start\": 4.0, \"end\": 3.0, \"text\": \" world \"},
+            {\"start\": 0.0, \"end\": 1.5, \"text\": \"hello\"},
+            {\"start\": 5.0, \"end\": 6.0, \"text\": \"  \"}
+        ]}\n```";
+        let transcript = Transcript::from_structured(raw).unwrap();
+        assert_eq!(transcript.language.as_deref(), Some("english"));
+        assert_eq!(transcript.text, "hello world");
+        assert_eq!(transcript.segments.len(), 2);
+        // 倒置的结束时间被修正
+        assert_eq!(transcript.segments[1].end, 4.0);
+        assert_eq!(transcript.duration(), 4.0);
+        assert!(Transcript::from_structured("not json").is_err());
+    }
+
+    #[test]
+    fn test_verbose_json_shape() {
+        let transcript = Transcript {
+            language: Some("english".to_string()),
+            text: "Hello there. General Kenobi!".to_string(),
+            segments: segments(),
+            words: vec![],
+        };
+        let body = to_verbose_json(&transcript, AudioTask::Translate, 0.2, true);
+        assert_eq!(body["task"], "translate");
+        assert_eq!(body["duration"], 3723.046);
+        assert_eq!(body["segments"][1]["id"], 1);
+        assert_eq!(body["segments"][1]["temperature"], 0.2);
+        assert!(body.get("words").is_none());
+
+        let no_segments = to_verbose_json(&transcript, AudioTask::Transcribe, 0.0, false);
+        assert!(no_segments.get("segments").is_none());
+    }
+
+    #[test]
+    fn test_response_format_parse() {
+        assert_eq!(AudioResponseFormat::parse("").unwrap(), AudioResponseFormat::Json);
+        assert!(AudioResponseFormat::parse("verbose_json").unwrap().needs_segments());
+        assert!(!AudioResponseFormat::parse("text").unwrap().needs_segments());
+        assert!(AudioResponseFormat::parse("docx").is_err());
+    }
+}
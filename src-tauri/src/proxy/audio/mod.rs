@@ -1,9 +1,16 @@
+pub mod chunk;
+pub mod format;
+pub mod speech;
+
 use base64::{engine::general_purpose, Engine as _};
 use std::path::Path;
 
 pub struct AudioProcessor;
 
 impl AudioProcessor {
+    /// 单次 Inline Data 请求的音频大小上限
+    pub const MAX_INLINE_SIZE: usize = 15 * 1024 * 1024; // 15MB
+
     /// 检测音频 MIME 类型
     pub fn detect_mime_type(filename: &str) -> Result<String, String> {
         let ext = Path::new(filename)
@@ -29,8 +36,7 @@ impl AudioProcessor {
 
     /// 判断文件是否超过大小限制
     pub fn exceeds_size_limit(size_bytes: usize) -> bool {
-        const MAX_SIZE: usize = 15 * 1024 * 1024; // 15MB
-        size_bytes > MAX_SIZE
+        size_bytes > Self::MAX_INLINE_SIZE
     }
 }
 
@@ -0,0 +1,337 @@
+// 语音合成 (TTS): OpenAI /v1/audio/speech 参数映射到 Gemini TTS，
+// 并将返回的 PCM (16-bit 单声道) 封装为目标容器
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// tts-1 / gpt-4o-mini-tts 等默认映射的模型
+pub const DEFAULT_TTS_MODEL: &str = "gemini-2.5-flash-preview-tts";
+/// tts-1-hd 映射的模型
+pub const HD_TTS_MODEL: &str = "gemini-2.5-pro-preview-tts";
+/// Gemini TTS 默认输出采样率
+pub const DEFAULT_SAMPLE_RATE: u32 = 24_000;
+/// 与 OpenAI 一致的单次输入长度上限
+pub const MAX_INPUT_CHARS: usize = 4096;
+
+/// Gemini TTS 预置音色
+const GEMINI_VOICES: &[&str] = &[
+    "Zephyr", "Puck", "Charon", "Kore", "Fenrir", "Leda", "Orus", "Aoede", "Callirrhoe", "Autonoe",
+    "Enceladus", "Iapetus", "Umbriel", "Algieba", "Despina", "Erinome", "Algenib", "Rasalgethi",
+    "Laomedeia", "Achernar", "Alnilam", "Schedar", "Gacrux", "Pulcherrima", "Achird",
+    "Zubenelgenubi", "Vindemiatrix", "Sadachbia", "Sadaltager", "Sulafat",
+];
+
+/// OpenAI 音色到 Gemini 音色的近似映射
+const OPENAI_VOICES: &[(&str, &str)] = &[
+    ("alloy", "Kore"),
+    ("ash", "Orus"),
+    ("ballad", "Sadaltager"),
+    ("coral", "Aoede"),
+    ("echo", "Charon"),
+    ("fable", "Fenrir"),
+    ("onyx", "Algenib"),
+    ("nova", "Leda"),
+    ("sage", "Achird"),
+    ("shimmer", "Zephyr"),
+    ("verse", "Puck"),
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpeechRequest {
+    #[serde(default = "default_model")]
+    pub model: String,
+    pub input: String,
+    pub voice: String,
+    #[serde(default)]
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub response_format: Option<String>,
+    #[serde(default)]
+    pub speed: Option<f64>,
+    #[serde(default)]
+    pub stream_format: Option<String>,
+}
+
+fn default_model() -> String {
+    "tts-1".to_string()
+}
+
+/// 输出容器 (mp3/opus/aac/flac 需要编码器，暂不支持)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeechFormat {
+    Wav,
+    Pcm,
+}
+
+impl SpeechFormat {
+    /// 未指定时返回 wav
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("wav") => Ok(Self::Wav),
+            Some("pcm") => Ok(Self::Pcm),
+            Some(other @ ("mp3" | "opus" | "aac" | "flac")) => {
+                Err(format!("暂不支持 response_format={}，请使用 wav 或 pcm", other))
+            }
+            Some(other) => Err(format!("无效的 response_format: {}", other)),
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Wav => "audio/wav",
+            Self::Pcm => "audio/pcm",
+        }
+    }
+
+    /// 将完整 PCM 封装为目标容器
+    pub fn encode(self, pcm: &[u8], sample_rate: u32) -> Vec<u8> {
+        match self {
+            Self::Wav => {
+                let mut out = wav_header(sample_rate, Some(pcm.len() as u32));
+                out.extend_from_slice(pcm);
+                out
+            }
+            Self::Pcm => pcm.to_vec(),
+        }
+    }
+}
+
+/// 流式输出方式 (对应 OpenAI `stream_format`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeechStream {
+    /// 不流式，合成完成后一次返回
+    Off,
+    /// 分块传输原始音频字节
+    Audio,
+    /// SSE: speech.audio.delta / speech.audio.done
+    Sse,
+}
+
+impl SpeechStream {
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("") => Ok(Self::Off),
+            Some("audio") => Ok(Self::Audio),
+            Some("sse") => Ok(Self::Sse),
+            Some(other) => Err(format!("无效的 stream_format: {}", other)),
+        }
+    }
+}
+
+/// 解析音色: 支持 OpenAI 音色名与 Gemini 音色名 (不区分大小写)
+pub fn resolve_voice(voice: &str) -> Result<String, String> {
+    let voice = voice.trim();
+    if let Some((_, gemini)) = OPENAI_VOICES.iter().find(|(name, _)| name.eq_ignore_ascii_case(voice)) {
+        return Ok(gemini.to_string());
+    }
+    GEMINI_VOICES
+        .iter()
+        .find(|name| name.eq_ignore_ascii_case(voice))
+        .map(|name| name.to_string())
+        .ok_or_else(|| format!("不支持的音色: {}", voice))
+}
+
+/// OpenAI TTS 模型名映射到 Gemini TTS 模型，gemini-* 原样透传
+pub fn resolve_model(model: &str) -> String {
+    if model.starts_with("gemini-") {
+        model.to_string()
+    } else if model == "tts-1-hd" {
+        HD_TTS_MODEL.to_string()
+    } else {
+        DEFAULT_TTS_MODEL.to_string()
+    }
+}
+
+/// Gemini TTS 没有语速参数，语速与风格通过自然语言指令控制
+pub fn build_prompt(input: &str, instructions: Option<&str>, speed: Option<f64>) -> String {
+    let mut directions = Vec::new();
+    if let Some(instructions) = instructions.map(str::trim).filter(|i| !i.is_empty()) {
+        directions.push(instructions.trim_end_matches('.').to_string());
+    }
+    if let Some(speed) = speed.filter(|s| (s - 1.0).abs() > 0.05) {
+        directions.push(format!("Speak at {:.2}x the normal speaking rate", speed));
+    }
+    if directions.is_empty() {
+        input.to_string()
+    } else {
+        format!("{}. Read the following text aloud:\n{}", directions.join(". "), input)
+    }
+}
+
+pub fn build_gemini_request(prompt: &str, voice: &str) -> Value {
+    json!({
+        "contents": [{
+            "role": "user",
+            "parts": [{"text": prompt}]
+        }],
+        "generationConfig": {
+            "responseModalities": ["AUDIO"],
+            "speechConfig": {
+                "voiceConfig": {
+                    "prebuiltVoiceConfig": {"voiceName": voice}
+                }
+            }
+        }
+    })
+}
+
+/// 从 Gemini 响应 (或流式事件) 中提取 PCM 数据与采样率
+pub fn extract_pcm(response: &Value) -> Result<(Vec<u8>, u32), String> {
+    let mut pcm = Vec::new();
+    let mut sample_rate = DEFAULT_SAMPLE_RATE;
+    let parts = response
+        .get("candidates")
+        .and_then(|c| c.get(0))
+        .and_then(|c| c.get("content"))
+        .and_then(|c| c.get("parts"))
+        .and_then(|p| p.as_array());
+
+    for inline in parts.into_iter().flatten().filter_map(|p| p.get("inlineData")) {
+        let mime_type = inline.get("mimeType").and_then(|m| m.as_str()).unwrap_or("");
+        if !mime_type.starts_with("audio/") {
+            continue;
+        }
+        sample_rate = parse_sample_rate(mime_type).unwrap_or(sample_rate);
+        let data = inline.get("data").and_then(|d| d.as_str()).unwrap_or("");
+        let bytes = general_purpose::STANDARD
+            .decode(data)
+            .map_err(|e| format!("音频数据解码失败: {}", e))?;
+        pcm.extend_from_slice(&bytes);
+    }
+    Ok((pcm, sample_rate))
+}
+
+/// 解析 "audio/L16;codec=pcm;rate=24000" 中的采样率
+fn parse_sample_rate(mime_type: &str) -> Option<u32> {
+    mime_type
+        .split(';')
+        .filter_map(|p| p.trim().strip_prefix("rate="))
+        .find_map(|r| r.parse().ok())
+}
+
+/// 16-bit 单声道 WAV 头；`data_len` 为 None 时写入 0xFFFFFFFF 表示长度未知 (流式)
+pub fn wav_header(sample_rate: u32, data_len: Option<u32>) -> Vec<u8> {
+    const CHANNELS: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+    let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+    let byte_rate = sample_rate * block_align as u32;
+    let (riff_size, data_size) = match data_len {
+        Some(len) => (36 + len, len),
+        None => (u32::MAX, u32::MAX),
+    };
+
+    let mut out = Vec::with_capacity(44);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&riff_size.to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&CHANNELS.to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&byte_rate.to_le_bytes());
+    out.extend_from_slice(&block_align.to_le_bytes());
+    out.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_size.to_le_bytes());
+    out
+}
+
+/// 上游 SSE 字节流的行缓冲，按 `data:` 行解析出 JSON 事件 (解包 v1internal 的 `response`)
+#[derive(Default)]
+pub struct SseEventBuffer {
+    buf: Vec<u8>,
+}
+
+impl SseEventBuffer {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Value> {
+        self.buf.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            if let Some(event) = parse_data_line(&line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// 流结束时处理没有换行结尾的最后一行
+    pub fn finish(&mut self) -> Option<Value> {
+        let line = std::mem::take(&mut self.buf);
+        parse_data_line(&line)
+    }
+}
+
+fn parse_data_line(line: &[u8]) -> Option<Value> {
+    let line = std::str::from_utf8(line).ok()?.trim();
+    let data = line.strip_prefix("data:")?.trim();
+    let mut event: Value = serde_json::from_str(data).ok()?;
+    Some(event.get_mut("response").map(Value::take).unwrap_or(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_voice_and_model() {
+        assert_eq!(resolve_voice("alloy").unwrap(), "Kore");
+        assert_eq!(resolve_voice("PUCK").unwrap(), "Puck");
+        assert!(resolve_voice("robot").is_err());
+        assert_eq!(resolve_model("tts-1"), DEFAULT_TTS_MODEL);
+        assert_eq!(resolve_model("tts-1-hd"), HD_TTS_MODEL);
+        assert_eq!(resolve_model("gemini-2.5-pro-preview-tts"), "gemini-2.5-pro-preview-tts");
+    }
+
+    #[test]
+    fn test_format_parse() {
+        assert_eq!(SpeechFormat::parse(None).unwrap(), SpeechFormat::Wav);
+        assert_eq!(SpeechFormat::parse(Some("PCM")).unwrap(), SpeechFormat::Pcm);
+        assert!(SpeechFormat::parse(Some("mp3")).unwrap_err().contains("暂不支持"));
+        assert!(SpeechFormat::parse(Some("ogg")).is_err());
+        assert_eq!(SpeechStream::parse(Some("sse")).unwrap(), SpeechStream::Sse);
+    }
+
+    #[test]
+    fn test_wav_encoding() {
+        let pcm = vec![1u8, 0, 2, 0];
+        let wav = SpeechFormat::Wav.encode(&pcm, 24_000);
+        assert_eq!(wav.len(), 48);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 40);
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 24_000);
+        assert_eq!(u32::from_le_bytes(wav[28..32].try_into().unwrap()), 48_000);
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 4);
+        assert_eq!(&wav[44..], &pcm[..]);
+
+        let streaming = wav_header(24_000, None);
+        assert_eq!(&streaming[40..44], &[0xFF; 4]);
+    }
+
+    #[test]
+    fn test_extract_pcm_from_stream() {
+        let event = json!({"response": {"candidates": [{"content": {"parts": [{
+            "inlineData": {"mimeType": "audio/L16;codec=pcm;rate=16000", "data": "AQACAA=="}
+        }]}}]}});
+        let sse = format!("data: {}\r\n\r\ndata: {}", event, event);
+
+        let mut buffer = SseEventBuffer::default();
+        let (head, tail) = sse.as_bytes().split_at(10);
+        assert!(buffer.push(head).is_empty());
+        let events = buffer.push(tail);
+        assert_eq!(events.len(), 1);
+        let (pcm, rate) = extract_pcm(&events[0]).unwrap();
+        assert_eq!(pcm, vec![1, 0, 2, 0]);
+        assert_eq!(rate, 16_000);
+        assert!(buffer.finish().is_some());
+    }
+
+    #[test]
+    fn test_build_prompt() {
+        assert_eq!(build_prompt("Hi", None, Some(1.0)), "Hi");
+        let prompt = build_prompt("Hi", Some("Cheerful."), Some(1.5));
+        assert_eq!(prompt, "Cheerful. Speak at 1.50x the normal speaking rate. Read the following text aloud:\nHi");
+    }
+}
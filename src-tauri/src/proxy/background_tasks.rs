@@ -0,0 +1,362 @@
+// 后台任务分类与路由 (Claude 客户端)
+// Claude Code 等客户端会发出标题生成、摘要、提示建议等后台请求，
+// 按 ProxyConfig 中配置的分类规则识别后，可路由到指定模型或直接返回固定响应
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::proxy::mappers::claude::models::{ClaudeRequest, ContentBlock, MessageContent};
+
+/// 后台任务默认路由的虚拟模型 ID (可通过 custom_mapping 映射到实际模型)
+pub const DEFAULT_BACKGROUND_MODEL: &str = "internal-background-task";
+
+/// 后台任务分类配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackgroundTaskConfig {
+    /// 是否启用后台任务识别
+    pub enabled: bool,
+    /// 关键词/正则只匹配最后一条用户消息的前 N 个字符
+    pub match_window_chars: usize,
+    /// 分类规则，按顺序匹配，先命中者生效
+    pub classes: Vec<BackgroundTaskClass>,
+}
+
+impl Default for BackgroundTaskConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            match_window_chars: 500,
+            classes: default_classes(),
+        }
+    }
+}
+
+/// 单个后台任务分类
+///
+/// 命中条件 (全部满足):
+/// - 消息长度不超过 `max_length` (字符数)
+/// - `has_tools` 与请求是否携带工具一致
+/// - 命中任一 `keywords` 或 `regex` (两者均为空时视为命中)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundTaskClass {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub regex: Vec<String>,
+    #[serde(default)]
+    pub max_length: Option<usize>,
+    #[serde(default)]
+    pub has_tools: Option<bool>,
+    #[serde(default)]
+    pub action: BackgroundTaskAction,
+}
+
+/// 命中后的处理方式
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackgroundTaskAction {
+    /// 路由到指定模型 (会再经过 custom_mapping 解析)
+    Route { model: String },
+    /// 不请求上游，直接返回固定文本
+    Respond { text: String },
+}
+
+impl Default for BackgroundTaskAction {
+    fn default() -> Self {
+        BackgroundTaskAction::Route {
+            model: DEFAULT_BACKGROUND_MODEL.to_string(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 默认分类 (与早期硬编码的关键词列表及优先级一致)
+fn default_classes() -> Vec<BackgroundTaskClass> {
+    fn class(name: &str, keywords: &[&str]) -> BackgroundTaskClass {
+        BackgroundTaskClass {
+            name: name.to_string(),
+            enabled: true,
+            keywords: keywords.iter().map(|s| s.to_string()).collect(),
+            regex: Vec::new(),
+            // 后台任务通常不超过 800 字符
+            max_length: Some(800),
+            has_tools: None,
+            action: BackgroundTaskAction::default(),
+        }
+    }
+
+    vec![
+        class("system_message", &["Warmup", "<system-reminder>", "This is a system message"]),
+        class(
+            "title_generation",
+            &[
+                "write a 5-10 word title",
+                "Please write a 5-10 word title",
+                "Respond with the title",
+                "Generate a title for",
+                "Create a brief title",
+                "title for the conversation",
+                "conversation title",
+                "生成标题",
+                "为对话起个标题",
+            ],
+        ),
+        class("simple_summary", &["in under 50 characters"]),
+        class(
+            "context_compression",
+            &[
+                "Summarize this coding conversation",
+                "Summarize the conversation",
+                "Concise summary",
+                "compress the context",
+                "Provide a concise summary",
+                "condense the previous messages",
+                "shorten the conversation history",
+                "extract key points from",
+            ],
+        ),
+        class(
+            "prompt_suggestion",
+            &[
+                "prompt suggestion generator",
+                "suggest next prompts",
+                "what should I ask next",
+                "generate follow-up questions",
+                "recommend next steps",
+                "possible next actions",
+            ],
+        ),
+        class(
+            "environment_probe",
+            &[
+                "check current directory",
+                "list available tools",
+                "verify environment",
+                "test connection",
+            ],
+        ),
+    ]
+}
+
+/// 分类结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BackgroundTaskMatch {
+    pub class: String,
+    pub action: BackgroundTaskAction,
+}
+
+/// 预编译的分类器 (配置更新时重建)
+pub struct BackgroundTaskClassifier {
+    config: BackgroundTaskConfig,
+    regexes: Vec<Vec<Regex>>,
+    errors: Vec<String>,
+}
+
+impl BackgroundTaskClassifier {
+    /// 编译分类规则，无效的正则会被跳过并记录在 `errors()` 中
+    pub fn new(config: BackgroundTaskConfig) -> Self {
+        let mut errors = Vec::new();
+        let regexes = config
+            .classes
+            .iter()
+            .map(|class| {
+                class
+                    .regex
+                    .iter()
+                    .filter_map(|pattern| match Regex::new(pattern) {
+                        Ok(re) => Some(re),
+                        Err(e) => {
+                            let msg = format!("class '{}': invalid regex '{}': {}", class.name, pattern, e);
+                            tracing::warn!("[Background-Task] {}", msg);
+                            errors.push(msg);
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self { config, regexes, errors }
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// 对 Claude 请求分类，非后台任务返回 None
+    pub fn classify(&self, request: &ClaudeRequest) -> Option<BackgroundTaskMatch> {
+        if !self.config.enabled {
+            return None;
+        }
+        let message = extract_last_user_message(request)?;
+        let has_tools = request.tools.as_ref().is_some_and(|t| !t.is_empty());
+        self.classify_text(&message, has_tools)
+    }
+
+    fn classify_text(&self, message: &str, has_tools: bool) -> Option<BackgroundTaskMatch> {
+        let length = message.chars().count();
+        let preview: String = message.chars().take(self.config.match_window_chars).collect();
+
+        self.config
+            .classes
+            .iter()
+            .zip(&self.regexes)
+            .find(|(class, regexes)| {
+                if !class.enabled {
+                    return false;
+                }
+                if class.max_length.is_some_and(|max| length > max) {
+                    return false;
+                }
+                if class.has_tools.is_some_and(|expected| expected != has_tools) {
+                    return false;
+                }
+                if class.keywords.is_empty() && class.regex.is_empty() {
+                    return true;
+                }
+                class.keywords.iter().any(|kw| preview.contains(kw.as_str()))
+                    || regexes.iter().any(|re| re.is_match(&preview))
+            })
+            .map(|(class, _)| BackgroundTaskMatch {
+                class: class.name.clone(),
+                action: class.action.clone(),
+            })
+    }
+}
+
+impl Default for BackgroundTaskClassifier {
+    fn default() -> Self {
+        Self::new(BackgroundTaskConfig::default())
+    }
+}
+
+/// 提取最后一条有意义的用户消息 (跳过空消息、Warmup 与 system-reminder)
+fn extract_last_user_message(request: &ClaudeRequest) -> Option<String> {
+    request.messages.iter().rev()
+        .filter(|m| m.role == "user")
+        .find_map(|m| {
+            let content = match &m.content {
+                MessageContent::String(s) => s.to_string(),
+                MessageContent::Array(arr) => arr
+                    .iter()
+                    .filter_map(|block| match block {
+                        ContentBlock::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+
+            if content.trim().is_empty()
+                || content.starts_with("Warmup")
+                || content.contains("<system-reminder>")
+            {
+                None
+            } else {
+                Some(content)
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(text: &str, tools: bool) -> ClaudeRequest {
+        let mut body = json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{ "role": "user", "content": text }]
+        });
+        if tools {
+            body["tools"] = json!([{ "name": "bash", "input_schema": { "type": "object" } }]);
+        }
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn class_of(classifier: &BackgroundTaskClassifier, text: &str) -> Option<String> {
+        classifier.classify(&request(text, false)).map(|m| m.class)
+    }
+
+    #[test]
+    fn test_default_classes() {
+        let classifier = BackgroundTaskClassifier::default();
+        assert_eq!(
+            class_of(&classifier, "Please write a 5-10 word title for this"),
+            Some("title_generation".to_string())
+        );
+        assert_eq!(
+            class_of(&classifier, "Summarize the conversation in under 50 characters"),
+            Some("simple_summary".to_string())
+        );
+        assert_eq!(
+            class_of(&classifier, "Summarize the conversation so far"),
+            Some("context_compression".to_string())
+        );
+        assert_eq!(class_of(&classifier, "Fix the failing test in main.rs"), None);
+
+        // 超长消息不视为后台任务
+        let long = format!("Generate a title for {}", "x".repeat(900));
+        assert_eq!(class_of(&classifier, &long), None);
+    }
+
+    #[test]
+    fn test_custom_rules_regex_tools_and_respond() {
+        let config = BackgroundTaskConfig {
+            classes: vec![
+                BackgroundTaskClass {
+                    name: "quota_check".to_string(),
+                    enabled: true,
+                    keywords: vec![],
+                    regex: vec![r"^(?i)quota\b".to_string(), "(".to_string()],
+                    max_length: None,
+                    has_tools: Some(false),
+                    action: BackgroundTaskAction::Respond { text: "OK".to_string() },
+                },
+                BackgroundTaskClass {
+                    name: "tool_probe".to_string(),
+                    enabled: true,
+                    keywords: vec!["ping".to_string()],
+                    regex: vec![],
+                    max_length: Some(10),
+                    has_tools: Some(true),
+                    action: BackgroundTaskAction::Route { model: "gemini-3-flash".to_string() },
+                },
+            ],
+            ..Default::default()
+        };
+        let classifier = BackgroundTaskClassifier::new(config);
+        assert_eq!(classifier.errors().len(), 1);
+
+        let m = classifier.classify(&request("QUOTA status", false)).unwrap();
+        assert_eq!(m.class, "quota_check");
+        assert_eq!(m.action, BackgroundTaskAction::Respond { text: "OK".to_string() });
+        assert!(classifier.classify(&request("QUOTA status", true)).is_none());
+
+        let m = classifier.classify(&request("ping", true)).unwrap();
+        assert_eq!(m.action, BackgroundTaskAction::Route { model: "gemini-3-flash".to_string() });
+        assert!(classifier.classify(&request("ping", false)).is_none());
+    }
+
+    #[test]
+    fn test_action_serde() {
+        let class: BackgroundTaskClass = serde_json::from_value(json!({
+            "name": "titles",
+            "keywords": ["title"],
+            "action": { "type": "respond", "text": "Untitled" }
+        }))
+        .unwrap();
+        assert!(class.enabled);
+        assert_eq!(class.action, BackgroundTaskAction::Respond { text: "Untitled".to_string() });
+
+        let class: BackgroundTaskClass = serde_json::from_value(json!({ "name": "x" })).unwrap();
+        assert_eq!(class.action, BackgroundTaskAction::default());
+    }
+}
@@ -0,0 +1,482 @@
+// OpenAI Batch API (本地执行)
+// 客户端通过 /v1/files 上传 JSONL (purpose=batch)，/v1/batches 创建任务后由后台 worker 逐行经内部
+// chat-completions 路由执行 (与请求重放共用同一套路由，经过排队、预算与监控)。
+// 每行结果作为检查点写入 batches.db，重启后从中断处继续；结束时生成 OpenAI 格式的输出 / 错误文件。
+
+pub mod worker;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use crate::modules::batch_db::{self, BatchFile, BatchRequest};
+
+/// 目前支持的批处理端点
+pub const SUPPORTED_ENDPOINT: &str = "/v1/chat/completions";
+/// 唯一支持的完成窗口 (与 OpenAI 一致)
+pub const COMPLETION_WINDOW: &str = "24h";
+pub const COMPLETION_WINDOW_SECS: i64 = 24 * 3600;
+/// 单个输入文件大小上限 (受路由 DefaultBodyLimit 限制)
+pub const MAX_FILE_BYTES: usize = 100 * 1024 * 1024;
+/// 单个批次的请求数上限
+pub const MAX_REQUESTS_PER_BATCH: usize = 50_000;
+/// 校验失败时最多返回的错误条数
+const MAX_REPORTED_ERRORS: usize = 100;
+
+/// 批处理执行配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatchConfig {
+    /// 是否执行批处理任务 (关闭时暂停执行，已创建的任务仍会在完成窗口结束后过期)
+    pub enabled: bool,
+    /// 同时执行的请求数
+    pub concurrency: usize,
+    /// 单行请求的最大尝试次数 (5xx / 网络错误重试；429 与账号池不可用不计入)
+    pub max_attempts: u32,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            concurrency: 4,
+            max_attempts: 3,
+        }
+    }
+}
+
+static CONFIG: Lazy<RwLock<Arc<BatchConfig>>> =
+    Lazy::new(|| RwLock::new(Arc::new(BatchConfig::default())));
+
+/// 当前生效的配置
+pub fn config() -> Arc<BatchConfig> {
+    CONFIG.read().unwrap().clone()
+}
+
+/// 应用批处理配置
+pub fn apply_config(config: &BatchConfig) {
+    *CONFIG.write().unwrap() = Arc::new(config.clone());
+    worker::wake();
+}
+
+pub fn now_secs() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+pub fn new_file_id() -> String {
+    format!("file-{}", uuid::Uuid::new_v4().simple())
+}
+
+pub fn new_batch_id() -> String {
+    format!("batch_{}", uuid::Uuid::new_v4().simple())
+}
+
+fn new_request_id() -> String {
+    format!("batch_req_{}", uuid::Uuid::new_v4().simple())
+}
+
+/// 校验文件 ID (`file-` + 32 位十六进制)，防止路径穿越
+pub fn is_valid_file_id(id: &str) -> bool {
+    id.strip_prefix("file-").is_some_and(|hex| {
+        hex.len() == 32
+            && hex
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    })
+}
+
+/// 文件内容目录 (不存在时创建)
+fn files_dir() -> Result<PathBuf, String> {
+    let dir = crate::modules::account::get_data_dir()?.join("batch_files");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create batch file dir: {}", e))?;
+    Ok(dir)
+}
+
+fn file_path(id: &str) -> Result<PathBuf, String> {
+    if !is_valid_file_id(id) {
+        return Err(format!("Invalid file id: {}", id));
+    }
+    Ok(files_dir()?.join(format!("{}.jsonl", id)))
+}
+
+/// 保存文件内容并登记元数据
+pub fn save_file(purpose: &str, filename: &str, content: &[u8]) -> Result<BatchFile, String> {
+    let file = BatchFile {
+        id: new_file_id(),
+        filename: filename.to_string(),
+        purpose: purpose.to_string(),
+        bytes: content.len() as i64,
+        created_at: now_secs(),
+    };
+    let path = file_path(&file.id)?;
+    std::fs::write(&path, content).map_err(|e| format!("Failed to write batch file: {}", e))?;
+    if let Err(e) = batch_db::insert_file(&file) {
+        let _ = std::fs::remove_file(&path);
+        return Err(e);
+    }
+    Ok(file)
+}
+
+pub fn read_file(id: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(file_path(id)?).map_err(|e| format!("Failed to read batch file {}: {}", id, e))
+}
+
+/// 删除文件元数据与内容，返回是否存在
+pub fn delete_file(id: &str) -> Result<bool, String> {
+    let path = file_path(id)?;
+    let existed = batch_db::delete_file(id)?;
+    if path.exists() {
+        std::fs::remove_file(&path).map_err(|e| format!("Failed to delete batch file: {}", e))?;
+    }
+    Ok(existed)
+}
+
+/// 输入文件中通过校验的一行
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedLine {
+    /// 从 1 开始的行号
+    pub line: i64,
+    pub custom_id: String,
+    pub body: Value,
+}
+
+fn validation_error(code: &str, message: String, param: Option<&str>, line: Option<i64>) -> Value {
+    json!({ "code": code, "message": message, "param": param, "line": line })
+}
+
+/// 校验输入 JSONL，失败时返回 OpenAI 格式的 `errors` 列表
+pub fn parse_input(content: &[u8], endpoint: &str) -> Result<Vec<ParsedLine>, Value> {
+    let fail = |errors: Vec<Value>| json!({ "object": "list", "data": errors });
+    let content = std::str::from_utf8(content).map_err(|_| {
+        fail(vec![validation_error(
+            "invalid_file_format",
+            "The input file must be UTF-8 encoded JSONL".to_string(),
+            None,
+            None,
+        )])
+    })?;
+
+    let mut parsed = Vec::new();
+    let mut errors = Vec::new();
+    let mut custom_ids = HashSet::new();
+    for (idx, raw) in content.lines().enumerate() {
+        let line = idx as i64 + 1;
+        let raw = raw.trim();
+        if raw.is_empty() {
+            continue;
+        }
+        let mut push = |code: &str, message: String, param: Option<&str>| {
+            if errors.len() < MAX_REPORTED_ERRORS {
+                errors.push(validation_error(code, message, param, Some(line)));
+            }
+        };
+
+        let Ok(Value::Object(mut obj)) = serde_json::from_str::<Value>(raw) else {
+            push(
+                "invalid_json_line",
+                "This line is not a valid JSON object".to_string(),
+                None,
+            );
+            continue;
+        };
+        let custom_id = match obj.get("custom_id").and_then(Value::as_str) {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => {
+                push(
+                    "missing_required_parameter",
+                    "custom_id is required".to_string(),
+                    Some("custom_id"),
+                );
+                continue;
+            }
+        };
+        if !custom_ids.insert(custom_id.clone()) {
+            push(
+                "duplicate_custom_id",
+                format!(
+                    "The custom_id '{}' is used more than once in this file",
+                    custom_id
+                ),
+                Some("custom_id"),
+            );
+            continue;
+        }
+        if !obj
+            .get("method")
+            .and_then(Value::as_str)
+            .is_some_and(|m| m.eq_ignore_ascii_case("POST"))
+        {
+            push(
+                "invalid_request",
+                "method must be POST".to_string(),
+                Some("method"),
+            );
+            continue;
+        }
+        if obj.get("url").and_then(Value::as_str) != Some(endpoint) {
+            push(
+                "invalid_url",
+                format!("url must match the batch endpoint {}", endpoint),
+                Some("url"),
+            );
+            continue;
+        }
+        let body = match obj.remove("body") {
+            Some(body @ Value::Object(_))
+                if body.get("model").and_then(Value::as_str).is_some() =>
+            {
+                body
+            }
+            _ => {
+                push(
+                    "missing_required_parameter",
+                    "body must be an object with a model".to_string(),
+                    Some("body"),
+                );
+                continue;
+            }
+        };
+        if body.get("stream").and_then(Value::as_bool) == Some(true) {
+            push(
+                "invalid_request",
+                "Streaming is not supported in batch requests".to_string(),
+                Some("body.stream"),
+            );
+            continue;
+        }
+        parsed.push(ParsedLine {
+            line,
+            custom_id,
+            body,
+        });
+    }
+
+    if !errors.is_empty() {
+        return Err(fail(errors));
+    }
+    if parsed.is_empty() {
+        return Err(fail(vec![validation_error(
+            "empty_file",
+            "The input file contains no requests".to_string(),
+            None,
+            None,
+        )]));
+    }
+    if parsed.len() > MAX_REQUESTS_PER_BATCH {
+        return Err(fail(vec![validation_error(
+            "too_many_requests",
+            format!(
+                "A batch may contain at most {} requests, got {}",
+                MAX_REQUESTS_PER_BATCH,
+                parsed.len()
+            ),
+            None,
+            None,
+        )]));
+    }
+    Ok(parsed)
+}
+
+/// 将校验通过的行转换为待执行的检查点记录
+pub fn to_requests(batch_id: &str, lines: Vec<ParsedLine>) -> Vec<BatchRequest> {
+    lines
+        .into_iter()
+        .map(|l| BatchRequest {
+            batch_id: batch_id.to_string(),
+            line: l.line,
+            request_id: new_request_id(),
+            custom_id: l.custom_id,
+            body: l.body.to_string(),
+            attempts: 0,
+            api_key: None,
+        })
+        .collect()
+}
+
+/// 响应体转换为 JSON (非 JSON 的错误文本包装为 OpenAI 错误对象)
+pub fn response_body(status: u16, bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes).unwrap_or_else(|_| {
+        json!({
+            "error": {
+                "message": String::from_utf8_lossy(bytes),
+                "type": if status >= 500 { "server_error" } else { "invalid_request_error" },
+                "code": status,
+            }
+        })
+    })
+}
+
+/// 收到上游响应的一行 (2xx 写入输出文件，其余写入错误文件)
+pub fn output_line(req: &BatchRequest, status: u16, body: Value) -> String {
+    json!({
+        "id": req.request_id,
+        "custom_id": req.custom_id,
+        "response": {
+            "status_code": status,
+            "request_id": format!("req_{}", uuid::Uuid::new_v4().simple()),
+            "body": body,
+        },
+        "error": null,
+    })
+    .to_string()
+}
+
+/// 未得到响应的一行 (过期、网络错误等)
+pub fn error_line(req: &BatchRequest, code: &str, message: &str) -> String {
+    json!({
+        "id": req.request_id,
+        "custom_id": req.custom_id,
+        "response": null,
+        "error": { "code": code, "message": message },
+    })
+    .to_string()
+}
+
+/// 单次执行结果的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    Succeeded,
+    /// 账号池限流 / 无可用账号：放回队列并暂停领取，不计入尝试次数
+    Throttled,
+    /// 可重试的服务端错误
+    Retry,
+    /// 终态失败 (含预算超额：预算不会在完成窗口内自行恢复)
+    Failed,
+}
+
+pub fn classify(status: u16, body: &[u8]) -> Disposition {
+    match status {
+        200..=299 => Disposition::Succeeded,
+        429 if is_budget_exceeded(body) => Disposition::Failed,
+        429 | 503 => Disposition::Throttled,
+        408 | 500..=599 => Disposition::Retry,
+        _ => Disposition::Failed,
+    }
+}
+
+/// 预算中间件返回的 429 (`"code": "budget_exceeded"`)
+fn is_budget_exceeded(body: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|v| v.pointer("/error/code").cloned())
+        .is_some_and(|code| code == "budget_exceeded")
+}
+
+/// 第 n 次尝试失败后的重试间隔 (秒)，指数退避，上限 5 分钟
+pub fn retry_delay_secs(attempts: u32) -> i64 {
+    2i64.saturating_pow(attempts.min(16)).min(300)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(custom_id: &str, body: Value) -> String {
+        json!({ "custom_id": custom_id, "method": "POST", "url": SUPPORTED_ENDPOINT, "body": body })
+            .to_string()
+    }
+
+    #[test]
+    fn test_parse_input() {
+        let body =
+            json!({ "model": "gemini-3-flash", "messages": [{ "role": "user", "content": "hi" }] });
+        let content = format!(
+            "{}\n\n{}\r\n",
+            line("a", body.clone()),
+            line("b", body.clone())
+        );
+        let parsed = parse_input(content.as_bytes(), SUPPORTED_ENDPOINT).unwrap();
+        assert_eq!(
+            parsed
+                .iter()
+                .map(|l| (l.line, l.custom_id.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(1, "a"), (3, "b")]
+        );
+        assert_eq!(parsed[0].body, body);
+
+        let content = [
+            line("a", body.clone()),
+            "not json".to_string(),
+            line("a", body.clone()),
+            json!({ "custom_id": "c", "method": "POST", "url": "/v1/embeddings", "body": body })
+                .to_string(),
+            line("d", json!({ "messages": [] })),
+            line("e", json!({ "model": "gemini-3-flash", "stream": true })),
+        ]
+        .join("\n");
+        let errors = parse_input(content.as_bytes(), SUPPORTED_ENDPOINT).unwrap_err();
+        let summary: Vec<_> = errors["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| (e["line"].as_i64().unwrap(), e["code"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (2, "invalid_json_line"),
+                (3, "duplicate_custom_id"),
+                (4, "invalid_url"),
+                (5, "missing_required_parameter"),
+                (6, "invalid_request"),
+            ]
+        );
+
+        let errors = parse_input(b"\n \n", SUPPORTED_ENDPOINT).unwrap_err();
+        assert_eq!(errors["data"][0]["code"], "empty_file");
+    }
+
+    #[test]
+    fn test_result_lines() {
+        let req = BatchRequest {
+            batch_id: "batch_1".to_string(),
+            line: 1,
+            request_id: "batch_req_1".to_string(),
+            custom_id: "task-1".to_string(),
+            body: "{}".to_string(),
+            attempts: 1,
+            api_key: None,
+        };
+        let ok: Value =
+            serde_json::from_str(&output_line(&req, 200, json!({ "id": "chatcmpl-1" }))).unwrap();
+        assert_eq!(ok["custom_id"], "task-1");
+        assert_eq!(ok["response"]["status_code"], 200);
+        assert_eq!(ok["response"]["body"]["id"], "chatcmpl-1");
+        assert!(ok["error"].is_null());
+
+        let body = response_body(400, b"Invalid request: missing messages");
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+        assert_eq!(response_body(200, b"{\"ok\":true}"), json!({ "ok": true }));
+
+        let err: Value =
+            serde_json::from_str(&error_line(&req, "batch_expired", "expired")).unwrap();
+        assert!(err["response"].is_null());
+        assert_eq!(err["error"]["code"], "batch_expired");
+    }
+
+    #[test]
+    fn test_classify_and_ids() {
+        assert_eq!(classify(200, b"{}"), Disposition::Succeeded);
+        assert_eq!(classify(429, b"All accounts exhausted"), Disposition::Throttled);
+        assert_eq!(classify(503, b""), Disposition::Throttled);
+        assert_eq!(classify(502, b""), Disposition::Retry);
+        assert_eq!(classify(400, b"{}"), Disposition::Failed);
+        let budget = json!({
+            "error": { "message": "Budget exceeded", "type": "insufficient_quota", "code": "budget_exceeded" }
+        });
+        assert_eq!(
+            classify(429, budget.to_string().as_bytes()),
+            Disposition::Failed
+        );
+        assert_eq!(retry_delay_secs(1), 2);
+        assert_eq!(retry_delay_secs(40), 300);
+
+        assert!(is_valid_file_id(&new_file_id()));
+        assert!(!is_valid_file_id("file-../../etc/passwd"));
+        assert!(!is_valid_file_id("batch_0123"));
+    }
+}
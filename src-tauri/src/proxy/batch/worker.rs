@@ -0,0 +1,316 @@
+// 批处理后台 worker
+// 随反代服务启动：校验新任务、按配置的并发领取待执行行并经内部路由执行，处理取消 / 过期并生成结果文件。
+// 账号池无空闲账号 (限流、配额保护、并发已满) 时暂停领取；429/503 放回队列且不计入尝试次数，
+// 预算超额的 429 直接记为失败。
+
+use axum::body::Body;
+use axum::http::{header, Method, Request};
+use axum::Router;
+use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+use tower::Service;
+
+use super::Disposition;
+use crate::modules::batch_db::{self, Batch, BatchRequest};
+use crate::proxy::server::AppState;
+
+/// 单行响应体读取上限
+const MAX_RESPONSE_SIZE: usize = 100 * 1024 * 1024;
+/// 无事件时的轮询间隔
+const IDLE_INTERVAL: Duration = Duration::from_secs(2);
+/// 账号池限流时暂停领取的最长时间
+const MAX_THROTTLE_BACKOFF_SECS: u64 = 60;
+
+static WAKE: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// 唤醒 worker (新建 / 取消任务、配置变更后立即处理)
+pub fn wake() {
+    WAKE.notify_one();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Done,
+    Throttled,
+}
+
+async fn blocking<T, F>(f: F) -> Result<T, String>
+where
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// 启动 worker，返回的句柄在服务停止时 abort (执行中的行重启后重新执行)
+pub fn start(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(run(state))
+}
+
+async fn run(state: AppState) {
+    match blocking(batch_db::requeue_interrupted).await {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("[Batch] Resuming {} interrupted request(s)", n),
+        Err(e) => tracing::warn!("[Batch] Failed to requeue interrupted requests: {}", e),
+    }
+
+    let router = crate::proxy::server::build_router(state.clone());
+    let mut tasks: JoinSet<Outcome> = JoinSet::new();
+    let mut paused_until: Option<Instant> = None;
+    let mut backoff_secs = 1;
+
+    loop {
+        if let Err(e) = blocking(maintain).await {
+            tracing::warn!("[Batch] Maintenance failed: {}", e);
+        }
+
+        let config = super::config();
+        let free = config.concurrency.max(1).saturating_sub(tasks.len());
+        let paused = paused_until.is_some_and(|t| Instant::now() < t);
+        if config.enabled && free > 0 && !paused && state.token_manager.has_free_account().await {
+            match blocking(move || batch_db::claim_requests(free, super::now_secs())).await {
+                Ok(claimed) => {
+                    for req in claimed {
+                        tasks.spawn(execute(router.clone(), req, config.max_attempts));
+                    }
+                }
+                Err(e) => tracing::warn!("[Batch] Failed to claim requests: {}", e),
+            }
+        }
+
+        tokio::select! {
+            Some(result) = tasks.join_next(), if !tasks.is_empty() => match result {
+                Ok(Outcome::Throttled) => {
+                    tracing::debug!("[Batch] Pool throttled, pausing for {}s", backoff_secs);
+                    paused_until = Some(Instant::now() + Duration::from_secs(backoff_secs));
+                    backoff_secs = (backoff_secs * 2).min(MAX_THROTTLE_BACKOFF_SECS);
+                }
+                Ok(Outcome::Done) => backoff_secs = 1,
+                Err(e) => tracing::warn!("[Batch] Request task failed: {}", e),
+            },
+            _ = WAKE.notified() => {}
+            _ = tokio::time::sleep(IDLE_INTERVAL) => {}
+        }
+    }
+}
+
+/// 经内部路由执行一行请求
+async fn call(mut router: Router, req: &BatchRequest) -> Result<(u16, bytes::Bytes), String> {
+    let mut builder = Request::builder()
+        .method(Method::POST)
+        .uri(super::SUPPORTED_ENDPOINT)
+        .header(header::CONTENT_TYPE, "application/json");
+    // 以创建者的 API Key 执行，预算与按 Key 排队对批处理同样生效
+    if let Some(key) = &req.api_key {
+        builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", key));
+    }
+    let request = builder
+        .body(Body::from(req.body.clone()))
+        .map_err(|e| e.to_string())?;
+    futures::future::poll_fn(|cx| Service::<Request<Body>>::poll_ready(&mut router, cx))
+        .await
+        .map_err(|e| e.to_string())?;
+    let response = router.call(request).await.map_err(|e| e.to_string())?;
+    let status = response.status().as_u16();
+    let bytes = axum::body::to_bytes(response.into_body(), MAX_RESPONSE_SIZE)
+        .await
+        .map_err(|e| format!("Failed to read response: {}", e))?;
+    Ok((status, bytes))
+}
+
+async fn execute(router: Router, req: BatchRequest, max_attempts: u32) -> Outcome {
+    let result = call(router, &req).await;
+    let can_retry = req.attempts < max_attempts.max(1);
+    let now = super::now_secs();
+
+    let (outcome, update) = match result {
+        Ok((status, bytes)) => match super::classify(status, &bytes) {
+            Disposition::Succeeded => (
+                Outcome::Done,
+                Ok((
+                    true,
+                    super::output_line(&req, status, super::response_body(status, &bytes)),
+                )),
+            ),
+            Disposition::Throttled => (Outcome::Throttled, Err((now + 1, true))),
+            Disposition::Retry if can_retry => (
+                Outcome::Done,
+                Err((now + super::retry_delay_secs(req.attempts), false)),
+            ),
+            Disposition::Retry | Disposition::Failed => (
+                Outcome::Done,
+                Ok((
+                    false,
+                    super::output_line(&req, status, super::response_body(status, &bytes)),
+                )),
+            ),
+        },
+        Err(e) if can_retry => {
+            tracing::debug!(
+                "[Batch] {} line {} failed, retrying: {}",
+                req.batch_id,
+                req.line,
+                e
+            );
+            (
+                Outcome::Done,
+                Err((now + super::retry_delay_secs(req.attempts), false)),
+            )
+        }
+        Err(e) => (
+            Outcome::Done,
+            Ok((false, super::error_line(&req, "request_failed", &e))),
+        ),
+    };
+
+    let (batch_id, line) = (req.batch_id.clone(), req.line);
+    let saved = match update {
+        Ok((succeeded, result)) => {
+            blocking(move || batch_db::complete_request(&batch_id, line, succeeded, &result)).await
+        }
+        Err((next_attempt_at, refund)) => {
+            blocking(move || batch_db::retry_request(&batch_id, line, next_attempt_at, refund))
+                .await
+        }
+    };
+    if let Err(e) = saved {
+        tracing::warn!(
+            "[Batch] Failed to checkpoint {} line {}: {}",
+            req.batch_id,
+            req.line,
+            e
+        );
+    }
+    outcome
+}
+
+/// 推进任务状态：校验、取消、过期与结束
+fn maintain() -> Result<(), String> {
+    let now = super::now_secs();
+    for batch in
+        batch_db::batches_with_status(&["validating", "in_progress", "cancelling", "finalizing"])?
+    {
+        // 单个任务出错不影响其他任务
+        if let Err(e) = maintain_batch(&batch, now) {
+            tracing::warn!("[Batch] Failed to advance {}: {}", batch.id, e);
+        }
+    }
+    Ok(())
+}
+
+fn maintain_batch(batch: &Batch, now: i64) -> Result<(), String> {
+    match batch.status.as_str() {
+        "validating" => validate(batch, now),
+        "in_progress" => {
+            if batch.expires_at <= now {
+                expire_pending(batch, now)?;
+            }
+            if batch_db::unfinished_count(&batch.id)? > 0 {
+                return Ok(());
+            }
+            batch_db::set_finalizing(&batch.id, now)?;
+            let status = if batch.expires_at <= now {
+                "expired"
+            } else {
+                "completed"
+            };
+            finalize(batch, status, now)
+        }
+        "cancelling" => {
+            batch_db::cancel_pending(&batch.id)?;
+            if batch_db::unfinished_count(&batch.id)? > 0 {
+                return Ok(());
+            }
+            finalize(batch, "cancelled", now)
+        }
+        // 生成结果文件时中断，重新生成
+        _ => {
+            let status = if batch.expired_at.is_some() {
+                "expired"
+            } else {
+                "completed"
+            };
+            finalize(batch, status, now)
+        }
+    }
+}
+
+fn validate(batch: &Batch, now: i64) -> Result<(), String> {
+    let content = match batch_db::get_file(&batch.input_file_id)? {
+        Some(_) => super::read_file(&batch.input_file_id),
+        None => Err(format!("Input file {} not found", batch.input_file_id)),
+    };
+    let parsed = content
+        .map_err(|e| {
+            serde_json::json!({
+                "object": "list",
+                "data": [{ "code": "file_not_found", "message": e, "param": "input_file_id", "line": null }],
+            })
+        })
+        .and_then(|content| super::parse_input(&content, &batch.endpoint));
+
+    match parsed {
+        Ok(lines) => {
+            let requests = super::to_requests(&batch.id, lines);
+            if batch_db::start_batch(&batch.id, &requests, now)? {
+                tracing::info!(
+                    "[Batch] {} started with {} request(s)",
+                    batch.id,
+                    requests.len()
+                );
+            }
+        }
+        Err(errors) => {
+            tracing::info!("[Batch] {} failed validation", batch.id);
+            batch_db::fail_batch(&batch.id, &errors, now)?;
+        }
+    }
+    Ok(())
+}
+
+/// 完成窗口已过：未执行的行以 batch_expired 写入错误文件
+fn expire_pending(batch: &Batch, now: i64) -> Result<(), String> {
+    batch_db::mark_expired(&batch.id, now)?;
+    for req in batch_db::pending_requests(&batch.id)? {
+        let line = super::error_line(
+            &req,
+            "batch_expired",
+            "This request could not be executed before the completion window expired.",
+        );
+        batch_db::complete_request(&batch.id, req.line, false, &line)?;
+    }
+    Ok(())
+}
+
+fn jsonl(lines: Vec<String>) -> Vec<u8> {
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content.into_bytes()
+}
+
+/// 生成输出 / 错误文件并进入终态
+fn finalize(batch: &Batch, status: &str, now: i64) -> Result<(), String> {
+    let save = |succeeded: bool, suffix: &str| -> Result<Option<String>, String> {
+        let lines = batch_db::request_results(&batch.id, succeeded)?;
+        if lines.is_empty() {
+            return Ok(None);
+        }
+        let filename = format!("{}_{}.jsonl", batch.id, suffix);
+        super::save_file("batch_output", &filename, &jsonl(lines)).map(|f| Some(f.id))
+    };
+    let output_file_id = save(true, "output")?;
+    let error_file_id = save(false, "error")?;
+    batch_db::finish_batch(
+        &batch.id,
+        status,
+        output_file_id.as_deref(),
+        error_file_id.as_deref(),
+        now,
+    )?;
+    tracing::info!("[Batch] {} {}", batch.id, status);
+    Ok(())
+}
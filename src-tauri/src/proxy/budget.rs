@@ -0,0 +1,529 @@
+// 费用与预算 (Cost & Budget)
+// 按模型价格表计算每次请求的费用，写入 token_usage 与小时聚合表；
+// 预算规则按 全局 / API Key / 账号分组 统计日、月累计费用，超出后告警 (日志 + 事件) 或直接拦截。
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// 单个模型的价格 (美元 / 百万 token)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// 命中缓存的输入 token 价格 (未设置时按 `input` 计价)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_input: Option<f64>,
+    /// 思考 token 价格 (未设置时按 `output` 计价)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<f64>,
+}
+
+/// 一次请求的 token 用量
+///
+/// `input` 包含 `cached_input`，`output` 不包含 `thinking`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsageTokens {
+    pub input: u32,
+    pub output: u32,
+    pub cached_input: u32,
+    pub thinking: u32,
+}
+
+impl ModelPrice {
+    /// 计算费用 (美元)
+    pub fn cost(&self, usage: &UsageTokens) -> f64 {
+        let cached = usage.cached_input.min(usage.input);
+        let uncached = usage.input - cached;
+        (uncached as f64 * self.input
+            + cached as f64 * self.cached_input.unwrap_or(self.input)
+            + usage.output as f64 * self.output
+            + usage.thinking as f64 * self.thinking.unwrap_or(self.output))
+            / 1_000_000.0
+    }
+}
+
+/// 预算统计范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetScope {
+    /// 所有请求
+    Global,
+    /// 指定 API Key (`target` 为 Key 明文，或统计中显示的 `key-xxxx` 标识)
+    ApiKey,
+    /// 指定账号分组 (`target` 为 `account_groups` 中的分组名)
+    AccountGroup,
+}
+
+/// 预算周期 (UTC 自然日 / 自然月)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Daily,
+    Monthly,
+}
+
+/// 超出预算后的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetAction {
+    /// 仅告警 (每个周期一次)
+    Warn,
+    /// 拦截：全局 / API Key 规则直接拒绝请求，账号分组规则使该分组账号不再参与调度
+    Block,
+}
+
+/// 预算规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetRule {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub scope: BudgetScope,
+    #[serde(default)]
+    pub target: String,
+    pub period: BudgetPeriod,
+    /// 预算上限 (美元)
+    pub limit_usd: f64,
+    pub action: BudgetAction,
+}
+
+/// 费用与预算配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostConfig {
+    /// 模型价格表 (key 为模型名，支持 `*` 通配符，精确匹配优先)
+    pub prices: HashMap<String, ModelPrice>,
+    /// 账号分组 (分组名 -> 账号邮箱列表)
+    pub account_groups: HashMap<String, Vec<String>>,
+    pub budgets: Vec<BudgetRule>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl CostConfig {
+    /// 查找模型价格：精确匹配优先，其次为最具体 (非通配字符最多) 的通配符规则
+    pub fn price_for(&self, model: &str) -> Option<&ModelPrice> {
+        if let Some(price) = self.prices.get(model) {
+            return Some(price);
+        }
+        self.prices
+            .iter()
+            .filter(|(pattern, _)| {
+                pattern.contains('*') && crate::proxy::common::model_mapping::wildcard_match(pattern, model)
+            })
+            .max_by_key(|(pattern, _)| pattern.chars().count() - pattern.matches('*').count())
+            .map(|(_, price)| price)
+    }
+
+    /// 计算请求费用：优先按实际路由后的模型计价，未配置价格时返回 None
+    pub fn cost_of(&self, mapped_model: Option<&str>, model: Option<&str>, usage: &UsageTokens) -> Option<f64> {
+        mapped_model
+            .and_then(|m| self.price_for(m))
+            .or_else(|| model.and_then(|m| self.price_for(m)))
+            .map(|price| price.cost(usage))
+    }
+}
+
+/// 预算规则的当前状态
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetStatus {
+    pub rule: String,
+    pub scope: BudgetScope,
+    pub target: String,
+    pub period: BudgetPeriod,
+    pub action: BudgetAction,
+    pub limit_usd: f64,
+    pub spent_usd: f64,
+    /// 当前周期，如 "2026-01-15" / "2026-01"
+    pub period_key: String,
+    pub exceeded: bool,
+}
+
+impl BudgetStatus {
+    pub fn message(&self) -> String {
+        let scope = match self.scope {
+            BudgetScope::Global => "global".to_string(),
+            BudgetScope::ApiKey => "API key".to_string(),
+            BudgetScope::AccountGroup => format!("account group '{}'", self.target),
+        };
+        let period = match self.period {
+            BudgetPeriod::Daily => "daily",
+            BudgetPeriod::Monthly => "monthly",
+        };
+        format!(
+            "Budget '{}' exceeded: {} {} spend ${:.2} of ${:.2}",
+            self.rule, scope, period, self.spent_usd, self.limit_usd
+        )
+    }
+}
+
+/// 累计费用的查询条件
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpendFilter {
+    All,
+    ApiKey(String),
+    Accounts(Vec<String>),
+}
+
+struct RuleState {
+    rule: BudgetRule,
+    /// API Key 规则对应的哈希标识
+    key_id: Option<String>,
+    /// 账号分组规则包含的账号
+    members: HashSet<String>,
+    period_key: String,
+    spent: f64,
+}
+
+impl RuleState {
+    fn matches(&self, account: &str, api_key: Option<&str>) -> bool {
+        match self.rule.scope {
+            BudgetScope::Global => true,
+            BudgetScope::ApiKey => api_key.is_some() && api_key == self.key_id.as_deref(),
+            BudgetScope::AccountGroup => self.members.contains(account),
+        }
+    }
+
+    fn filter(&self) -> SpendFilter {
+        match self.rule.scope {
+            BudgetScope::Global => SpendFilter::All,
+            BudgetScope::ApiKey => SpendFilter::ApiKey(self.key_id.clone().unwrap_or_default()),
+            BudgetScope::AccountGroup => SpendFilter::Accounts(self.members.iter().cloned().collect()),
+        }
+    }
+
+    fn exceeded(&self) -> bool {
+        self.spent >= self.rule.limit_usd
+    }
+
+    fn status(&self) -> BudgetStatus {
+        BudgetStatus {
+            rule: self.rule.name.clone(),
+            scope: self.rule.scope,
+            target: self.rule.target.clone(),
+            period: self.rule.period,
+            action: self.rule.action,
+            limit_usd: self.rule.limit_usd,
+            spent_usd: self.spent,
+            period_key: self.period_key.clone(),
+            exceeded: self.exceeded(),
+        }
+    }
+}
+
+/// 预算账本：各规则在当前周期内的累计费用
+pub struct BudgetBook {
+    config: Arc<CostConfig>,
+    rules: Vec<RuleState>,
+    /// 已告警的 (规则名, 周期)，每个周期只告警一次
+    alerted: HashSet<(String, String)>,
+}
+
+impl Default for BudgetBook {
+    fn default() -> Self {
+        Self {
+            config: Arc::new(CostConfig::default()),
+            rules: Vec::new(),
+            alerted: HashSet::new(),
+        }
+    }
+}
+
+fn period_key(period: BudgetPeriod, now: chrono::DateTime<chrono::Utc>) -> String {
+    match period {
+        BudgetPeriod::Daily => now.format("%Y-%m-%d").to_string(),
+        BudgetPeriod::Monthly => now.format("%Y-%m").to_string(),
+    }
+}
+
+/// 周期起始时间 (unix 秒)
+fn period_start(period: BudgetPeriod, now: chrono::DateTime<chrono::Utc>) -> i64 {
+    use chrono::{Datelike, TimeZone};
+    let day = now.date_naive();
+    let start = match period {
+        BudgetPeriod::Daily => day,
+        BudgetPeriod::Monthly => day.with_day(1).unwrap_or(day),
+    };
+    chrono::Utc
+        .from_utc_datetime(&start.and_hms_opt(0, 0, 0).unwrap_or_default())
+        .timestamp()
+}
+
+impl BudgetBook {
+    /// 按新配置重建账本，`load_spend(filter, since)` 返回周期内已产生的费用
+    pub fn reset<F>(&mut self, config: Arc<CostConfig>, now: chrono::DateTime<chrono::Utc>, mut load_spend: F) -> Vec<BudgetStatus>
+    where
+        F: FnMut(&SpendFilter, i64) -> Result<f64, String>,
+    {
+        let mut rules = Vec::new();
+        for rule in config.budgets.iter().filter(|r| r.enabled) {
+            let key_id = (rule.scope == BudgetScope::ApiKey).then(|| {
+                let target = rule.target.trim();
+                if target.starts_with("key-") || target == crate::proxy::request_queue::ANONYMOUS_KEY {
+                    target.to_string()
+                } else {
+                    crate::proxy::request_queue::api_key_id(target)
+                }
+            });
+            let members = if rule.scope == BudgetScope::AccountGroup {
+                match config.account_groups.get(&rule.target) {
+                    Some(emails) => emails.iter().cloned().collect(),
+                    None => {
+                        tracing::warn!("[Budget] Rule '{}' references unknown account group '{}'", rule.name, rule.target);
+                        HashSet::new()
+                    }
+                }
+            } else {
+                HashSet::new()
+            };
+            let mut state = RuleState {
+                rule: rule.clone(),
+                key_id,
+                members,
+                period_key: period_key(rule.period, now),
+                spent: 0.0,
+            };
+            state.spent = match load_spend(&state.filter(), period_start(rule.period, now)) {
+                Ok(spent) => spent,
+                Err(e) => {
+                    tracing::warn!("[Budget] Failed to load spend for rule '{}': {}", rule.name, e);
+                    0.0
+                }
+            };
+            rules.push(state);
+        }
+        self.config = config;
+        self.rules = rules;
+        self.collect_alerts()
+    }
+
+    /// 是否有规则的周期已切换 (需要重建账本)
+    pub fn is_stale(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.rules.iter().any(|r| r.period_key != period_key(r.rule.period, now))
+    }
+
+    /// 计入一次请求的费用，返回本次新触发的超额规则
+    pub fn record(&mut self, account: &str, api_key: Option<&str>, cost: f64) -> Vec<BudgetStatus> {
+        for rule in self.rules.iter_mut().filter(|r| r.matches(account, api_key)) {
+            rule.spent += cost;
+        }
+        self.collect_alerts()
+    }
+
+    fn collect_alerts(&mut self) -> Vec<BudgetStatus> {
+        let mut alerts = Vec::new();
+        for rule in self.rules.iter().filter(|r| r.exceeded()) {
+            if self.alerted.insert((rule.rule.name.clone(), rule.period_key.clone())) {
+                alerts.push(rule.status());
+            }
+        }
+        alerts
+    }
+
+    /// 当前周期内已超出的拦截规则 (全局或该 API Key)
+    pub fn blocking_rule(&self, api_key: Option<&str>, now: chrono::DateTime<chrono::Utc>) -> Option<BudgetStatus> {
+        self.rules
+            .iter()
+            .filter(|r| r.rule.action == BudgetAction::Block && r.exceeded())
+            .filter(|r| r.period_key == period_key(r.rule.period, now))
+            .find(|r| match r.rule.scope {
+                BudgetScope::Global => true,
+                BudgetScope::ApiKey => api_key.is_some() && api_key == r.key_id.as_deref(),
+                BudgetScope::AccountGroup => false,
+            })
+            .map(|r| r.status())
+    }
+
+    /// 因分组预算超额而暂停调度的账号 (email -> 原因)
+    pub fn blocked_accounts(&self, now: chrono::DateTime<chrono::Utc>) -> HashMap<String, String> {
+        let mut blocked = HashMap::new();
+        for rule in self.rules.iter().filter(|r| {
+            r.rule.scope == BudgetScope::AccountGroup
+                && r.rule.action == BudgetAction::Block
+                && r.exceeded()
+                && r.period_key == period_key(r.rule.period, now)
+        }) {
+            let message = rule.status().message();
+            for email in &rule.members {
+                blocked.entry(email.clone()).or_insert_with(|| message.clone());
+            }
+        }
+        blocked
+    }
+
+    pub fn statuses(&self) -> Vec<BudgetStatus> {
+        self.rules.iter().map(|r| r.status()).collect()
+    }
+}
+
+static BOOK: Lazy<RwLock<BudgetBook>> = Lazy::new(|| RwLock::new(BudgetBook::default()));
+
+/// 串行化 "写入用量 + 计入账本" 与 "查库重建账本"，避免重建期间计入的费用被覆盖或重复统计
+static LEDGER: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// 当前生效的费用配置
+pub fn config() -> Arc<CostConfig> {
+    BOOK.read().unwrap().config.clone()
+}
+
+/// 应用费用与预算配置，并从用量数据库重新统计各规则的当期费用
+pub fn apply_config(config: &CostConfig) {
+    let config = Arc::new(config.clone());
+    BOOK.write().unwrap().config = config.clone();
+    // 统计需要查询数据库，放到阻塞线程执行
+    tokio::task::spawn_blocking(move || {
+        let _ledger = LEDGER.lock().unwrap_or_else(|e| e.into_inner());
+        rebuild(config)
+    });
+}
+
+/// 从数据库重建账本 (调用方需持有 `LEDGER`)
+fn rebuild(config: Arc<CostConfig>) -> Vec<BudgetStatus> {
+    let now = chrono::Utc::now();
+    let mut book = BudgetBook::default();
+    let alerts = book.reset(config, now, |filter, since| crate::modules::token_stats::get_cost_since(since, filter));
+    let mut current = BOOK.write().unwrap();
+    book.alerted = std::mem::take(&mut current.alerted);
+    let alerts: Vec<BudgetStatus> = alerts
+        .into_iter()
+        .filter(|a| book.alerted.insert((a.rule.clone(), a.period_key.clone())))
+        .collect();
+    *current = book;
+    for alert in &alerts {
+        tracing::warn!("[Budget] {}", alert.message());
+    }
+    alerts
+}
+
+/// 写入一次请求的用量并计入费用，返回新触发的超额规则
+///
+/// 会访问数据库，请在阻塞线程中调用
+pub fn record_usage(record: &crate::modules::token_stats::UsageRecord) -> Result<Vec<BudgetStatus>, String> {
+    let _ledger = LEDGER.lock().unwrap_or_else(|e| e.into_inner());
+    crate::modules::token_stats::record_usage(record)?;
+    if record.cost <= 0.0 {
+        return Ok(Vec::new());
+    }
+    let now = chrono::Utc::now();
+    if BOOK.read().unwrap().is_stale(now) {
+        // 新周期直接从数据库重建，本次费用已包含在内
+        return Ok(rebuild(config()));
+    }
+    let alerts = BOOK.write().unwrap().record(&record.account_email, record.api_key.as_deref(), record.cost);
+    for alert in &alerts {
+        tracing::warn!("[Budget] {}", alert.message());
+    }
+    Ok(alerts)
+}
+
+/// 检查请求是否被全局或 API Key 预算拦截
+pub fn check_request(api_key: Option<&str>) -> Option<BudgetStatus> {
+    BOOK.read().unwrap().blocking_rule(api_key, chrono::Utc::now())
+}
+
+/// 因分组预算超额而暂停调度的账号
+pub fn blocked_accounts() -> HashMap<String, String> {
+    BOOK.read().unwrap().blocked_accounts(chrono::Utc::now())
+}
+
+/// 各预算规则的当前状态
+pub fn statuses() -> Vec<BudgetStatus> {
+    BOOK.read().unwrap().statuses()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn rule(name: &str, scope: BudgetScope, target: &str, action: BudgetAction) -> BudgetRule {
+        BudgetRule {
+            name: name.to_string(),
+            enabled: true,
+            scope,
+            target: target.to_string(),
+            period: BudgetPeriod::Daily,
+            limit_usd: 1.0,
+            action,
+        }
+    }
+
+    #[test]
+    fn test_price_lookup_and_cost() {
+        let mut config = CostConfig::default();
+        config.prices.insert(
+            "gemini-*".to_string(),
+            ModelPrice { input: 1.0, output: 2.0, ..Default::default() },
+        );
+        config.prices.insert(
+            "gemini-2.5-pro*".to_string(),
+            ModelPrice { input: 1.25, output: 10.0, cached_input: Some(0.125), thinking: None },
+        );
+        config.prices.insert(
+            "gemini-2.5-pro".to_string(),
+            ModelPrice { input: 2.0, output: 20.0, ..Default::default() },
+        );
+
+        assert_eq!(config.price_for("gemini-2.5-pro").unwrap().input, 2.0);
+        assert_eq!(config.price_for("gemini-2.5-pro-preview").unwrap().input, 1.25);
+        assert_eq!(config.price_for("gemini-2.5-flash").unwrap().input, 1.0);
+        assert!(config.price_for("claude-sonnet-4-5").is_none());
+
+        let usage = UsageTokens { input: 1_000_000, output: 100_000, cached_input: 400_000, thinking: 200_000 };
+        let cost = config.cost_of(Some("gemini-2.5-pro-preview"), Some("claude-sonnet-4-5"), &usage).unwrap();
+        // 0.6M * 1.25 + 0.4M * 0.125 + 0.1M * 10 + 0.2M * 10 (思考按输出计价)
+        assert!((cost - (0.75 + 0.05 + 1.0 + 2.0)).abs() < 1e-9);
+        assert!(config.cost_of(None, Some("claude-sonnet-4-5"), &usage).is_none());
+    }
+
+    #[test]
+    fn test_budget_rules_warn_and_block() {
+        let key = "sk-test";
+        let mut config = CostConfig::default();
+        config.account_groups.insert("team-a".to_string(), vec!["a@example.com".to_string()]);
+        config.budgets = vec![
+            rule("global-warn", BudgetScope::Global, "", BudgetAction::Warn),
+            rule("key-block", BudgetScope::ApiKey, key, BudgetAction::Block),
+            rule("team-block", BudgetScope::AccountGroup, "team-a", BudgetAction::Block),
+        ];
+        let now = chrono::Utc.with_ymd_and_hms(2026, 1, 15, 12, 0, 0).unwrap();
+        let key_id = crate::proxy::request_queue::api_key_id(key);
+
+        let mut book = BudgetBook::default();
+        let mut loaded = Vec::new();
+        let alerts = book.reset(Arc::new(config), now, |filter, since| {
+            loaded.push((filter.clone(), since));
+            Ok(0.5)
+        });
+        assert!(alerts.is_empty());
+        assert_eq!(loaded[0], (SpendFilter::All, 1768435200));
+        assert_eq!(loaded[1].0, SpendFilter::ApiKey(key_id.clone()));
+        assert_eq!(loaded[2].0, SpendFilter::Accounts(vec!["a@example.com".to_string()]));
+
+        // 其他 Key、分组外账号的费用只计入全局规则
+        let alerts = book.record("b@example.com", Some("key-other"), 0.6);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "global-warn");
+        assert!(book.blocking_rule(Some(&key_id), now).is_none());
+        assert!(book.blocked_accounts(now).is_empty());
+
+        let alerts = book.record("a@example.com", Some(&key_id), 0.5);
+        let names: Vec<_> = alerts.iter().map(|a| a.rule.as_str()).collect();
+        assert_eq!(names, vec!["key-block", "team-block"]);
+        assert_eq!(book.blocking_rule(Some(&key_id), now).unwrap().rule, "key-block");
+        assert!(book.blocking_rule(Some("key-other"), now).is_none());
+        assert!(book.blocked_accounts(now).contains_key("a@example.com"));
+
+        // 同一周期内不重复告警，新周期拦截自动失效
+        assert!(book.record("a@example.com", Some(&key_id), 0.1).is_empty());
+        let tomorrow = now + chrono::Duration::days(1);
+        assert!(book.is_stale(tomorrow));
+        assert!(book.blocking_rule(Some(&key_id), tomorrow).is_none());
+        assert!(book.blocked_accounts(tomorrow).is_empty());
+    }
+}
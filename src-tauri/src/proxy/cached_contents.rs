@@ -0,0 +1,601 @@
+// Gemini 上下文缓存 (cachedContents) 本地模拟
+// v1internal 不支持显式缓存，这里把 cachedContents 保存在数据目录的 cached_contents/ 下，
+// generateContent 引用 cachedContent 时在 wrap_request 之前展开为完整请求。
+// 展开后的前缀在多次请求间保持一致，配合粘性会话可命中上游的隐式缓存。
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// 资源名前缀
+pub const NAME_PREFIX: &str = "cachedContents/";
+/// 未指定 ttl / expireTime 时的默认有效期 (与 Google 一致为 1 小时)
+const DEFAULT_TTL_MS: i64 = 3600 * 1000;
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+/// 图片 / 文件类 part 的估算 token 数
+const MEDIA_PART_TOKENS: u32 = 258;
+
+/// 一条缓存内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedContent {
+    pub name: String,
+    pub model: String,
+    pub display_name: Option<String>,
+    pub contents: Vec<Value>,
+    pub system_instruction: Option<Value>,
+    pub tools: Option<Value>,
+    pub tool_config: Option<Value>,
+    pub create_time: i64,
+    pub update_time: i64,
+    pub expire_time: i64,
+    pub total_token_count: u32,
+    /// 创建者的 API Key 标识 (`key-xxxx`)，只有同一 Key 可以查看、使用与删除
+    #[serde(default)]
+    pub api_key_id: String,
+}
+
+impl CachedContent {
+    /// API 返回的元数据 (与 Google 一致，不回显缓存的内容本身)
+    pub fn to_api_json(&self) -> Value {
+        let mut value = json!({
+            "name": self.name,
+            "model": self.model,
+            "createTime": rfc3339(self.create_time),
+            "updateTime": rfc3339(self.update_time),
+            "expireTime": rfc3339(self.expire_time),
+            "usageMetadata": { "totalTokenCount": self.total_token_count }
+        });
+        if let Some(display_name) = &self.display_name {
+            value["displayName"] = json!(display_name);
+        }
+        value
+    }
+
+    /// 将缓存内容展开到 generateContent 请求体中
+    pub fn apply_to(&self, body: &mut Value, model: &str) -> Result<(), String> {
+        if strip_model_prefix(&self.model) != strip_model_prefix(model) {
+            return Err(format!(
+                "Model used by GenerateContent request ({}) and CachedContent ({}) has to be the same.",
+                model, self.model
+            ));
+        }
+        let obj = body
+            .as_object_mut()
+            .ok_or_else(|| "Request body must be a JSON object".to_string())?;
+        let conflicts = [
+            "systemInstruction",
+            "system_instruction",
+            "tools",
+            "toolConfig",
+            "tool_config",
+        ];
+        if conflicts.iter().any(|key| obj.contains_key(*key)) {
+            return Err(
+                "CachedContent can not be used with GenerateContent request setting system_instruction, tools or tool_config."
+                    .to_string(),
+            );
+        }
+
+        obj.remove("cachedContent");
+        obj.remove("cached_content");
+
+        let mut contents = self.contents.clone();
+        if let Some(request_contents) = obj.get("contents").and_then(|v| v.as_array()) {
+            contents.extend(request_contents.iter().cloned());
+        }
+        obj.insert("contents".to_string(), Value::Array(contents));
+        if let Some(system_instruction) = &self.system_instruction {
+            obj.insert("systemInstruction".to_string(), system_instruction.clone());
+        }
+        if let Some(tools) = &self.tools {
+            obj.insert("tools".to_string(), tools.clone());
+        }
+        if let Some(tool_config) = &self.tool_config {
+            obj.insert("toolConfig".to_string(), tool_config.clone());
+        }
+        Ok(())
+    }
+}
+
+/// 请求中引用的缓存名 (cachedContent 字段)
+pub fn referenced_name(body: &Value) -> Option<String> {
+    body.get("cachedContent")
+        .or_else(|| body.get("cached_content"))
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+}
+
+/// 本地缓存存储: 每条缓存一个 JSON 文件，内存中保留索引
+pub struct CacheStore {
+    dir: PathBuf,
+    entries: RwLock<HashMap<String, Arc<CachedContent>>>,
+}
+
+/// 全局存储 (首次使用时从数据目录加载)
+pub fn store() -> Result<&'static CacheStore, String> {
+    static STORE: OnceCell<CacheStore> = OnceCell::new();
+    STORE.get_or_try_init(|| {
+        let dir = crate::modules::account::get_data_dir()?.join("cached_contents");
+        CacheStore::open(&dir, now_ms())
+    })
+}
+
+impl CacheStore {
+    /// 打开存储目录并加载未过期的缓存
+    pub fn open(dir: &Path, now: i64) -> Result<Self, String> {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create cache dir: {}", e))?;
+        let mut entries = HashMap::new();
+        let read_dir =
+            std::fs::read_dir(dir).map_err(|e| format!("Failed to read cache dir: {}", e))?;
+        for entry in read_dir.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let parsed = std::fs::read(&path)
+                .ok()
+                .and_then(|data| serde_json::from_slice::<CachedContent>(&data).ok());
+            match parsed {
+                Some(content) if content.expire_time > now => {
+                    entries.insert(content.name.clone(), Arc::new(content));
+                }
+                _ => {
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+        tracing::debug!(
+            "[CachedContents] Loaded {} cached content(s)",
+            entries.len()
+        );
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            entries: RwLock::new(entries),
+        })
+    }
+
+    /// 创建缓存 (POST /v1beta/cachedContents)，归属于 `api_key_id`
+    pub fn create(
+        &self,
+        body: &Value,
+        api_key_id: &str,
+        now: i64,
+    ) -> Result<Arc<CachedContent>, String> {
+        let model = body
+            .get("model")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+            .ok_or("Missing 'model' field")?;
+        let contents = match body.get("contents") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(items)) => items.clone(),
+            Some(_) => return Err("'contents' must be an array".to_string()),
+        };
+        let system_instruction = field(body, "systemInstruction", "system_instruction").cloned();
+        if contents.is_empty() && system_instruction.is_none() {
+            return Err("CachedContent requires 'contents' or 'systemInstruction'".to_string());
+        }
+        let tools = body.get("tools").filter(|v| !v.is_null()).cloned();
+        let tool_config = field(body, "toolConfig", "tool_config").cloned();
+        let display_name = field(body, "displayName", "display_name")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+        if display_name
+            .as_ref()
+            .is_some_and(|s| s.chars().count() > 128)
+        {
+            return Err("'displayName' must be at most 128 characters".to_string());
+        }
+        let expire_time = parse_expiration(body, now)?.unwrap_or(now + DEFAULT_TTL_MS);
+
+        let mut content = CachedContent {
+            name: format!("{}{}", NAME_PREFIX, uuid::Uuid::new_v4().simple()),
+            model: format!("models/{}", strip_model_prefix(model)),
+            display_name,
+            contents,
+            system_instruction,
+            tools,
+            tool_config,
+            create_time: now,
+            update_time: now,
+            expire_time,
+            total_token_count: 0,
+            api_key_id: api_key_id.to_string(),
+        };
+        content.total_token_count = estimate_tokens(&content);
+
+        self.prune(now);
+        self.persist(&content)?;
+        let content = Arc::new(content);
+        self.entries
+            .write()
+            .unwrap()
+            .insert(content.name.clone(), content.clone());
+        Ok(content)
+    }
+
+    /// 查询 `api_key_id` 名下未过期的缓存，过期条目顺带删除
+    pub fn get(&self, name: &str, api_key_id: &str, now: i64) -> Option<Arc<CachedContent>> {
+        let name = normalize_name(name)?;
+        let content = self.entries.read().unwrap().get(&name).cloned()?;
+        if content.api_key_id != api_key_id {
+            return None;
+        }
+        if content.expire_time <= now {
+            let _ = self.remove(&name);
+            return None;
+        }
+        Some(content)
+    }
+
+    /// 分页列出 `api_key_id` 名下的缓存 (按创建时间排序，pageToken 为偏移量)
+    pub fn list(
+        &self,
+        api_key_id: &str,
+        page_size: Option<usize>,
+        page_token: Option<&str>,
+        now: i64,
+    ) -> Result<(Vec<Arc<CachedContent>>, Option<String>), String> {
+        self.prune(now);
+        let mut all: Vec<_> = self
+            .entries
+            .read()
+            .unwrap()
+            .values()
+            .filter(|c| c.api_key_id == api_key_id)
+            .cloned()
+            .collect();
+        all.sort_by(|a, b| (a.create_time, &a.name).cmp(&(b.create_time, &b.name)));
+
+        let page_size = page_size
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .min(MAX_PAGE_SIZE);
+        let offset = match page_token.filter(|t| !t.is_empty()) {
+            Some(token) => token
+                .parse::<usize>()
+                .map_err(|_| "Invalid page token".to_string())?,
+            None => 0,
+        };
+        let page: Vec<_> = all.iter().skip(offset).take(page_size).cloned().collect();
+        let next = offset + page.len();
+        let next_token = (next < all.len()).then(|| next.to_string());
+        Ok((page, next_token))
+    }
+
+    /// 更新有效期 (PATCH，仅 ttl / expireTime 可修改)
+    pub fn update(
+        &self,
+        name: &str,
+        api_key_id: &str,
+        body: &Value,
+        now: i64,
+    ) -> Result<Option<Arc<CachedContent>>, String> {
+        let Some(existing) = self.get(name, api_key_id, now) else {
+            return Ok(None);
+        };
+        let expire_time =
+            parse_expiration(body, now)?.ok_or("Only 'ttl' or 'expireTime' can be updated")?;
+        let mut content = (*existing).clone();
+        content.expire_time = expire_time;
+        content.update_time = now;
+        self.persist(&content)?;
+        let content = Arc::new(content);
+        self.entries
+            .write()
+            .unwrap()
+            .insert(content.name.clone(), content.clone());
+        Ok(Some(content))
+    }
+
+    /// 删除 `api_key_id` 名下的缓存，返回是否存在
+    pub fn delete(&self, name: &str, api_key_id: &str) -> Result<bool, String> {
+        let Some(name) = normalize_name(name) else {
+            return Ok(false);
+        };
+        let owned = self
+            .entries
+            .read()
+            .unwrap()
+            .get(&name)
+            .is_some_and(|c| c.api_key_id == api_key_id);
+        if !owned {
+            return Ok(false);
+        }
+        self.remove(&name)
+    }
+
+    /// 删除缓存条目与文件 (不检查归属)
+    fn remove(&self, name: &str) -> Result<bool, String> {
+        let removed = self.entries.write().unwrap().remove(name).is_some();
+        let path = self.path_for(name);
+        if path.exists() {
+            std::fs::remove_file(&path)
+                .map_err(|e| format!("Failed to delete cached content: {}", e))?;
+        }
+        Ok(removed)
+    }
+
+    /// 清理过期缓存
+    pub fn prune(&self, now: i64) -> usize {
+        let expired: Vec<String> = self
+            .entries
+            .read()
+            .unwrap()
+            .values()
+            .filter(|c| c.expire_time <= now)
+            .map(|c| c.name.clone())
+            .collect();
+        for name in &expired {
+            let _ = self.remove(name);
+        }
+        expired.len()
+    }
+
+    fn path_for(&self, name: &str) -> PathBuf {
+        let id = name.strip_prefix(NAME_PREFIX).unwrap_or(name);
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// 先写临时文件再重命名，避免读到半写入的文件
+    fn persist(&self, content: &CachedContent) -> Result<(), String> {
+        let path = self.path_for(&content.name);
+        let tmp = path.with_extension("json.tmp");
+        let data = serde_json::to_vec(content)
+            .map_err(|e| format!("Failed to serialize cached content: {}", e))?;
+        std::fs::write(&tmp, data).map_err(|e| format!("Failed to write cached content: {}", e))?;
+        std::fs::rename(&tmp, &path).map_err(|e| format!("Failed to write cached content: {}", e))
+    }
+}
+
+/// 规范化资源名 (接受 "cachedContents/{id}" 或单独的 id)，拒绝非法字符
+pub fn normalize_name(name: &str) -> Option<String> {
+    let id = name.strip_prefix(NAME_PREFIX).unwrap_or(name);
+    let valid = !id.is_empty()
+        && id.len() <= 64
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+    valid.then(|| format!("{}{}", NAME_PREFIX, id))
+}
+
+fn strip_model_prefix(model: &str) -> &str {
+    model.strip_prefix("models/").unwrap_or(model)
+}
+
+fn field<'a>(body: &'a Value, camel: &str, snake: &str) -> Option<&'a Value> {
+    body.get(camel)
+        .or_else(|| body.get(snake))
+        .filter(|v| !v.is_null())
+}
+
+/// 解析 ttl ("3600s" / "1.5s") 或 expireTime (RFC 3339)，返回过期时间 (毫秒)
+fn parse_expiration(body: &Value, now: i64) -> Result<Option<i64>, String> {
+    let ttl = body.get("ttl").filter(|v| !v.is_null());
+    let expire_time = field(body, "expireTime", "expire_time");
+    let expire_at = match (ttl, expire_time) {
+        (Some(_), Some(_)) => {
+            return Err("Only one of 'ttl' and 'expireTime' can be set".to_string())
+        }
+        (Some(ttl), None) => {
+            let seconds = ttl
+                .as_str()
+                .and_then(|s| s.trim().strip_suffix('s'))
+                .and_then(|s| s.parse::<f64>().ok())
+                .filter(|s| s.is_finite() && *s > 0.0)
+                .ok_or_else(|| {
+                    format!("Invalid 'ttl' {}, expected a duration like \"3600s\"", ttl)
+                })?;
+            now + (seconds * 1000.0) as i64
+        }
+        (None, Some(expire_time)) => expire_time
+            .as_str()
+            .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
+            .map(|t| t.timestamp_millis())
+            .ok_or_else(|| format!("Invalid 'expireTime' {}, expected RFC 3339", expire_time))?,
+        (None, None) => return Ok(None),
+    };
+    if expire_at <= now {
+        return Err("Expiration time must be in the future".to_string());
+    }
+    Ok(Some(expire_at))
+}
+
+/// 估算缓存内容的 token 数 (文本按字符估算，媒体 part 按固定值)
+fn estimate_tokens(content: &CachedContent) -> u32 {
+    fn walk(value: &Value, total: &mut u32) {
+        match value {
+            Value::Object(map) => {
+                if map.contains_key("inlineData") || map.contains_key("fileData") {
+                    *total += MEDIA_PART_TOKENS;
+                    return;
+                }
+                for (key, v) in map {
+                    match (key.as_str(), v) {
+                        ("text", Value::String(text)) => {
+                            *total +=
+                                crate::proxy::mappers::context_manager::estimate_tokens_from_str(
+                                    text,
+                                )
+                        }
+                        _ => walk(v, total),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|v| walk(v, total)),
+            _ => {}
+        }
+    }
+
+    let mut total = 0;
+    content.contents.iter().for_each(|c| walk(c, &mut total));
+    if let Some(system_instruction) = &content.system_instruction {
+        walk(system_instruction, &mut total);
+    }
+    if let Some(tools) = &content.tools {
+        total +=
+            crate::proxy::mappers::context_manager::estimate_tokens_from_str(&tools.to_string());
+    }
+    total
+}
+
+fn rfc3339(ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(ms)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+}
+
+pub fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "key-aaaaaaaaaaaa";
+
+    fn temp_store() -> (CacheStore, PathBuf) {
+        let dir =
+            std::env::temp_dir().join(format!("cached_contents_test_{}", uuid::Uuid::new_v4()));
+        (CacheStore::open(&dir, 0).unwrap(), dir)
+    }
+
+    fn cache_body() -> Value {
+        json!({
+            "model": "models/gemini-2.5-flash",
+            "displayName": "manual",
+            "systemInstruction": {"parts": [{"text": "You are a helpful assistant."}]},
+            "contents": [{"role": "user", "parts": [{"text": "A very long document ..."}]}],
+            "ttl": "300s"
+        })
+    }
+
+    #[test]
+    fn test_create_get_update_delete() {
+        let (store, dir) = temp_store();
+        let created = store.create(&cache_body(), KEY, 1_000).unwrap();
+        assert!(created.name.starts_with(NAME_PREFIX));
+        assert_eq!(created.expire_time, 301_000);
+        assert!(created.total_token_count > 0);
+
+        let api = created.to_api_json();
+        assert_eq!(api["model"], "models/gemini-2.5-flash");
+        assert_eq!(api["displayName"], "manual");
+        assert!(api.get("contents").is_none());
+
+        // 重新打开后从磁盘恢复
+        let reopened = CacheStore::open(&dir, 2_000).unwrap();
+        assert!(reopened.get(&created.name, KEY, 2_000).is_some());
+
+        let updated = store
+            .update(&created.name, KEY, &json!({"ttl": "60s"}), 5_000)
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.expire_time, 65_000);
+        assert!(store.get(&created.name, KEY, 65_000).is_none(), "expired");
+        assert!(!dir
+            .join(format!("{}.json", &created.name[NAME_PREFIX.len()..]))
+            .exists());
+
+        let second = store.create(&cache_body(), KEY, 1_000).unwrap();
+        assert!(store.delete(&second.name, KEY).unwrap());
+        assert!(!store.delete(&second.name, KEY).unwrap());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_validation_and_paging() {
+        let (store, dir) = temp_store();
+        assert!(store
+            .create(&json!({"model": "gemini-2.5-flash"}), KEY, 0)
+            .is_err());
+        assert!(store.create(&json!({"contents": []}), KEY, 0).is_err());
+        let mut body = cache_body();
+        body["expireTime"] = json!("2030-01-01T00:00:00Z");
+        assert!(store
+            .create(&body, KEY, 0)
+            .unwrap_err()
+            .contains("Only one of"));
+        body["ttl"] = Value::Null;
+        assert_eq!(
+            store.create(&body, KEY, 0).unwrap().expire_time,
+            chrono::DateTime::parse_from_rfc3339("2030-01-01T00:00:00Z")
+                .unwrap()
+                .timestamp_millis()
+        );
+
+        for i in 1..5 {
+            store.create(&cache_body(), KEY, i).unwrap();
+        }
+        let (page, next) = store.list(KEY, Some(3), None, 10).unwrap();
+        assert_eq!((page.len(), next.as_deref()), (3, Some("3")));
+        let (page, next) = store.list(KEY, Some(3), next.as_deref(), 10).unwrap();
+        assert_eq!((page.len(), next), (2, None));
+
+        assert_eq!(normalize_name("../etc"), None);
+        assert_eq!(
+            normalize_name("abc-1").as_deref(),
+            Some("cachedContents/abc-1")
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_entries_are_scoped_to_api_key() {
+        let (store, dir) = temp_store();
+        let other = "key-bbbbbbbbbbbb";
+        let created = store.create(&cache_body(), KEY, 0).unwrap();
+
+        assert!(store.get(&created.name, other, 10).is_none());
+        assert!(store.list(other, None, None, 10).unwrap().0.is_empty());
+        assert!(store
+            .update(&created.name, other, &json!({"ttl": "60s"}), 10)
+            .unwrap()
+            .is_none());
+        assert!(!store.delete(&created.name, other).unwrap());
+
+        assert_eq!(store.list(KEY, None, None, 10).unwrap().0.len(), 1);
+        assert!(store.delete(&created.name, KEY).unwrap());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_apply_to_request() {
+        let (store, dir) = temp_store();
+        let cached = store.create(&cache_body(), KEY, 0).unwrap();
+
+        let mut request = json!({
+            "cachedContent": cached.name,
+            "contents": [{"role": "user", "parts": [{"text": "Summarize it"}]}]
+        });
+        cached.apply_to(&mut request, "gemini-2.5-flash").unwrap();
+        assert!(request.get("cachedContent").is_none());
+        assert_eq!(request["contents"].as_array().unwrap().len(), 2);
+        assert_eq!(request["contents"][1]["parts"][0]["text"], "Summarize it");
+        assert_eq!(
+            request["systemInstruction"]["parts"][0]["text"],
+            "You are a helpful assistant."
+        );
+
+        let mut conflicting = json!({"systemInstruction": {"parts": []}, "contents": []});
+        assert!(cached
+            .apply_to(&mut conflicting, "gemini-2.5-flash")
+            .is_err());
+        let mut other_model = json!({"contents": []});
+        assert!(cached
+            .apply_to(&mut other_model, "gemini-2.5-pro")
+            .unwrap_err()
+            .contains("has to be the same"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -144,7 +144,7 @@ pub async fn get_all_dynamic_models(
 /// - `claude-*-sonnet-*` matches `claude-3-5-sonnet-20241022` ✓
 /// - `*-thinking` matches `claude-opus-4-5-thinking` ✓
 /// - `a*b*c` matches `a123b456c` ✓
-fn wildcard_match(pattern: &str, text: &str) -> bool {
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
     let parts: Vec<&str> = pattern.split('*').collect();
 
     // No wildcard - exact match
@@ -190,6 +190,11 @@ fn wildcard_match(pattern: &str, text: &str) -> bool {
 /// 
 /// # 返回
 /// 映射后的目标模型名称
+#[tracing::instrument(
+    name = "proxy.route",
+    skip(custom_mapping),
+    fields(route = tracing::field::Empty, target = tracing::field::Empty)
+)]
 pub fn resolve_model_route(
     original_model: &str,
     custom_mapping: &std::collections::HashMap<String, String>,
@@ -197,6 +202,7 @@ pub fn resolve_model_route(
     // 1. 精确匹配 (最高优先级)
     if let Some(target) = custom_mapping.get(original_model) {
         crate::modules::logger::log_info(&format!("[Router] 精确映射: {} -> {}", original_model, target));
+        record_route("exact", target);
         return target.clone();
     }
     
@@ -220,6 +226,7 @@ pub fn resolve_model_route(
             "[Router] Wildcard match: {} -> {} (rule: {})",
             original_model, target, pattern
         ));
+        record_route("wildcard", target);
         return target.to_string();
     }
     
@@ -228,9 +235,17 @@ pub fn resolve_model_route(
     if result != original_model {
         crate::modules::logger::log_info(&format!("[Router] 系统默认映射: {} -> {}", original_model, result));
     }
+    record_route("default", &result);
     result
 }
 
+/// 在当前路由 span 上记录命中的规则类型与目标模型
+fn record_route(route: &str, target: &str) {
+    let span = tracing::Span::current();
+    span.record("route", route);
+    span.record("target", target);
+}
+
 /// Normalize any physical model name to one of the 3 standard protection IDs.
 /// This ensures quota protection works consistently regardless of API versioning or request variations.
 /// 
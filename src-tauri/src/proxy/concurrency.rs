@@ -0,0 +1,218 @@
+// 单账号并发租约 (in-flight leases)
+// TokenManager 每分配一次账号即发放一个 TokenLease，请求 (或流) 结束时 Drop 自动归还名额
+
+use dashmap::DashMap;
+use futures::Stream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// 账号并发租约登记表
+#[derive(Default)]
+pub struct LeaseRegistry {
+    /// account_id -> 当前在途请求数
+    accounts: DashMap<String, Arc<AtomicUsize>>,
+    /// "account_id|model" -> 当前在途请求数
+    models: DashMap<String, Arc<AtomicUsize>>,
+    /// 任一租约释放时唤醒排队者
+    released: Arc<Notify>,
+}
+
+impl LeaseRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn account_counter(&self, account_id: &str) -> Arc<AtomicUsize> {
+        self.accounts
+            .entry(account_id.to_string())
+            .or_insert_with(|| Arc::new(AtomicUsize::new(0)))
+            .clone()
+    }
+
+    fn model_counter(&self, account_id: &str, model: &str) -> Arc<AtomicUsize> {
+        self.models
+            .entry(format!("{}|{}", account_id, model))
+            .or_insert_with(|| Arc::new(AtomicUsize::new(0)))
+            .clone()
+    }
+
+    /// 当前账号的在途请求数
+    pub fn in_flight(&self, account_id: &str) -> usize {
+        self.accounts
+            .get(account_id)
+            .map(|c| c.load(Ordering::SeqCst))
+            .unwrap_or(0)
+    }
+
+    /// 账号 (或账号上的该模型) 是否已达并发上限
+    pub fn is_saturated(
+        &self,
+        account_id: &str,
+        model: &str,
+        account_limit: Option<usize>,
+        model_limit: Option<usize>,
+    ) -> bool {
+        if let Some(limit) = account_limit {
+            if self.in_flight(account_id) >= limit {
+                return true;
+            }
+        }
+        if let Some(limit) = model_limit {
+            let key = format!("{}|{}", account_id, model);
+            if self.models.get(&key).map(|c| c.load(Ordering::SeqCst)).unwrap_or(0) >= limit {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 任一租约释放的通知
+    pub fn released(&self) -> tokio::sync::futures::Notified<'_> {
+        self.released.notified()
+    }
+
+    /// 尝试原子地占用一个名额，饱和时返回 None
+    pub fn try_acquire(
+        &self,
+        account_id: &str,
+        model: &str,
+        account_limit: Option<usize>,
+        model_limit: Option<usize>,
+    ) -> Option<TokenLease> {
+        let account = self.account_counter(account_id);
+        if !try_increment(&account, account_limit) {
+            return None;
+        }
+
+        let model_counter = self.model_counter(account_id, model);
+        if !try_increment(&model_counter, model_limit) {
+            account.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+
+        Some(TokenLease {
+            account,
+            model: model_counter,
+            released: self.released.clone(),
+        })
+    }
+
+    /// 在 `max_wait` 内排队等待空位 (CacheFirst 模式下用于保持会话粘性)
+    pub async fn acquire_with_wait(
+        &self,
+        account_id: &str,
+        model: &str,
+        account_limit: Option<usize>,
+        model_limit: Option<usize>,
+        max_wait: Duration,
+    ) -> Option<TokenLease> {
+        let deadline = tokio::time::Instant::now() + max_wait;
+        loop {
+            // 先注册通知再检查，避免检查与等待之间的释放被错过
+            let notified = self.released.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if let Some(lease) = self.try_acquire(account_id, model, account_limit, model_limit) {
+                return Some(lease);
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return None;
+            }
+        }
+    }
+}
+
+fn try_increment(counter: &AtomicUsize, limit: Option<usize>) -> bool {
+    counter
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |current| match limit {
+            Some(l) if current >= l => None,
+            _ => Some(current + 1),
+        })
+        .is_ok()
+}
+
+/// 账号并发租约，Drop 时归还名额
+///
+/// 流式响应需通过 [`TokenLease::hold_stream`] 将租约移入响应流，
+/// 保证名额一直占用到流结束或客户端断开。
+#[derive(Debug)]
+pub struct TokenLease {
+    account: Arc<AtomicUsize>,
+    model: Arc<AtomicUsize>,
+    released: Arc<Notify>,
+}
+
+impl TokenLease {
+    /// 将租约绑定到流的生命周期上
+    pub fn hold_stream<S>(self, stream: S) -> impl Stream<Item = S::Item> + Send + 'static
+    where
+        S: Stream + Send + 'static,
+    {
+        use futures::StreamExt;
+        stream.map(move |item| {
+            let _ = &self;
+            item
+        })
+    }
+}
+
+impl Drop for TokenLease {
+    fn drop(&mut self) {
+        self.account.fetch_sub(1, Ordering::SeqCst);
+        self.model.fetch_sub(1, Ordering::SeqCst);
+        self.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_released_on_drop() {
+        let registry = LeaseRegistry::new();
+        let lease = registry.try_acquire("acc1", "gemini-3-flash", Some(1), None).unwrap();
+        assert_eq!(registry.in_flight("acc1"), 1);
+        assert!(registry.try_acquire("acc1", "gemini-3-flash", Some(1), None).is_none());
+
+        drop(lease);
+        assert_eq!(registry.in_flight("acc1"), 0);
+        assert!(registry.try_acquire("acc1", "gemini-3-flash", Some(1), None).is_some());
+    }
+
+    #[test]
+    fn test_model_limit_rolls_back_account_count() {
+        let registry = LeaseRegistry::new();
+        let _a = registry.try_acquire("acc1", "claude-sonnet-4-5", Some(5), Some(1)).unwrap();
+        assert!(registry.try_acquire("acc1", "claude-sonnet-4-5", Some(5), Some(1)).is_none());
+        // 失败的模型级占用不应泄漏账号级计数
+        assert_eq!(registry.in_flight("acc1"), 1);
+        // 其他模型不受影响
+        assert!(registry.try_acquire("acc1", "gemini-3-flash", Some(5), Some(1)).is_some());
+        assert!(registry.is_saturated("acc1", "claude-sonnet-4-5", Some(5), Some(1)));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_with_wait() {
+        let registry = Arc::new(LeaseRegistry::new());
+        let lease = registry.try_acquire("acc1", "m", Some(1), None).unwrap();
+
+        // 无人释放时超时返回 None
+        assert!(registry
+            .acquire_with_wait("acc1", "m", Some(1), None, Duration::from_millis(50))
+            .await
+            .is_none());
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(lease);
+        });
+        assert!(registry
+            .acquire_with_wait("acc1", "m", Some(1), None, Duration::from_secs(2))
+            .await
+            .is_some());
+    }
+}
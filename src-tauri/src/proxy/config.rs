@@ -147,6 +147,14 @@ pub struct ExperimentalConfig {
     /// 上下文压缩阈值 L3 (Fork + Summary)
     #[serde(default = "default_threshold_l3")]
     pub context_compression_threshold_l3: f32,
+    /// 对冲请求 (Hedged Requests)
+    /// 非流式请求超过近期延迟分位数仍未返回时，换账号发出副本并取先成功者
+    #[serde(default)]
+    pub hedging: crate::proxy::hedging::HedgingConfig,
+    /// 精确匹配响应缓存 (Response Cache)
+    /// temperature = 0 或客户端携带 x-proxy-cache 请求头时，相同请求直接返回缓存结果
+    #[serde(default)]
+    pub response_cache: crate::proxy::response_cache::ResponseCacheConfig,
 }
 
 impl Default for ExperimentalConfig {
@@ -159,6 +167,8 @@ impl Default for ExperimentalConfig {
             context_compression_threshold_l1: 0.4,
             context_compression_threshold_l2: 0.55,
             context_compression_threshold_l3: 0.7,
+            hedging: crate::proxy::hedging::HedgingConfig::default(),
+            response_cache: crate::proxy::response_cache::ResponseCacheConfig::default(),
         }
     }
 }
@@ -186,7 +196,7 @@ pub struct ProxyConfig {
     /// Authorization policy for the proxy.
     /// - off: no auth required
     /// - strict: auth required for all routes
-    /// - all_except_health: auth required for all routes except `/healthz`
+    /// - all_except_health: auth required for all routes except `/healthz` and `/readyz`
     /// - auto: recommended defaults (currently: allow_lan_access => all_except_health, else off)
     #[serde(default)]
     pub auth_mode: ProxyAuthMode,
@@ -227,6 +237,34 @@ pub struct ProxyConfig {
     /// 实验性功能配置
     #[serde(default)]
     pub experimental: ExperimentalConfig,
+
+    /// 上游端点熔断配置
+    #[serde(default)]
+    pub circuit_breaker: crate::proxy::upstream::circuit_breaker::CircuitBreakerConfig,
+
+    /// 后台任务分类与路由规则 (Claude 协议)
+    #[serde(default)]
+    pub background_tasks: crate::proxy::background_tasks::BackgroundTaskConfig,
+
+    /// 请求日志脱敏、截断与保留策略
+    #[serde(default)]
+    pub log_policy: crate::proxy::log_policy::LogPolicyConfig,
+
+    /// OpenTelemetry 链路追踪导出 (OTLP)
+    #[serde(default)]
+    pub telemetry: crate::proxy::telemetry::TelemetryConfig,
+
+    /// 模型价格表与预算规则
+    #[serde(default)]
+    pub cost: crate::proxy::budget::CostConfig,
+
+    /// 生成图片的本地存储 (response_format=url)
+    #[serde(default)]
+    pub image_store: crate::proxy::image_store::ImageStoreConfig,
+
+    /// OpenAI Batch API 执行配置
+    #[serde(default)]
+    pub batch: crate::proxy::batch::BatchConfig,
 }
 
 /// 上游代理配置
@@ -254,6 +292,13 @@ impl Default for ProxyConfig {
             zai: ZaiConfig::default(),
             scheduling: crate::proxy::sticky_config::StickySessionConfig::default(),
             experimental: ExperimentalConfig::default(),
+            circuit_breaker: crate::proxy::upstream::circuit_breaker::CircuitBreakerConfig::default(),
+            background_tasks: crate::proxy::background_tasks::BackgroundTaskConfig::default(),
+            log_policy: crate::proxy::log_policy::LogPolicyConfig::default(),
+            telemetry: crate::proxy::telemetry::TelemetryConfig::default(),
+            cost: crate::proxy::budget::CostConfig::default(),
+            image_store: crate::proxy::image_store::ImageStoreConfig::default(),
+            batch: crate::proxy::batch::BatchConfig::default(),
         }
     }
 }
@@ -0,0 +1,729 @@
+// Gemini Files API 本地模拟
+// 大文件 (视频 / PDF 等) 经 /upload/v1beta/files 可续传上传到数据目录的 files/ 下，
+// generateContent 中的 fileData.fileUri 引用在 wrap_request 之前解析为 inlineData，
+// 避免客户端每次以 base64 内联发送触及请求体上限并撑大请求日志。
+
+use base64::{engine::general_purpose, Engine as _};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// 资源名前缀
+pub const NAME_PREFIX: &str = "files/";
+/// 上传路由
+pub const UPLOAD_PATH: &str = "/upload/v1beta/files";
+/// 单个文件大小上限 (与 Google 一致为 2GB)
+pub const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+/// 解析为 inlineData 时的单文件上限
+pub const MAX_INLINE_BYTES: u64 = 100 * 1024 * 1024;
+/// 文件保留时长 (与 Google 一致为 48 小时)
+const FILE_TTL_MS: i64 = 48 * 3600 * 1000;
+/// 未完成的上传会话保留时长
+const UPLOAD_SESSION_TTL_MS: i64 = 24 * 3600 * 1000;
+const DEFAULT_PAGE_SIZE: usize = 10;
+const MAX_PAGE_SIZE: usize = 100;
+
+/// 已上传的文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredFile {
+    pub name: String,
+    pub display_name: Option<String>,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+    pub create_time: i64,
+    pub update_time: i64,
+    pub expire_time: i64,
+}
+
+impl StoredFile {
+    /// API 返回的 File 资源，uri 指向本反代
+    pub fn to_api_json(&self, base_url: &str) -> Value {
+        let mut value = json!({
+            "name": self.name,
+            "mimeType": self.mime_type,
+            "sizeBytes": self.size_bytes.to_string(),
+            "createTime": rfc3339(self.create_time),
+            "updateTime": rfc3339(self.update_time),
+            "expirationTime": rfc3339(self.expire_time),
+            "sha256Hash": general_purpose::STANDARD.encode(&self.sha256),
+            "uri": format!("{}/v1beta/{}", base_url, self.name),
+            "state": "ACTIVE",
+            "source": "UPLOADED"
+        });
+        if let Some(display_name) = &self.display_name {
+            value["displayName"] = json!(display_name);
+        }
+        value
+    }
+}
+
+/// 进行中的可续传上传
+struct PendingUpload {
+    display_name: Option<String>,
+    mime_type: String,
+    expected_size: Option<u64>,
+    received: u64,
+    hasher: Sha256,
+    create_time: i64,
+    /// 正在写入分块 (同一会话同时只允许一个写入方)
+    in_flight: bool,
+}
+
+/// 分块写入被拒绝的原因
+#[derive(Debug)]
+pub enum ChunkError {
+    /// 同一会话已有分块正在写入
+    InFlight,
+    Rejected(String),
+}
+
+impl std::fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChunkError::InFlight => {
+                write!(f, "Another chunk is already being written to this upload")
+            }
+            ChunkError::Rejected(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// 已预留的分块写入 (释放时清除 in_flight，客户端中途断开同样生效)
+pub struct ChunkReservation<'a> {
+    store: &'a FileStore,
+    upload_id: String,
+    path: PathBuf,
+}
+
+impl ChunkReservation<'_> {
+    /// 分片文件路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ChunkReservation<'_> {
+    fn drop(&mut self) {
+        if let Some(pending) = self.store.uploads.lock().unwrap().get_mut(&self.upload_id) {
+            pending.in_flight = false;
+        }
+    }
+}
+
+/// 本地文件存储: 数据 `<id>.bin` + 元数据 `<id>.json`，未完成的上传位于 uploads/ 下
+pub struct FileStore {
+    dir: PathBuf,
+    files: RwLock<HashMap<String, Arc<StoredFile>>>,
+    uploads: Mutex<HashMap<String, PendingUpload>>,
+}
+
+/// 全局存储 (首次使用时从数据目录加载)
+pub fn store() -> Result<&'static FileStore, String> {
+    static STORE: OnceCell<FileStore> = OnceCell::new();
+    STORE.get_or_try_init(|| {
+        let dir = crate::modules::account::get_data_dir()?.join("files");
+        FileStore::open(&dir, now_ms())
+    })
+}
+
+/// 是否为进行中上传会话的分块请求 (上传 URL 本身即凭证，与 Google 一致无需 API Key)
+pub fn is_upload_session_request(path: &str, query: Option<&str>) -> bool {
+    if path != UPLOAD_PATH {
+        return false;
+    }
+    let Some(upload_id) = query_param(query, "upload_id") else {
+        return false;
+    };
+    store()
+        .map(|s| s.uploads.lock().unwrap().contains_key(upload_id))
+        .unwrap_or(false)
+}
+
+/// 读取查询参数
+pub fn query_param<'a>(query: Option<&'a str>, key: &str) -> Option<&'a str> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+impl FileStore {
+    /// 打开存储目录，加载未过期的文件并清理残留的上传分片
+    pub fn open(dir: &Path, now: i64) -> Result<Self, String> {
+        std::fs::create_dir_all(dir.join("uploads"))
+            .map_err(|e| format!("Failed to create file store dir: {}", e))?;
+        let mut files = HashMap::new();
+        let read_dir =
+            std::fs::read_dir(dir).map_err(|e| format!("Failed to read file store dir: {}", e))?;
+        for entry in read_dir.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let parsed = std::fs::read(&path)
+                .ok()
+                .and_then(|data| serde_json::from_slice::<StoredFile>(&data).ok());
+            match parsed {
+                Some(file) if file.expire_time > now && path.with_extension("bin").exists() => {
+                    files.insert(file.name.clone(), Arc::new(file));
+                }
+                _ => {
+                    let _ = std::fs::remove_file(path.with_extension("bin"));
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+        // 会话状态只在内存中，重启后的分片无法续传
+        if let Ok(parts) = std::fs::read_dir(dir.join("uploads")) {
+            for part in parts.flatten() {
+                let _ = std::fs::remove_file(part.path());
+            }
+        }
+        tracing::debug!("[GeminiFiles] Loaded {} file(s)", files.len());
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            files: RwLock::new(files),
+            uploads: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// 开始上传会话，返回 upload_id
+    pub fn start_upload(
+        &self,
+        metadata: &Value,
+        mime_type: Option<&str>,
+        expected_size: Option<u64>,
+        now: i64,
+    ) -> Result<String, String> {
+        if expected_size.is_some_and(|size| size > MAX_FILE_BYTES) {
+            return Err(format!(
+                "File is too large, maximum is {} GB",
+                MAX_FILE_BYTES / (1024 * 1024 * 1024)
+            ));
+        }
+        let file = metadata.get("file").unwrap_or(metadata);
+        let display_name = file
+            .get("displayName")
+            .or_else(|| file.get("display_name"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+        let mime_type = file
+            .get("mimeType")
+            .or_else(|| file.get("mime_type"))
+            .and_then(|v| v.as_str())
+            .or(mime_type)
+            .filter(|s| !s.is_empty())
+            .unwrap_or("application/octet-stream")
+            .to_string();
+
+        self.prune(now);
+        let upload_id = uuid::Uuid::new_v4().simple().to_string();
+        self.uploads.lock().unwrap().insert(
+            upload_id.clone(),
+            PendingUpload {
+                display_name,
+                mime_type,
+                expected_size,
+                received: 0,
+                hasher: Sha256::new(),
+                create_time: now,
+                in_flight: false,
+            },
+        );
+        Ok(upload_id)
+    }
+
+    /// 校验分块偏移并预留写入 (偏移校验与 in_flight 标记在同一把锁内完成)
+    pub fn reserve_chunk(
+        &self,
+        upload_id: &str,
+        offset: u64,
+    ) -> Result<ChunkReservation<'_>, ChunkError> {
+        let mut uploads = self.uploads.lock().unwrap();
+        let pending = uploads.get_mut(upload_id).ok_or_else(|| {
+            ChunkError::Rejected(format!("Unknown or expired upload session: {}", upload_id))
+        })?;
+        if pending.in_flight {
+            return Err(ChunkError::InFlight);
+        }
+        if offset != pending.received {
+            return Err(ChunkError::Rejected(format!(
+                "Upload offset mismatch: expected {}, got {}",
+                pending.received, offset
+            )));
+        }
+        pending.in_flight = true;
+        Ok(ChunkReservation {
+            store: self,
+            upload_id: upload_id.to_string(),
+            path: self.dir.join("uploads").join(format!("{}.part", upload_id)),
+        })
+    }
+
+    /// 写入分片前记录数据 (校验大小上限并计入哈希)
+    pub fn record_chunk(&self, upload_id: &str, data: &[u8]) -> Result<(), String> {
+        let mut uploads = self.uploads.lock().unwrap();
+        let pending = uploads
+            .get_mut(upload_id)
+            .ok_or_else(|| format!("Unknown or expired upload session: {}", upload_id))?;
+        let received = pending.received + data.len() as u64;
+        let limit = pending
+            .expected_size
+            .unwrap_or(MAX_FILE_BYTES)
+            .min(MAX_FILE_BYTES);
+        if received > limit {
+            return Err(format!(
+                "Upload exceeds the declared size of {} bytes",
+                limit
+            ));
+        }
+        pending.received = received;
+        pending.hasher.update(data);
+        Ok(())
+    }
+
+    /// 已接收的字节数
+    pub fn received(&self, upload_id: &str) -> Option<u64> {
+        self.uploads
+            .lock()
+            .unwrap()
+            .get(upload_id)
+            .map(|p| p.received)
+    }
+
+    /// 取消上传
+    pub fn cancel_upload(&self, upload_id: &str) {
+        if self.uploads.lock().unwrap().remove(upload_id).is_some() {
+            let _ =
+                std::fs::remove_file(self.dir.join("uploads").join(format!("{}.part", upload_id)));
+        }
+    }
+
+    /// 完成上传，生成 File 资源
+    pub fn finalize(&self, upload_id: &str, now: i64) -> Result<Arc<StoredFile>, String> {
+        let pending = {
+            let mut uploads = self.uploads.lock().unwrap();
+            if uploads.get(upload_id).is_some_and(|p| p.in_flight) {
+                return Err(ChunkError::InFlight.to_string());
+            }
+            uploads
+                .remove(upload_id)
+                .ok_or_else(|| format!("Unknown or expired upload session: {}", upload_id))?
+        };
+        let part = self.dir.join("uploads").join(format!("{}.part", upload_id));
+        if let Some(expected) = pending
+            .expected_size
+            .filter(|size| *size != pending.received)
+        {
+            let _ = std::fs::remove_file(&part);
+            return Err(format!(
+                "Upload incomplete: received {} of {} bytes",
+                pending.received, expected
+            ));
+        }
+        if pending.received == 0 {
+            let _ = std::fs::remove_file(&part);
+            return Err("Uploaded file is empty".to_string());
+        }
+
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let file = StoredFile {
+            name: format!("{}{}", NAME_PREFIX, id),
+            display_name: pending.display_name,
+            mime_type: pending.mime_type,
+            size_bytes: pending.received,
+            sha256: format!("{:x}", pending.hasher.finalize()),
+            create_time: now,
+            update_time: now,
+            expire_time: now + FILE_TTL_MS,
+        };
+        std::fs::rename(&part, self.dir.join(format!("{}.bin", id)))
+            .map_err(|e| format!("Failed to store file: {}", e))?;
+        let metadata =
+            serde_json::to_vec(&file).map_err(|e| format!("Failed to serialize file: {}", e))?;
+        std::fs::write(self.dir.join(format!("{}.json", id)), metadata)
+            .map_err(|e| format!("Failed to store file metadata: {}", e))?;
+
+        let file = Arc::new(file);
+        self.files
+            .write()
+            .unwrap()
+            .insert(file.name.clone(), file.clone());
+        Ok(file)
+    }
+
+    /// 查询未过期的文件
+    pub fn get(&self, name: &str, now: i64) -> Option<Arc<StoredFile>> {
+        let name = normalize_name(name)?;
+        let file = self.files.read().unwrap().get(&name).cloned()?;
+        if file.expire_time <= now {
+            let _ = self.delete(&name);
+            return None;
+        }
+        Some(file)
+    }
+
+    /// 分页列出 (按创建时间倒序，pageToken 为偏移量)
+    pub fn list(
+        &self,
+        page_size: Option<usize>,
+        page_token: Option<&str>,
+        now: i64,
+    ) -> Result<(Vec<Arc<StoredFile>>, Option<String>), String> {
+        self.prune(now);
+        let mut all: Vec<_> = self.files.read().unwrap().values().cloned().collect();
+        all.sort_by(|a, b| (b.create_time, &b.name).cmp(&(a.create_time, &a.name)));
+
+        let page_size = page_size
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .min(MAX_PAGE_SIZE);
+        let offset = match page_token.filter(|t| !t.is_empty()) {
+            Some(token) => token
+                .parse::<usize>()
+                .map_err(|_| "Invalid page token".to_string())?,
+            None => 0,
+        };
+        let page: Vec<_> = all.iter().skip(offset).take(page_size).cloned().collect();
+        let next = offset + page.len();
+        let next_token = (next < all.len()).then(|| next.to_string());
+        Ok((page, next_token))
+    }
+
+    /// 删除文件，返回是否存在
+    pub fn delete(&self, name: &str) -> Result<bool, String> {
+        let Some(name) = normalize_name(name) else {
+            return Ok(false);
+        };
+        let removed = self.files.write().unwrap().remove(&name).is_some();
+        let id = &name[NAME_PREFIX.len()..];
+        for ext in ["bin", "json"] {
+            let path = self.dir.join(format!("{}.{}", id, ext));
+            if path.exists() {
+                std::fs::remove_file(&path).map_err(|e| format!("Failed to delete file: {}", e))?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// 清理过期文件与超时未完成的上传
+    pub fn prune(&self, now: i64) -> usize {
+        let expired: Vec<String> = self
+            .files
+            .read()
+            .unwrap()
+            .values()
+            .filter(|f| f.expire_time <= now)
+            .map(|f| f.name.clone())
+            .collect();
+        for name in &expired {
+            let _ = self.delete(name);
+        }
+        let stale: Vec<String> = self
+            .uploads
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, p)| p.create_time + UPLOAD_SESSION_TTL_MS <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for upload_id in &stale {
+            self.cancel_upload(upload_id);
+        }
+        expired.len() + stale.len()
+    }
+
+    /// 读取文件内容
+    pub fn read(&self, file: &StoredFile) -> Result<Vec<u8>, String> {
+        let id = &file.name[NAME_PREFIX.len()..];
+        std::fs::read(self.dir.join(format!("{}.bin", id)))
+            .map_err(|e| format!("Failed to read {}: {}", file.name, e))
+    }
+
+    /// 将请求中指向本地文件的 fileData 替换为 inlineData，返回替换数量
+    pub fn resolve_file_references(&self, body: &mut Value, now: i64) -> Result<usize, String> {
+        let mut parts: Vec<&mut Value> = Vec::new();
+        if let Some(contents) = body.get_mut("contents").and_then(|v| v.as_array_mut()) {
+            for content in contents {
+                if let Some(items) = content.get_mut("parts").and_then(|v| v.as_array_mut()) {
+                    parts.extend(items.iter_mut());
+                }
+            }
+        }
+
+        let mut resolved = 0;
+        for part in parts {
+            let Some(obj) = part.as_object_mut() else {
+                continue;
+            };
+            let key = if obj.contains_key("fileData") {
+                "fileData"
+            } else {
+                "file_data"
+            };
+            let Some(file_data) = obj.get(key) else {
+                continue;
+            };
+            let Some(uri) = file_data
+                .get("fileUri")
+                .or_else(|| file_data.get("file_uri"))
+                .and_then(|v| v.as_str())
+            else {
+                continue;
+            };
+            // 非 Files API 的链接 (如 gs:// 或 YouTube) 原样交给上游
+            let Some(name) = file_name_from_uri(uri) else {
+                continue;
+            };
+            let file = self
+                .get(&name, now)
+                .ok_or_else(|| format!("File {} not found or expired", uri))?;
+            if file.size_bytes > MAX_INLINE_BYTES {
+                return Err(format!(
+                    "File {} is too large to send upstream ({} MB, maximum is {} MB)",
+                    file.name,
+                    file.size_bytes / (1024 * 1024),
+                    MAX_INLINE_BYTES / (1024 * 1024)
+                ));
+            }
+            let mime_type = file_data
+                .get("mimeType")
+                .or_else(|| file_data.get("mime_type"))
+                .and_then(|v| v.as_str())
+                .unwrap_or(&file.mime_type)
+                .to_string();
+            let data = self.read(&file)?;
+
+            obj.remove(key);
+            obj.insert(
+                "inlineData".to_string(),
+                json!({
+                    "mimeType": mime_type,
+                    "data": general_purpose::STANDARD.encode(data)
+                }),
+            );
+            resolved += 1;
+        }
+        Ok(resolved)
+    }
+}
+
+/// 请求中是否引用了本地文件 (无引用时跳过阻塞读取)
+pub fn has_file_references(body: &Value) -> bool {
+    body.get("contents")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|c| c.get("parts").and_then(|p| p.as_array()))
+        .flatten()
+        .filter_map(|p| p.get("fileData").or_else(|| p.get("file_data")))
+        .filter_map(|f| {
+            f.get("fileUri")
+                .or_else(|| f.get("file_uri"))
+                .and_then(|v| v.as_str())
+        })
+        .any(|uri| file_name_from_uri(uri).is_some())
+}
+
+/// 从 fileUri 中提取资源名，支持完整 URI (.../v1beta/files/{id}) 与 "files/{id}"
+pub fn file_name_from_uri(uri: &str) -> Option<String> {
+    let rest = match uri.find("/v1beta/files/") {
+        Some(pos) => &uri[pos + "/v1beta/".len()..],
+        None if uri.starts_with(NAME_PREFIX) => uri,
+        None => return None,
+    };
+    let name = rest.split(['?', '#', ':']).next().unwrap_or(rest);
+    normalize_name(name)
+}
+
+/// 规范化资源名 (接受 "files/{id}" 或单独的 id)，拒绝非法字符
+pub fn normalize_name(name: &str) -> Option<String> {
+    let id = name.strip_prefix(NAME_PREFIX).unwrap_or(name);
+    let valid = !id.is_empty()
+        && id.len() <= 64
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+    valid.then(|| format!("{}{}", NAME_PREFIX, id))
+}
+
+fn rfc3339(ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(ms)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+}
+
+pub fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> (FileStore, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("gemini_files_test_{}", uuid::Uuid::new_v4()));
+        (FileStore::open(&dir, 0).unwrap(), dir)
+    }
+
+    /// 模拟 handler: 先记录 (校验大小) 再写入分片
+    fn upload(store: &FileStore, upload_id: &str, offset: u64, data: &[u8]) -> Result<(), String> {
+        let part = store
+            .reserve_chunk(upload_id, offset)
+            .map_err(|e| e.to_string())?;
+        store.record_chunk(upload_id, data)?;
+        let mut existing = std::fs::read(part.path()).unwrap_or_default();
+        existing.extend_from_slice(data);
+        std::fs::write(part.path(), existing).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_resumable_upload_lifecycle() {
+        let (store, dir) = temp_store();
+        let metadata = json!({"file": {"display_name": "clip"}});
+        let upload_id = store
+            .start_upload(&metadata, Some("video/mp4"), Some(10), 1_000)
+            .unwrap();
+
+        upload(&store, &upload_id, 0, b"01234").unwrap();
+        assert!(upload(&store, &upload_id, 3, b"xx")
+            .unwrap_err()
+            .contains("offset mismatch"));
+        assert_eq!(store.received(&upload_id), Some(5));
+        assert!(
+            upload(&store, &upload_id, 5, b"56789!").is_err(),
+            "exceeds declared size"
+        );
+        upload(&store, &upload_id, 5, b"56789").unwrap();
+
+        let file = store.finalize(&upload_id, 2_000).unwrap();
+        assert_eq!(
+            (file.size_bytes, file.mime_type.as_str()),
+            (10, "video/mp4")
+        );
+        assert_eq!(file.expire_time, 2_000 + FILE_TTL_MS);
+        let api = file.to_api_json("http://127.0.0.1:8045");
+        assert_eq!(api["displayName"], "clip");
+        assert_eq!(api["sizeBytes"], "10");
+        assert_eq!(
+            api["uri"],
+            format!("http://127.0.0.1:8045/v1beta/{}", file.name)
+        );
+        assert!(store.received(&upload_id).is_none());
+
+        // 重启后恢复，过期后删除
+        let reopened = FileStore::open(&dir, 3_000).unwrap();
+        assert_eq!(
+            reopened
+                .read(&reopened.get(&file.name, 3_000).unwrap())
+                .unwrap(),
+            b"0123456789"
+        );
+        assert!(store.get(&file.name, file.expire_time).is_none());
+        assert!(!store.delete(&file.name).unwrap());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_concurrent_chunk_rejected() {
+        let (store, dir) = temp_store();
+        let upload_id = store.start_upload(&json!({}), None, Some(4), 0).unwrap();
+
+        let first = store.reserve_chunk(&upload_id, 0).unwrap();
+        assert!(matches!(
+            store.reserve_chunk(&upload_id, 0),
+            Err(ChunkError::InFlight)
+        ));
+        assert!(store
+            .finalize(&upload_id, 0)
+            .unwrap_err()
+            .contains("already being written"));
+        store.record_chunk(&upload_id, b"ab").unwrap();
+        drop(first);
+
+        // 写入结束后释放，按新偏移继续
+        assert!(matches!(
+            store.reserve_chunk(&upload_id, 0),
+            Err(ChunkError::Rejected(_))
+        ));
+        assert!(store.reserve_chunk(&upload_id, 2).is_ok());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_incomplete_upload_and_listing() {
+        let (store, dir) = temp_store();
+        let upload_id = store.start_upload(&json!({}), None, Some(4), 0).unwrap();
+        upload(&store, &upload_id, 0, b"ab").unwrap();
+        assert!(store
+            .finalize(&upload_id, 0)
+            .unwrap_err()
+            .contains("incomplete"));
+
+        for i in 0..3 {
+            let id = store
+                .start_upload(&json!({}), Some("application/pdf"), None, i)
+                .unwrap();
+            upload(&store, &id, 0, b"%PDF").unwrap();
+            store.finalize(&id, i).unwrap();
+        }
+        let (page, next) = store.list(Some(2), None, 10).unwrap();
+        assert_eq!((page.len(), next.as_deref()), (2, Some("2")));
+        assert_eq!(page[0].create_time, 2, "newest first");
+        let (page, next) = store.list(Some(2), next.as_deref(), 10).unwrap();
+        assert_eq!((page.len(), next), (1, None));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_resolve_file_references() {
+        let (store, dir) = temp_store();
+        let upload_id = store
+            .start_upload(&json!({}), Some("application/pdf"), None, 0)
+            .unwrap();
+        upload(&store, &upload_id, 0, b"%PDF-1.7").unwrap();
+        let file = store.finalize(&upload_id, 0).unwrap();
+
+        let mut body = json!({
+            "contents": [{"role": "user", "parts": [
+                {"text": "Summarize"},
+                {"fileData": {"fileUri": format!("http://localhost:8045/v1beta/{}", file.name), "mimeType": "application/pdf"}},
+                {"file_data": {"file_uri": "gs://bucket/video.mp4"}}
+            ]}]
+        });
+        assert!(has_file_references(&body));
+        assert_eq!(store.resolve_file_references(&mut body, 1).unwrap(), 1);
+        assert!(!has_file_references(&body));
+        let part = &body["contents"][0]["parts"][1];
+        assert!(part.get("fileData").is_none());
+        assert_eq!(
+            part["inlineData"]["data"],
+            general_purpose::STANDARD.encode(b"%PDF-1.7")
+        );
+        assert!(body["contents"][0]["parts"][2].get("file_data").is_some());
+
+        let mut missing =
+            json!({"contents": [{"parts": [{"fileData": {"fileUri": "files/doesnotexist"}}]}]});
+        assert!(store.resolve_file_references(&mut missing, 1).is_err());
+
+        assert_eq!(
+            file_name_from_uri("https://generativelanguage.googleapis.com/v1beta/files/abc-123")
+                .as_deref(),
+            Some("files/abc-123")
+        );
+        assert_eq!(file_name_from_uri("https://example.com/video.mp4"), None);
+        assert_eq!(normalize_name("../../etc"), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
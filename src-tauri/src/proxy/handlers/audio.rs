@@ -1,29 +1,52 @@
 use axum::{
+    body::Body,
     extract::{Multipart, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use futures::{stream, StreamExt, TryStreamExt};
 use serde_json::{json, Value};
 use tracing::{debug, info};
 use uuid::Uuid;
 
 use crate::proxy::{
-    audio::AudioProcessor,
+    audio::{
+        chunk::{self, AudioChunk, ChunkTranscript},
+        format::{self, AudioResponseFormat, AudioTask, Transcript},
+        speech::{self, SpeechFormat, SpeechRequest, SpeechStream},
+        AudioProcessor,
+    },
+    monitor::{report_upstream_usage, UpstreamUsage},
     server::AppState,
+    upstream::retry::send_v1_internal_with_retry,
 };
 
-/// 处理音频转录请求 (OpenAI Whisper API 兼容)
-pub async fn handle_audio_transcription(
-    State(state): State<AppState>,
-    mut multipart: Multipart,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+/// 分片并发转录数
+const CHUNK_CONCURRENCY: usize = 4;
+
+/// 解析后的 multipart 表单 (Whisper 参数)
+struct AudioForm {
+    audio_bytes: Vec<u8>,
+    file_name: String,
+    model: String,
+    prompt: Option<String>,
+    response_format: AudioResponseFormat,
+    language: Option<String>,
+    temperature: Option<f64>,
+    timestamp_granularities: Vec<String>,
+}
+
+async fn parse_audio_form(mut multipart: Multipart) -> Result<AudioForm, (StatusCode, String)> {
     let mut audio_data: Option<Vec<u8>> = None;
     let mut filename: Option<String> = None;
     let mut model = "gemini-2.0-flash-exp".to_string();
-    let mut prompt = "Generate a transcript of the speech.".to_string();
+    let mut prompt = None;
+    let mut response_format = AudioResponseFormat::default();
+    let mut language = None;
+    let mut temperature = None;
+    let mut timestamp_granularities = Vec::new();
 
-    // 1. 解析 multipart/form-data
     while let Some(field) = multipart.next_field().await.map_err(|e| {
         (StatusCode::BAD_REQUEST, format!("解析表单失败: {}", e))
     })? {
@@ -40,7 +63,31 @@ pub async fn handle_audio_transcription(
                 model = field.text().await.unwrap_or(model);
             }
             "prompt" => {
-                prompt = field.text().await.unwrap_or(prompt);
+                prompt = field.text().await.ok();
+            }
+            "response_format" => {
+                let value = field.text().await.unwrap_or_default();
+                response_format = AudioResponseFormat::parse(&value)
+                    .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            }
+            "language" => {
+                language = field.text().await.ok().filter(|l| !l.trim().is_empty());
+            }
+            "temperature" => {
+                let value = field.text().await.unwrap_or_default();
+                temperature = Some(value.trim().parse::<f64>().map_err(|_| {
+                    (StatusCode::BAD_REQUEST, format!("temperature 无效: {}", value))
+                })?);
+            }
+            // 兼容 timestamp_granularities[]=word 与逗号分隔写法
+            "timestamp_granularities" | "timestamp_granularities[]" => {
+                let value = field.text().await.unwrap_or_default();
+                timestamp_granularities.extend(
+                    value
+                        .split(',')
+                        .map(|g| g.trim().to_string())
+                        .filter(|g| !g.is_empty()),
+                );
             }
             _ => {}
         }
@@ -56,107 +103,478 @@ pub async fn handle_audio_transcription(
         "无法获取文件名".to_string(),
     ))?;
 
-    info!(
-        "收到音频转录请求: 文件={}, 大小={} bytes, 模型={}",
+    Ok(AudioForm {
+        audio_bytes,
         file_name,
-        audio_bytes.len(),
-        model
+        model,
+        prompt,
+        response_format,
+        language,
+        temperature,
+        timestamp_granularities,
+    })
+}
+
+/// 处理音频转录请求 (OpenAI Whisper API 兼容)
+pub async fn handle_audio_transcription(
+    State(state): State<AppState>,
+    multipart: Multipart,
+) -> Result<Response, (StatusCode, String)> {
+    handle_audio(state, multipart, AudioTask::Transcribe).await
+}
+
+/// 处理音频翻译请求 (翻译为英文, OpenAI Whisper API 兼容)
+pub async fn handle_audio_translation(
+    State(state): State<AppState>,
+    multipart: Multipart,
+) -> Result<Response, (StatusCode, String)> {
+    handle_audio(state, multipart, AudioTask::Translate).await
+}
+
+async fn handle_audio(
+    state: AppState,
+    multipart: Multipart,
+    task: AudioTask,
+) -> Result<Response, (StatusCode, String)> {
+    let mut form = parse_audio_form(multipart).await?;
+
+    info!(
+        "收到音频{}请求: 文件={}, 大小={} bytes, 模型={}, 格式={:?}",
+        if task == AudioTask::Translate { "翻译" } else { "转录" },
+        form.file_name,
+        form.audio_bytes.len(),
+        form.model,
+        form.response_format
     );
 
-    // 2. 检测 MIME 类型
-    let mime_type = AudioProcessor::detect_mime_type(&file_name)
+    // 1. 检测 MIME 类型
+    let mime_type = AudioProcessor::detect_mime_type(&form.file_name)
         .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
 
-    // 3. 验证文件大小
-    if AudioProcessor::exceeds_size_limit(audio_bytes.len()) {
-        let size_mb = audio_bytes.len() as f64 / (1024.0 * 1024.0);
-        return Err((
-            StatusCode::PAYLOAD_TOO_LARGE,
-            format!(
-                "音频文件过大 ({:.1} MB)。最大支持 15 MB (约 16 分钟 MP3)。建议: 1) 压缩音频质量 2) 分段上传",
-                size_mb
-            ),
-        ));
-    }
+    // 2. 验证文件大小，超过 15 MB 的 WAV / MP3 / FLAC 自动分片
+    let chunks = if AudioProcessor::exceeds_size_limit(form.audio_bytes.len()) {
+        let size_mb = form.audio_bytes.len() as f64 / (1024.0 * 1024.0);
+        if !chunk::supports_chunking(&mime_type) {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!(
+                    "音频文件过大 ({:.1} MB)。单次最大支持 15 MB，超过时仅 WAV / MP3 / FLAC 支持自动分片，请转换格式后重试",
+                    size_mb
+                ),
+            ));
+        }
+        // 解码与切分属于 CPU 密集操作，放到阻塞线程执行 (分片模式之后不再使用原始音频)
+        let audio_bytes = std::mem::take(&mut form.audio_bytes);
+        let chunk_mime_type = mime_type.clone();
+        let chunks = tokio::task::spawn_blocking(move || {
+            chunk::split_audio(
+                &audio_bytes,
+                &chunk_mime_type,
+                chunk::CHUNK_SECONDS,
+                chunk::OVERLAP_SECONDS,
+                AudioProcessor::MAX_INLINE_SIZE,
+            )
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("音频分片任务失败: {}", e)))?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        info!("音频文件 {:.1} MB 超过单次上限，切分为 {} 个分片", size_mb, chunks.len());
+        Some(chunks)
+    } else {
+        None
+    };
+
+    // 3. 时间戳粒度: 默认 segment；word 仅对 verbose_json 生效
+    let format = form.response_format;
+    let with_words = format == AudioResponseFormat::VerboseJson
+        && form.timestamp_granularities.iter().any(|g| g == "word");
+    let with_segments = form.timestamp_granularities.is_empty()
+        || form.timestamp_granularities.iter().any(|g| g == "segment");
+    // 分片模式需要时间戳来拼接与去重
+    let structured = format.needs_segments() || chunks.is_some();
 
     // 4. 使用 Inline Data 方式
     debug!("使用 Inline Data 方式处理");
-    let base64_audio = AudioProcessor::encode_to_base64(&audio_bytes);
+    let instruction = format::build_instruction(
+        task,
+        structured,
+        form.language.as_deref(),
+        form.prompt.as_deref(),
+        with_words,
+    );
 
-    // 5. 构建 Gemini 请求
-    let gemini_request = json!({
+    let mut generation_config = json!({});
+    if let Some(temperature) = form.temperature {
+        generation_config["temperature"] = json!(temperature);
+    }
+    if structured {
+        generation_config["responseMimeType"] = json!("application/json");
+        generation_config["responseSchema"] = format::transcript_schema(with_words);
+    }
+
+    // 5. 构建 Gemini 请求并转录
+    let (transcript, email) = match chunks {
+        Some(chunks) => {
+            transcribe_chunks(
+                &state,
+                &form.model,
+                &mime_type,
+                chunks,
+                &instruction,
+                &generation_config,
+            )
+            .await?
+        }
+        None => {
+            let gemini_request = build_audio_request(
+                &instruction,
+                &mime_type,
+                &form.audio_bytes,
+                &generation_config,
+            );
+            let (text, email) =
+                generate_audio_content(&state, &form.model, gemini_request).await?;
+            let transcript = if structured {
+                Transcript::from_structured(&text).map_err(|e| (StatusCode::BAD_GATEWAY, e))?
+            } else {
+                Transcript::from_text(&text)
+            };
+            (transcript, email)
+        }
+    };
+
+    info!("音频{}完成，返回 {} 字符", task.as_str(), transcript.text.len());
+
+    // 6. 按 response_format 返回
+    let content_type = format.content_type();
+    let headers = [
+        (header::CONTENT_TYPE, content_type.to_string()),
+        (header::HeaderName::from_static("x-account-email"), email),
+        (header::HeaderName::from_static("x-mapped-model"), form.model.clone()),
+    ];
+    let response = match format {
+        AudioResponseFormat::Json => (headers, Json(json!({ "text": transcript.text }))).into_response(),
+        AudioResponseFormat::Text => (headers, transcript.text).into_response(),
+        AudioResponseFormat::Srt => (headers, format::to_srt(&transcript.segments)).into_response(),
+        AudioResponseFormat::Vtt => (headers, format::to_vtt(&transcript.segments)).into_response(),
+        AudioResponseFormat::VerboseJson => (
+            headers,
+            Json(format::to_verbose_json(
+                &transcript,
+                task,
+                form.temperature.unwrap_or(0.0),
+                with_segments,
+            )),
+        )
+            .into_response(),
+    };
+    Ok(response)
+}
+
+fn build_audio_request(
+    instruction: &str,
+    mime_type: &str,
+    audio_bytes: &[u8],
+    generation_config: &Value,
+) -> Value {
+    json!({
         "contents": [{
+            "role": "user",
             "parts": [
-                {"text": prompt},
+                {"text": instruction},
                 {
                     "inlineData": {
                         "mimeType": mime_type,
-                        "data": base64_audio
+                        "data": AudioProcessor::encode_to_base64(audio_bytes)
                     }
                 }
             ]
-        }]
-    });
+        }],
+        "generationConfig": generation_config
+    })
+}
 
-    // 6. 获取 Token 和上游客户端
-    let token_manager = state.token_manager;
-    let (access_token, project_id, email) = token_manager
-        .get_token("text", false, None, &model)
-        .await
-        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))?;
-
-    info!("使用账号: {}", email);
-
-    // 7. 包装请求为 v1internal 格式
-    let wrapped_body = json!({
-        "project": project_id,
-        "requestId": format!("audio-{}", Uuid::new_v4()),
-        "request": gemini_request,
-        "model": model,
-        "userAgent": "antigravity",
-        "requestType": "text"
-    });
-
-    // 8. 发送请求到 Gemini
-    let upstream = state.upstream.clone();
-    let response = upstream
-        .call_v1_internal("generateContent", &access_token, wrapped_body, None)
-        .await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("上游请求失败: {}", e)))?;
-
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err((
-            StatusCode::BAD_GATEWAY,
-            format!("Gemini API 错误: {}", error_text),
-        ));
-    }
+/// 并发转录各分片 (每个分片独立取号，分散到池中不同账号)，按时间偏移拼接。
+/// 返回的账号邮箱为第一个分片所用账号
+async fn transcribe_chunks(
+    state: &AppState,
+    model: &str,
+    mime_type: &str,
+    chunks: Vec<AudioChunk>,
+    instruction: &str,
+    generation_config: &Value,
+) -> Result<(Transcript, String), (StatusCode, String)> {
+    let total = chunks.len();
+    let results: Vec<(ChunkTranscript, String)> = stream::iter(chunks)
+        .map(|audio_chunk| async move {
+            let request =
+                build_audio_request(instruction, mime_type, &audio_chunk.data, generation_config);
+            let failed = |(status, e): (StatusCode, String)| {
+                (status, format!("分片 {}/{} 转录失败: {}", audio_chunk.index + 1, total, e))
+            };
+            let (text, email) = generate_audio_content(state, model, request)
+                .await
+                .map_err(failed)?;
+            let transcript = Transcript::from_structured(&text)
+                .map_err(|e| failed((StatusCode::BAD_GATEWAY, e)))?;
+            debug!(
+                "分片 {}/{} 完成: 偏移 {:.1}s, {} 个分段",
+                audio_chunk.index + 1,
+                total,
+                audio_chunk.offset,
+                transcript.segments.len()
+            );
+            Ok::<_, (StatusCode, String)>((
+                ChunkTranscript {
+                    offset: audio_chunk.offset,
+                    duration: audio_chunk.duration,
+                    transcript,
+                },
+                email,
+            ))
+        })
+        .buffered(CHUNK_CONCURRENCY)
+        .try_collect()
+        .await?;
 
-    let result: Value = response
-        .json()
-        .await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("解析响应失败: {}", e)))?;
+    let email = results.first().map(|(_, e)| e.clone()).unwrap_or_default();
+    let parts = results.into_iter().map(|(part, _)| part).collect();
+    Ok((chunk::merge_transcripts(parts), email))
+}
+
+/// 发送 generateContent 请求 (共享重试/轮换/限流/用量上报流程)，返回 (文本, 账号邮箱)
+async fn generate_audio_content(
+    state: &AppState,
+    model: &str,
+    gemini_request: Value,
+) -> Result<(String, String), (StatusCode, String)> {
+    let success = send_v1_internal_with_retry(
+        &state.token_manager,
+        &state.upstream,
+        "text",
+        model,
+        "generateContent",
+        None,
+        |project_id| {
+            // 包装请求为 v1internal 格式
+            json!({
+                "project": project_id,
+                "requestId": format!("audio-{}", Uuid::new_v4()),
+                "request": gemini_request,
+                "model": model,
+                "userAgent": "antigravity",
+                "requestType": "text"
+            })
+        },
+    )
+    .await?
+    .into_json()
+    .await?;
+
+    info!("使用账号: {}", success.email);
 
-    // 9. 提取文本响应（解包 v1internal 响应）
-    let inner_response = result.get("response").unwrap_or(&result);
-    let text = inner_response
+    // 提取文本响应（跳过思考部分）
+    let text = success
+        .body
         .get("candidates")
         .and_then(|c| c.get(0))
         .and_then(|c| c.get("content"))
         .and_then(|c| c.get("parts"))
-        .and_then(|p| p.get(0))
-        .and_then(|p| p.get("text"))
-        .and_then(|t| t.as_str())
-        .unwrap_or("");
-
-    info!("音频转录完成，返回 {} 字符", text.len());
-
-    // 10. 返回标准格式响应
-    Ok((
-        StatusCode::OK,
-        [("X-Account-Email", email.as_str())],
-        Json(json!({
-            "text": text
-        }))
-    ).into_response())
+        .and_then(|p| p.as_array())
+        .map(|parts| {
+            parts
+                .iter()
+                .filter(|p| p.get("thought").and_then(|t| t.as_bool()) != Some(true))
+                .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                .collect::<String>()
+        })
+        .unwrap_or_default();
+
+    Ok((text, success.email))
+}
+
+/// 处理语音合成请求 (OpenAI /v1/audio/speech 兼容)
+pub async fn handle_audio_speech(
+    State(state): State<AppState>,
+    Json(req): Json<SpeechRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+
+    if req.input.trim().is_empty() {
+        return Err(bad_request("input 不能为空".to_string()));
+    }
+    if req.input.chars().count() > speech::MAX_INPUT_CHARS {
+        return Err(bad_request(format!(
+            "input 过长，最多 {} 个字符",
+            speech::MAX_INPUT_CHARS
+        )));
+    }
+    if let Some(speed) = req.speed {
+        if !(0.25..=4.0).contains(&speed) {
+            return Err(bad_request(format!("speed 需在 0.25 到 4.0 之间: {}", speed)));
+        }
+    }
+    let format = SpeechFormat::parse(req.response_format.as_deref()).map_err(bad_request)?;
+    let stream_mode = SpeechStream::parse(req.stream_format.as_deref()).map_err(bad_request)?;
+    let voice = speech::resolve_voice(&req.voice).map_err(bad_request)?;
+
+    // 自定义映射优先 (精确匹配)，否则按 TTS 默认规则映射
+    let model = state
+        .custom_mapping
+        .read()
+        .await
+        .get(&req.model)
+        .cloned()
+        .unwrap_or_else(|| speech::resolve_model(&req.model));
+
+    info!(
+        "收到语音合成请求: {} 字符, 模型={}, 音色={}, 格式={:?}, 流式={:?}",
+        req.input.chars().count(),
+        model,
+        voice,
+        format,
+        stream_mode
+    );
+
+    let prompt = speech::build_prompt(&req.input, req.instructions.as_deref(), req.speed);
+    let gemini_request = speech::build_gemini_request(&prompt, &voice);
+    let build_body = |project_id: &str| {
+        json!({
+            "project": project_id,
+            "requestId": format!("speech-{}", Uuid::new_v4()),
+            "request": gemini_request,
+            "model": model,
+            "userAgent": "antigravity",
+            "requestType": "text"
+        })
+    };
+
+    if stream_mode == SpeechStream::Off {
+        let success = send_v1_internal_with_retry(
+            &state.token_manager,
+            &state.upstream,
+            "text",
+            &model,
+            "generateContent",
+            None,
+            build_body,
+        )
+        .await?
+        .into_json()
+        .await?;
+        let (pcm, sample_rate) =
+            speech::extract_pcm(&success.body).map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+        if pcm.is_empty() {
+            return Err((StatusCode::BAD_GATEWAY, "上游未返回音频数据".to_string()));
+        }
+
+        info!("语音合成完成: {} bytes PCM, 账号 {}", pcm.len(), success.email);
+        let headers = [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::HeaderName::from_static("x-account-email"), success.email),
+            (header::HeaderName::from_static("x-mapped-model"), model),
+        ];
+        return Ok((headers, format.encode(&pcm, sample_rate)).into_response());
+    }
+
+    // 流式: 首个音频块到达前的错误仍走重试与账号轮换
+    let sent = send_v1_internal_with_retry(
+        &state.token_manager,
+        &state.upstream,
+        "text",
+        &model,
+        "streamGenerateContent",
+        Some("alt=sse"),
+        build_body,
+    )
+    .await?;
+    info!("语音合成流式输出，账号 {}", sent.email);
+
+    let mut upstream_stream = sent.response.bytes_stream();
+    let audio_stream = async_stream::stream! {
+        let mut events = speech::SseEventBuffer::default();
+        let mut header_sent = false;
+        let mut usage = None;
+        let mut finished = false;
+
+        while !finished {
+            let batch = match upstream_stream.next().await {
+                Some(Ok(data)) => events.push(&data),
+                Some(Err(e)) => {
+                    tracing::warn!("语音合成流中断: {}", e);
+                    yield Err(std::io::Error::other(e.to_string()));
+                    return;
+                }
+                None => {
+                    finished = true;
+                    events.finish().into_iter().collect()
+                }
+            };
+
+            for event in batch {
+                if let Some(u) = event.get("usageMetadata") {
+                    usage = Some(UpstreamUsage::from_gemini(u));
+                }
+                let (pcm, sample_rate) = match speech::extract_pcm(&event) {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        yield Err(std::io::Error::other(e));
+                        return;
+                    }
+                };
+                if pcm.is_empty() {
+                    continue;
+                }
+
+                // WAV 头在首块音频到达时写入 (此时才知道采样率)，长度标记为未知
+                let mut chunk = Vec::with_capacity(pcm.len() + 44);
+                if format == SpeechFormat::Wav && !header_sent {
+                    chunk.extend(speech::wav_header(sample_rate, None));
+                }
+                header_sent = true;
+                chunk.extend(pcm);
+
+                match stream_mode {
+                    SpeechStream::Sse => {
+                        let delta = json!({
+                            "type": "speech.audio.delta",
+                            "audio": AudioProcessor::encode_to_base64(&chunk)
+                        });
+                        yield Ok(bytes::Bytes::from(format!("data: {}\n\n", delta)));
+                    }
+                    _ => yield Ok(bytes::Bytes::from(chunk)),
+                }
+            }
+        }
+
+        if let Some(u) = usage {
+            report_upstream_usage(u);
+        }
+        if stream_mode == SpeechStream::Sse {
+            let u = usage.unwrap_or_default();
+            let done = json!({
+                "type": "speech.audio.done",
+                "usage": {
+                    "input_tokens": u.input,
+                    "output_tokens": u.output,
+                    "total_tokens": u.input + u.output
+                }
+            });
+            yield Ok(bytes::Bytes::from(format!("data: {}\n\n", done)));
+        }
+    };
+
+    let content_type = match stream_mode {
+        SpeechStream::Sse => "text/event-stream",
+        _ => format.content_type(),
+    };
+    let body = Body::from_stream(sent.lease.hold_stream(audio_stream));
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header("X-Account-Email", &sent.email)
+        .header("X-Mapped-Model", &model)
+        .body(body)
+        .unwrap()
+        .into_response())
 }
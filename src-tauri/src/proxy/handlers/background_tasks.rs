@@ -0,0 +1,146 @@
+// 后台任务分类试运行处理器
+//
+// 提供 /internal/background-tasks/classify 端点，用于对照已记录的请求调试分类规则：
+// - 直接提交一个 Claude 请求体
+// - 指定请求日志 ID 列表
+// - 取最近 N 条 /v1/messages 请求日志
+// 可附带候选配置 (config)，不影响正在生效的规则
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::proxy::background_tasks::{BackgroundTaskAction, BackgroundTaskClassifier, BackgroundTaskConfig};
+use crate::proxy::mappers::claude::models::ClaudeRequest;
+use crate::proxy::server::AppState;
+
+/// 单次试运行最多读取的日志条数
+const MAX_DRY_RUN_LOGS: usize = 1000;
+
+/// 试运行请求体
+#[derive(Debug, Deserialize)]
+pub struct ClassifyRequest {
+    /// 候选规则 (缺省时使用当前生效的规则)
+    pub config: Option<BackgroundTaskConfig>,
+    /// 直接提交的 Claude 请求体
+    pub request: Option<Value>,
+    /// 请求日志 ID 列表
+    #[serde(default)]
+    pub log_ids: Vec<String>,
+    /// 最近 N 条 /v1/messages 请求日志
+    pub recent: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct ClassifyResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_id: Option<String>,
+    model: Option<String>,
+    class: Option<String>,
+    action: Option<BackgroundTaskAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// POST /internal/background-tasks/classify
+pub async fn handle_classify(
+    State(state): State<AppState>,
+    Json(req): Json<ClassifyRequest>,
+) -> Response {
+    // 1. 收集待分类的请求体: (日志 ID, 请求体)
+    let mut samples: Vec<(Option<String>, Result<Value, String>)> = Vec::new();
+    if let Some(body) = req.request {
+        samples.push((None, Ok(body)));
+    }
+
+    let log_ids = req.log_ids;
+    let recent = req.recent.map(|n| n.min(MAX_DRY_RUN_LOGS));
+    let logged = tokio::task::spawn_blocking(move || -> Result<Vec<(String, Option<String>)>, String> {
+        let mut rows = Vec::new();
+        for id in log_ids {
+            let body = crate::modules::proxy_db::get_log_detail(&id).map(|log| log.request_body);
+            rows.push((id, body.unwrap_or(None)));
+        }
+        if let Some(limit) = recent {
+            for (id, body) in crate::modules::proxy_db::get_recent_request_bodies("/v1/messages", limit)? {
+                rows.push((id, Some(body)));
+            }
+        }
+        Ok(rows)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|r| r);
+
+    match logged {
+        Ok(rows) => {
+            for (id, body) in rows {
+                let parsed = body
+                    .ok_or_else(|| "request body not found".to_string())
+                    .and_then(|b| serde_json::from_str::<Value>(&b).map_err(|e| e.to_string()));
+                samples.push((Some(id), parsed));
+            }
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": format!("Failed to load request logs: {}", e) })),
+            )
+                .into_response();
+        }
+    }
+
+    if samples.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Provide one of `request`, `log_ids` or `recent`" })),
+        )
+            .into_response();
+    }
+
+    // 2. 使用候选规则或当前规则分类
+    let candidate = req.config.map(BackgroundTaskClassifier::new);
+    let current = state.background_tasks.read().await;
+    let classifier = candidate.as_ref().unwrap_or(&*current);
+
+    let mut summary: BTreeMap<String, u64> = BTreeMap::new();
+    let results: Vec<ClassifyResult> = samples
+        .into_iter()
+        .map(|(log_id, body)| {
+            let request = body.and_then(|v| serde_json::from_value::<ClaudeRequest>(v).map_err(|e| e.to_string()));
+            match request {
+                Ok(request) => {
+                    let matched = classifier.classify(&request);
+                    let key = matched.as_ref().map_or("none", |m| m.class.as_str());
+                    *summary.entry(key.to_string()).or_insert(0) += 1;
+                    ClassifyResult {
+                        log_id,
+                        model: Some(request.model),
+                        class: matched.as_ref().map(|m| m.class.clone()),
+                        action: matched.map(|m| m.action),
+                        error: None,
+                    }
+                }
+                Err(e) => ClassifyResult {
+                    log_id,
+                    model: None,
+                    class: None,
+                    action: None,
+                    error: Some(e),
+                },
+            }
+        })
+        .collect();
+
+    Json(json!({
+        "rule_errors": classifier.errors(),
+        "summary": summary,
+        "results": results,
+    }))
+    .into_response()
+}
@@ -0,0 +1,323 @@
+// OpenAI Batch API 处理器 (/v1/files、/v1/batches)
+use axum::{
+    extract::{Multipart, Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::modules::batch_db::{self, Batch};
+use crate::proxy::batch;
+
+/// 列表默认 / 最大分页大小
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    limit: Option<usize>,
+    after: Option<String>,
+    purpose: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBatchRequest {
+    input_file_id: String,
+    endpoint: String,
+    completion_window: String,
+    #[serde(default)]
+    metadata: Option<Value>,
+}
+
+async fn blocking<T, F>(f: F) -> Result<T, (StatusCode, String)>
+where
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+fn page_size(limit: Option<usize>) -> usize {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// OpenAI 列表对象
+fn list_json(data: Vec<Value>, has_more: bool) -> Value {
+    json!({
+        "object": "list",
+        "first_id": data.first().and_then(|v| v.get("id")).cloned(),
+        "last_id": data.last().and_then(|v| v.get("id")).cloned(),
+        "data": data,
+        "has_more": has_more,
+    })
+}
+
+/// POST /v1/files (multipart: file, purpose=batch)
+pub async fn handle_upload_file(
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mut purpose = None;
+    let mut file = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Multipart error: {}", e)))?
+    {
+        match field.name().unwrap_or("") {
+            "purpose" => {
+                purpose = Some(field.text().await.map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("Purpose read error: {}", e),
+                    )
+                })?);
+            }
+            "file" => {
+                let filename = field.file_name().unwrap_or("batch.jsonl").to_string();
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|e| (StatusCode::BAD_REQUEST, format!("File read error: {}", e)))?;
+                file = Some((filename, data));
+            }
+            _ => {}
+        }
+    }
+
+    match purpose.as_deref().map(str::trim) {
+        Some("batch") => {}
+        Some(other) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Unsupported purpose '{}': only purpose=batch is supported",
+                    other
+                ),
+            ))
+        }
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Missing 'purpose' field".to_string(),
+            ))
+        }
+    }
+    let (filename, data) =
+        file.ok_or((StatusCode::BAD_REQUEST, "Missing 'file' field".to_string()))?;
+    if data.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The uploaded file is empty".to_string(),
+        ));
+    }
+    if data.len() > batch::MAX_FILE_BYTES {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "File exceeds the {} MB limit",
+                batch::MAX_FILE_BYTES / 1024 / 1024
+            ),
+        ));
+    }
+
+    let stored = blocking(move || batch::save_file("batch", &filename, &data)).await?;
+    tracing::info!(
+        "[Batch] Stored input file {} ({} bytes)",
+        stored.id,
+        stored.bytes
+    );
+    Ok(Json(stored.to_api_json()))
+}
+
+/// GET /v1/files
+pub async fn handle_list_files(
+    Query(query): Query<ListQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let limit = page_size(query.limit);
+    let (files, has_more) = blocking(move || {
+        batch_db::list_files(query.purpose.as_deref(), query.after.as_deref(), limit)
+    })
+    .await?;
+    Ok(Json(list_json(
+        files.iter().map(|f| f.to_api_json()).collect(),
+        has_more,
+    )))
+}
+
+/// GET /v1/files/:id
+pub async fn handle_get_file(
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let lookup = id.clone();
+    blocking(move || batch_db::get_file(&lookup))
+        .await?
+        .map(|f| Json(f.to_api_json()))
+        .ok_or((StatusCode::NOT_FOUND, format!("File not found: {}", id)))
+}
+
+/// DELETE /v1/files/:id
+pub async fn handle_delete_file(
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if !batch::is_valid_file_id(&id) {
+        return Err((StatusCode::NOT_FOUND, format!("File not found: {}", id)));
+    }
+    let target = id.clone();
+    if blocking(move || batch::delete_file(&target)).await? {
+        Ok(Json(json!({ "id": id, "object": "file", "deleted": true })))
+    } else {
+        Err((StatusCode::NOT_FOUND, format!("File not found: {}", id)))
+    }
+}
+
+/// GET /v1/files/:id/content
+pub async fn handle_get_file_content(
+    Path(id): Path<String>,
+) -> Result<Response, (StatusCode, String)> {
+    let lookup = id.clone();
+    if blocking(move || batch_db::get_file(&lookup))
+        .await?
+        .is_none()
+    {
+        return Err((StatusCode::NOT_FOUND, format!("File not found: {}", id)));
+    }
+    let content = blocking(move || batch::read_file(&id)).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        content,
+    )
+        .into_response())
+}
+
+/// POST /v1/batches
+pub async fn handle_create_batch(
+    headers: HeaderMap,
+    Json(req): Json<CreateBatchRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if req.endpoint != batch::SUPPORTED_ENDPOINT {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Unsupported endpoint '{}': only {} is supported",
+                req.endpoint,
+                batch::SUPPORTED_ENDPOINT
+            ),
+        ));
+    }
+    if req.completion_window != batch::COMPLETION_WINDOW {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("completion_window must be '{}'", batch::COMPLETION_WINDOW),
+        ));
+    }
+    if req
+        .metadata
+        .as_ref()
+        .is_some_and(|m| !m.is_object() && !m.is_null())
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "metadata must be an object".to_string(),
+        ));
+    }
+
+    let file_id = req.input_file_id.clone();
+    let input = blocking(move || batch_db::get_file(&file_id))
+        .await?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("File not found: {}", req.input_file_id),
+        ))?;
+    if input.purpose != "batch" {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "File {} has purpose '{}', expected 'batch'",
+                input.id, input.purpose
+            ),
+        ));
+    }
+
+    let now = batch::now_secs();
+    let created = Batch {
+        id: batch::new_batch_id(),
+        endpoint: req.endpoint,
+        input_file_id: input.id,
+        completion_window: req.completion_window,
+        status: "validating".to_string(),
+        output_file_id: None,
+        error_file_id: None,
+        errors: None,
+        metadata: req.metadata.filter(|m| !m.is_null()),
+        created_at: now,
+        in_progress_at: None,
+        expires_at: now + batch::COMPLETION_WINDOW_SECS,
+        finalizing_at: None,
+        completed_at: None,
+        failed_at: None,
+        expired_at: None,
+        cancelling_at: None,
+        cancelled_at: None,
+        total: 0,
+        completed: 0,
+        failed: 0,
+        api_key: crate::proxy::request_queue::api_key_from_headers(&headers)
+            .filter(|k| !k.is_empty())
+            .map(str::to_string),
+    };
+    let record = created.clone();
+    blocking(move || batch_db::insert_batch(&record)).await?;
+    batch::worker::wake();
+    tracing::info!(
+        "[Batch] Created {} from {}",
+        created.id,
+        created.input_file_id
+    );
+    Ok(Json(created.to_api_json()))
+}
+
+/// GET /v1/batches
+pub async fn handle_list_batches(
+    Query(query): Query<ListQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let limit = page_size(query.limit);
+    let (batches, has_more) =
+        blocking(move || batch_db::list_batches(query.after.as_deref(), limit)).await?;
+    Ok(Json(list_json(
+        batches.iter().map(|b| b.to_api_json()).collect(),
+        has_more,
+    )))
+}
+
+/// GET /v1/batches/:id
+pub async fn handle_get_batch(
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let lookup = id.clone();
+    blocking(move || batch_db::get_batch(&lookup))
+        .await?
+        .map(|b| Json(b.to_api_json()))
+        .ok_or((StatusCode::NOT_FOUND, format!("Batch not found: {}", id)))
+}
+
+/// POST /v1/batches/:id/cancel
+pub async fn handle_cancel_batch(
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let target = id.clone();
+    let found = blocking(move || batch_db::request_cancel(&target, batch::now_secs()))
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, format!("Batch not found: {}", id)))?;
+    if found.status != "cancelling" {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Cannot cancel a batch with status '{}'", found.status),
+        ));
+    }
+    batch::worker::wake();
+    Ok(Json(found.to_api_json()))
+}
@@ -9,8 +9,8 @@ use axum::{
 use bytes::Bytes;
 use futures::StreamExt;
 use serde_json::{json, Value};
-use tokio::time::{sleep, Duration};
-use tracing::{debug, error, info};
+use tokio::time::Duration;
+use tracing::{debug, error, info, Instrument};
 
 use crate::proxy::mappers::claude::{
     transform_claude_request_in, transform_response, create_claude_sse_stream, ClaudeRequest,
@@ -23,12 +23,17 @@ use crate::proxy::mappers::context_manager::ContextManager;
 use crate::proxy::mappers::estimation_calibrator::get_calibrator;
 use axum::http::HeaderMap;
 use std::sync::{atomic::Ordering, Arc};
-
-const MAX_RETRY_ATTEMPTS: usize = 3;
+use crate::proxy::background_tasks::BackgroundTaskAction;
+use crate::proxy::hedging::{call_generate_with_hedge, is_hedge_eligible, HedgeOutcome};
+use crate::proxy::response_cache::{self, CacheStatus};
+use crate::proxy::upstream::retry::{
+    apply_retry_strategy, determine_retry_strategy, should_rotate_account, RetryStrategy,
+    MAX_RETRY_ATTEMPTS,
+};
 
 // ===== Model Constants for Background Tasks =====
 // These can be adjusted for performance/cost optimization or overridden by custom_mapping
-const INTERNAL_BACKGROUND_TASK: &str = "internal-background-task";  // Unified virtual ID for all background tasks
+const INTERNAL_BACKGROUND_TASK: &str = crate::proxy::background_tasks::DEFAULT_BACKGROUND_MODEL;  // Unified virtual ID for all background tasks
 
 // ===== Layer 3: XML Summary Prompt Template =====
 // Borrowed from Practical-Guide-to-Context-Engineering + Claude Code official practice
@@ -94,148 +99,6 @@ The structure MUST be as follows:
 // Jitter was causing connection instability, reverted to fixed delays
 // const JITTER_FACTOR: f64 = 0.2;
 
-
-// ===== 统一退避策略模块 =====
-
-// [REMOVED] apply_jitter function
-// Jitter logic removed to restore stability (v3.3.16 fix)
-
-/// 重试策略枚举
-#[derive(Debug, Clone)]
-enum RetryStrategy {
-    /// 不重试，直接返回错误
-    NoRetry,
-    /// 固定延迟
-    FixedDelay(Duration),
-    /// 线性退避：base_ms * (attempt + 1)
-    LinearBackoff { base_ms: u64 },
-    /// 指数退避：base_ms * 2^attempt，上限 max_ms
-    ExponentialBackoff { base_ms: u64, max_ms: u64 },
-}
-
-/// 根据错误状态码和错误信息确定重试策略
-fn determine_retry_strategy(
-    status_code: u16,
-    error_text: &str,
-    retried_without_thinking: bool,
-) -> RetryStrategy {
-    match status_code {
-        // 400 错误：Thinking 签名失败
-        400 if !retried_without_thinking
-            && (error_text.contains("Invalid `signature`")
-                || error_text.contains("thinking.signature")
-                || error_text.contains("thinking.thinking")) =>
-        {
-            // 固定 200ms 延迟后重试
-            RetryStrategy::FixedDelay(Duration::from_millis(200))
-        }
-
-        // 429 限流错误
-        429 => {
-            // 优先使用服务端返回的 Retry-After
-            if let Some(delay_ms) = crate::proxy::upstream::retry::parse_retry_delay(error_text) {
-                let actual_delay = delay_ms.saturating_add(200).min(10_000);
-                RetryStrategy::FixedDelay(Duration::from_millis(actual_delay))
-            } else {
-                // 否则使用线性退避：1s, 2s, 3s
-                RetryStrategy::LinearBackoff { base_ms: 1000 }
-            }
-        }
-
-        // 503 服务不可用 / 529 服务器过载
-        503 | 529 => {
-            // 指数退避：1s, 2s, 4s, 8s
-            RetryStrategy::ExponentialBackoff {
-                base_ms: 1000,
-                max_ms: 8000,
-            }
-        }
-
-        // 500 服务器内部错误
-        500 => {
-            // 线性退避：500ms, 1s, 1.5s
-            RetryStrategy::LinearBackoff { base_ms: 500 }
-        }
-
-        // 401/403 认证/权限错误：可重试（轮换账号）
-        401 | 403 => RetryStrategy::FixedDelay(Duration::from_millis(100)),
-
-        // 其他错误：不重试
-        _ => RetryStrategy::NoRetry,
-    }
-}
-
-/// 执行退避策略并返回是否应该继续重试
-async fn apply_retry_strategy(
-    strategy: RetryStrategy,
-    attempt: usize,
-    status_code: u16,
-    trace_id: &str,
-) -> bool {
-    match strategy {
-        RetryStrategy::NoRetry => {
-            debug!("[{}] Non-retryable error {}, stopping", trace_id, status_code);
-            false
-        }
-
-        RetryStrategy::FixedDelay(duration) => {
-            let base_ms = duration.as_millis() as u64;
-            info!(
-                "[{}] ⏱️  Retry with fixed delay: status={}, attempt={}/{}, base={}ms",
-                trace_id,
-                status_code,
-                attempt + 1,
-                MAX_RETRY_ATTEMPTS,
-                base_ms
-            );
-            sleep(duration).await;
-            true
-        }
-
-        RetryStrategy::LinearBackoff { base_ms } => {
-            let calculated_ms = base_ms * (attempt as u64 + 1);
-            info!(
-                "[{}] ⏱️  Retry with linear backoff: status={}, attempt={}/{}, base={}ms",
-                trace_id,
-                status_code,
-                attempt + 1,
-                MAX_RETRY_ATTEMPTS,
-                calculated_ms
-            );
-            sleep(Duration::from_millis(calculated_ms)).await;
-            true
-        }
-
-        RetryStrategy::ExponentialBackoff { base_ms, max_ms } => {
-            let calculated_ms = (base_ms * 2_u64.pow(attempt as u32)).min(max_ms);
-            info!(
-                "[{}] ⏱️  Retry with exponential backoff: status={}, attempt={}/{}, base={}ms",
-                trace_id,
-                status_code,
-                attempt + 1,
-                MAX_RETRY_ATTEMPTS,
-                calculated_ms
-            );
-            sleep(Duration::from_millis(calculated_ms)).await;
-            true
-        }
-    }
-}
-
-/// 判断是否应该轮换账号
-fn should_rotate_account(status_code: u16) -> bool {
-    match status_code {
-        // 这些错误是账号级别的，需要轮换
-        429 | 401 | 403 | 500 => true,
-        // 这些错误是服务端级别的，轮换账号无意义
-        400 | 503 | 529 => false,
-        // 其他错误默认不轮换
-        _ => false,
-    }
-}
-
-// ===== 退避策略模块结束 =====
-
 /// 处理 Claude messages 请求
 /// 
 /// 处理 Chat 消息请求流程
@@ -251,6 +114,8 @@ pub async fn handle_messages(
         .take(6)
         .map(char::from)
         .collect::<String>().to_lowercase();
+    // 关联本地日志中的 trace_id 与导出的链路
+    tracing::Span::current().record("proxy.trace_id", trace_id.as_str());
         
     // Decide whether this request should be handled by z.ai (Anthropic passthrough) or the existing Google flow.
     let zai = state.zai.read().await.clone();
@@ -337,7 +202,7 @@ pub async fn handle_messages(
 
     // [New] Recover from broken tool loops (where signatures were stripped)
     // This prevents "Assistant message must start with thinking" errors by closing the loop with synthetic messages
-    if state.experimental.read().await.enable_tool_loop_recovery {
+    if crate::proxy::replay::experimental_config(&state.experimental).await.enable_tool_loop_recovery {
         close_tool_loop_for_thinking(&mut request.messages);
     }
 
@@ -352,6 +217,21 @@ pub async fn handle_messages(
         return create_warmup_response(&request, request.stream);
     }
 
+    // ===== 后台任务分类 (规则见 ProxyConfig.background_tasks) =====
+    // 命中 respond 规则的请求直接返回固定文本；route 规则在下方 Google Flow 中改写目标模型
+    let background_task = state.background_tasks.read().await.classify(&request);
+    let background_task_header = background_task
+        .as_ref()
+        .and_then(|t| axum::http::HeaderValue::from_str(&t.class).ok());
+    if let Some(task) = &background_task {
+        state.monitor.record_background_task(&task.class).await;
+        if let BackgroundTaskAction::Respond { text } = &task.action {
+            info!("[{}][AUTO] 后台任务 '{}' 命中固定响应规则，不请求上游", trace_id, task.class);
+            let response = create_canned_response(&request, request.stream, text, "X-Background-Task-Intercepted");
+            return tag_background_task(response, background_task_header.as_ref());
+        }
+    }
+
     if use_zai {
         // 重新序列化修复后的请求体
         let new_body = match serde_json::to_value(&request) {
@@ -376,11 +256,12 @@ pub async fn handle_messages(
     // (后续代码不需要再次 filter_invalid_thinking_blocks)
     
     // [NEW] 获取上下文控制配置
-    let experimental = state.experimental.read().await;
+    let experimental = crate::proxy::replay::experimental_config(&state.experimental).await;
     let scaling_enabled = experimental.enable_usage_scaling;
     let threshold_l1 = experimental.context_compression_threshold_l1;
     let threshold_l2 = experimental.context_compression_threshold_l2;
     let threshold_l3 = experimental.context_compression_threshold_l3;
+    let cache_config = experimental.response_cache.clone();
 
     // 获取最新一条“有意义”的消息内容（用于日志记录和后台任务检测）
     // 策略：反向遍历，首先筛选出所有角色为 "user" 的消息，然后从中找到第一条非 "Warmup" 且非空的文本消息
@@ -492,7 +373,67 @@ pub async fn handle_messages(
     let mut last_error = String::new();
     let retried_without_thinking = false;
     let mut last_email: Option<String> = None;
-    
+
+    // 【新增】精确匹配响应缓存：在获取账号之前查询，命中时直接回放，不消耗账号配额与并发
+    // (缓存键基于客户端原始请求与最终模型计算，已剔除 project / requestId / sessionId，与账号及重试无关)
+    let cache_model = match background_task.as_ref().map(|t| &t.action) {
+        Some(BackgroundTaskAction::Route { model }) => crate::proxy::common::model_mapping::resolve_model_route(
+            model,
+            &*state.custom_mapping.read().await,
+        ),
+        _ => crate::proxy::common::model_mapping::resolve_model_route(
+            &request_for_body.model,
+            &*state.custom_mapping.read().await,
+        ),
+    };
+    let cache_key = if cache_config.enabled {
+        let mut cache_request = request_for_body.clone();
+        cache_request.model = cache_model.clone();
+        transform_claude_request_in(&cache_request, "", retried_without_thinking)
+            .ok()
+            .and_then(|cache_body| response_cache::cache_key(&cache_config, &headers, &cache_model, &cache_body))
+    } else {
+        None
+    };
+    if let Some(key) = cache_key.as_deref() {
+        if let Some(cached) = response_cache::lookup(key).await {
+            info!("[{}] Response cache hit for {}", trace_id, cache_model);
+            let session_id_str = crate::proxy::session_manager::SessionManager::extract_session_id(&request_for_body);
+            let context_limit = crate::proxy::mappers::claude::utils::get_context_limit_for_model(&cache_model);
+            let mut response = if request.stream {
+                let claude_stream = create_claude_sse_stream(
+                    response_cache::replay_stream(&cached),
+                    trace_id.clone(),
+                    String::new(),
+                    Some(session_id_str),
+                    scaling_enabled,
+                    context_limit,
+                    None,
+                );
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header(header::CONTENT_TYPE, "text/event-stream")
+                    .header(header::CACHE_CONTROL, "no-cache")
+                    .header(header::CONNECTION, "keep-alive")
+                    .header("X-Accel-Buffering", "no")
+                    .header("X-Mapped-Model", &cache_model)
+                    .body(Body::from_stream(claude_stream))
+                    .unwrap()
+            } else {
+                let raw = cached.get("response").unwrap_or(&cached).clone();
+                let claude_response = serde_json::from_value::<crate::proxy::mappers::claude::models::GeminiResponse>(raw)
+                    .map_err(|e| e.to_string())
+                    .and_then(|r| transform_response(&r, scaling_enabled, context_limit, Some(session_id_str), cache_model.clone()));
+                match claude_response {
+                    Ok(r) => (StatusCode::OK, [("X-Mapped-Model", cache_model.as_str())], Json(r)).into_response(),
+                    Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Cached response transform error: {}", e)).into_response(),
+                }
+            };
+            response_cache::annotate_response(&mut response, CacheStatus::Hit);
+            return tag_background_task(response, background_task_header.as_ref());
+        }
+    }
+
     for attempt in 0..max_attempts {
         // 2. 模型路由解析
         let mut mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
@@ -519,7 +460,7 @@ pub async fn handle_messages(
         let session_id = Some(session_id_str.as_str());
 
         let force_rotate_token = attempt > 0;
-        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, force_rotate_token, session_id, &config.final_model).await {
+        let (access_token, project_id, email, lease) = match token_manager.get_token(&config.request_type, force_rotate_token, session_id, &config.final_model).await {
             Ok(t) => t,
             Err(e) => {
                 let safe_message = if e.contains("invalid_grant") {
@@ -545,16 +486,13 @@ pub async fn handle_messages(
         
         
         // ===== 【优化】后台任务智能检测与降级 =====
-        // 使用新的检测系统，支持 5 大类关键词和多 Flash 模型策略
-        let background_task_type = detect_background_task_type(&request_for_body);
-        
+        // 分类在循环外完成 (见 ProxyConfig.background_tasks)，此处按规则的目标模型重定向
         // 传递映射后的模型名
         let mut request_with_mapped = request_for_body.clone();
 
-        if let Some(task_type) = background_task_type {
-            // 检测到后台任务,强制降级到 Flash 模型
-            let virtual_model_id = select_background_model(task_type);
-            
+        if let Some((task, BackgroundTaskAction::Route { model: virtual_model_id })) =
+            background_task.as_ref().map(|t| (t, &t.action))
+        {
             // [FIX] 必须根据虚拟 ID Re-resolve 路由，以支持用户自定义映射 (如 internal-task -> gemini-3)
             // 否则会直接使用 generic ID 导致下游无法识别或只能使用静态默认值
             let resolved_model = crate::proxy::common::model_mapping::resolve_model_route(
@@ -563,9 +501,9 @@ pub async fn handle_messages(
             );
 
             info!(
-                "[{}][AUTO] 检测到后台任务 (类型: {:?}), 路由重定向: {} -> {} (最终物理模型: {})",
+                "[{}][AUTO] 检测到后台任务 (类型: {}), 路由重定向: {} -> {} (最终物理模型: {})",
                 trace_id,
-                task_type,
+                task.class,
                 mapped_model,
                 virtual_model_id,
                 resolved_model
@@ -769,11 +707,16 @@ pub async fn handle_messages(
                 ).into_response();
             }
         };
-        
+
     // 4. 上游调用 - 自动转换逻辑
     let client_wants_stream = request.stream;
+    // 对冲以完整响应的延迟为阈值，启用对冲时符合条件的非流式请求保持非流式
+    let hedging_config = crate::proxy::replay::experimental_config(&state.experimental).await.hedging.clone();
+    let hedge_eligible = !client_wants_stream
+        && hedging_config.enabled
+        && is_hedge_eligible(&config.request_type, &tools_val);
     // [AUTO-CONVERSION] 非 Stream 请求自动转换为 Stream 以享受更宽松的配额
-    let force_stream_internally = !client_wants_stream;
+    let force_stream_internally = !client_wants_stream && !hedge_eligible;
     let actual_stream = client_wants_stream || force_stream_internally;
     
     if force_stream_internally {
@@ -790,18 +733,59 @@ pub async fn handle_messages(
         }
 
         // 5. 上游调用
-        let response = match upstream
-            .call_v1_internal_with_headers(method, &access_token, gemini_body, query, extra_headers.clone())
-            .await {
+        let attempt_span = crate::proxy::telemetry::attempt_span(attempt, &email, &request_with_mapped.model);
+        // 【新增】非流式请求超过近期延迟分位数时在另一账号上对冲
+        let (response, hedge_outcome, hedge_account) = async {
+            if !hedge_eligible {
+                let response = upstream
+                    .call_v1_internal_with_headers(method, &access_token, gemini_body, query, extra_headers.clone())
+                    .await;
+                (response, HedgeOutcome::NotFired, None)
+            } else {
+                let hedge_delay = crate::proxy::hedging::hedge_delay(&hedging_config, &request_with_mapped.model);
+                let call = call_generate_with_hedge(
+                    &token_manager,
+                    &upstream,
+                    hedge_delay,
+                    &access_token,
+                    gemini_body.clone(),
+                    extra_headers.clone(),
+                    &email,
+                    &config.request_type,
+                    &config.final_model,
+                    &request_with_mapped.model,
+                    |hedge_project_id| {
+                        let mut body = gemini_body.clone();
+                        body["project"] = json!(hedge_project_id);
+                        body
+                    },
+                )
+                .await;
+                (call.response, call.outcome, call.hedge_account)
+            }
+        }
+        .instrument(attempt_span.clone())
+        .await;
+
+        let response = match response {
             Ok(r) => r,
             Err(e) => {
+                attempt_span.record("otel.status_code", "ERROR");
                 last_error = e.clone();
                 debug!("Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
                 continue;
             }
         };
+
+        // 对冲请求胜出时，后续的错误处理与日志归属到对冲账号
+        let (email, _hedge_lease) = match hedge_account {
+            Some(h) => (h.email, Some(h.lease)),
+            None => (email, None),
+        };
+        last_email = Some(email.clone());
         
         let status = response.status();
+        crate::proxy::telemetry::record_status(&attempt_span, status.as_u16());
         
         // 成功
         if status.is_success() {
@@ -813,8 +797,14 @@ pub async fn handle_messages(
 
             // 处理流式响应
             if actual_stream {
-                let stream = response.bytes_stream();
-                let gemini_stream = Box::pin(stream);
+                // 可缓存的请求在流正常结束后写入响应缓存
+                // 上报未经缩放的真实用量 (含思考 token)，供监控与费用统计使用
+                let gemini_stream = crate::proxy::monitor::report_stream_usage(response_cache::record_stream(
+                    response.bytes_stream(),
+                    &cache_config,
+                    cache_key.clone(),
+                    &request_with_mapped.model,
+                ));
 
 
                 // [FIX #530/#529/#859] Enhanced Peek logic to handle heartbeats and slow start
@@ -892,7 +882,7 @@ pub async fn handle_messages(
                         // 判断客户端期望的格式
                         if client_wants_stream {
                             // 客户端本就要 Stream，直接返回 SSE
-                            return Response::builder()
+                            let mut response = Response::builder()
                                 .status(StatusCode::OK)
                                 .header(header::CONTENT_TYPE, "text/event-stream")
                                 .header(header::CACHE_CONTROL, "no-cache")
@@ -901,8 +891,12 @@ pub async fn handle_messages(
                                 .header("X-Account-Email", &email)
                                 .header("X-Mapped-Model", &request_with_mapped.model)
                                 .header("X-Context-Purified", if is_purified { "true" } else { "false" })
-                                .body(Body::from_stream(combined_stream))
+                                .body(Body::from_stream(lease.hold_stream(combined_stream)))
                                 .unwrap();
+                            if cache_key.is_some() {
+                                response_cache::annotate_response(&mut response, CacheStatus::Miss);
+                            }
+                            return tag_background_task(response, background_task_header.as_ref());
                         } else {
                             // 客户端要非 Stream，需要收集完整响应并转换为 JSON
                             use crate::proxy::mappers::claude::collect_stream_to_json;
@@ -910,7 +904,7 @@ pub async fn handle_messages(
                             match collect_stream_to_json(combined_stream).await {
                                 Ok(full_response) => {
                                     info!("[{}] ✓ Stream collected and converted to JSON", trace_id);
-                                    return Response::builder()
+                                    let mut response = Response::builder()
                                         .status(StatusCode::OK)
                                         .header(header::CONTENT_TYPE, "application/json")
                                         .header("X-Account-Email", &email)
@@ -918,6 +912,10 @@ pub async fn handle_messages(
                                         .header("X-Context-Purified", if is_purified { "true" } else { "false" })
                                         .body(Body::from(serde_json::to_string(&full_response).unwrap()))
                                         .unwrap();
+                                    if cache_key.is_some() {
+                                        response_cache::annotate_response(&mut response, CacheStatus::Miss);
+                                    }
+                                    return tag_background_task(response, background_task_header.as_ref());
                                 }
                                 Err(e) => {
                                     return (StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)).into_response();
@@ -949,6 +947,9 @@ pub async fn handle_messages(
                     Err(e) => return (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)).into_response(),
                 };
 
+                // 上报未经缩放的真实用量 (含思考 token)，供监控与费用统计使用
+                crate::proxy::monitor::report_response_usage(&gemini_resp);
+
                 // 解包 response 字段（v1internal 格式）
                 let raw = gemini_resp.get("response").unwrap_or(&gemini_resp);
 
@@ -985,7 +986,13 @@ pub async fn handle_messages(
                     cache_info
                 );
 
-                return (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", request_with_mapped.model.as_str())], Json(claude_response)).into_response();
+                let mut response = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", request_with_mapped.model.as_str())], Json(claude_response)).into_response();
+                crate::proxy::hedging::annotate_response(&mut response, hedge_outcome);
+                if let Some(key) = cache_key.clone() {
+                    response_cache::store(&cache_config, key, &request_with_mapped.model, &gemini_resp);
+                    response_cache::annotate_response(&mut response, CacheStatus::Miss);
+                }
+                return tag_background_task(response, background_task_header.as_ref());
             }
         }
         
@@ -1230,152 +1237,12 @@ mod tests {
 }
 */
 
-// ===== 后台任务检测辅助函数 =====
-
-/// 后台任务类型
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum BackgroundTaskType {
-    TitleGeneration,      // 标题生成
-    SimpleSummary,        // 简单摘要
-    ContextCompression,   // 上下文压缩
-    PromptSuggestion,     // 提示建议
-    SystemMessage,        // 系统消息
-    EnvironmentProbe,     // 环境探测
-}
-
-/// 标题生成关键词
-const TITLE_KEYWORDS: &[&str] = &[
-    "write a 5-10 word title",
-    "Please write a 5-10 word title",
-    "Respond with the title",
-    "Generate a title for",
-    "Create a brief title",
-    "title for the conversation",
-    "conversation title",
-    "生成标题",
-    "为对话起个标题",
-];
-
-/// 摘要生成关键词
-const SUMMARY_KEYWORDS: &[&str] = &[
-    "Summarize this coding conversation",
-    "Summarize the conversation",
-    "Concise summary",
-    "in under 50 characters",
-    "compress the context",
-    "Provide a concise summary",
-    "condense the previous messages",
-    "shorten the conversation history",
-    "extract key points from",
-];
-
-/// 建议生成关键词
-const SUGGESTION_KEYWORDS: &[&str] = &[
-    "prompt suggestion generator",
-    "suggest next prompts",
-    "what should I ask next",
-    "generate follow-up questions",
-    "recommend next steps",
-    "possible next actions",
-];
-
-/// 系统消息关键词
-const SYSTEM_KEYWORDS: &[&str] = &[
-    "Warmup",
-    "<system-reminder>",
-    // Removed: "Caveat: The messages below were generated" - this is a normal Claude Desktop system prompt
-    "This is a system message",
-];
-
-/// 环境探测关键词
-const PROBE_KEYWORDS: &[&str] = &[
-    "check current directory",
-    "list available tools",
-    "verify environment",
-    "test connection",
-];
-
-/// 检测后台任务并返回任务类型
-fn detect_background_task_type(request: &ClaudeRequest) -> Option<BackgroundTaskType> {
-    let last_user_msg = extract_last_user_message_for_detection(request)?;
-    let preview = last_user_msg.chars().take(500).collect::<String>();
-    
-    // 长度过滤：后台任务通常不超过 800 字符
-    if last_user_msg.len() > 800 {
-        return None;
-    }
-    
-    // 按优先级匹配
-    if matches_keywords(&preview, SYSTEM_KEYWORDS) {
-        return Some(BackgroundTaskType::SystemMessage);
-    }
-    
-    if matches_keywords(&preview, TITLE_KEYWORDS) {
-        return Some(BackgroundTaskType::TitleGeneration);
-    }
-    
-    if matches_keywords(&preview, SUMMARY_KEYWORDS) {
-        if preview.contains("in under 50 characters") {
-            return Some(BackgroundTaskType::SimpleSummary);
-        }
-        return Some(BackgroundTaskType::ContextCompression);
-    }
-    
-    if matches_keywords(&preview, SUGGESTION_KEYWORDS) {
-        return Some(BackgroundTaskType::PromptSuggestion);
-    }
-    
-    if matches_keywords(&preview, PROBE_KEYWORDS) {
-        return Some(BackgroundTaskType::EnvironmentProbe);
-    }
-    
-    None
-}
-
-/// 辅助函数：关键词匹配
-fn matches_keywords(text: &str, keywords: &[&str]) -> bool {
-    keywords.iter().any(|kw| text.contains(kw))
-}
-
-/// 辅助函数：提取最后一条用户消息（用于检测）
-fn extract_last_user_message_for_detection(request: &ClaudeRequest) -> Option<String> {
-    request.messages.iter().rev()
-        .filter(|m| m.role == "user")
-        .find_map(|m| {
-            let content = match &m.content {
-                crate::proxy::mappers::claude::models::MessageContent::String(s) => s.to_string(),
-                crate::proxy::mappers::claude::models::MessageContent::Array(arr) => {
-                    arr.iter()
-                        .filter_map(|block| match block {
-                            crate::proxy::mappers::claude::models::ContentBlock::Text { text } => Some(text.as_str()),
-                            _ => None,
-                        })
-                        .collect::<Vec<_>>()
-                        .join(" ")
-                }
-            };
-            
-            if content.trim().is_empty() 
-                || content.starts_with("Warmup") 
-                || content.contains("<system-reminder>") 
-            {
-                None 
-            } else {
-                Some(content)
-            }
-        })
-}
-
-/// 根据后台任务类型选择合适的模型
-fn select_background_model(task_type: BackgroundTaskType) -> &'static str {
-    match task_type {
-        BackgroundTaskType::TitleGeneration => INTERNAL_BACKGROUND_TASK,
-        BackgroundTaskType::SimpleSummary => INTERNAL_BACKGROUND_TASK,
-        BackgroundTaskType::SystemMessage => INTERNAL_BACKGROUND_TASK,
-        BackgroundTaskType::PromptSuggestion => INTERNAL_BACKGROUND_TASK,
-        BackgroundTaskType::EnvironmentProbe => INTERNAL_BACKGROUND_TASK,
-        BackgroundTaskType::ContextCompression => INTERNAL_BACKGROUND_TASK,
+/// 为后台任务响应附加分类标记 (供监控中间件统计各分类命中次数)
+fn tag_background_task(mut response: Response, class: Option<&axum::http::HeaderValue>) -> Response {
+    if let Some(class) = class {
+        response.headers_mut().insert("X-Background-Task", class.clone());
     }
+    response
 }
 
 // ===== [Issue #467 Fix] Warmup 请求拦截 =====
@@ -1439,11 +1306,21 @@ fn is_warmup_request(request: &ClaudeRequest) -> bool {
 /// 
 /// 返回一个简单的响应，不消耗上游配额
 fn create_warmup_response(request: &ClaudeRequest, is_stream: bool) -> Response {
+    create_canned_response(request, is_stream, "OK", "X-Warmup-Intercepted")
+}
+
+/// 创建固定文本的模拟响应 (Warmup 拦截与后台任务 respond 规则共用)
+fn create_canned_response(request: &ClaudeRequest, is_stream: bool, text: &str, marker_header: &'static str) -> Response {
     let model = &request.model;
     let message_id = format!("msg_warmup_{}", chrono::Utc::now().timestamp_millis());
     
     if is_stream {
         // 流式响应：发送标准的 SSE 事件序列
+        let delta = json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": { "type": "text_delta", "text": text }
+        });
         let events = vec![
             // message_start
             format!(
@@ -1453,7 +1330,7 @@ fn create_warmup_response(request: &ClaudeRequest, is_stream: bool) -> Response
             // content_block_start
             "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n".to_string(),
             // content_block_delta
-            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"OK\"}}\n\n".to_string(),
+            format!("event: content_block_delta\ndata: {}\n\n", delta),
             // content_block_stop
             "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n".to_string(),
             // message_delta
@@ -1469,7 +1346,7 @@ fn create_warmup_response(request: &ClaudeRequest, is_stream: bool) -> Response
             .header(header::CONTENT_TYPE, "text/event-stream")
             .header(header::CACHE_CONTROL, "no-cache")
             .header(header::CONNECTION, "keep-alive")
-            .header("X-Warmup-Intercepted", "true")
+            .header(marker_header, "true")
             .body(Body::from(body))
             .unwrap()
     } else {
@@ -1480,7 +1357,7 @@ fn create_warmup_response(request: &ClaudeRequest, is_stream: bool) -> Response
             "role": "assistant",
             "content": [{
                 "type": "text",
-                "text": "OK"
+                "text": text
             }],
             "model": model,
             "stop_reason": "end_turn",
@@ -1493,10 +1370,8 @@ fn create_warmup_response(request: &ClaudeRequest, is_stream: bool) -> Response
         
         (
             StatusCode::OK,
-            [("X-Warmup-Intercepted", "true")],
-
-    
-    Json(response)
+            [(marker_header, "true")],
+            Json(response)
         ).into_response()
     }
 }
@@ -1516,7 +1391,7 @@ async fn call_gemini_sync(
     trace_id: &str,
 ) -> Result<String, String> {
     // Get token and transform request
-    let (access_token, project_id, _) = token_manager
+    let (access_token, project_id, _, _lease) = token_manager
         .get_token("gemini", false, None, model)
         .await
         .map_err(|e| format!("Failed to get account: {}", e))?;
@@ -0,0 +1,278 @@
+// 本地文件访问处理器 (生成图片的链接、Gemini Files API)
+use axum::{
+    body::Body,
+    extract::{Path, Query, RawQuery},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+
+use crate::proxy::gemini_files::{self, ChunkError, FileStore};
+use crate::proxy::image_store;
+
+/// 可续传上传的建议分块粒度 (8MB)
+const UPLOAD_CHUNK_GRANULARITY: &str = "8388608";
+/// 上传开始请求中元数据 JSON 的大小上限
+const MAX_METADATA_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct SignedQuery {
+    expires: Option<i64>,
+    sig: Option<String>,
+}
+
+/// GET /v1/files/images/:id
+pub async fn handle_get_image(Path(id): Path<String>, Query(query): Query<SignedQuery>) -> Response {
+    // 带签名参数时必须有效；未带签名的请求已由认证中间件校验 API Key
+    if let (Some(expires), Some(sig)) = (query.expires, query.sig.as_deref()) {
+        if !image_store::verify(&id, expires, sig) {
+            return (StatusCode::FORBIDDEN, "Invalid or expired image link").into_response();
+        }
+    }
+
+    let mime_type = image_store::mime_for(&id);
+    match tokio::task::spawn_blocking(move || image_store::load(&id)).await {
+        Ok(Ok(Some(data))) => (
+            [
+                (header::CONTENT_TYPE, mime_type),
+                (header::CACHE_CONTROL, "private, max-age=3600"),
+            ],
+            data,
+        )
+            .into_response(),
+        Ok(Ok(None)) => (StatusCode::NOT_FOUND, "Image not found").into_response(),
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+// ===== Gemini Files API =====
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn files_store() -> Result<&'static FileStore, (StatusCode, String)> {
+    gemini_files::store().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// 上传会话状态响应 (不含 body)
+fn upload_status(status: &str, received: Option<u64>) -> Response {
+    let mut builder = Response::builder().header("X-Goog-Upload-Status", status);
+    if let Some(received) = received {
+        builder = builder.header("X-Goog-Upload-Size-Received", received.to_string());
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+/// 上传完成响应
+fn upload_final(file: &gemini_files::StoredFile, base_url: &str) -> Response {
+    let mut resp = Json(json!({ "file": file.to_api_json(base_url) })).into_response();
+    resp.headers_mut()
+        .insert("x-goog-upload-status", "final".parse().unwrap());
+    resp
+}
+
+/// 将请求体流式追加到上传分片 (先计数再写盘，失败时会话保持一致可续传)
+async fn write_upload_chunk(
+    store: &'static FileStore,
+    upload_id: &str,
+    offset: u64,
+    body: Body,
+) -> Result<(), (StatusCode, String)> {
+    // 预留在写入结束 (含出错或客户端断开) 时释放
+    let part = store.reserve_chunk(upload_id, offset).map_err(|e| match e {
+        ChunkError::InFlight => (StatusCode::CONFLICT, e.to_string()),
+        ChunkError::Rejected(e) => (StatusCode::BAD_REQUEST, e),
+    })?;
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(part.path())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to open upload: {}", e)))?;
+
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| (StatusCode::BAD_REQUEST, format!("Upload read error: {}", e)))?;
+        store
+            .record_chunk(upload_id, &chunk)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if let Err(e) = file.write_all(&chunk).await {
+            // 已计数但未落盘，分片与会话状态不再一致
+            store.cancel_upload(upload_id);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write upload: {}", e)));
+        }
+    }
+    file.flush()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write upload: {}", e)))
+}
+
+/// POST /upload/v1beta/files
+/// - X-Goog-Upload-Protocol: resumable + Command: start  开始会话，返回 X-Goog-Upload-URL
+/// - ?upload_id=...  + Command: upload / finalize / query / cancel  分块上传
+/// - X-Goog-Upload-Protocol: raw (或 uploadType=media)  一次性上传
+pub async fn handle_upload_file(
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+    body: Body,
+) -> Result<Response, (StatusCode, String)> {
+    let store = files_store()?;
+    let base_url = image_store::base_url(&headers);
+    let command = header_str(&headers, "x-goog-upload-command")
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let has_command = |name: &str| command.split(',').any(|c| c.trim() == name);
+
+    // 1. 已有会话的分块请求
+    if let Some(upload_id) = gemini_files::query_param(query.as_deref(), "upload_id") {
+        if store.received(upload_id).is_none() {
+            return Err((StatusCode::NOT_FOUND, format!("Unknown or expired upload session: {}", upload_id)));
+        }
+        if has_command("cancel") {
+            store.cancel_upload(upload_id);
+            return Ok(upload_status("cancelled", None));
+        }
+        if has_command("query") {
+            return Ok(upload_status("active", store.received(upload_id)));
+        }
+        if has_command("upload") {
+            let offset = header_str(&headers, "x-goog-upload-offset")
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .unwrap_or(0);
+            write_upload_chunk(store, upload_id, offset, body).await?;
+        }
+        if has_command("finalize") {
+            let file = store
+                .finalize(upload_id, gemini_files::now_ms())
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            tracing::info!(
+                "[GeminiFiles] Uploaded {} ({}, {} bytes)",
+                file.name, file.mime_type, file.size_bytes
+            );
+            return Ok(upload_final(&file, &base_url));
+        }
+        return Ok(upload_status("active", store.received(upload_id)));
+    }
+
+    let protocol = header_str(&headers, "x-goog-upload-protocol")
+        .or_else(|| gemini_files::query_param(query.as_deref(), "uploadType"))
+        .unwrap_or("raw")
+        .to_ascii_lowercase();
+    let declared_size = |name: &str| header_str(&headers, name).and_then(|v| v.trim().parse::<u64>().ok());
+
+    match protocol.as_str() {
+        // 2. 开始可续传上传
+        "resumable" => {
+            if !has_command("start") {
+                return Err((StatusCode::BAD_REQUEST, "Resumable upload must start with X-Goog-Upload-Command: start".to_string()));
+            }
+            let bytes = axum::body::to_bytes(body, MAX_METADATA_SIZE)
+                .await
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to read metadata: {}", e)))?;
+            let metadata = if bytes.is_empty() {
+                json!({})
+            } else {
+                serde_json::from_slice::<Value>(&bytes)
+                    .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid metadata JSON: {}", e)))?
+            };
+            let upload_id = store
+                .start_upload(
+                    &metadata,
+                    header_str(&headers, "x-goog-upload-header-content-type"),
+                    declared_size("x-goog-upload-header-content-length"),
+                    gemini_files::now_ms(),
+                )
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let upload_url = format!(
+                "{}{}?upload_id={}&upload_protocol=resumable",
+                base_url,
+                gemini_files::UPLOAD_PATH,
+                upload_id
+            );
+            Ok(Response::builder()
+                .header("X-Goog-Upload-Status", "active")
+                .header("X-Goog-Upload-URL", &upload_url)
+                .header("X-Goog-Upload-Control-URL", &upload_url)
+                .header("X-Goog-Upload-Chunk-Granularity", UPLOAD_CHUNK_GRANULARITY)
+                .body(Body::empty())
+                .unwrap())
+        }
+        // 3. 一次性上传 (请求体即文件内容)
+        "raw" | "media" => {
+            let upload_id = store
+                .start_upload(
+                    &json!({}),
+                    header_str(&headers, header::CONTENT_TYPE.as_str()),
+                    declared_size(header::CONTENT_LENGTH.as_str()),
+                    gemini_files::now_ms(),
+                )
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            if let Err(e) = write_upload_chunk(store, &upload_id, 0, body).await {
+                store.cancel_upload(&upload_id);
+                return Err(e);
+            }
+            let file = store
+                .finalize(&upload_id, gemini_files::now_ms())
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            Ok(upload_final(&file, &base_url))
+        }
+        other => Err((
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported upload protocol '{}', use resumable or raw", other),
+        )),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListFilesQuery {
+    page_size: Option<usize>,
+    page_token: Option<String>,
+}
+
+/// GET /v1beta/files
+pub async fn handle_list_files(
+    headers: HeaderMap,
+    Query(query): Query<ListFilesQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let store = files_store()?;
+    let base_url = image_store::base_url(&headers);
+    let (page, next_page_token) = store
+        .list(query.page_size, query.page_token.as_deref(), gemini_files::now_ms())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut resp = json!({
+        "files": page.iter().map(|f| f.to_api_json(&base_url)).collect::<Vec<_>>()
+    });
+    if let Some(token) = next_page_token {
+        resp["nextPageToken"] = json!(token);
+    }
+    Ok(Json(resp))
+}
+
+/// GET /v1beta/files/:id
+pub async fn handle_get_file(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let store = files_store()?;
+    store
+        .get(&id, gemini_files::now_ms())
+        .map(|f| Json(f.to_api_json(&image_store::base_url(&headers))))
+        .ok_or((StatusCode::NOT_FOUND, format!("File not found: {}", id)))
+}
+
+/// DELETE /v1beta/files/:id
+pub async fn handle_delete_file(Path(id): Path<String>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let store = files_store()?;
+    if store.delete(&id).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))? {
+        Ok(Json(json!({})))
+    } else {
+        Err((StatusCode::NOT_FOUND, format!("File not found: {}", id)))
+    }
+}
@@ -1,9 +1,13 @@
 // Gemini Handler
 use axum::{extract::State, extract::{Json, Path}, http::StatusCode, response::IntoResponse};
 use serde_json::{json, Value};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, Instrument};
 
+use crate::proxy::hedging::{call_generate_with_hedge, is_hedge_eligible, HedgeOutcome};
+use crate::proxy::{cached_contents, gemini_files};
+use crate::proxy::response_cache::{self, CacheStatus};
 use crate::proxy::mappers::gemini::{wrap_request, unwrap_response};
+use crate::proxy::mappers::gemini::streaming::{SseDecoder, StreamEncoder, StreamFraming, StreamItem};
 use crate::proxy::server::AppState;
 use crate::proxy::session_manager::SessionManager;
  
@@ -14,6 +18,8 @@ const MAX_RETRY_ATTEMPTS: usize = 3;
 pub async fn handle_generate(
     State(state): State<AppState>,
     Path(model_action): Path<String>,
+    axum::extract::RawQuery(query): axum::extract::RawQuery,
+    headers: axum::http::HeaderMap,
     Json(mut body): Json<Value>  // 改为 mut 以支持修复提示词注入
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // 解析 model:method
@@ -31,6 +37,39 @@ pub async fn handle_generate(
     }
     let is_stream = method == "streamGenerateContent";
 
+    // 引用 cachedContent 时在本地展开 (上游不支持显式缓存)
+    let mut cached_content_name = None;
+    if let Some(name) = cached_contents::referenced_name(&body) {
+        let api_key_id = crate::proxy::request_queue::queue_key_from_headers(&headers);
+        let cached = with_cache_store(move |store| {
+            store
+                .get(&name, &api_key_id, cached_contents::now_ms())
+                .ok_or((StatusCode::NOT_FOUND, format!("CachedContent not found (or expired): {}", name)))
+        })
+        .await?;
+        cached
+            .apply_to(&mut body, &model_name)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        debug!("[Gemini] Expanded {} ({} cached tokens)", cached.name, cached.total_token_count);
+        cached_content_name = Some(cached.name.clone());
+    }
+
+    // fileData 引用本地上传的文件时解析为 inlineData (上游无法访问本地 Files API)
+    if gemini_files::has_file_references(&body) {
+        let files = gemini_files::store().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let (resolved, count) = tokio::task::spawn_blocking(move || {
+            let count = files.resolve_file_references(&mut body, gemini_files::now_ms())?;
+            Ok::<_, String>((body, count))
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        debug!("[Gemini] Resolved {} file reference(s) to inline data", count);
+        body = resolved;
+    }
+    // 流式帧格式由客户端 alt 参数决定 (上游始终使用 alt=sse)
+    let framing = StreamFraming::from_query(query.as_deref());
+
     // 2. 获取 UpstreamClient 和 TokenManager
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
@@ -40,12 +79,34 @@ pub async fn handle_generate(
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
 
+    // 3. 模型路由解析
+    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        &model_name,
+        &*state.custom_mapping.read().await,
+    );
+    // 提取 SessionId (粘性指纹)
+    let session_id = match &cached_content_name {
+        Some(name) => SessionManager::cached_content_session_id(name),
+        None => SessionManager::extract_gemini_session_id(&body, &model_name),
+    };
+
+    // 【新增】精确匹配响应缓存：在获取账号之前查询，命中时直接回放，不消耗账号配额与并发
+    // (缓存键已剔除 project / requestId / sessionId，与账号无关)
+    let cache_config = crate::proxy::replay::experimental_config(&state.experimental).await.response_cache.clone();
+    let cache_key = if cache_config.enabled {
+        let cache_body = wrap_request(&body, "", &mapped_model, Some(&session_id));
+        response_cache::cache_key(&cache_config, &headers, &mapped_model, &cache_body)
+    } else {
+        None
+    };
+    if let Some(key) = cache_key.as_deref() {
+        if let Some(cached) = response_cache::lookup(key).await {
+            info!("[Gemini] Response cache hit for {}", mapped_model);
+            return Ok(cached_gemini_response(&cached, &mapped_model, is_stream.then_some(framing)));
+        }
+    }
+
     for attempt in 0..max_attempts {
-        // 3. 模型路由解析
-        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &model_name,
-            &*state.custom_mapping.read().await,
-        );
         // 提取 tools 列表以进行联网探测 (Gemini 风格可能是嵌套的)
         let tools_val: Option<Vec<Value>> = body.get("tools").and_then(|t| t.as_array()).map(|arr| {
             let mut flattened = Vec::new();
@@ -68,11 +129,8 @@ pub async fn handle_generate(
         );
 
         // 4. 获取 Token (使用准确的 request_type)
-        // 提取 SessionId (粘性指纹)
-        let session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
-
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
-        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id), &config.final_model).await {
+        let (access_token, project_id, email, lease) = match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id), &config.final_model).await {
             Ok(t) => t,
             Err(e) => {
                 return Err((StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)));
@@ -90,28 +148,74 @@ pub async fn handle_generate(
         let query_string = if is_stream { Some("alt=sse") } else { None };
         let upstream_method = if is_stream { "streamGenerateContent" } else { "generateContent" };
 
-        let response = match upstream
-            .call_v1_internal(upstream_method, &access_token, wrapped_body, query_string)
-            .await {
-                Ok(r) => r,
-                Err(e) => {
-                    last_error = e.clone();
-                    debug!("Gemini Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
-                    continue;
-                }
-            };
+        let attempt_span = crate::proxy::telemetry::attempt_span(attempt, &email, &mapped_model);
+        // 【新增】非流式请求超过近期延迟分位数时在另一账号上对冲
+        let (response, hedge_outcome, hedge_account) = async {
+            if is_stream {
+                let response = upstream
+                    .call_v1_internal(upstream_method, &access_token, wrapped_body, query_string)
+                    .await;
+                (response, HedgeOutcome::NotFired, None)
+            } else {
+                let hedge_delay = if is_hedge_eligible(&config.request_type, &tools_val) {
+                    crate::proxy::hedging::hedge_delay(&crate::proxy::replay::experimental_config(&state.experimental).await.hedging, &mapped_model)
+                } else {
+                    None
+                };
+                let call = call_generate_with_hedge(
+                    &token_manager,
+                    &upstream,
+                    hedge_delay,
+                    &access_token,
+                    wrapped_body,
+                    std::collections::HashMap::new(),
+                    &email,
+                    &config.request_type,
+                    &config.final_model,
+                    &mapped_model,
+                    |hedge_project_id| wrap_request(&body, hedge_project_id, &mapped_model, Some(&session_id)),
+                )
+                .await;
+                (call.response, call.outcome, call.hedge_account)
+            }
+        }
+        .instrument(attempt_span.clone())
+        .await;
+
+        let response = match response {
+            Ok(r) => r,
+            Err(e) => {
+                attempt_span.record("otel.status_code", "ERROR");
+                last_error = e.clone();
+                debug!("Gemini Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                continue;
+            }
+        };
+
+        // 对冲请求胜出时，后续的错误处理与日志归属到对冲账号
+        let (email, _hedge_lease) = match hedge_account {
+            Some(h) => (h.email, Some(h.lease)),
+            None => (email, None),
+        };
+        last_email = Some(email.clone());
 
         let status = response.status();
+        crate::proxy::telemetry::record_status(&attempt_span, status.as_u16());
         if status.is_success() {
             // 6. 响应处理
             if is_stream {
                 use axum::body::Body;
                 use axum::response::Response;
-                use bytes::{Bytes, BytesMut};
+                use bytes::Bytes;
                 use futures::StreamExt;
                 
-                let mut response_stream = response.bytes_stream();
-                let mut buffer = BytesMut::new();
+                // 可缓存的请求在流正常结束后写入响应缓存
+                let mut response_stream = response_cache::record_stream(
+                    response.bytes_stream(),
+                    &cache_config,
+                    cache_key.clone(),
+                    &mapped_model,
+                );
                 let s_id = session_id.clone(); // Clone for stream closure
 
                 // [FIX #859] Implement peek logic for Gemini stream to prevent 0-token 200 OK
@@ -149,6 +253,8 @@ pub async fn handle_generate(
                 }
 
                 let stream = async_stream::stream! {
+                    let mut decoder = SseDecoder::new();
+                    let mut encoder = StreamEncoder::new(framing);
                     let mut first_data = first_chunk;
                     loop {
                         let item = if let Some(fd) = first_data.take() {
@@ -157,84 +263,41 @@ pub async fn handle_generate(
                             response_stream.next().await
                         };
 
-                        let bytes = match item {
-                            Some(Ok(b)) => b,
+                        let (items, done) = match item {
+                            Some(Ok(b)) => {
+                                debug!("[Gemini-Stream] Received chunk: {} bytes", b.len());
+                                (decoder.push(&b), false)
+                            }
                             Some(Err(e)) => {
-                                error!("[Gemini-SSE] Connection error: {}", e);
+                                error!("[Gemini-Stream] Connection error: {}", e);
                                 yield Err(format!("Stream error: {}", e));
                                 break;
                             }
-                            None => break,
+                            None => (decoder.finish(), true),
                         };
 
-                        debug!("[Gemini-SSE] Received chunk: {} bytes", bytes.len());
-                        buffer.extend_from_slice(&bytes);
-                        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                            let line_raw = buffer.split_to(pos + 1);
-                            if let Ok(line_str) = std::str::from_utf8(&line_raw) {
-                                let line = line_str.trim();
-                                if line.is_empty() { continue; }
-                                
-                                if line.starts_with("data: ") {
-                                    let json_part = line.trim_start_matches("data: ").trim();
-                                    if json_part == "[DONE]" {
-                                        yield Ok::<Bytes, String>(Bytes::from("data: [DONE]\n\n"));
-                                        continue;
-                                    }
-                                    
-                                    match serde_json::from_str::<Value>(json_part) {
-                                        Ok(mut json) => {
-                                            // [FIX #765] Extract thoughtSignature from stream
-                                            let inner_val = if json.get("response").is_some() {
-                                                json.get("response")
-                                            } else {
-                                                Some(&json)
-                                            };
-
-                                            if let Some(resp) = inner_val {
-                                                if let Some(candidates) = resp.get("candidates").and_then(|c| c.as_array()) {
-                                                    for cand in candidates {
-                                                        if let Some(parts) = cand.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
-                                                            for part in parts {
-                                                                if let Some(sig) = part.get("thoughtSignature").and_then(|s| s.as_str()) {
-                                                                    crate::proxy::SignatureCache::global().cache_session_signature(&s_id, sig.to_string());
-                                                                    debug!("[Gemini-SSE] Cached signature (len: {}) for session: {}", sig.len(), s_id);
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-
-                                            // Unwrap v1internal response wrapper
-                                            if let Some(inner) = json.get_mut("response").map(|v| v.take()) {
-                                                let new_line = format!("data: {}\n\n", serde_json::to_string(&inner).unwrap_or_default());
-                                                yield Ok::<Bytes, String>(Bytes::from(new_line));
-                                            } else {
-                                                yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&json).unwrap_or_default())));
-                                            }
-                                        }
-                                        Err(e) => {
-                                            debug!("[Gemini-SSE] JSON parse error: {}, passing raw line", e);
-                                            yield Ok::<Bytes, String>(Bytes::from(format!("{}\n\n", line)));
-                                        }
-                                    }
-                                } else {
-                                    // Non-data lines (comments, etc.)
-                                    yield Ok::<Bytes, String>(Bytes::from(format!("{}\n\n", line)));
-                                }
-                            } else {
-                                // Non-UTF8 data? Just pass it through or skip
-                                debug!("[Gemini-SSE] Non-UTF8 line encountered");
-                                yield Ok::<Bytes, String>(line_raw.freeze());
+                        for item in &items {
+                            // [FIX #765] Extract thoughtSignature from stream
+                            if let StreamItem::Chunk(chunk) = item {
+                                cache_thought_signatures(chunk, &s_id);
+                            }
+                            if let Some(bytes) = encoder.encode(item) {
+                                yield Ok::<Bytes, String>(bytes);
                             }
                         }
+
+                        if done {
+                            // JSON 数组需要闭合
+                            if let Some(bytes) = encoder.finish() {
+                                yield Ok::<Bytes, String>(bytes);
+                            }
+                            break;
+                        }
                     }
                 };
-                
-                let body = Body::from_stream(stream);
-                return Ok(Response::builder()
-                    .header("Content-Type", "text/event-stream")
+                let body = Body::from_stream(lease.hold_stream(stream));
+                let mut resp = Response::builder()
+                    .header("Content-Type", framing.content_type())
                     .header("Cache-Control", "no-cache")
                     .header("Connection", "keep-alive")
                     .header("X-Accel-Buffering", "no")
@@ -242,7 +305,11 @@ pub async fn handle_generate(
                     .header("X-Mapped-Model", &mapped_model)
                     .body(body)
                     .unwrap()
-                    .into_response());
+                    .into_response();
+                if cache_key.is_some() {
+                    response_cache::annotate_response(&mut resp, CacheStatus::Miss);
+                }
+                return Ok(resp);
             }
 
             let gemini_resp: Value = response
@@ -251,29 +318,16 @@ pub async fn handle_generate(
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
 
             // [FIX #765] Extract thoughtSignature from non-streaming response
-            let inner_val = if gemini_resp.get("response").is_some() {
-                gemini_resp.get("response")
-            } else {
-                Some(&gemini_resp)
-            };
-
-            if let Some(resp) = inner_val {
-                if let Some(candidates) = resp.get("candidates").and_then(|c| c.as_array()) {
-                    for cand in candidates {
-                        if let Some(parts) = cand.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
-                            for part in parts {
-                                if let Some(sig) = part.get("thoughtSignature").and_then(|s| s.as_str()) {
-                                    crate::proxy::SignatureCache::global().cache_session_signature(&session_id, sig.to_string());
-                                    debug!("[Gemini-Response] Cached signature (len: {}) for session: {}", sig.len(), session_id);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-
             let unwrapped = unwrap_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(unwrapped)).into_response());
+            cache_thought_signatures(&unwrapped, &session_id);
+
+            let mut resp = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(unwrapped)).into_response();
+            crate::proxy::hedging::annotate_response(&mut resp, hedge_outcome);
+            if let Some(key) = cache_key.clone() {
+                response_cache::store(&cache_config, key, &mapped_model, &gemini_resp);
+                response_cache::annotate_response(&mut resp, CacheStatus::Miss);
+            }
+            return Ok(resp);
         }
 
         // 处理错误并重试
@@ -336,6 +390,45 @@ pub async fn handle_generate(
     }
 }
 
+/// 缓存 (已解包) 响应中的 thoughtSignature，供同会话后续请求回填
+fn cache_thought_signatures(resp: &Value, session_id: &str) {
+    let parts = resp
+        .get("candidates")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|cand| cand.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()))
+        .flatten();
+    for part in parts {
+        if let Some(sig) = part.get("thoughtSignature").and_then(|s| s.as_str()) {
+            crate::proxy::SignatureCache::global().cache_session_signature(session_id, sig.to_string());
+            debug!("[Gemini] Cached signature (len: {}) for session: {}", sig.len(), session_id);
+        }
+    }
+}
+
+/// 由缓存的上游响应构造 Gemini 响应 (流式客户端按其帧格式收到单个 chunk)
+fn cached_gemini_response(cached: &Value, mapped_model: &str, stream: Option<StreamFraming>) -> axum::response::Response {
+    let unwrapped = unwrap_response(cached);
+    let mut resp = if let Some(framing) = stream {
+        let mut encoder = StreamEncoder::new(framing);
+        let mut event = encoder.encode(&StreamItem::Chunk(unwrapped)).unwrap_or_default().to_vec();
+        event.extend_from_slice(&encoder.finish().unwrap_or_default());
+        axum::response::Response::builder()
+            .header("Content-Type", framing.content_type())
+            .header("Cache-Control", "no-cache")
+            .header("Connection", "keep-alive")
+            .header("X-Accel-Buffering", "no")
+            .header("X-Mapped-Model", mapped_model)
+            .body(axum::body::Body::from(event))
+            .unwrap()
+    } else {
+        (StatusCode::OK, [("X-Mapped-Model", mapped_model)], Json(unwrapped)).into_response()
+    };
+    response_cache::annotate_response(&mut resp, CacheStatus::Hit);
+    resp
+}
+
 pub async fn handle_list_models(State(state): State<AppState>) -> Result<impl IntoResponse, (StatusCode, String)> {
     use crate::proxy::common::model_mapping::get_all_dynamic_models;
 
@@ -372,8 +465,112 @@ pub async fn handle_get_model(Path(model_name): Path<String>) -> impl IntoRespon
 
 pub async fn handle_count_tokens(State(state): State<AppState>, Path(_model_name): Path<String>, Json(_body): Json<Value>) -> Result<impl IntoResponse, (StatusCode, String)> {
     let model_group = "gemini";
-    let (_access_token, _project_id, _) = state.token_manager.get_token(model_group, false, None, "gemini").await
+    let (_access_token, _project_id, _, _lease) = state.token_manager.get_token(model_group, false, None, "gemini").await
         .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)))?;
     
     Ok(Json(json!({"totalTokens": 0})))
 }
+
+/// 在阻塞线程中访问本地 cachedContents 存储 (首次使用时从磁盘加载，写入会落盘)
+async fn with_cache_store<T, F>(f: F) -> Result<T, (StatusCode, String)>
+where
+    T: Send + 'static,
+    F: FnOnce(&'static cached_contents::CacheStore) -> Result<T, (StatusCode, String)> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let store = cached_contents::store().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        f(store)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+}
+
+/// POST /v1beta/cachedContents (缓存归属于请求的 API Key，其他 Key 不可见)
+pub async fn handle_create_cached_content(
+    headers: axum::http::HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let api_key_id = crate::proxy::request_queue::queue_key_from_headers(&headers);
+    let created = with_cache_store(move |store| {
+        store
+            .create(&body, &api_key_id, cached_contents::now_ms())
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))
+    })
+    .await?;
+    info!(
+        "[Gemini] Created {} for {} (~{} tokens)",
+        created.name, created.model, created.total_token_count
+    );
+    Ok(Json(created.to_api_json()))
+}
+
+/// GET /v1beta/cachedContents?pageSize=&pageToken=
+pub async fn handle_list_cached_contents(
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let api_key_id = crate::proxy::request_queue::queue_key_from_headers(&headers);
+    let page_size = params.get("pageSize").and_then(|v| v.parse().ok());
+    let (page, next_page_token) = with_cache_store(move |store| {
+        store
+            .list(&api_key_id, page_size, params.get("pageToken").map(|s| s.as_str()), cached_contents::now_ms())
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))
+    })
+    .await?;
+
+    let mut resp = json!({
+        "cachedContents": page.iter().map(|c| c.to_api_json()).collect::<Vec<_>>()
+    });
+    if let Some(token) = next_page_token {
+        resp["nextPageToken"] = json!(token);
+    }
+    Ok(Json(resp))
+}
+
+/// GET /v1beta/cachedContents/:id
+pub async fn handle_get_cached_content(
+    headers: axum::http::HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let api_key_id = crate::proxy::request_queue::queue_key_from_headers(&headers);
+    with_cache_store(move |store| {
+        store
+            .get(&id, &api_key_id, cached_contents::now_ms())
+            .map(|c| Json(c.to_api_json()))
+            .ok_or((StatusCode::NOT_FOUND, format!("CachedContent not found: {}", id)))
+    })
+    .await
+}
+
+/// PATCH /v1beta/cachedContents/:id (仅支持更新 ttl / expireTime)
+pub async fn handle_update_cached_content(
+    headers: axum::http::HeaderMap,
+    Path(id): Path<String>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let api_key_id = crate::proxy::request_queue::queue_key_from_headers(&headers);
+    with_cache_store(move |store| {
+        store
+            .update(&id, &api_key_id, &body, cached_contents::now_ms())
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?
+            .map(|c| Json(c.to_api_json()))
+            .ok_or((StatusCode::NOT_FOUND, format!("CachedContent not found: {}", id)))
+    })
+    .await
+}
+
+/// DELETE /v1beta/cachedContents/:id
+pub async fn handle_delete_cached_content(
+    headers: axum::http::HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let api_key_id = crate::proxy::request_queue::queue_key_from_headers(&headers);
+    with_cache_store(move |store| {
+        if store.delete(&id, &api_key_id).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))? {
+            Ok(Json(json!({})))
+        } else {
+            Err((StatusCode::NOT_FOUND, format!("CachedContent not found: {}", id)))
+        }
+    })
+    .await
+}
@@ -0,0 +1,124 @@
+// 就绪检查处理器
+// /healthz 只表示进程存活；/readyz 检查账号池、上游、z.ai 与本地数据库，供负载均衡/编排系统判断是否接流量
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension, Json};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+use crate::proxy::middleware::auth::UnauthenticatedHealthCheck;
+use crate::proxy::server::AppState;
+
+/// z.ai 连通性探测超时
+const ZAI_PROBE_TIMEOUT_SECS: u64 = 5;
+
+/// z.ai 探测结果缓存时长 (避免频繁的就绪检查放大为对 z.ai 的请求)
+const ZAI_PROBE_TTL: Duration = Duration::from_secs(30);
+
+/// 一次 z.ai 探测: (探测时间, base_url, 结果)
+type ZaiProbe = (Instant, String, Result<(), String>);
+
+/// 最近一次 z.ai 探测
+static ZAI_PROBE_CACHE: Lazy<tokio::sync::Mutex<Option<ZaiProbe>>> = Lazy::new(|| tokio::sync::Mutex::new(None));
+
+/// GET /readyz
+///
+/// 所有受监控模型都没有可用账号时返回 503。
+/// 因 `all_except_health` 免鉴权访问时只返回粗粒度状态，不暴露账号池、上游与数据库细节
+pub async fn handle_readyz(
+    State(state): State<AppState>,
+    unauthenticated: Option<Extension<UnauthenticatedHealthCheck>>,
+) -> impl IntoResponse {
+    let quota_protection = state.token_manager.get_quota_protection().await;
+
+    let pool = state
+        .token_manager
+        .pool_readiness(&quota_protection.monitored_models, quota_protection.enabled);
+    let ready = pool.available_per_model.values().any(|&n| n > 0);
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let status_text = if ready { "ready" } else { "unavailable" };
+
+    if unauthenticated.is_some() {
+        return (status, Json(json!({ "status": status_text })));
+    }
+
+    let zai = state.zai.read().await.clone();
+    let zai_enabled = zai.enabled && !matches!(zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
+    let zai_status = if zai_enabled {
+        let upstream_proxy = state.upstream_proxy.read().await.clone();
+        Some(cached_probe_zai(&zai.base_url, upstream_proxy).await)
+    } else {
+        None
+    };
+
+    let (proxy_db, token_stats) = tokio::task::spawn_blocking(|| {
+        (
+            crate::modules::proxy_db::health_check(),
+            crate::modules::token_stats::health_check(),
+        )
+    })
+    .await
+    .unwrap_or_else(|e| (Err(e.to_string()), Err(e.to_string())));
+
+    let body = json!({
+        "status": status_text,
+        "token_pool": {
+            "size": pool.pool_size,
+            "rate_limited": pool.rate_limited,
+            "available_per_model": pool.available_per_model,
+        },
+        "upstream": {
+            "last_success_at": state.upstream.last_success_at(),
+            "endpoints": state.upstream.endpoint_health(),
+        },
+        "zai": match &zai_status {
+            None => json!({ "enabled": false }),
+            Some(result) => json!({ "enabled": true, "reachable": result.is_ok(), "error": result.as_ref().err() }),
+        },
+        "databases": {
+            "proxy_db": check_json(&proxy_db),
+            "token_stats": check_json(&token_stats),
+        },
+    });
+
+    (status, Json(body))
+}
+
+fn check_json(result: &Result<(), String>) -> Value {
+    match result {
+        Ok(()) => json!({ "ok": true }),
+        Err(e) => json!({ "ok": false, "error": e }),
+    }
+}
+
+/// 在缓存有效期内复用上次探测结果 (base_url 变化时重新探测)
+async fn cached_probe_zai(
+    base_url: &str,
+    upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
+) -> Result<(), String> {
+    // 持锁探测：并发的就绪检查只触发一次探测
+    let mut cache = ZAI_PROBE_CACHE.lock().await;
+    if let Some((at, url, result)) = cache.as_ref() {
+        if url == base_url && at.elapsed() < ZAI_PROBE_TTL {
+            return result.clone();
+        }
+    }
+    let result = probe_zai(base_url, upstream_proxy).await;
+    *cache = Some((Instant::now(), base_url.to_string(), result.clone()));
+    result
+}
+
+/// 探测 z.ai 是否可达：收到任意 HTTP 响应即视为可达 (不消耗配额)
+async fn probe_zai(
+    base_url: &str,
+    upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
+) -> Result<(), String> {
+    let client = crate::proxy::providers::zai_anthropic::build_client(Some(upstream_proxy), ZAI_PROBE_TIMEOUT_SECS)?;
+    tokio::time::timeout(
+        Duration::from_secs(ZAI_PROBE_TIMEOUT_SECS),
+        client.head(base_url).send(),
+    )
+    .await
+    .map_err(|_| format!("timeout after {}s", ZAI_PROBE_TIMEOUT_SECS))?
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
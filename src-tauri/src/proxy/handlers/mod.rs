@@ -7,5 +7,10 @@ pub mod gemini;
 pub mod mcp;
 pub mod common;
 pub mod audio;  // 音频转录处理器
+pub mod files;  // 本地文件 (生成图片) 访问
+pub mod batch;  // OpenAI Batch API
 pub mod warmup; // 预热处理器
+pub mod health; // 就绪检查
 
+pub mod background_tasks; // 后台任务分类试运行
+pub mod replay; // 请求重放
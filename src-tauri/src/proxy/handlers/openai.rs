@@ -3,72 +3,21 @@ use axum::{extract::Json, extract::State, http::StatusCode, response::IntoRespon
 use base64::Engine as _; 
 use bytes::Bytes;
 use serde_json::{json, Value};
-use tracing::{debug, error, info}; // Import Engine trait for encode method
+use tracing::{debug, error, info, Instrument}; // Import Engine trait for encode method
 
+use crate::proxy::hedging::{call_generate_with_hedge, is_hedge_eligible, HedgeOutcome};
+use crate::proxy::response_cache::{self, CacheStatus};
 use crate::proxy::mappers::openai::{
     transform_openai_request, transform_openai_response, OpenAIRequest,
 };
 // use crate::proxy::upstream::client::UpstreamClient; // 通过 state 获取
 use crate::proxy::server::AppState;
-
-const MAX_RETRY_ATTEMPTS: usize = 3;
+use crate::proxy::upstream::retry::{apply_retry_strategy, determine_retry_strategy, MAX_RETRY_ATTEMPTS};
 use crate::proxy::session_manager::SessionManager;
-use tokio::time::{sleep, Duration};
-
-/// 重试策略枚举
-#[derive(Debug, Clone)]
-enum RetryStrategy {
-    NoRetry,
-    FixedDelay(Duration),
-    LinearBackoff { base_ms: u64 },
-    ExponentialBackoff { base_ms: u64, max_ms: u64 },
-}
-
-fn determine_retry_strategy(status_code: u16, error_text: &str) -> RetryStrategy {
-    match status_code {
-        429 => {
-            if let Some(delay_ms) = crate::proxy::upstream::retry::parse_retry_delay(error_text) {
-                let actual_delay = delay_ms.saturating_add(200).min(10_000);
-                RetryStrategy::FixedDelay(Duration::from_millis(actual_delay))
-            } else {
-                RetryStrategy::LinearBackoff { base_ms: 1000 }
-            }
-        }
-        503 | 529 => RetryStrategy::ExponentialBackoff { base_ms: 1000, max_ms: 8000 },
-        500 => RetryStrategy::LinearBackoff { base_ms: 500 },
-        401 | 403 => RetryStrategy::FixedDelay(Duration::from_millis(100)),
-        _ => RetryStrategy::NoRetry,
-    }
-}
-
-async fn apply_retry_strategy(strategy: RetryStrategy, attempt: usize, status_code: u16, trace_id: &str) -> bool {
-    match strategy {
-        RetryStrategy::NoRetry => {
-            debug!("[{}] Non-retryable error {}, stopping", trace_id, status_code);
-            false
-        }
-        RetryStrategy::FixedDelay(duration) => {
-            info!("[{}] ⏱️ Retry with fixed delay: status={}, attempt={}/{}", trace_id, status_code, attempt + 1, MAX_RETRY_ATTEMPTS);
-            sleep(duration).await;
-            true
-        }
-        RetryStrategy::LinearBackoff { base_ms } => {
-            let delay = base_ms * (attempt as u64 + 1);
-            info!("[{}] ⏱️ Retry with linear backoff: status={}, attempt={}/{}", trace_id, status_code, attempt + 1, MAX_RETRY_ATTEMPTS);
-            sleep(Duration::from_millis(delay)).await;
-            true
-        }
-        RetryStrategy::ExponentialBackoff { base_ms, max_ms } => {
-             let delay = (base_ms * 2_u64.pow(attempt as u32)).min(max_ms);
-             info!("[{}] ⏱️ Retry with exponential backoff: status={}, attempt={}/{}", trace_id, status_code, attempt + 1, MAX_RETRY_ATTEMPTS);
-             sleep(Duration::from_millis(delay)).await;
-             true
-        }
-    }
-}
 
 pub async fn handle_chat_completions(
     State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
     Json(mut body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // [NEW] 自动检测并转换 Responses 格式
@@ -157,6 +106,22 @@ pub async fn handle_chat_completions(
         &*state.custom_mapping.read().await,
     );
 
+    // 【新增】精确匹配响应缓存：在获取账号之前查询，命中时直接回放，不消耗账号配额与并发
+    // (缓存键已剔除 project / requestId / sessionId，与账号无关)
+    let cache_config = crate::proxy::replay::experimental_config(&state.experimental).await.response_cache.clone();
+    let cache_key = if cache_config.enabled {
+        let cache_body = transform_openai_request(&openai_req, "", &mapped_model);
+        response_cache::cache_key(&cache_config, &headers, &mapped_model, &cache_body)
+    } else {
+        None
+    };
+    if let Some(key) = cache_key.as_deref() {
+        if let Some(cached) = response_cache::lookup(key).await {
+            info!("[OpenAI] Response cache hit for {}", mapped_model);
+            return Ok(cached_openai_response(&cached, &openai_req.model, &mapped_model, openai_req.stream));
+        }
+    }
+
     for attempt in 0..max_attempts {
         // 将 OpenAI 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = openai_req
@@ -176,7 +141,7 @@ pub async fn handle_chat_completions(
 
         // 4. 获取 Token (使用准确的 request_type)
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
-        let (access_token, project_id, email) = match token_manager
+        let (access_token, project_id, email, lease) = match token_manager
             .get_token(&config.request_type, attempt > 0, Some(&session_id), &openai_req.model)
             .await
         {
@@ -202,7 +167,8 @@ pub async fn handle_chat_completions(
 
         // 5. 发送请求
         let actual_stream = openai_req.stream;
-        
+
+
         let method = if actual_stream {
             "streamGenerateContent"
         } else {
@@ -210,12 +176,44 @@ pub async fn handle_chat_completions(
         };
         let query_string = if actual_stream { Some("alt=sse") } else { None };
 
-        let response = match upstream
-            .call_v1_internal(method, &access_token, gemini_body, query_string)
-            .await
-        {
+        let attempt_span = crate::proxy::telemetry::attempt_span(attempt, &email, &mapped_model);
+        // 【新增】非流式请求超过近期延迟分位数时在另一账号上对冲
+        let (response, hedge_outcome, hedge_account) = async {
+            if actual_stream {
+                let response = upstream
+                    .call_v1_internal(method, &access_token, gemini_body, query_string)
+                    .await;
+                (response, HedgeOutcome::NotFired, None)
+            } else {
+                let hedge_delay = if is_hedge_eligible(&config.request_type, &tools_val) {
+                    crate::proxy::hedging::hedge_delay(&crate::proxy::replay::experimental_config(&state.experimental).await.hedging, &mapped_model)
+                } else {
+                    None
+                };
+                let call = call_generate_with_hedge(
+                    &token_manager,
+                    &upstream,
+                    hedge_delay,
+                    &access_token,
+                    gemini_body,
+                    std::collections::HashMap::new(),
+                    &email,
+                    &config.request_type,
+                    &openai_req.model,
+                    &mapped_model,
+                    |hedge_project_id| transform_openai_request(&openai_req, hedge_project_id, &mapped_model),
+                )
+                .await;
+                (call.response, call.outcome, call.hedge_account)
+            }
+        }
+        .instrument(attempt_span.clone())
+        .await;
+
+        let response = match response {
             Ok(r) => r,
             Err(e) => {
+                attempt_span.record("otel.status_code", "ERROR");
                 last_error = e.clone();
                 debug!(
                     "OpenAI Request failed on attempt {}/{}: {}",
@@ -227,8 +225,19 @@ pub async fn handle_chat_completions(
             }
         };
 
+        // 对冲请求胜出时，后续的错误处理与日志归属到对冲账号
+        let (email, _hedge_lease) = match hedge_account {
+            Some(h) => (h.email, Some(h.lease)),
+            None => (email, None),
+        };
+        last_email = Some(email.clone());
+
         let status = response.status();
+        crate::proxy::telemetry::record_status(&attempt_span, status.as_u16());
         if status.is_success() {
+            // [智能限流] 请求成功，重置该账号的连续失败计数
+            token_manager.mark_account_success(&email);
+
             // 5. 处理流式 vs 非流式
             if actual_stream {
                 use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
@@ -236,12 +245,18 @@ pub async fn handle_chat_completions(
                 use axum::response::Response;
                 use futures::StreamExt;
 
-                let gemini_stream = response.bytes_stream();
+                // 可缓存的请求在流正常结束后写入响应缓存
+                let gemini_stream = crate::proxy::monitor::report_stream_usage(response_cache::record_stream(
+                    response.bytes_stream(),
+                    &cache_config,
+                    cache_key.clone(),
+                    &mapped_model,
+                ));
                 
                 // [P1 FIX] Enhanced Peek logic to handle heartbeats and slow start
                 // Pre-read until we find meaningful content, skip heartbeats
                 let mut openai_stream =
-                    create_openai_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
+                    create_openai_sse_stream(gemini_stream, openai_req.model.clone());
                 
                 let mut first_data_chunk = None;
                 let mut retry_this_account = false;
@@ -306,8 +321,8 @@ pub async fn handle_chat_completions(
                 
                 if actual_stream {
                     // 客户端请求流式，返回 SSE
-                    let body = Body::from_stream(combined_stream);
-                    return Ok(Response::builder()
+                    let body = Body::from_stream(lease.hold_stream(combined_stream));
+                    let mut resp = Response::builder()
                         .header("Content-Type", "text/event-stream")
                         .header("Cache-Control", "no-cache")
                         .header("Connection", "keep-alive")
@@ -316,7 +331,11 @@ pub async fn handle_chat_completions(
                         .header("X-Mapped-Model", &mapped_model)
                         .body(body)
                         .unwrap()
-                        .into_response());
+                        .into_response();
+                    if cache_key.is_some() {
+                        response_cache::annotate_response(&mut resp, CacheStatus::Miss);
+                    }
+                    return Ok(resp);
                 } else {
                     // 非流式请求（虽然内部可能走流但这里按原始需求转换）
                     // 实际上既然实际流已经是 actual_stream 了，这里的逻辑应该一致
@@ -329,8 +348,15 @@ pub async fn handle_chat_completions(
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
 
+            crate::proxy::monitor::report_response_usage(&gemini_resp);
             let openai_response = transform_openai_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response());
+            let mut resp = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response();
+            crate::proxy::hedging::annotate_response(&mut resp, hedge_outcome);
+            if let Some(key) = cache_key.clone() {
+                response_cache::store(&cache_config, key, &mapped_model, &gemini_resp);
+                response_cache::annotate_response(&mut resp, CacheStatus::Miss);
+            }
+            return Ok(resp);
         }
 
         // 处理特定错误并重试
@@ -346,46 +372,9 @@ pub async fn handle_chat_completions(
             error_text
         );
 
-        // 429/529/503 智能处理
+        // 记录限流信息 (模型级别，避免不同模型配额互相影响)
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
-            // 记录限流信息 (全局同步)
-            token_manager.mark_rate_limited(&email, status_code, retry_after.as_deref(), &error_text);
-
-            // 1. 优先尝试解析 RetryInfo (由 Google Cloud 直接下发)
-            if let Some(delay_ms) = crate::proxy::upstream::retry::parse_retry_delay(&error_text) {
-                let actual_delay = delay_ms.saturating_add(200).min(10_000);
-                tracing::warn!(
-                    "OpenAI Upstream {} on {} attempt {}/{}, waiting {}ms then retrying",
-                    status_code,
-                    email,
-                    attempt + 1,
-                    max_attempts,
-                    actual_delay
-                );
-                tokio::time::sleep(tokio::time::Duration::from_millis(actual_delay)).await;
-                continue;
-            }
-
-            // 2. 只有明确包含 "QUOTA_EXHAUSTED" 才停止，避免误判频率提示 (如 "check quota")
-            if error_text.contains("QUOTA_EXHAUSTED") {
-                error!(
-                    "OpenAI Quota exhausted (429) on account {} attempt {}/{}, stopping to protect pool.",
-                    email,
-                    attempt + 1,
-                    max_attempts
-                );
-                return Ok((status, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], error_text).into_response());
-            }
-
-            // 3. 其他限流或服务器过载情况，轮换账号
-            tracing::warn!(
-                "OpenAI Upstream {} on {} attempt {}/{}, rotating account",
-                status_code,
-                email,
-                attempt + 1,
-                max_attempts
-            );
-            continue;
+            token_manager.mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, Some(&mapped_model)).await;
         }
 
         // [NEW] 处理 400 错误 (Thinking 签名失效)
@@ -425,15 +414,10 @@ pub async fn handle_chat_completions(
             continue; // 重试
         }
 
-        // 只有 403 (权限/地区限制) 和 401 (认证失效) 触发账号轮换
-        if status_code == 403 || status_code == 401 {
-            tracing::warn!(
-                "OpenAI Upstream {} on account {} attempt {}/{}, rotating account",
-                status_code,
-                email,
-                attempt + 1,
-                max_attempts
-            );
+        // 与 Codex / Claude 共用的退避策略 (429/5xx 退避，401/403 轮换)
+        // 签名错误已在上方追加修复提示重试，其余签名类 400 原样重发无意义，不再重试
+        let strategy = determine_retry_strategy(status_code, &error_text, true);
+        if apply_retry_strategy(strategy, attempt, status_code, "OpenAI").await {
             continue;
         }
 
@@ -838,6 +822,7 @@ pub async fn handle_completions(
         &*state.custom_mapping.read().await,
     );
     let trace_id = format!("req_{}", chrono::Utc::now().timestamp_subsec_millis());
+    tracing::Span::current().record("proxy.trace_id", trace_id.as_str());
 
     for attempt in 0..max_attempts {
         // 3. 模型配置解析
@@ -862,7 +847,7 @@ pub async fn handle_completions(
         // 重试时强制轮换，除非只是简单的网络抖动但 Claude 逻辑里 attempt > 0 总是 force_rotate
         let force_rotate = attempt > 0;
 
-        let (access_token, project_id, email) =
+        let (access_token, project_id, email, lease) =
             match token_manager.get_token(&config.request_type, force_rotate, session_id, &openai_req.model).await {
                 Ok(t) => t,
                 Err(e) => {
@@ -892,19 +877,59 @@ pub async fn handle_completions(
         };
         let query_string = if list_response { Some("alt=sse") } else { None };
 
-        let response = match upstream
-            .call_v1_internal(method, &access_token, gemini_body, query_string)
-            .await
-        {
+        let attempt_span = crate::proxy::telemetry::attempt_span(attempt, &email, &mapped_model);
+        // 【新增】非流式请求超过近期延迟分位数时在另一账号上对冲
+        let (response, hedge_outcome, hedge_account) = async {
+            if list_response {
+                let response = upstream
+                    .call_v1_internal(method, &access_token, gemini_body, query_string)
+                    .await;
+                (response, HedgeOutcome::NotFired, None)
+            } else {
+                let hedge_delay = if is_hedge_eligible(&config.request_type, &tools_val) {
+                    crate::proxy::hedging::hedge_delay(&crate::proxy::replay::experimental_config(&state.experimental).await.hedging, &mapped_model)
+                } else {
+                    None
+                };
+                let call = call_generate_with_hedge(
+                    &token_manager,
+                    &upstream,
+                    hedge_delay,
+                    &access_token,
+                    gemini_body,
+                    std::collections::HashMap::new(),
+                    &email,
+                    &config.request_type,
+                    &openai_req.model,
+                    &mapped_model,
+                    |hedge_project_id| transform_openai_request(&openai_req, hedge_project_id, &mapped_model),
+                )
+                .await;
+                (call.response, call.outcome, call.hedge_account)
+            }
+        }
+        .instrument(attempt_span.clone())
+        .await;
+
+        let response = match response {
             Ok(r) => r,
             Err(e) => {
+                attempt_span.record("otel.status_code", "ERROR");
                 last_error = e.clone();
                 debug!("Codex Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
                 continue;
             }
         };
 
+        // 对冲请求胜出时，后续的错误处理与日志归属到对冲账号
+        let (email, _hedge_lease) = match hedge_account {
+            Some(h) => (h.email, Some(h.lease)),
+            None => (email, None),
+        };
+        last_email = Some(email.clone());
+
         let status = response.status();
+        crate::proxy::telemetry::record_status(&attempt_span, status.as_u16());
         if status.is_success() {
             // [智能限流] 请求成功，重置该账号的连续失败计数
             token_manager.mark_account_success(&email);
@@ -914,13 +939,13 @@ pub async fn handle_completions(
                 use axum::response::Response;
                 use futures::StreamExt;
 
-                let gemini_stream = response.bytes_stream();
+                let gemini_stream = crate::proxy::monitor::report_stream_usage(Box::pin(response.bytes_stream()));
                 let mut openai_stream = if is_codex_style {
                     use crate::proxy::mappers::openai::streaming::create_codex_sse_stream;
-                    create_codex_sse_stream(Box::pin(gemini_stream), openai_req.model.clone())
+                    create_codex_sse_stream(gemini_stream, openai_req.model.clone())
                 } else {
                     use crate::proxy::mappers::openai::streaming::create_legacy_sse_stream;
-                    create_legacy_sse_stream(Box::pin(gemini_stream), openai_req.model.clone())
+                    create_legacy_sse_stream(gemini_stream, openai_req.model.clone())
                 };
 
                 // [P1 FIX] Enhanced Peek logic to handle heartbeats and slow start
@@ -991,7 +1016,7 @@ pub async fn handle_completions(
                     .header("Connection", "keep-alive")
                     .header("X-Account-Email", &email)
                     .header("X-Mapped-Model", &mapped_model)
-                    .body(Body::from_stream(combined_stream))
+                    .body(Body::from_stream(lease.hold_stream(combined_stream)))
                     .unwrap()
                     .into_response();
             }
@@ -1007,6 +1032,7 @@ pub async fn handle_completions(
                 }
             };
 
+            crate::proxy::monitor::report_response_usage(&gemini_resp);
             let chat_resp = transform_openai_response(&gemini_resp);
 
             // Map Chat Response -> Legacy Completions Response
@@ -1031,7 +1057,9 @@ pub async fn handle_completions(
                 "usage": chat_resp.usage
             });
 
-            return (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(legacy_resp)).into_response();
+            let mut resp = (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(legacy_resp)).into_response();
+            crate::proxy::hedging::annotate_response(&mut resp, hedge_outcome);
+            return resp;
         }
 
         // Handle errors and retry
@@ -1051,8 +1079,8 @@ pub async fn handle_completions(
             token_manager.mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, Some(&mapped_model)).await;
         }
 
-        // 确定重试策略
-        let strategy = determine_retry_strategy(status_code, &error_text);
+        // 确定重试策略 (Codex 请求体无法剥离 thinking 签名，签名错误不重试)
+        let strategy = determine_retry_strategy(status_code, &error_text, true);
         
         if apply_retry_strategy(strategy, attempt, status_code, &trace_id).await {
             // 继续重试 (loop 会增加 attempt, 导致 force_rotate=true)
@@ -1105,8 +1133,11 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
 /// 处理图像生成请求，转换为 Gemini API 格式
 pub async fn handle_images_generations(
     State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
     Json(body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let base_url = crate::proxy::image_store::base_url(&headers);
+
     // 1. 解析请求参数
     let prompt = body.get("prompt").and_then(|v| v.as_str()).ok_or((
         StatusCode::BAD_REQUEST,
@@ -1171,7 +1202,7 @@ pub async fn handle_images_generations(
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
 
-    let (access_token, project_id, email) = match token_manager.get_token("image_gen", false, None, "dall-e-3").await
+    let (access_token, project_id, email, _lease) = match token_manager.get_token("image_gen", false, None, "dall-e-3").await
     {
         Ok(t) => t,
         Err(e) => {
@@ -1267,7 +1298,7 @@ pub async fn handle_images_generations(
                                             .and_then(|v| v.as_str())
                                             .unwrap_or("image/png");
                                         images.push(json!({
-                                            "url": format!("data:{};base64,{}", mime_type, data)
+                                            "url": stored_image_url(&base_url, mime_type, data).await
                                         }));
                                     } else {
                                         images.push(json!({
@@ -1332,19 +1363,31 @@ pub async fn handle_images_generations(
     ).into_response())
 }
 
-pub async fn handle_images_edits(
-    State(state): State<AppState>,
-    mut multipart: axum::extract::Multipart,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    tracing::info!("[Images] Received edit request");
+/// 图像编辑 / 变体请求的 multipart 表单
+struct ImageForm {
+    image: Option<Vec<u8>>,
+    mask: Option<Vec<u8>>,
+    prompt: String,
+    n: i64,
+    size: Option<String>,
+    quality: Option<String>,
+    response_format: String,
+    model: String,
+}
 
-    let mut image_data = None;
-    let mut mask_data = None;
-    let mut prompt = String::new();
-    let mut n = 1;
-    let mut size = "1024x1024".to_string();
-    let mut response_format = "b64_json".to_string(); // Default to b64_json for better compatibility with tools handling edits
-    let mut model = "gemini-3-pro-image".to_string();
+async fn parse_image_form(
+    multipart: &mut axum::extract::Multipart,
+) -> Result<ImageForm, (StatusCode, String)> {
+    let mut form = ImageForm {
+        image: None,
+        mask: None,
+        prompt: String::new(),
+        n: 1,
+        size: None,
+        quality: None,
+        response_format: "b64_json".to_string(), // Default to b64_json for better compatibility with tools handling edits
+        model: "gemini-3-pro-image".to_string(),
+    };
 
     while let Some(field) = multipart
         .next_field()
@@ -1353,131 +1396,90 @@ pub async fn handle_images_edits(
     {
         let name = field.name().unwrap_or("").to_string();
 
-        if name == "image" {
-            let data = field
-                .bytes()
-                .await
-                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Image read error: {}", e)))?;
-            image_data = Some(base64::engine::general_purpose::STANDARD.encode(data));
-        } else if name == "mask" {
-            let data = field
-                .bytes()
-                .await
-                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Mask read error: {}", e)))?;
-            mask_data = Some(base64::engine::general_purpose::STANDARD.encode(data));
-        } else if name == "prompt" {
-            prompt = field
-                .text()
-                .await
-                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Prompt read error: {}", e)))?;
-        } else if name == "n" {
-            if let Ok(val) = field.text().await {
-                n = val.parse().unwrap_or(1);
+        match name.as_str() {
+            // 兼容 image[] 形式，仅取第一张
+            "image" | "image[]" => {
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|e| (StatusCode::BAD_REQUEST, format!("Image read error: {}", e)))?;
+                if form.image.is_none() {
+                    form.image = Some(data.to_vec());
+                }
             }
-        } else if name == "size" {
-            if let Ok(val) = field.text().await {
-                size = val;
+            "mask" => {
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|e| (StatusCode::BAD_REQUEST, format!("Mask read error: {}", e)))?;
+                form.mask = Some(data.to_vec());
             }
-        } else if name == "response_format" {
-            if let Ok(val) = field.text().await {
-                response_format = val;
+            "prompt" => {
+                form.prompt = field
+                    .text()
+                    .await
+                    .map_err(|e| (StatusCode::BAD_REQUEST, format!("Prompt read error: {}", e)))?;
             }
-        } else if name == "model" {
-            if let Ok(val) = field.text().await {
-                if !val.is_empty() {
-                    model = val;
+            "n" => {
+                if let Ok(val) = field.text().await {
+                    form.n = val.trim().parse().map_err(|_| {
+                        (StatusCode::BAD_REQUEST, format!("'n' must be an integer, got '{}'", val))
+                    })?;
+                }
+            }
+            "size" | "quality" | "response_format" | "model" => {
+                if let Ok(val) = field.text().await {
+                    let val = val.trim().to_string();
+                    if val.is_empty() {
+                        continue;
+                    }
+                    match name.as_str() {
+                        "size" => form.size = Some(val),
+                        "quality" => form.quality = Some(val),
+                        "response_format" => form.response_format = val,
+                        _ => form.model = val,
+                    }
                 }
             }
+            _ => {}
         }
     }
 
-    if image_data.is_none() {
-        return Err((StatusCode::BAD_REQUEST, "Missing image".to_string()));
-    }
-    if prompt.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "Missing prompt".to_string()));
-    }
-
-    tracing::info!(
-        "[Images] Edit Request: model={}, prompt={}, n={}, size={}, mask={}, response_format={}",
-        model,
-        prompt,
-        n,
-        size,
-        mask_data.is_some(),
-        response_format
-    );
-
-    // FIX: Client Display Issue
-    // Cherry Studio (and potentially others) might accept Data URI for generations but display raw text for edits
-    // if 'url' format is used with a data-uri.
-    // If request asks for 'url' but we are a local proxy, returning b64_json is often safer for correct rendering if the client supports it.
-    // However, strictly following spec means 'url' should be 'url'.
-    // Let's rely on client requesting the right thing, BUT allow a server-side heuristic:
-    // If we simply return b64_json structure even if url was requested? No, that breaks spec.
-    // Instead, let's assume successful clients request b64_json.
-    // But if users see raw text, it means client defaulted to 'url' or we defaulted to 'url'.
-    // Let's keep the log to confirm.
-
-    // 1. 获取 Upstream
-    let upstream = state.upstream.clone();
-    let token_manager = state.token_manager;
-    // Fix: Proper get_token call with correct signature and unwrap (using image_gen quota)
-    let (access_token, project_id, email) = match token_manager.get_token("image_gen", false, None, "dall-e-3").await
-    {
-        Ok(t) => t,
-        Err(e) => {
-            return Err((
-                StatusCode::SERVICE_UNAVAILABLE,
-                format!("Token error: {}", e),
-            ))
-        }
-    };
-
-    // 2. 映射配置
-    let mut contents_parts = Vec::new();
-
-    contents_parts.push(json!({
-        "text": format!("Edit this image: {}", prompt)
-    }));
-
-    if let Some(data) = image_data {
-        contents_parts.push(json!({
-            "inlineData": {
-                "mimeType": "image/png",
-                "data": data
-            }
-        }));
+    if form.response_format != "url" && form.response_format != "b64_json" {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "'response_format' must be 'url' or 'b64_json', got '{}'",
+                form.response_format
+            ),
+        ));
     }
 
-    if let Some(data) = mask_data {
-        contents_parts.push(json!({
-            "inlineData": {
-                "mimeType": "image/png",
-                "data": data
-            }
-        }));
-    }
+    Ok(form)
+}
 
-    // 构造 Gemini 内网 API Body (Envelope Structure)
-    let gemini_body = json!({
+/// 构造单张图片任务的 Gemini 内网请求 (Envelope Structure)
+fn build_image_task_body(
+    project_id: &str,
+    request_id_prefix: &str,
+    upstream_model: &str,
+    parts: Vec<Value>,
+    image_config: &Value,
+) -> Value {
+    json!({
         "project": project_id,
-        "requestId": format!("img-edit-{}", uuid::Uuid::new_v4()),
-        "model": model,
+        "requestId": format!("{}-{}", request_id_prefix, uuid::Uuid::new_v4()),
+        "model": upstream_model,
         "userAgent": "antigravity",
         "requestType": "image_gen",
         "request": {
             "contents": [{
                 "role": "user",
-                "parts": contents_parts
+                "parts": parts
             }],
             "generationConfig": {
                 "candidateCount": 1,
-                "maxOutputTokens": 8192,
-                "stopSequences": [],
-                "temperature": 1.0,
-                "topP": 0.95,
-                "topK": 40
+                "imageConfig": image_config
             },
             "safetySettings": [
                 { "category": "HARM_CATEGORY_HARASSMENT", "threshold": "OFF" },
@@ -1487,86 +1489,113 @@ pub async fn handle_images_edits(
                 { "category": "HARM_CATEGORY_CIVIC_INTEGRITY", "threshold": "OFF" },
             ]
         }
-    });
+    })
+}
 
+/// 并发执行图片任务 (每组 parts 产出一张图)，返回 (mimeType, base64) 列表、错误列表与首个成功任务的账号
+/// 每个任务独立走 v1internal 重试流程：取号、失败时轮换账号、限流标记与上游用量上报
+async fn run_image_tasks(
+    state: &AppState,
+    request_id_prefix: &'static str,
+    upstream_model: &str,
+    image_config: &Value,
+    task_parts: Vec<Vec<Value>>,
+) -> (Vec<(String, String)>, Vec<String>, String) {
+    // 新任务不继承 task-local：上游用量槽位需在任务内重新设置，各任务用量累加
+    let usage_slot = crate::proxy::monitor::current_upstream_usage();
     let mut tasks = Vec::new();
-    for _ in 0..n {
-        let upstream = upstream.clone();
-        let access_token = access_token.clone();
-        let body = gemini_body.clone();
+    for parts in task_parts {
+        let token_manager = state.token_manager.clone();
+        let upstream = state.upstream.clone();
+        let upstream_model = upstream_model.to_string();
+        let image_config = image_config.clone();
+        let usage_slot = usage_slot.clone();
 
         tasks.push(tokio::spawn(async move {
-            match upstream
-                .call_v1_internal("generateContent", &access_token, body, None)
+            let send = async {
+                crate::proxy::upstream::retry::send_v1_internal_with_retry(
+                    &token_manager,
+                    &upstream,
+                    "image_gen",
+                    &upstream_model,
+                    "generateContent",
+                    None,
+                    |project_id| {
+                        build_image_task_body(project_id, request_id_prefix, &upstream_model, parts.clone(), &image_config)
+                    },
+                )
+                .await?
+                .into_json()
                 .await
-            {
-                Ok(response) => {
-                    let status = response.status();
-                    if !status.is_success() {
-                        let err_text = response.text().await.unwrap_or_default();
-                        return Err(format!("Upstream error {}: {}", status, err_text));
-                    }
-                    match response.json::<Value>().await {
-                        Ok(json) => Ok(json),
-                        Err(e) => Err(format!("Parse error: {}", e)),
-                    }
-                }
-                Err(e) => Err(format!("Network error: {}", e)),
-            }
+            };
+            let result = match usage_slot {
+                Some(slot) => crate::proxy::monitor::scope_upstream_usage(slot, send).await,
+                None => send.await,
+            };
+            result.map_err(|(status, e)| format!("Upstream error {}: {}", status, e))
         }));
     }
 
-    let mut images: Vec<Value> = Vec::new();
-    let mut errors: Vec<String> = Vec::new();
+    let mut images = Vec::new();
+    let mut errors = Vec::new();
+    let mut email = String::new();
 
     for (idx, task) in tasks.into_iter().enumerate() {
         match task.await {
-            Ok(result) => match result {
-                Ok(gemini_resp) => {
-                    let raw = gemini_resp.get("response").unwrap_or(&gemini_resp);
-                    if let Some(parts) = raw
-                        .get("candidates")
-                        .and_then(|c| c.get(0))
-                        .and_then(|cand| cand.get("content"))
-                        .and_then(|content| content.get("parts"))
-                        .and_then(|p| p.as_array())
-                    {
-                        for part in parts {
-                            if let Some(img) = part.get("inlineData") {
-                                let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
-                                if !data.is_empty() {
-                                    if response_format == "url" {
-                                        let mime_type = img
-                                            .get("mimeType")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("image/png");
-                                        images.push(json!({
-                                            "url": format!("data:{};base64,{}", mime_type, data)
-                                        }));
-                                    } else {
-                                        images.push(json!({
-                                            "b64_json": data
-                                        }));
-                                    }
-                                    tracing::debug!("[Images] Task {} succeeded", idx);
-                                }
-                            }
-                        }
-                    }
+            Ok(Ok(success)) => {
+                if email.is_empty() {
+                    email = success.email;
                 }
-                Err(e) => {
-                    tracing::error!("[Images] Task {} failed: {}", idx, e);
-                    errors.push(e);
+                let image = success
+                    .body
+                    .get("candidates")
+                    .and_then(|c| c.get(0))
+                    .and_then(|cand| cand.get("content"))
+                    .and_then(|content| content.get("parts"))
+                    .and_then(|p| p.as_array())
+                    .and_then(|parts| {
+                        parts.iter().filter_map(|part| part.get("inlineData")).find_map(|img| {
+                            let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
+                            let mime_type = img
+                                .get("mimeType")
+                                .and_then(|v| v.as_str())
+                                .unwrap_or("image/png");
+                            (!data.is_empty()).then(|| (mime_type.to_string(), data.to_string()))
+                        })
+                    });
+                match image {
+                    Some(image) => {
+                        tracing::debug!("[Images] Task {} succeeded", idx);
+                        images.push(image);
+                    }
+                    None => {
+                        tracing::warn!("[Images] Task {} returned no image", idx);
+                        errors.push(format!("Task {} returned no image", idx));
+                    }
                 }
-            },
+            }
+            Ok(Err(e)) => {
+                tracing::error!("[Images] Task {} failed: {}", idx, e);
+                errors.push(e);
+            }
             Err(e) => {
-                let err_msg = format!("Task join error: {}", e);
                 tracing::error!("[Images] Task {} join error: {}", idx, e);
-                errors.push(err_msg);
+                errors.push(format!("Task join error: {}", e));
             }
         }
     }
 
+    (images, errors, email)
+}
+
+/// 汇总任务结果并构造 OpenAI 格式响应
+fn image_task_response(
+    kind: &str,
+    requested: usize,
+    images: Vec<Value>,
+    errors: Vec<String>,
+    email: &str,
+) -> Result<Response, (StatusCode, String)> {
     if images.is_empty() {
         let error_msg = if !errors.is_empty() {
             errors.join("; ")
@@ -1574,8 +1603,9 @@ pub async fn handle_images_edits(
             "No images generated".to_string()
         };
         tracing::error!(
-            "[Images] All {} edit requests failed. Errors: {}",
-            n,
+            "[Images] All {} {} requests failed. Errors: {}",
+            requested,
+            kind,
             error_msg
         );
         return Err((StatusCode::BAD_GATEWAY, error_msg));
@@ -1585,15 +1615,16 @@ pub async fn handle_images_edits(
         tracing::warn!(
             "[Images] Partial success: {} out of {} requests succeeded. Errors: {}",
             images.len(),
-            n,
+            requested,
             errors.join("; ")
         );
     }
 
     tracing::info!(
-        "[Images] Successfully generated {} out of {} requested edited image(s)",
+        "[Images] Successfully generated {} out of {} requested {} image(s)",
         images.len(),
-        n
+        requested,
+        kind
     );
 
     let openai_response = json!({
@@ -1603,7 +1634,245 @@ pub async fn handle_images_edits(
 
     Ok((
         StatusCode::OK,
-        [("X-Account-Email", email.as_str())],
-        Json(openai_response)
-    ).into_response())
+        [("X-Account-Email", email)],
+        Json(openai_response),
+    )
+        .into_response())
+}
+
+/// 按 response_format 输出单张图片
+async fn format_image_entry(base_url: &str, response_format: &str, mime_type: &str, data: &str) -> Value {
+    if response_format == "url" {
+        json!({ "url": stored_image_url(base_url, mime_type, data).await })
+    } else {
+        json!({ "b64_json": data })
+    }
+}
+
+/// OpenAI Images API: POST /v1/images/edits
+/// 带蒙版时只允许修改蒙版透明区域：向模型发送高亮引导图，返回后再与原图合成
+pub async fn handle_images_edits(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    mut multipart: axum::extract::Multipart,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    use crate::proxy::mappers::openai::images;
+
+    let base_url = crate::proxy::image_store::base_url(&headers);
+
+    tracing::info!("[Images] Received edit request");
+
+    let form = parse_image_form(&mut multipart).await?;
+    let image_data = form
+        .image
+        .ok_or((StatusCode::BAD_REQUEST, "Missing image".to_string()))?;
+    if form.prompt.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Missing prompt".to_string()));
+    }
+    let n = images::validate_count(form.n).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    // 图片解码 / 蒙版解析与引导图生成为 CPU 密集操作，放到阻塞线程池执行
+    let mask_data = form.mask;
+    let (image, mask, guide) = tokio::task::spawn_blocking(move || {
+        let image = images::validate_image("image", image_data).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let mask = mask_data
+            .as_deref()
+            .map(|data| images::parse_mask(data, &image))
+            .transpose()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let guide = mask
+            .as_ref()
+            .map(|mask| mask.guide_png(&image))
+            .transpose()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        Ok::<_, (StatusCode, String)>((image, mask, guide))
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))??;
+
+    // 带蒙版时输出须与原图同尺寸，宽高比跟随原图；否则优先使用 size
+    let size = match (&mask, &form.size) {
+        (None, Some(size)) => size.clone(),
+        _ => image.size_hint(),
+    };
+    let (image_config, upstream_model) =
+        crate::proxy::mappers::common_utils::parse_image_config_with_params(
+            &form.model,
+            Some(&size),
+            form.quality.as_deref(),
+        );
+
+    tracing::info!(
+        "[Images] Edit Request: model={}, prompt={}, n={}, size={}, image={}x{}, mask={}, response_format={}",
+        form.model,
+        form.prompt,
+        n,
+        size,
+        image.width,
+        image.height,
+        mask.is_some(),
+        form.response_format
+    );
+
+    let mut parts = vec![
+        json!({ "text": images::edit_instruction(&form.prompt, mask.as_ref()) }),
+        json!({
+            "inlineData": {
+                "mimeType": image.mime_type,
+                "data": base64::engine::general_purpose::STANDARD.encode(&image.data)
+            }
+        }),
+    ];
+    if let Some(guide) = guide {
+        parts.push(json!({
+            "inlineData": {
+                "mimeType": "image/png",
+                "data": base64::engine::general_purpose::STANDARD.encode(guide)
+            }
+        }));
+    }
+
+    let (results, mut errors, email) =
+        run_image_tasks(&state, "img-edit", &upstream_model, &image_config, vec![parts; n]).await;
+
+    let results: Vec<Result<(String, String), String>> = match mask {
+        None => results.into_iter().map(Ok).collect(),
+        // 蒙版外保留原图像素 (解码 / 合成在阻塞线程池执行)
+        Some(mask) => tokio::task::spawn_blocking(move || {
+            results
+                .into_iter()
+                .map(|(_, b64)| {
+                    base64::engine::general_purpose::STANDARD
+                        .decode(&b64)
+                        .map_err(|e| format!("Invalid image data from upstream: {}", e))
+                        .and_then(|edited| mask.composite(&image, &edited))
+                        .map(|png| ("image/png".to_string(), base64::engine::general_purpose::STANDARD.encode(png)))
+                })
+                .collect()
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+    };
+
+    let mut data = Vec::new();
+    for result in results {
+        match result {
+            Ok((mime_type, b64)) => {
+                data.push(format_image_entry(&base_url, &form.response_format, &mime_type, &b64).await);
+            }
+            Err(e) => {
+                tracing::error!("[Images] Mask composite failed: {}", e);
+                errors.push(e);
+            }
+        }
+    }
+
+    image_task_response("edited", n, data, errors, &email)
+}
+
+/// OpenAI Images API: POST /v1/images/variations
+/// 每张变体使用不同的风格方向，保证 n 张结果彼此不同
+pub async fn handle_images_variations(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    mut multipart: axum::extract::Multipart,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    use crate::proxy::mappers::openai::images;
+
+    let base_url = crate::proxy::image_store::base_url(&headers);
+
+    let form = parse_image_form(&mut multipart).await?;
+    let image_data = form
+        .image
+        .ok_or((StatusCode::BAD_REQUEST, "Missing image".to_string()))?;
+    let n = images::validate_count(form.n).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let image = tokio::task::spawn_blocking(move || images::validate_image("image", image_data))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let size = form.size.clone().unwrap_or_else(|| image.size_hint());
+    let (image_config, upstream_model) =
+        crate::proxy::mappers::common_utils::parse_image_config_with_params(
+            &form.model,
+            Some(&size),
+            form.quality.as_deref(),
+        );
+
+    tracing::info!(
+        "[Images] Variation Request: model={}, n={}, size={}, image={}x{}, response_format={}",
+        form.model,
+        n,
+        size,
+        image.width,
+        image.height,
+        form.response_format
+    );
+
+    let image_b64 = base64::engine::general_purpose::STANDARD.encode(&image.data);
+    let task_parts = (0..n)
+        .map(|index| {
+            vec![
+                json!({ "text": images::variation_instruction(index) }),
+                json!({
+                    "inlineData": {
+                        "mimeType": image.mime_type,
+                        "data": image_b64
+                    }
+                }),
+            ]
+        })
+        .collect();
+
+    let (results, errors, email) =
+        run_image_tasks(&state, "img-var", &upstream_model, &image_config, task_parts).await;
+    let mut data = Vec::with_capacity(results.len());
+    for (mime_type, b64) in &results {
+        data.push(format_image_entry(&base_url, &form.response_format, mime_type, b64).await);
+    }
+
+    image_task_response("variation", n, data, errors, &email)
+}
+
+/// response_format=url 时保存到本地图片存储并返回链接，保存失败时退回 data URI
+///
+/// 解码与写文件在阻塞线程执行
+async fn stored_image_url(base_url: &str, mime_type: &str, data: &str) -> String {
+    let (owned_base, owned_data, owned_mime) = (base_url.to_string(), data.to_string(), mime_type.to_string());
+    let saved = tokio::task::spawn_blocking(move || {
+        let id = crate::proxy::image_store::save_base64(&owned_data, &owned_mime)?;
+        // 首次签名会从磁盘读取签名密钥
+        Ok::<_, String>(crate::proxy::image_store::image_url(&owned_base, &id))
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|r| r);
+    match saved {
+        Ok(url) => url,
+        Err(e) => {
+            tracing::warn!("[Images] Failed to store image, falling back to data URI: {}", e);
+            format!("data:{};base64,{}", mime_type, data)
+        }
+    }
+}
+
+/// 由缓存的上游响应构造 OpenAI 响应 (流式客户端经 SSE 转换器回放)
+fn cached_openai_response(cached: &Value, client_model: &str, mapped_model: &str, stream: bool) -> Response {
+    use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
+
+    let mut resp = if stream {
+        let openai_stream = create_openai_sse_stream(response_cache::replay_stream(cached), client_model.to_string());
+        Response::builder()
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header("Connection", "keep-alive")
+            .header("X-Accel-Buffering", "no")
+            .header("X-Mapped-Model", mapped_model)
+            .body(axum::body::Body::from_stream(openai_stream))
+            .unwrap()
+    } else {
+        let openai_response = transform_openai_response(cached);
+        (StatusCode::OK, [("X-Mapped-Model", mapped_model)], Json(openai_response)).into_response()
+    };
+    response_cache::annotate_response(&mut resp, CacheStatus::Hit);
+    resp
 }
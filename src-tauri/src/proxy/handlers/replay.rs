@@ -0,0 +1,49 @@
+// 请求重放处理器
+//
+// - POST /internal/logs/:id/replay   重放指定日志的请求，可覆盖 model / account_email / experimental
+// - GET  /internal/logs/:id/replays  列出该日志的全部重放结果
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+
+use crate::proxy::replay::ReplayOptions;
+use crate::proxy::server::AppState;
+
+/// POST /internal/logs/:id/replay
+pub async fn handle_replay(
+    State(state): State<AppState>,
+    Path(log_id): Path<String>,
+    headers: HeaderMap,
+    body: Option<Json<ReplayOptions>>,
+) -> Response {
+    let options = body.map(|Json(o)| o).unwrap_or_default();
+    match crate::proxy::replay::replay_log(state, &log_id, options, &headers).await {
+        Ok(outcome) => Json(outcome).into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": format!("Replay failed: {}", e) })),
+        )
+            .into_response(),
+    }
+}
+
+/// GET /internal/logs/:id/replays
+pub async fn handle_list_replays(Path(log_id): Path<String>) -> Response {
+    let result = tokio::task::spawn_blocking(move || crate::modules::proxy_db::get_replays(&log_id))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r);
+
+    match result {
+        Ok(replays) => Json(json!({ "replays": replays })).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": format!("Failed to load replays: {}", e) })),
+        )
+            .into_response(),
+    }
+}
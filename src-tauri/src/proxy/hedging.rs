@@ -0,0 +1,416 @@
+// 对冲请求 (Hedged Requests)
+// 非流式请求在超过近期延迟分位数仍未返回时，换一个账号发出副本，取先成功者，另一个被取消
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::proxy::concurrency::TokenLease;
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::TokenManager;
+
+/// 每个模型保留的最近延迟样本数
+const MAX_SAMPLES_PER_MODEL: usize = 200;
+
+/// 对冲策略配置 (默认关闭)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HedgingConfig {
+    /// 是否启用对冲请求
+    pub enabled: bool,
+    /// 触发对冲的延迟分位数 (0.0 - 1.0)，如 0.95 表示超过近期 P95 仍未返回时对冲
+    pub percentile: f64,
+    /// 样本数不足时不对冲，避免冷启动阶段误触发
+    pub min_samples: usize,
+    /// 对冲延迟下限 (毫秒)
+    pub min_delay_ms: u64,
+    /// 对冲延迟上限 (毫秒)
+    pub max_delay_ms: u64,
+}
+
+impl Default for HedgingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            percentile: 0.95,
+            min_samples: 20,
+            min_delay_ms: 1_000,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+/// 对冲结果，写入响应头 `X-Hedge` 并记录到请求日志
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HedgeOutcome {
+    /// 未触发对冲 (主请求在阈值内返回)
+    NotFired,
+    /// 已触发，但未能取得另一个账号
+    Skipped,
+    /// 已触发，主请求先成功
+    PrimaryWon,
+    /// 已触发，对冲请求先成功
+    HedgeWon,
+    /// 已触发，两者均失败 (返回主请求结果)
+    BothFailed,
+}
+
+impl HedgeOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HedgeOutcome::NotFired => "not_fired",
+            HedgeOutcome::Skipped => "skipped",
+            HedgeOutcome::PrimaryWon => "primary_won",
+            HedgeOutcome::HedgeWon => "hedge_won",
+            HedgeOutcome::BothFailed => "both_failed",
+        }
+    }
+
+    pub fn fired(&self) -> bool {
+        !matches!(self, HedgeOutcome::NotFired)
+    }
+}
+
+/// 按映射后模型统计的近期延迟
+#[derive(Default)]
+pub struct LatencyTracker {
+    samples: DashMap<String, VecDeque<u64>>,
+}
+
+static GLOBAL_TRACKER: Lazy<LatencyTracker> = Lazy::new(LatencyTracker::new);
+
+impl LatencyTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn global() -> &'static LatencyTracker {
+        &GLOBAL_TRACKER
+    }
+
+    pub fn record(&self, model: &str, latency: Duration) {
+        let mut entry = self.samples.entry(model.to_string()).or_default();
+        if entry.len() >= MAX_SAMPLES_PER_MODEL {
+            entry.pop_front();
+        }
+        entry.push_back(latency.as_millis() as u64);
+    }
+
+    /// 计算分位数延迟，样本不足时返回 None
+    pub fn percentile(&self, model: &str, p: f64, min_samples: usize) -> Option<Duration> {
+        let entry = self.samples.get(model)?;
+        if entry.is_empty() || entry.len() < min_samples {
+            return None;
+        }
+        let mut sorted: Vec<u64> = entry.iter().copied().collect();
+        sorted.sort_unstable();
+        let p = p.clamp(0.0, 1.0);
+        let idx = ((sorted.len() - 1) as f64 * p).round() as usize;
+        Some(Duration::from_millis(sorted[idx]))
+    }
+}
+
+/// 请求是否允许对冲
+///
+/// 图像生成 (配额昂贵) 与携带客户端自定义工具 (可能有副作用) 的请求从不对冲；
+/// 仅含联网搜索等只读内置工具的请求不受限制。
+pub fn is_hedge_eligible(request_type: &str, tools: &Option<Vec<Value>>) -> bool {
+    if request_type == "image_gen" {
+        return false;
+    }
+    !crate::proxy::mappers::common_utils::contains_non_networking_tool(tools)
+}
+
+/// 计算本次请求的对冲等待时间，None 表示不对冲
+pub fn hedge_delay(config: &HedgingConfig, mapped_model: &str) -> Option<Duration> {
+    if !config.enabled {
+        return None;
+    }
+    let p = LatencyTracker::global().percentile(mapped_model, config.percentile, config.min_samples)?;
+    let min = Duration::from_millis(config.min_delay_ms);
+    let max = Duration::from_millis(config.max_delay_ms.max(config.min_delay_ms));
+    Some(p.clamp(min, max))
+}
+
+/// 主请求与延迟触发的对冲请求赛跑，返回先成功者
+///
+/// - 主请求在 `delay` 内完成时不触发对冲
+/// - `hedge` 返回 None 表示未能发出对冲 (如无其他可用账号)
+/// - 两者都失败时返回主请求的结果，便于沿用原有的错误处理
+/// - 函数返回即丢弃未完成的一方，reqwest 请求随之取消
+/// - 第三项为已完成但未被返回的失败结果，调用方需自行处理 (如限流标记)
+pub async fn race<T, P, H>(
+    delay: Duration,
+    primary: P,
+    hedge: H,
+    is_success: impl Fn(&T) -> bool,
+) -> (T, HedgeOutcome, Option<T>)
+where
+    P: Future<Output = T>,
+    H: Future<Output = Option<T>>,
+{
+    tokio::pin!(primary);
+    tokio::select! {
+        biased;
+        v = &mut primary => return (v, HedgeOutcome::NotFired, None),
+        _ = tokio::time::sleep(delay) => {}
+    }
+
+    tokio::pin!(hedge);
+    // None: 对冲仍在进行; Some(true): 对冲已发出但失败; Some(false): 未能发出对冲
+    let mut hedge_failed: Option<bool> = None;
+    let mut hedge_result: Option<T> = None;
+    let mut primary_failed: Option<T> = None;
+
+    let outcome_on_failure = |hedge_failed: Option<bool>| match hedge_failed {
+        Some(false) => HedgeOutcome::Skipped,
+        _ => HedgeOutcome::BothFailed,
+    };
+
+    loop {
+        tokio::select! {
+            v = &mut primary, if primary_failed.is_none() => {
+                if is_success(&v) {
+                    let outcome = if hedge_failed == Some(false) { HedgeOutcome::Skipped } else { HedgeOutcome::PrimaryWon };
+                    return (v, outcome, hedge_result);
+                }
+                if hedge_failed.is_some() {
+                    return (v, outcome_on_failure(hedge_failed), hedge_result);
+                }
+                primary_failed = Some(v);
+            }
+            h = &mut hedge, if hedge_failed.is_none() => {
+                match h {
+                    Some(v) if is_success(&v) => return (v, HedgeOutcome::HedgeWon, primary_failed),
+                    Some(v) => {
+                        hedge_failed = Some(true);
+                        hedge_result = Some(v);
+                    }
+                    None => hedge_failed = Some(false),
+                }
+                if let Some(p) = primary_failed.take() {
+                    return (p, outcome_on_failure(hedge_failed), hedge_result);
+                }
+            }
+        }
+    }
+}
+
+/// 对冲已触发时在响应上标记 `X-Hedge`，由监控中间件写入请求日志
+pub fn annotate_response(response: &mut axum::response::Response, outcome: HedgeOutcome) {
+    if outcome.fired() {
+        response.headers_mut().insert(
+            "X-Hedge",
+            axum::http::HeaderValue::from_static(outcome.as_str()),
+        );
+    }
+}
+
+/// 对冲请求胜出时使用的账号，调用方需持有租约直到响应读取完毕
+pub struct HedgeAccount {
+    pub email: String,
+    pub lease: TokenLease,
+}
+
+/// 对冲调用的结果
+pub struct HedgedCall {
+    pub response: Result<reqwest::Response, String>,
+    pub outcome: HedgeOutcome,
+    /// 对冲请求胜出时为 Some，后续错误处理与日志应归属到该账号
+    pub hedge_account: Option<HedgeAccount>,
+}
+
+/// 发送非流式 generateContent 请求，`delay` 为 Some 时在超时后于另一账号上对冲
+///
+/// `build_body` 接收对冲账号的 project_id 重新构造请求体。
+/// 每次调用 (含失败) 的首包延迟都会计入 [`LatencyTracker`]，供后续请求计算对冲阈值；
+/// 未被返回的一方若以限流类错误结束，在此处为其账号标记限流 (返回的一方由调用方按原有流程处理)。
+#[allow(clippy::too_many_arguments)]
+pub async fn call_generate_with_hedge<F>(
+    token_manager: &TokenManager,
+    upstream: &UpstreamClient,
+    delay: Option<Duration>,
+    access_token: &str,
+    body: Value,
+    extra_headers: HashMap<String, String>,
+    primary_email: &str,
+    request_type: &str,
+    target_model: &str,
+    mapped_model: &str,
+    build_body: F,
+) -> HedgedCall
+where
+    F: Fn(&str) -> Value,
+{
+    let tracker = LatencyTracker::global();
+    let primary = async {
+        let started = Instant::now();
+        let res = upstream
+            .call_v1_internal_with_headers("generateContent", access_token, body, None, extra_headers.clone())
+            .await;
+        tracker.record(mapped_model, started.elapsed());
+        (res, None)
+    };
+
+    let Some(delay) = delay else {
+        let (response, _) = primary.await;
+        return HedgedCall { response, outcome: HedgeOutcome::NotFired, hedge_account: None };
+    };
+
+    let hedge = async {
+        // 对冲拿不到账号就放弃，不进入排队
+        let (token, project_id, email, lease) = match token_manager
+            .get_token_once(request_type, true, None, target_model)
+            .await
+        {
+            Ok(t) => t,
+            Err(e) => {
+                tracing::debug!("[Hedge] No account available for hedge: {}", e);
+                return None;
+            }
+        };
+        if email == primary_email {
+            tracing::debug!("[Hedge] Rotation returned the primary account {}, skipping hedge", email);
+            return None;
+        }
+
+        tracing::info!("[Hedge] {} exceeded {}ms on {}, hedging on {}", mapped_model, delay.as_millis(), primary_email, email);
+        let started = Instant::now();
+        let res = upstream
+            .call_v1_internal_with_headers("generateContent", &token, build_body(&project_id), None, extra_headers.clone())
+            .await;
+        tracker.record(mapped_model, started.elapsed());
+        Some((res, Some(HedgeAccount { email, lease })))
+    };
+
+    let ((response, hedge_account), outcome, discarded) = race(delay, primary, hedge, |(r, _)| {
+        matches!(r, Ok(resp) if resp.status().is_success())
+    })
+    .await;
+
+    if let Some((Ok(failed), account)) = discarded {
+        let email = account.as_ref().map_or(primary_email, |a| a.email.as_str());
+        mark_failed_attempt(token_manager, email, failed, mapped_model).await;
+    }
+
+    if outcome.fired() {
+        tracing::info!("[Hedge] {} outcome: {}", mapped_model, outcome.as_str());
+    }
+    HedgedCall { response, outcome, hedge_account }
+}
+
+/// 为未被返回的失败调用标记限流 (与各 handler 相同的状态码与模型级限流)
+async fn mark_failed_attempt(token_manager: &TokenManager, email: &str, response: reqwest::Response, model: &str) {
+    let status_code = response.status().as_u16();
+    if !matches!(status_code, 429 | 500 | 503 | 529) {
+        return;
+    }
+    let retry_after = response
+        .headers()
+        .get("Retry-After")
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.to_string());
+    let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
+    tracing::warn!("[Hedge] Discarded attempt on {} failed with {}", email, status_code);
+    token_manager
+        .mark_rate_limited_async(email, status_code, retry_after.as_deref(), &error_text, Some(model))
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_percentile() {
+        let tracker = LatencyTracker::new();
+        assert!(tracker.percentile("m", 0.95, 1).is_none());
+        for ms in 1..=100 {
+            tracker.record("m", Duration::from_millis(ms));
+        }
+        assert_eq!(tracker.percentile("m", 0.5, 10), Some(Duration::from_millis(51)));
+        assert_eq!(tracker.percentile("m", 0.95, 10), Some(Duration::from_millis(95)));
+        assert!(tracker.percentile("m", 0.95, 101).is_none());
+    }
+
+    #[test]
+    fn test_hedge_eligibility() {
+        assert!(is_hedge_eligible("agent", &None));
+        assert!(!is_hedge_eligible("image_gen", &None));
+        let fn_tools = Some(vec![json!({"type": "function", "function": {"name": "delete_file"}})]);
+        assert!(!is_hedge_eligible("agent", &fn_tools));
+    }
+
+    #[tokio::test]
+    async fn test_race_primary_fast() {
+        let (v, outcome, discarded) = race(
+            Duration::from_millis(200),
+            async { Ok::<u32, ()>(1) },
+            async { Some(Ok(2)) },
+            |r| r.is_ok(),
+        )
+        .await;
+        assert_eq!(v, Ok(1));
+        assert_eq!(outcome, HedgeOutcome::NotFired);
+        assert_eq!(discarded, None);
+    }
+
+    #[tokio::test]
+    async fn test_race_hedge_wins_when_primary_stalls() {
+        let (v, outcome, _) = race(
+            Duration::from_millis(10),
+            async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok::<u32, ()>(1)
+            },
+            async { Some(Ok(2)) },
+            |r| r.is_ok(),
+        )
+        .await;
+        assert_eq!(v, Ok(2));
+        assert_eq!(outcome, HedgeOutcome::HedgeWon);
+    }
+
+    #[tokio::test]
+    async fn test_race_both_fail_returns_primary() {
+        let (v, outcome, discarded) = race(
+            Duration::from_millis(10),
+            async {
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                Err::<u32, u32>(1)
+            },
+            async { Some(Err(2)) },
+            |r| r.is_ok(),
+        )
+        .await;
+        assert_eq!(v, Err(1));
+        assert_eq!(outcome, HedgeOutcome::BothFailed);
+        // 失败的对冲结果交还调用方 (用于限流标记)
+        assert_eq!(discarded, Some(Err(2)));
+    }
+
+    #[tokio::test]
+    async fn test_race_hedge_wins_returns_failed_primary() {
+        let (v, outcome, discarded) = race(
+            Duration::from_millis(10),
+            async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Err::<u32, u32>(1)
+            },
+            async {
+                tokio::time::sleep(Duration::from_millis(40)).await;
+                Some(Ok(2))
+            },
+            |r| r.is_ok(),
+        )
+        .await;
+        assert_eq!(v, Ok(2));
+        assert_eq!(outcome, HedgeOutcome::HedgeWon);
+        assert_eq!(discarded, Some(Err(1)));
+    }
+}
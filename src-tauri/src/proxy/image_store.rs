@@ -0,0 +1,399 @@
+// 本地图片存储 (Local Image Store)
+// 生成的图片按内容哈希保存在数据目录的 images/ 下，由反代通过 /v1/files/images/{id} 提供访问，
+// 使 response_format=url 返回真实链接而非数 MB 的 base64。
+// 链接可附带过期签名 (无需 API Key 即可访问)，后台任务按保留时长与总大小清理。
+
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 图片访问路由前缀
+pub const ROUTE_PREFIX: &str = "/v1/files/images/";
+
+/// 图片存储配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageStoreConfig {
+    /// 图片保留时长 (小时)，0 表示不按时间清理
+    pub retention_hours: u64,
+    /// 存储总大小上限 (MB)，超出时删除最早的图片，0 表示不限制
+    pub max_size_mb: u64,
+    /// 是否为链接附加签名 (签名有效期内无需 API Key 即可访问)
+    pub signed_urls: bool,
+    /// 签名链接有效期 (分钟)
+    pub url_ttl_minutes: u64,
+    /// 生成链接使用的外部地址 (如 http://192.168.1.2:8045)，为空时使用请求的 Host
+    pub public_base_url: Option<String>,
+}
+
+impl Default for ImageStoreConfig {
+    fn default() -> Self {
+        Self {
+            retention_hours: 24,
+            max_size_mb: 1024,
+            signed_urls: true,
+            url_ttl_minutes: 60,
+            public_base_url: None,
+        }
+    }
+}
+
+static CONFIG: Lazy<RwLock<Arc<ImageStoreConfig>>> =
+    Lazy::new(|| RwLock::new(Arc::new(ImageStoreConfig::default())));
+
+/// 签名密钥 (首次使用时生成并持久化到存储目录)
+static SIGNING_KEY: Lazy<Vec<u8>> = Lazy::new(|| load_or_create_signing_key().unwrap_or_else(|e| {
+    tracing::warn!("[ImageStore] Failed to persist signing key, using ephemeral key: {}", e);
+    random_key()
+}));
+
+/// 当前生效的配置
+pub fn config() -> Arc<ImageStoreConfig> {
+    CONFIG.read().unwrap().clone()
+}
+
+/// 应用图片存储配置
+pub fn apply_config(config: &ImageStoreConfig) {
+    *CONFIG.write().unwrap() = Arc::new(config.clone());
+}
+
+/// 存储目录 (不存在时创建)
+pub fn store_dir() -> Result<PathBuf, String> {
+    let dir = crate::modules::account::get_data_dir()?.join("images");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create image dir: {}", e))?;
+    Ok(dir)
+}
+
+fn extension_for(mime_type: &str) -> &'static str {
+    match mime_type {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        _ => "png",
+    }
+}
+
+/// 由图片 ID 的扩展名推断 MIME 类型
+pub fn mime_for(id: &str) -> &'static str {
+    match id.rsplit('.').next() {
+        Some("jpg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        _ => "image/png",
+    }
+}
+
+/// 校验图片 ID (64 位十六进制哈希 + 扩展名)，防止路径穿越
+pub fn is_valid_id(id: &str) -> bool {
+    match id.split_once('.') {
+        Some((hash, ext)) => {
+            hash.len() == 64
+                && hash.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+                && matches!(ext, "png" | "jpg" | "webp" | "gif")
+        }
+        None => false,
+    }
+}
+
+/// 以内容哈希为 ID 保存图片；相同内容只保存一份 (重复保存会刷新保留时间)
+pub fn save_in(dir: &Path, data: &[u8], mime_type: &str) -> Result<String, String> {
+    let id = format!("{:x}.{}", Sha256::digest(data), extension_for(mime_type));
+    let path = dir.join(&id);
+    if path.exists() {
+        let file = std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open image: {}", e))?;
+        let _ = file.set_modified(SystemTime::now());
+        return Ok(id);
+    }
+
+    // 先写临时文件再重命名，避免并发读取到不完整的图片
+    let tmp = dir.join(format!(".{}.tmp", uuid::Uuid::new_v4()));
+    std::fs::write(&tmp, data).map_err(|e| format!("Failed to write image: {}", e))?;
+    std::fs::rename(&tmp, &path).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        format!("Failed to store image: {}", e)
+    })?;
+    Ok(id)
+}
+
+/// 保存 base64 编码的图片
+pub fn save_base64(data: &str, mime_type: &str) -> Result<String, String> {
+    let bytes = general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| format!("Invalid image data: {}", e))?;
+    save_in(&store_dir()?, &bytes, mime_type)
+}
+
+/// 读取图片，不存在时返回 None
+pub fn load(id: &str) -> Result<Option<Vec<u8>>, String> {
+    if !is_valid_id(id) {
+        return Ok(None);
+    }
+    match std::fs::read(store_dir()?.join(id)) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read image: {}", e)),
+    }
+}
+
+// ===== 签名链接 =====
+
+fn random_key() -> Vec<u8> {
+    use rand::RngCore;
+    let mut key = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    key
+}
+
+fn load_or_create_signing_key() -> Result<Vec<u8>, String> {
+    let path = store_dir()?.join(".signing_key");
+    if let Ok(key) = std::fs::read(&path) {
+        if key.len() >= 32 {
+            return Ok(key);
+        }
+    }
+    let key = random_key();
+    std::fs::write(&path, &key).map_err(|e| format!("Failed to write signing key: {}", e))?;
+    Ok(key)
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn signing_mac(key: &[u8], message: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    signing_mac(key, message).finalize().into_bytes().into()
+}
+
+fn signature(key: &[u8], id: &str, expires: i64) -> String {
+    hmac_sha256(key, format!("{}:{}", id, expires).as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 解析十六进制签名 (长度或字符非法时返回 None)
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
+
+fn verify_with(key: &[u8], id: &str, expires: i64, sig: &str, now: i64) -> bool {
+    if expires < now {
+        return false;
+    }
+    let Some(sig) = decode_hex(sig) else {
+        return false;
+    };
+    // 常量时间比较，避免泄露匹配长度
+    signing_mac(key, format!("{}:{}", id, expires).as_bytes())
+        .verify_slice(&sig)
+        .is_ok()
+}
+
+/// 校验签名链接参数
+pub fn verify(id: &str, expires: i64, sig: &str) -> bool {
+    verify_with(&SIGNING_KEY, id, expires, sig, chrono::Utc::now().timestamp())
+}
+
+/// 认证中间件使用: 请求是否为有效的签名图片链接
+pub fn is_signed_request(path: &str, query: Option<&str>) -> bool {
+    let Some(id) = path.strip_prefix(ROUTE_PREFIX) else {
+        return false;
+    };
+    let mut expires = None;
+    let mut sig = None;
+    for pair in query.unwrap_or("").split('&') {
+        match pair.split_once('=') {
+            Some(("expires", v)) => expires = v.parse::<i64>().ok(),
+            Some(("sig", v)) => sig = Some(v),
+            _ => {}
+        }
+    }
+    match (expires, sig) {
+        (Some(expires), Some(sig)) => verify(id, expires, sig),
+        _ => false,
+    }
+}
+
+/// 生成图片访问链接 (`base_url` 不含结尾斜杠)
+pub fn image_url(base_url: &str, id: &str) -> String {
+    let config = config();
+    let url = format!("{}{}{}", base_url.trim_end_matches('/'), ROUTE_PREFIX, id);
+    if !config.signed_urls {
+        return url;
+    }
+    let expires = chrono::Utc::now().timestamp() + (config.url_ttl_minutes.max(1) * 60) as i64;
+    format!("{}?expires={}&sig={}", url, expires, signature(&SIGNING_KEY, id, expires))
+}
+
+/// 由配置或请求头推断对外访问地址
+pub fn base_url(headers: &axum::http::HeaderMap) -> String {
+    if let Some(base) = config().public_base_url.as_deref().filter(|b| !b.trim().is_empty()) {
+        return base.trim().trim_end_matches('/').to_string();
+    }
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let scheme = header("x-forwarded-proto").unwrap_or("http");
+    let host = header("x-forwarded-host")
+        .or_else(|| header("host"))
+        .unwrap_or("127.0.0.1");
+    format!("{}://{}", scheme, host)
+}
+
+// ===== 保留策略 =====
+
+/// 清理结果
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PruneStats {
+    pub removed: usize,
+    pub remaining_bytes: u64,
+}
+
+/// 按保留时长删除过期图片，再按修改时间从旧到新删除直至总大小不超过上限
+pub fn prune_dir(dir: &Path, config: &ImageStoreConfig, now: SystemTime) -> Result<PruneStats, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("Failed to read image dir: {}", e))?;
+    let retention = Duration::from_secs(config.retention_hours * 3600);
+
+    let mut files = Vec::new();
+    let mut stats = PruneStats::default();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Ok(meta) = entry.metadata() else { continue };
+        let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+        let age = now.duration_since(modified).unwrap_or_default();
+
+        // 残留的临时文件 (写入中断) 超过 1 小时即删除
+        let stale_tmp = name.ends_with(".tmp") && age > Duration::from_secs(3600);
+        let expired = is_valid_id(&name) && config.retention_hours > 0 && age > retention;
+        if stale_tmp || expired {
+            if std::fs::remove_file(entry.path()).is_ok() {
+                stats.removed += 1;
+            }
+        } else if is_valid_id(&name) {
+            files.push((modified, meta.len(), entry.path()));
+        }
+    }
+
+    let max_bytes = config.max_size_mb * 1024 * 1024;
+    let mut total: u64 = files.iter().map(|f| f.1).sum();
+    if config.max_size_mb > 0 && total > max_bytes {
+        files.sort_by_key(|f| f.0);
+        for (_, len, path) in &files {
+            if total <= max_bytes {
+                break;
+            }
+            if std::fs::remove_file(path).is_ok() {
+                total -= len;
+                stats.removed += 1;
+            }
+        }
+    }
+    stats.remaining_bytes = total;
+    Ok(stats)
+}
+
+/// 启动后台清理任务 (每 30 分钟)
+pub fn start_cleanup_job() {
+    tauri::async_runtime::spawn(async {
+        let mut interval = tokio::time::interval(Duration::from_secs(30 * 60));
+        loop {
+            interval.tick().await;
+            let result = tokio::task::spawn_blocking(|| prune_dir(&store_dir()?, &config(), SystemTime::now())).await;
+            match result {
+                Ok(Ok(stats)) if stats.removed > 0 => {
+                    tracing::info!(
+                        "[ImageStore] Removed {} image(s), {} bytes remaining",
+                        stats.removed,
+                        stats.remaining_bytes
+                    );
+                }
+                Ok(Err(e)) => tracing::warn!("[ImageStore] Cleanup failed: {}", e),
+                _ => {}
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256_rfc4231() {
+        // RFC 4231 测试用例 2
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn test_signed_url_verification() {
+        let key = b"test-key";
+        let id = format!("{}.png", "a".repeat(64));
+        let sig = signature(key, &id, 1_000);
+        assert!(verify_with(key, &id, 1_000, &sig, 999));
+        assert!(!verify_with(key, &id, 1_000, &sig, 1_001), "expired");
+        assert!(!verify_with(key, &id, 2_000, &sig, 999), "tampered expiry");
+        assert!(!verify_with(b"other-key", &id, 1_000, &sig, 999));
+        assert!(!verify_with(key, &id, 1_000, &sig[..62], 999), "truncated");
+        assert!(!verify_with(key, &id, 1_000, &"zz".repeat(32), 999), "not hex");
+    }
+
+    #[test]
+    fn test_id_validation() {
+        assert!(is_valid_id(&format!("{}.png", "0f".repeat(32))));
+        assert!(!is_valid_id("../../etc/passwd"));
+        assert!(!is_valid_id(&format!("{}.exe", "0f".repeat(32))));
+        assert!(!is_valid_id(&format!("{}.png", "0F".repeat(32))));
+    }
+
+    #[test]
+    fn test_save_and_prune() {
+        let dir = std::env::temp_dir().join(format!("image_store_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let first = save_in(&dir, &[1u8; 600 * 1024], "image/png").unwrap();
+        let second = save_in(&dir, &[2u8; 600 * 1024], "image/jpeg").unwrap();
+        assert!(second.ends_with(".jpg"));
+        assert_eq!(save_in(&dir, &[1u8; 600 * 1024], "image/png").unwrap(), first);
+
+        // 总大小超过 1MB: 删除最早的一张
+        let old = SystemTime::now() - Duration::from_secs(60);
+        std::fs::File::options().write(true).open(dir.join(&first)).unwrap().set_modified(old).unwrap();
+        let config = ImageStoreConfig {
+            retention_hours: 0,
+            max_size_mb: 1,
+            ..Default::default()
+        };
+        let stats = prune_dir(&dir, &config, SystemTime::now()).unwrap();
+        assert_eq!(stats.removed, 1);
+        assert!(!dir.join(&first).exists());
+        assert!(dir.join(&second).exists());
+
+        // 超过保留时长: 全部删除
+        let config = ImageStoreConfig {
+            retention_hours: 1,
+            ..Default::default()
+        };
+        let later = SystemTime::now() + Duration::from_secs(2 * 3600);
+        assert_eq!(prune_dir(&dir, &config, later).unwrap().removed, 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -34,7 +34,8 @@ pub fn create_claude_sse_stream(
     use bytes::BytesMut;
     use futures::StreamExt;
 
-    Box::pin(stream! {
+    let span = tracing::info_span!("proxy.stream_mapping", protocol = "claude", account = %email);
+    let stream = stream! {
         let mut state = StreamingState::new();
         state.session_id = session_id; // Set session ID for signature caching
         state.scaling_enabled = scaling_enabled; // Set scaling enabled flag
@@ -136,7 +137,8 @@ pub fn create_claude_sse_stream(
         for chunk in emit_force_stop(&mut state) {
             yield Ok(chunk);
         }
-    })
+    };
+    Box::pin(crate::proxy::telemetry::instrument_stream(span, stream))
 }
 
 /// 处理单行 SSE 数据
@@ -329,6 +329,7 @@ fn reorder_gemini_parts(parts: &mut Vec<Value>) {
     parts.extend(tool_parts);
 }
 
+#[tracing::instrument(name = "proxy.transform", skip_all, fields(protocol = "claude", model = %claude_req.model))]
 pub fn transform_claude_request_in(
     claude_req: &ClaudeRequest,
     project_id: &str,
@@ -114,6 +114,7 @@ mod tests {
             candidates_token_count: Some(50),
             total_token_count: Some(150),
             cached_content_token_count: None,
+            thoughts_token_count: None,
         };
 
         let claude_usage = to_claude_usage(&usage, true, 1_000_000);
@@ -127,6 +128,7 @@ mod tests {
             candidates_token_count: Some(10),
             total_token_count: Some(500_010),
             cached_content_token_count: None,
+            thoughts_token_count: None,
         };
         let res_50 = to_claude_usage(&usage_50, true, 1_000_000);
         // 50% * 0.6 = 30% of 195k = 58,500
@@ -138,6 +140,7 @@ mod tests {
             candidates_token_count: Some(10),
             total_token_count: Some(700_010),
             cached_content_token_count: None,
+            thoughts_token_count: None,
         };
         let res_70 = to_claude_usage(&usage_70, true, 1_000_000);
         // 50% of 195k = 97,500
@@ -149,6 +152,7 @@ mod tests {
             candidates_token_count: Some(10),
             total_token_count: Some(850_010),
             cached_content_token_count: None,
+            thoughts_token_count: None,
         };
         let res_85 = to_claude_usage(&usage_85, true, 1_000_000);
         // 70% of 195k = 136,500
@@ -160,6 +164,7 @@ mod tests {
             candidates_token_count: Some(10),
             total_token_count: Some(1_000_010),
             cached_content_token_count: None,
+            thoughts_token_count: None,
         };
         let res_100 = to_claude_usage(&usage_100, true, 1_000_000);
         // 97% of 195k = 189,150
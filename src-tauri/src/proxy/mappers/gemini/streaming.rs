@@ -0,0 +1,301 @@
+// Gemini 原生流式响应转换
+// 上游 v1internal 固定以 alt=sse 请求，这里逐 chunk 解包后按客户端期望的帧格式输出:
+// - alt=sse: `data: {...}\r\n\r\n` 事件流
+// - 其他 (默认 alt=json): 渐进输出的 JSON 数组 `[{...}\n,\r\n{...}\n]`
+
+use bytes::Bytes;
+use serde_json::Value;
+
+use super::wrapper::unwrap_response;
+
+/// 客户端期望的流式帧格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFraming {
+    Sse,
+    JsonArray,
+}
+
+impl StreamFraming {
+    /// 根据查询参数 `alt` (或 `$alt`) 选择帧格式，与 Google 一致默认为 JSON 数组
+    pub fn from_query(query: Option<&str>) -> Self {
+        let is_sse = query
+            .unwrap_or("")
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .any(|(key, value)| {
+                (key == "alt" || key == "$alt") && value.eq_ignore_ascii_case("sse")
+            });
+        if is_sse {
+            StreamFraming::Sse
+        } else {
+            StreamFraming::JsonArray
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            StreamFraming::Sse => "text/event-stream",
+            StreamFraming::JsonArray => "application/json; charset=UTF-8",
+        }
+    }
+}
+
+/// 上游 SSE 解析出的单个事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamItem {
+    /// 已解包的 GenerateContentResponse
+    Chunk(Value),
+    /// 无法解析为 JSON 的 data 负载
+    Unparsed(String),
+}
+
+/// 解包单个流式 chunk，并把 envelope 层的 usageMetadata 移回响应体顶层 (与 Google 格式一致)
+pub fn normalize_chunk(chunk: Value) -> Value {
+    let envelope_usage = chunk
+        .get("response")
+        .and(chunk.get("usageMetadata"))
+        .cloned();
+    let mut inner = unwrap_response(&chunk);
+    if let (Some(usage), Some(obj)) = (envelope_usage, inner.as_object_mut()) {
+        obj.entry("usageMetadata").or_insert(usage);
+    }
+    inner
+}
+
+/// 增量解析上游 SSE 字节流 (容忍任意位置的分包与 CRLF 行尾)
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<StreamItem> {
+        self.buffer.extend_from_slice(bytes);
+        let mut items = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            self.process_line(&String::from_utf8_lossy(&line), &mut items);
+        }
+        items
+    }
+
+    /// 上游结束时处理残留的未终止事件
+    pub fn finish(&mut self) -> Vec<StreamItem> {
+        let mut items = Vec::new();
+        if !self.buffer.is_empty() {
+            let line = std::mem::take(&mut self.buffer);
+            self.process_line(&String::from_utf8_lossy(&line), &mut items);
+        }
+        self.dispatch(&mut items);
+        items
+    }
+
+    fn process_line(&mut self, line: &str, items: &mut Vec<StreamItem>) {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            self.dispatch(items);
+        } else if let Some(rest) = line.strip_prefix("data:") {
+            self.data
+                .push(rest.strip_prefix(' ').unwrap_or(rest).to_string());
+        }
+        // 注释 (": keep-alive") 及 event/id 等字段对 Gemini 客户端无意义，直接忽略
+    }
+
+    fn dispatch(&mut self, items: &mut Vec<StreamItem>) {
+        if self.data.is_empty() {
+            return;
+        }
+        let payload = self.data.join("\n");
+        self.data.clear();
+        if payload.trim() == "[DONE]" {
+            return;
+        }
+        match serde_json::from_str::<Value>(&payload) {
+            Ok(json) => items.push(StreamItem::Chunk(normalize_chunk(json))),
+            Err(e) => {
+                tracing::debug!("[Gemini-Stream] JSON parse error: {}", e);
+                items.push(StreamItem::Unparsed(payload));
+            }
+        }
+    }
+}
+
+/// 按客户端帧格式编码 chunk
+#[derive(Debug)]
+pub struct StreamEncoder {
+    framing: StreamFraming,
+    started: bool,
+}
+
+impl StreamEncoder {
+    pub fn new(framing: StreamFraming) -> Self {
+        Self {
+            framing,
+            started: false,
+        }
+    }
+
+    pub fn encode(&mut self, item: &StreamItem) -> Option<Bytes> {
+        match (self.framing, item) {
+            (StreamFraming::Sse, StreamItem::Chunk(chunk)) => Some(Bytes::from(format!(
+                "data: {}\r\n\r\n",
+                serde_json::to_string(chunk).unwrap_or_default()
+            ))),
+            (StreamFraming::Sse, StreamItem::Unparsed(raw)) => {
+                Some(Bytes::from(format!("data: {}\r\n\r\n", raw)))
+            }
+            (StreamFraming::JsonArray, StreamItem::Chunk(chunk)) => {
+                let prefix = if self.started { "\n,\r\n" } else { "[" };
+                self.started = true;
+                Some(Bytes::from(format!(
+                    "{}{}",
+                    prefix,
+                    serde_json::to_string_pretty(chunk).unwrap_or_default()
+                )))
+            }
+            // 非法 JSON 无法嵌入数组，丢弃
+            (StreamFraming::JsonArray, StreamItem::Unparsed(_)) => None,
+        }
+    }
+
+    /// 流结束时的收尾字节 (JSON 数组的右括号)
+    pub fn finish(&mut self) -> Option<Bytes> {
+        match self.framing {
+            StreamFraming::Sse => None,
+            StreamFraming::JsonArray if self.started => Some(Bytes::from_static(b"\n]")),
+            StreamFraming::JsonArray => Some(Bytes::from_static(b"[]")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 以固定分包大小喂入上游字节，返回客户端收到的完整输出
+    fn convert(framing: StreamFraming, upstream: &[u8], split: usize) -> String {
+        let mut decoder = SseDecoder::new();
+        let mut encoder = StreamEncoder::new(framing);
+        let mut out = Vec::new();
+        let mut emit = |items: Vec<StreamItem>, encoder: &mut StreamEncoder| {
+            for item in items {
+                if let Some(bytes) = encoder.encode(&item) {
+                    out.extend_from_slice(&bytes);
+                }
+            }
+        };
+        for piece in upstream.chunks(split) {
+            let items = decoder.push(piece);
+            emit(items, &mut encoder);
+        }
+        let items = decoder.finish();
+        emit(items, &mut encoder);
+        if let Some(bytes) = encoder.finish() {
+            out.extend_from_slice(&bytes);
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    /// 对比帧结构 (逐字节) 与 JSON 负载 (按值，避免依赖字段顺序)
+    fn assert_golden(framing: StreamFraming, actual: &str, expected: &str) {
+        match framing {
+            StreamFraming::Sse => {
+                let split = |s: &str| -> Vec<Value> {
+                    assert!(s.ends_with("\r\n\r\n"));
+                    s.split_terminator("\r\n\r\n")
+                        .map(|event| {
+                            let payload = event.strip_prefix("data: ").expect("data event");
+                            serde_json::from_str(payload).unwrap()
+                        })
+                        .collect()
+                };
+                assert_eq!(split(actual), split(expected));
+            }
+            StreamFraming::JsonArray => {
+                assert!(actual.starts_with("[{\n") && actual.ends_with("\n}\n]"));
+                assert_eq!(
+                    actual.matches("\n,\r\n").count(),
+                    expected.matches("\n,\r\n").count()
+                );
+                let actual: Value = serde_json::from_str(actual).unwrap();
+                let expected: Value = serde_json::from_str(expected).unwrap();
+                assert_eq!(actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_golden_streams() {
+        let cases: [(&str, &[u8], &str, &str); 2] = [
+            (
+                "stream_text",
+                include_bytes!("testdata/stream_text.upstream.sse"),
+                include_str!("testdata/stream_text.expected.sse"),
+                include_str!("testdata/stream_text.expected.json"),
+            ),
+            (
+                "stream_function_call",
+                include_bytes!("testdata/stream_function_call.upstream.sse"),
+                include_str!("testdata/stream_function_call.expected.sse"),
+                include_str!("testdata/stream_function_call.expected.json"),
+            ),
+        ];
+
+        for (name, upstream, expected_sse, expected_json) in cases {
+            // 不同分包大小 (含按字节切分多字节字符) 输出必须一致
+            for split in [1, 7, 64, upstream.len()] {
+                let sse = convert(StreamFraming::Sse, upstream, split);
+                assert_golden(StreamFraming::Sse, &sse, expected_sse);
+                let array = convert(StreamFraming::JsonArray, upstream, split);
+                assert_golden(StreamFraming::JsonArray, &array, expected_json);
+                assert!(
+                    !array.contains("traceId"),
+                    "{} leaked envelope fields",
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_framing_from_query() {
+        assert_eq!(
+            StreamFraming::from_query(Some("alt=sse")),
+            StreamFraming::Sse
+        );
+        assert_eq!(
+            StreamFraming::from_query(Some("key=abc&$alt=SSE")),
+            StreamFraming::Sse
+        );
+        assert_eq!(
+            StreamFraming::from_query(Some("alt=json")),
+            StreamFraming::JsonArray
+        );
+        assert_eq!(StreamFraming::from_query(None), StreamFraming::JsonArray);
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        // 空流仍是合法 JSON 数组
+        assert_eq!(convert(StreamFraming::JsonArray, b"", 8), "[]");
+        // 缺少结尾空行的最后一个事件也会输出
+        let out = convert(
+            StreamFraming::Sse,
+            b"data: {\"response\":{\"candidates\":[]}}",
+            8,
+        );
+        assert_eq!(out, "data: {\"candidates\":[]}\r\n\r\n");
+        // 内层已有 usageMetadata 时不被 envelope 覆盖
+        let chunk = normalize_chunk(json!({
+            "response": {"usageMetadata": {"promptTokenCount": 1}},
+            "usageMetadata": {"promptTokenCount": 2}
+        }));
+        assert_eq!(chunk["usageMetadata"]["promptTokenCount"], 1);
+    }
+}
@@ -2,6 +2,7 @@
 use serde_json::{json, Value};
 
 /// 包装请求体为 v1internal 格式
+#[tracing::instrument(name = "proxy.transform", skip_all, fields(protocol = "gemini", model = %mapped_model))]
 pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str, session_id: Option<&str>) -> Value {
     // 优先使用传入的 mapped_model，其次尝试从 body 获取
     let original_model = body.get("model").and_then(|v| v.as_str()).unwrap_or(mapped_model);
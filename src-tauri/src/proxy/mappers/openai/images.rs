@@ -0,0 +1,348 @@
+// OpenAI 图像编辑 / 变体的输入校验与蒙版处理
+// - 校验输入图片格式与尺寸
+// - 蒙版 (透明区域为可编辑区域) 以高亮引导图 + 文字描述告知模型
+// - 模型返回后再按蒙版与原图合成，保证蒙版外像素不变
+
+use image::{DynamicImage, GenericImageView, ImageFormat, Rgba, RgbaImage};
+use std::io::Cursor;
+
+/// 单张输入图片大小上限
+pub const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+/// 输入图片边长范围 (像素)
+pub const MIN_DIMENSION: u32 = 32;
+pub const MAX_DIMENSION: u32 = 8192;
+/// 单次请求最多生成的图片数量
+pub const MAX_IMAGES_PER_REQUEST: usize = 10;
+
+/// 蒙版 alpha 低于该值视为可编辑区域 (兼容抗锯齿边缘)
+const MASK_ALPHA_THRESHOLD: u8 = 128;
+
+/// 变体的风格方向 (按序号轮换，保证多张变体彼此不同)
+const VARIATION_DIRECTIONS: &[&str] = &[
+    "a different color palette",
+    "different lighting and mood",
+    "a different composition and camera angle",
+    "a different artistic medium and texture",
+    "a different time of day and atmosphere",
+    "a more minimalist interpretation",
+    "a more detailed and intricate interpretation",
+    "a different season and setting details",
+];
+
+/// 校验通过的输入图片
+#[derive(Debug, Clone)]
+pub struct ImageInput {
+    pub data: Vec<u8>,
+    pub mime_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageInput {
+    /// 未指定 size 时按原图尺寸推断宽高比
+    pub fn size_hint(&self) -> String {
+        format!("{}x{}", self.width, self.height)
+    }
+}
+
+/// 解析后的蒙版: 可编辑像素标记与其包围盒
+#[derive(Debug, Clone)]
+pub struct EditMask {
+    width: u32,
+    height: u32,
+    editable: Vec<bool>,
+    /// (left, top, right, bottom)，右/下为开区间
+    bounds: (u32, u32, u32, u32),
+    editable_pixels: usize,
+}
+
+/// 校验图片格式 (PNG / JPEG / WEBP)、大小与尺寸
+pub fn validate_image(field: &str, data: Vec<u8>) -> Result<ImageInput, String> {
+    if data.is_empty() {
+        return Err(format!("'{}' is empty", field));
+    }
+    if data.len() > MAX_IMAGE_BYTES {
+        return Err(format!(
+            "'{}' is too large ({:.1} MB), maximum is {} MB",
+            field,
+            data.len() as f64 / (1024.0 * 1024.0),
+            MAX_IMAGE_BYTES / (1024 * 1024)
+        ));
+    }
+
+    let format = image::guess_format(&data).map_err(|_| {
+        format!(
+            "'{}' is not a recognized image, expected PNG, JPEG or WEBP",
+            field
+        )
+    })?;
+    let mime_type = match format {
+        ImageFormat::Png => "image/png",
+        ImageFormat::Jpeg => "image/jpeg",
+        ImageFormat::WebP => "image/webp",
+        other => {
+            return Err(format!(
+                "'{}' has unsupported format {:?}, expected PNG, JPEG or WEBP",
+                field, other
+            ))
+        }
+    };
+
+    let (width, height) = image::ImageReader::with_format(Cursor::new(&data), format)
+        .into_dimensions()
+        .map_err(|e| format!("'{}' could not be decoded: {}", field, e))?;
+    for (name, value) in [("width", width), ("height", height)] {
+        if !(MIN_DIMENSION..=MAX_DIMENSION).contains(&value) {
+            return Err(format!(
+                "'{}' {} must be between {} and {} pixels, got {}",
+                field, name, MIN_DIMENSION, MAX_DIMENSION, value
+            ));
+        }
+    }
+
+    Ok(ImageInput {
+        data,
+        mime_type,
+        width,
+        height,
+    })
+}
+
+/// 校验蒙版: 须为带 alpha 通道的 PNG，尺寸与原图一致，且包含透明 (可编辑) 区域
+pub fn parse_mask(data: &[u8], image: &ImageInput) -> Result<EditMask, String> {
+    if image::guess_format(data).ok() != Some(ImageFormat::Png) {
+        return Err("'mask' must be a PNG image".to_string());
+    }
+    let mask = image::load_from_memory_with_format(data, ImageFormat::Png)
+        .map_err(|e| format!("'mask' could not be decoded: {}", e))?;
+    if !mask.color().has_alpha() {
+        return Err(
+            "'mask' must have an alpha channel; transparent areas mark the region to edit"
+                .to_string(),
+        );
+    }
+    if mask.dimensions() != (image.width, image.height) {
+        return Err(format!(
+            "'mask' dimensions {}x{} must match the image dimensions {}x{}",
+            mask.width(),
+            mask.height(),
+            image.width,
+            image.height
+        ));
+    }
+
+    let (width, height) = mask.dimensions();
+    let rgba = mask.to_rgba8();
+    let mut editable = Vec::with_capacity((width * height) as usize);
+    let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        let is_editable = pixel[3] < MASK_ALPHA_THRESHOLD;
+        if is_editable {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x + 1);
+            bottom = bottom.max(y + 1);
+        }
+        editable.push(is_editable);
+    }
+
+    let editable_pixels = editable.iter().filter(|e| **e).count();
+    if editable_pixels == 0 {
+        return Err("'mask' has no transparent area, so there is nothing to edit".to_string());
+    }
+    Ok(EditMask {
+        width,
+        height,
+        editable,
+        bounds: (left, top, right, bottom),
+        editable_pixels,
+    })
+}
+
+impl EditMask {
+    fn is_editable(&self, x: u32, y: u32) -> bool {
+        self.editable[(y * self.width + x) as usize]
+    }
+
+    /// 可编辑区域的文字描述 (百分比坐标)
+    pub fn describe(&self) -> String {
+        let pct = |v: u32, total: u32| (v as f64 * 100.0 / total as f64).round() as u32;
+        let (left, top, right, bottom) = self.bounds;
+        format!(
+            "The editable region spans roughly {}%-{}% from the left and {}%-{}% from the top of the image, covering about {}% of its area.",
+            pct(left, self.width),
+            pct(right, self.width),
+            pct(top, self.height),
+            pct(bottom, self.height),
+            (self.editable_pixels as f64 * 100.0 / self.editable.len() as f64).round().max(1.0) as u32
+        )
+    }
+
+    /// 引导图: 原图上以半透明品红高亮可编辑区域 (PNG)
+    pub fn guide_png(&self, image: &ImageInput) -> Result<Vec<u8>, String> {
+        let mut canvas = decode_rgba(&image.data)?;
+        for (x, y, pixel) in canvas.enumerate_pixels_mut() {
+            if self.is_editable(x, y) {
+                let [r, g, b, _] = pixel.0;
+                *pixel = Rgba([
+                    ((r as u16 + 255) / 2) as u8,
+                    (g / 2),
+                    ((b as u16 + 255) / 2) as u8,
+                    255,
+                ]);
+            }
+        }
+        encode_png(canvas)
+    }
+
+    /// 合成: 蒙版内取模型结果 (缩放到原图尺寸)，蒙版外保留原图像素
+    pub fn composite(&self, original: &ImageInput, edited: &[u8]) -> Result<Vec<u8>, String> {
+        let base = decode_rgba(&original.data)?;
+        let mut edited = decode_rgba(edited)?;
+        if edited.dimensions() != base.dimensions() {
+            edited = image::imageops::resize(
+                &edited,
+                base.width(),
+                base.height(),
+                image::imageops::FilterType::Lanczos3,
+            );
+        }
+
+        let mut output = base;
+        for (x, y, pixel) in output.enumerate_pixels_mut() {
+            if self.is_editable(x, y) {
+                *pixel = *edited.get_pixel(x, y);
+            }
+        }
+        encode_png(output)
+    }
+}
+
+fn decode_rgba(data: &[u8]) -> Result<RgbaImage, String> {
+    image::load_from_memory(data)
+        .map(|img| img.to_rgba8())
+        .map_err(|e| format!("Failed to decode image: {}", e))
+}
+
+fn encode_png(image: RgbaImage) -> Result<Vec<u8>, String> {
+    let mut out = Cursor::new(Vec::new());
+    DynamicImage::ImageRgba8(image)
+        .write_to(&mut out, ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(out.into_inner())
+}
+
+/// 编辑指令；带蒙版时说明第二张图为高亮引导图
+pub fn edit_instruction(prompt: &str, mask: Option<&EditMask>) -> String {
+    match mask {
+        Some(mask) => format!(
+            "Edit the first image: {}\n\nThe second image is the same picture with the editable region highlighted in magenta. \
+             Only change the content inside that region and keep everything outside it exactly as it is. {} \
+             Return the full edited image without any highlight.",
+            prompt,
+            mask.describe()
+        ),
+        None => format!("Edit this image: {}", prompt),
+    }
+}
+
+/// 第 `index` 张变体的指令
+pub fn variation_instruction(index: usize) -> String {
+    let direction = VARIATION_DIRECTIONS[index % VARIATION_DIRECTIONS.len()];
+    format!(
+        "Create a new variation of this image. Keep the same subject and overall concept, but reinterpret it with {}. \
+         Return only the new image.",
+        direction
+    )
+}
+
+/// 校验 n 参数 (1 ~ MAX_IMAGES_PER_REQUEST)
+pub fn validate_count(n: i64) -> Result<usize, String> {
+    if (1..=MAX_IMAGES_PER_REQUEST as i64).contains(&n) {
+        Ok(n as usize)
+    } else {
+        Err(format!(
+            "'n' must be between 1 and {}, got {}",
+            MAX_IMAGES_PER_REQUEST, n
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32, pixel: impl Fn(u32, u32) -> Rgba<u8>) -> Vec<u8> {
+        encode_png(RgbaImage::from_fn(width, height, pixel)).unwrap()
+    }
+
+    #[test]
+    fn test_validate_image() {
+        let ok = validate_image("image", png(64, 48, |_, _| Rgba([1, 2, 3, 255]))).unwrap();
+        assert_eq!((ok.width, ok.height, ok.mime_type), (64, 48, "image/png"));
+        assert_eq!(ok.size_hint(), "64x48");
+
+        let err = validate_image("image", png(16, 64, |_, _| Rgba([0, 0, 0, 255]))).unwrap_err();
+        assert!(err.contains("width must be between"));
+        assert!(validate_image("image", b"not an image".to_vec())
+            .unwrap_err()
+            .contains("not a recognized image"));
+        assert!(validate_count(0).is_err());
+        assert_eq!(validate_count(3).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_mask_validation() {
+        let image = validate_image("image", png(64, 64, |_, _| Rgba([0, 0, 0, 255]))).unwrap();
+
+        let opaque = png(64, 64, |_, _| Rgba([0, 0, 0, 255]));
+        assert!(parse_mask(&opaque, &image)
+            .unwrap_err()
+            .contains("no transparent area"));
+        let wrong_size = png(32, 64, |_, _| Rgba([0, 0, 0, 0]));
+        assert!(parse_mask(&wrong_size, &image)
+            .unwrap_err()
+            .contains("must match"));
+
+        // 右半边透明
+        let mask = png(64, 64, |x, _| {
+            Rgba([0, 0, 0, if x >= 32 { 0 } else { 255 }])
+        });
+        let mask = parse_mask(&mask, &image).unwrap();
+        assert_eq!(mask.bounds, (32, 0, 64, 64));
+        assert!(mask.describe().contains("50%-100% from the left"));
+    }
+
+    #[test]
+    fn test_composite_keeps_unmasked_pixels() {
+        let original =
+            validate_image("image", png(64, 64, |_, _| Rgba([10, 20, 30, 255]))).unwrap();
+        let mask = parse_mask(
+            &png(64, 64, |x, _| {
+                Rgba([0, 0, 0, if x >= 32 { 0 } else { 255 }])
+            }),
+            &original,
+        )
+        .unwrap();
+
+        // 模型返回不同尺寸的纯白图
+        let edited = png(128, 128, |_, _| Rgba([255, 255, 255, 255]));
+        let result = decode_rgba(&mask.composite(&original, &edited).unwrap()).unwrap();
+        assert_eq!(result.dimensions(), (64, 64));
+        assert_eq!(result.get_pixel(0, 0), &Rgba([10, 20, 30, 255]));
+        assert_eq!(result.get_pixel(63, 63), &Rgba([255, 255, 255, 255]));
+
+        let guide = decode_rgba(&mask.guide_png(&original).unwrap()).unwrap();
+        assert_eq!(guide.get_pixel(0, 0), &Rgba([10, 20, 30, 255]));
+        assert_ne!(guide.get_pixel(63, 0), &Rgba([10, 20, 30, 255]));
+    }
+
+    #[test]
+    fn test_variation_instructions_differ() {
+        assert_ne!(variation_instruction(0), variation_instruction(1));
+        assert_eq!(
+            variation_instruction(0),
+            variation_instruction(VARIATION_DIRECTIONS.len())
+        );
+    }
+}
@@ -3,6 +3,7 @@ use super::models::*;
 use super::streaming::get_thought_signature;
 use serde_json::{json, Value};
 
+#[tracing::instrument(name = "proxy.transform", skip_all, fields(protocol = "openai", model = %mapped_model))]
 pub fn transform_openai_request(
     request: &OpenAIRequest,
     project_id: &str,
@@ -171,18 +171,24 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
             .get("promptTokenCount")
             .and_then(|v| v.as_u64())
             .unwrap_or(0) as u32;
+        let cached_tokens = u
+            .get("cachedContentTokenCount")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as u32);
+        let reasoning_tokens = u
+            .get("thoughtsTokenCount")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as u32);
+        // OpenAI 语义: completion_tokens 包含 reasoning_tokens
         let completion_tokens = u
             .get("candidatesTokenCount")
             .and_then(|v| v.as_u64())
-            .unwrap_or(0) as u32;
+            .unwrap_or(0) as u32
+            + reasoning_tokens.unwrap_or(0);
         let total_tokens = u
             .get("totalTokenCount")
             .and_then(|v| v.as_u64())
             .unwrap_or(0) as u32;
-        let cached_tokens = u
-            .get("cachedContentTokenCount")
-            .and_then(|v| v.as_u64())
-            .map(|v| v as u32);
 
         Some(super::models::OpenAIUsage {
             prompt_tokens,
@@ -191,7 +197,9 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
             prompt_tokens_details: cached_tokens.map(|ct| super::models::PromptTokensDetails {
                 cached_tokens: Some(ct),
             }),
-            completion_tokens_details: None,
+            completion_tokens_details: reasoning_tokens.map(|rt| super::models::CompletionTokensDetails {
+                reasoning_tokens: Some(rt),
+            }),
         })
     });
 
@@ -269,6 +277,27 @@ mod tests {
         assert_eq!(usage.prompt_tokens_details.unwrap().cached_tokens, Some(25));
     }
 
+    #[test]
+    fn test_usage_completion_includes_reasoning() {
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": {"parts": [{"text": "Hello!"}]},
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": {
+                "promptTokenCount": 100,
+                "candidatesTokenCount": 20,
+                "thoughtsTokenCount": 30,
+                "totalTokenCount": 150
+            }
+        });
+
+        let usage = transform_openai_response(&gemini_resp).usage.unwrap();
+        assert_eq!(usage.completion_tokens, 50);
+        assert_eq!(usage.prompt_tokens + usage.completion_tokens, usage.total_tokens);
+        assert_eq!(usage.completion_tokens_details.unwrap().reasoning_tokens, Some(30));
+    }
+
     #[test]
     fn test_response_without_usage_metadata() {
         let gemini_resp = json!({
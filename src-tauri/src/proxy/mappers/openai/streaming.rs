@@ -55,24 +55,30 @@ pub fn get_thought_signature() -> Option<String> {
 
 /// Extract and convert Gemini usageMetadata to OpenAI usage format
 fn extract_usage_metadata(u: &Value) -> Option<super::models::OpenAIUsage> {
-    use super::models::{OpenAIUsage, PromptTokensDetails};
+    use super::models::{CompletionTokensDetails, OpenAIUsage, PromptTokensDetails};
 
     let prompt_tokens = u
         .get("promptTokenCount")
         .and_then(|v| v.as_u64())
         .unwrap_or(0) as u32;
+    let cached_tokens = u
+        .get("cachedContentTokenCount")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as u32);
+    let reasoning_tokens = u
+        .get("thoughtsTokenCount")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as u32);
+    // OpenAI 语义: completion_tokens 包含 reasoning_tokens
     let completion_tokens = u
         .get("candidatesTokenCount")
         .and_then(|v| v.as_u64())
-        .unwrap_or(0) as u32;
+        .unwrap_or(0) as u32
+        + reasoning_tokens.unwrap_or(0);
     let total_tokens = u
         .get("totalTokenCount")
         .and_then(|v| v.as_u64())
         .unwrap_or(0) as u32;
-    let cached_tokens = u
-        .get("cachedContentTokenCount")
-        .and_then(|v| v.as_u64())
-        .map(|v| v as u32);
 
     Some(OpenAIUsage {
         prompt_tokens,
@@ -81,7 +87,9 @@ fn extract_usage_metadata(u: &Value) -> Option<super::models::OpenAIUsage> {
         prompt_tokens_details: cached_tokens.map(|ct| PromptTokensDetails {
             cached_tokens: Some(ct),
         }),
-        completion_tokens_details: None,
+        completion_tokens_details: reasoning_tokens.map(|rt| CompletionTokensDetails {
+            reasoning_tokens: Some(rt),
+        }),
     })
 }
 
@@ -394,7 +402,10 @@ pub fn create_openai_sse_stream(
         }
     };
 
-    Box::pin(stream)
+    Box::pin(crate::proxy::telemetry::instrument_stream(
+        tracing::info_span!("proxy.stream_mapping", protocol = "openai"),
+        stream,
+    ))
 }
 
 pub fn create_legacy_sse_stream(
@@ -574,7 +585,10 @@ pub fn create_legacy_sse_stream(
         }
     };
 
-    Box::pin(stream)
+    Box::pin(crate::proxy::telemetry::instrument_stream(
+        tracing::info_span!("proxy.stream_mapping", protocol = "openai_legacy"),
+        stream,
+    ))
 }
 
 pub fn create_codex_sse_stream(
@@ -845,5 +859,8 @@ pub fn create_codex_sse_stream(
         yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&completed_ev).unwrap())));
     };
 
-    Box::pin(stream)
+    Box::pin(crate::proxy::telemetry::instrument_stream(
+        tracing::info_span!("proxy.stream_mapping", protocol = "codex"),
+        stream,
+    ))
 }
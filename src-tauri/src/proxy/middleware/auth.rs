@@ -11,70 +11,102 @@ use tokio::sync::RwLock;
 
 use crate::proxy::{ProxyAuthMode, ProxySecurityConfig};
 
+/// 请求扩展标记：健康检查因 `all_except_health` 免鉴权放行 (未携带有效 API Key)
+#[derive(Debug, Clone, Copy)]
+pub struct UnauthenticatedHealthCheck;
+
 /// API Key 认证中间件
 pub async fn auth_middleware(
     State(security): State<Arc<RwLock<ProxySecurityConfig>>>,
-    request: Request,
+    mut request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
     let method = request.method().clone();
     let path = request.uri().path().to_string();
 
     // 过滤心跳和健康检查请求,避免日志噪音
-    if !path.contains("event_logging") && path != "/healthz" {
+    let is_health_path = path == "/healthz" || path == "/readyz";
+    if !path.contains("event_logging") && !is_health_path {
         tracing::info!("Request: {} {}", method, path);
     } else {
         tracing::trace!("Heartbeat: {} {}", method, path);
     }
 
+    let security = security.read().await.clone();
+    let auth_span = tracing::info_span!(
+        "proxy.auth",
+        auth.mode = ?security.effective_auth_mode(),
+        auth.outcome = tracing::field::Empty,
+    );
+    // 有效的签名图片链接无需 API Key (便于浏览器/客户端直接展示)
+    let is_signed_image = crate::proxy::image_store::is_signed_request(&path, request.uri().query());
+    // 进行中的文件上传会话: upload_id 即凭证 (与 Google 的上传 URL 一致)
+    let is_upload_session = crate::proxy::gemini_files::is_upload_session_request(&path, request.uri().query());
+    let authorized = is_signed_image
+        || is_upload_session
+        || auth_span.in_scope(|| is_authorized(&security, &method, is_health_path, request.headers()));
+    auth_span.record("auth.outcome", if authorized { "allowed" } else { "denied" });
+    // 鉴权 span 只覆盖鉴权本身，不包含后续处理
+    drop(auth_span);
+
+    if authorized {
+        if is_health_path
+            && matches!(security.effective_auth_mode(), ProxyAuthMode::AllExceptHealth)
+            && !is_authorized(&security, &method, false, request.headers())
+        {
+            request.extensions_mut().insert(UnauthenticatedHealthCheck);
+        }
+        Ok(next.run(request).await)
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+/// 按鉴权模式判断请求是否放行
+fn is_authorized(
+    security: &ProxySecurityConfig,
+    method: &axum::http::Method,
+    is_health_path: bool,
+    headers: &axum::http::HeaderMap,
+) -> bool {
     // Allow CORS preflight regardless of auth policy.
     if method == axum::http::Method::OPTIONS {
-        return Ok(next.run(request).await);
+        return true;
     }
 
-    let security = security.read().await.clone();
     let effective_mode = security.effective_auth_mode();
 
     if matches!(effective_mode, ProxyAuthMode::Off) {
-        return Ok(next.run(request).await);
+        return true;
     }
 
-    if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && path == "/healthz" {
-        return Ok(next.run(request).await);
+    if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && is_health_path {
+        return true;
     }
     
     // 从 header 中提取 API key
-    let api_key = request
-        .headers()
+    let api_key = headers
         .get(header::AUTHORIZATION)
         .and_then(|h| h.to_str().ok())
         .and_then(|s| s.strip_prefix("Bearer ").or(Some(s)))
         .or_else(|| {
-            request
-                .headers()
+            headers
                 .get("x-api-key")
                 .and_then(|h| h.to_str().ok())
         })
         .or_else(|| {
-            request
-                .headers()
+            headers
                 .get("x-goog-api-key")
                 .and_then(|h| h.to_str().ok())
         });
 
     if security.api_key.is_empty() {
         tracing::error!("Proxy auth is enabled but api_key is empty; denying request");
-        return Err(StatusCode::UNAUTHORIZED);
+        return false;
     }
 
     // Constant-time compare is unnecessary here, but keep strict equality and avoid leaking values.
-    let authorized = api_key.map(|k| k == security.api_key).unwrap_or(false);
-
-    if authorized {
-        Ok(next.run(request).await)
-    } else {
-        Err(StatusCode::UNAUTHORIZED)
-    }
+    api_key.map(|k| k == security.api_key).unwrap_or(false)
 }
 
 #[cfg(test)]
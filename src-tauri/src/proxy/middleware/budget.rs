@@ -0,0 +1,64 @@
+// 预算拦截中间件
+// 全局或当前 API Key 的拦截型预算在本周期已超出时，直接返回与客户端协议匹配的 429 错误
+
+use axum::{
+    extract::Request,
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{json, Value};
+
+use crate::proxy::request_queue::queue_key_from_headers;
+
+pub async fn budget_middleware(request: Request, next: Next) -> Response {
+    if request.method() != Method::POST || request.uri().path().starts_with("/internal/") {
+        return next.run(request).await;
+    }
+
+    let api_key = queue_key_from_headers(request.headers());
+    match crate::proxy::budget::check_request(Some(&api_key)) {
+        Some(status) => {
+            let message = status.message();
+            tracing::warn!("[Budget] Rejected {} ({}): {}", request.uri().path(), api_key, message);
+            (StatusCode::TOO_MANY_REQUESTS, Json(budget_error_body(request.uri().path(), &message))).into_response()
+        }
+        None => next.run(request).await,
+    }
+}
+
+/// 构造与客户端协议匹配的预算超额错误体
+fn budget_error_body(path: &str, message: &str) -> Value {
+    if path.starts_with("/v1/messages") {
+        json!({
+            "type": "error",
+            "error": { "type": "rate_limit_error", "message": message }
+        })
+    } else if path.starts_with("/v1beta/") {
+        json!({
+            "error": { "code": 429, "message": message, "status": "RESOURCE_EXHAUSTED" }
+        })
+    } else {
+        json!({
+            "error": { "message": message, "type": "insufficient_quota", "code": "budget_exceeded" }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_error_body_per_protocol() {
+        let claude = budget_error_body("/v1/messages", "over");
+        assert_eq!(claude["error"]["type"], "rate_limit_error");
+
+        let gemini = budget_error_body("/v1beta/models/gemini-2.5-pro:generateContent", "over");
+        assert_eq!(gemini["error"]["status"], "RESOURCE_EXHAUSTED");
+
+        let openai = budget_error_body("/v1/chat/completions", "over");
+        assert_eq!(openai["error"]["type"], "insufficient_quota");
+        assert_eq!(openai["error"]["message"], "over");
+    }
+}
@@ -1,9 +1,12 @@
 // Middleware 模块 - Axum 中间件
 
 pub mod auth;
+pub mod budget;
 pub mod cors;
 pub mod logging;
 pub mod monitor;
+pub mod queue;
+pub mod telemetry;
 
 pub use auth::auth_middleware;
 pub use cors::cors_layer;
@@ -6,7 +6,7 @@ use axum::{
 };
 use std::time::Instant;
 use crate::proxy::server::AppState;
-use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::monitor::{ProxyRequestLog, UpstreamUsage};
 use serde_json::Value;
 use futures::StreamExt;
 
@@ -22,6 +22,10 @@ pub async fn monitor_middleware(
     
     let method = request.method().to_string();
     let uri = request.uri().to_string();
+    // 重放请求需要拿到本次日志 ID 以便与原始日志关联
+    let is_replay = request.headers().contains_key(crate::proxy::replay::REPLAY_OF_HEADER);
+    let api_key = crate::proxy::request_queue::queue_key_from_headers(request.headers());
+    let request_headers = (method == "POST").then(|| crate::proxy::replay::encode_headers(request.headers()));
     
     if uri.contains("event_logging") {
         return next.run(request).await;
@@ -39,7 +43,10 @@ pub async fn monitor_middleware(
     };
 
     let request_body_str;
-    let request = if method == "POST" {
+    let mut session_id = None;
+    // 文件上传请求体可达 GB 级，流式交给 handler，不缓冲记录
+    let is_upload = uri.starts_with("/upload/");
+    let request = if method == "POST" && !is_upload {
         let (parts, body) = request.into_parts();
         match axum::body::to_bytes(body, MAX_REQUEST_LOG_SIZE).await {
             Ok(bytes) => {
@@ -48,6 +55,7 @@ pub async fn monitor_middleware(
                         v.get("model").and_then(|m| m.as_str()).map(|s| s.to_string())
                     );
                 }
+                session_id = extract_session_id(&uri, &bytes, model.as_deref());
                 request_body_str = if let Ok(s) = std::str::from_utf8(&bytes) {
                     Some(s.to_string())
                 } else {
@@ -65,7 +73,15 @@ pub async fn monitor_middleware(
         request
     };
     
-    let response = next.run(request).await;
+    // 协议转换层在此槽位上报上游原始用量 (流式响应在下方转发任务中继续写入)
+    let upstream_usage = std::sync::Arc::new(std::sync::Mutex::new(None));
+    let mut response = crate::proxy::monitor::scope_upstream_usage(upstream_usage.clone(), next.run(request)).await;
+    let log_id = uuid::Uuid::new_v4().to_string();
+    if is_replay {
+        if let Ok(v) = axum::http::HeaderValue::from_str(&log_id) {
+            response.headers_mut().insert(crate::proxy::replay::LOG_ID_HEADER, v);
+        }
+    }
     
     let duration = start.elapsed().as_millis() as u64;
     let status = response.status().as_u16();
@@ -89,6 +105,32 @@ pub async fn monitor_middleware(
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
+    // multipart 等非 JSON 请求体无法解析出 model，以 handler 回报的模型为准
+    if model.is_none() {
+        model = mapped_model.clone();
+    }
+
+    // Extract hedge outcome from X-Hedge header if present
+    let hedge = response
+        .headers()
+        .get("X-Hedge")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    // Extract background task class from X-Background-Task header if present
+    let background_task = response
+        .headers()
+        .get("X-Background-Task")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    // Extract response cache status from X-Cache header if present
+    let cache = response
+        .headers()
+        .get("X-Cache")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
     // Determine protocol from URL path
     let protocol = if uri.contains("/v1/messages") {
         Some("anthropic".to_string())
@@ -100,9 +142,12 @@ pub async fn monitor_middleware(
         None
     };
 
+    // Gemini 原生流式默认以 JSON 数组分帧 (application/json)，同样需要边转发边记录
+    let is_json_stream = uri.contains(":streamGenerateContent") && content_type.contains("application/json");
+
     let monitor = state.monitor.clone();
     let mut log = ProxyRequestLog {
-        id: uuid::Uuid::new_v4().to_string(),
+        id: log_id,
         timestamp: chrono::Utc::now().timestamp_millis(),
         method,
         url: uri,
@@ -117,14 +162,22 @@ pub async fn monitor_middleware(
         input_tokens: None,
         output_tokens: None,
         protocol,
+        hedge,
+        background_task,
+        cache,
+        api_key: Some(api_key),
+        cost: None,
+        cached_input_tokens: None,
+        thinking_tokens: None,
+        session_id,
     };
 
-    if content_type.contains("text/event-stream") {
+    if content_type.contains("text/event-stream") || is_json_stream {
         let (parts, body) = response.into_parts();
         let mut stream = body.into_data_stream();
         let (tx, rx) = tokio::sync::mpsc::channel(64);
         
-        tokio::spawn(async move {
+        tokio::spawn(crate::proxy::monitor::scope_upstream_usage(upstream_usage.clone(), async move {
             let mut all_stream_data = Vec::new();
             let mut last_few_bytes = Vec::new();
             
@@ -199,23 +252,15 @@ pub async fn monitor_middleware(
                             .or(json.get("usageMetadata"))
                             .or(json.get("response").and_then(|r| r.get("usage")))
                         {
-                            log.input_tokens = usage.get("prompt_tokens")
-                                .or(usage.get("input_tokens"))
-                                .or(usage.get("promptTokenCount"))
-                                .and_then(|v| v.as_u64())
-                                .map(|v| v as u32);
-                            log.output_tokens = usage.get("completion_tokens")
-                                .or(usage.get("output_tokens"))
-                                .or(usage.get("candidatesTokenCount"))
-                                .and_then(|v| v.as_u64())
-                                .map(|v| v as u32);
-                            
-                            if log.input_tokens.is_none() && log.output_tokens.is_none() {
-                                log.output_tokens = usage.get("total_tokens")
-                                    .or(usage.get("totalTokenCount"))
-                                    .and_then(|v| v.as_u64())
-                                    .map(|v| v as u32);
-                            }
+                            apply_usage(&mut log, usage);
+                        }
+                    }
+                }
+
+                if is_json_stream {
+                    if let Ok(Value::Array(chunks)) = serde_json::from_str::<Value>(full_response) {
+                        for usage in chunks.iter().filter_map(|c| c.get("usageMetadata")) {
+                            apply_usage(&mut log, usage);
                         }
                     }
                 }
@@ -260,16 +305,7 @@ pub async fn monitor_middleware(
                                     .or(json.get("usageMetadata"))
                                     .or(json.get("response").and_then(|r| r.get("usage")))
                                 {
-                                    log.input_tokens = usage.get("prompt_tokens")
-                                        .or(usage.get("input_tokens"))
-                                        .or(usage.get("promptTokenCount"))
-                                        .and_then(|v| v.as_u64())
-                                        .map(|v| v as u32);
-                                    log.output_tokens = usage.get("completion_tokens")
-                                        .or(usage.get("output_tokens"))
-                                        .or(usage.get("candidatesTokenCount"))
-                                        .and_then(|v| v.as_u64())
-                                        .map(|v| v as u32);
+                                    apply_usage(&mut log, usage);
                                     break;
                                 }
                             }
@@ -278,11 +314,14 @@ pub async fn monitor_middleware(
                 }
             }
             
+            if let Some(usage) = *upstream_usage.lock().unwrap() {
+                apply_upstream_usage(&mut log, usage);
+            }
             if log.status >= 400 {
                 log.error = Some("Stream Error or Failed".to_string());
             }
-            monitor.log_request(log).await;
-        });
+            monitor.log_request(log, request_headers).await;
+        }));
 
         Response::from_parts(parts, Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)))
     } else if content_type.contains("application/json") || content_type.contains("text/") {
@@ -293,23 +332,7 @@ pub async fn monitor_middleware(
                     if let Ok(json) = serde_json::from_str::<Value>(&s) {
                         // 支持 OpenAI "usage" 或 Gemini "usageMetadata"
                         if let Some(usage) = json.get("usage").or(json.get("usageMetadata")) {
-                            log.input_tokens = usage.get("prompt_tokens")
-                                .or(usage.get("input_tokens"))
-                                .or(usage.get("promptTokenCount"))
-                                .and_then(|v| v.as_u64())
-                                .map(|v| v as u32);
-                            log.output_tokens = usage.get("completion_tokens")
-                                .or(usage.get("output_tokens"))
-                                .or(usage.get("candidatesTokenCount"))
-                                .and_then(|v| v.as_u64())
-                                .map(|v| v as u32);
-                                
-                            if log.input_tokens.is_none() && log.output_tokens.is_none() {
-                                log.output_tokens = usage.get("total_tokens")
-                                    .or(usage.get("totalTokenCount"))
-                                    .and_then(|v| v.as_u64())
-                                    .map(|v| v as u32);
-                            }
+                            apply_usage(&mut log, usage);
                         }
                     }
                     log.response_body = Some(s.to_string());
@@ -317,21 +340,170 @@ pub async fn monitor_middleware(
                     log.response_body = Some("[Binary Response Data]".to_string());
                 }
                 
+                if let Some(usage) = *upstream_usage.lock().unwrap() {
+                    apply_upstream_usage(&mut log, usage);
+                }
                 if log.status >= 400 {
                     log.error = log.response_body.clone();
                 }
-                monitor.log_request(log).await;
+                monitor.log_request(log, request_headers).await;
                 Response::from_parts(parts, Body::from(bytes))
             }
             Err(_) => {
                 log.response_body = Some("[Response too large (>100MB)]".to_string());
-                monitor.log_request(log).await;
+                monitor.log_request(log, request_headers).await;
                 Response::from_parts(parts, Body::empty())
             }
         }
     } else {
-        log.response_body = Some(format!("[{}]", content_type));
-        monitor.log_request(log).await;
-        response
+        // 二进制响应 (如合成的音频) 不解析响应体，用量以上游上报为准。
+        // 流式音频在响应体读完时才上报用量，因此与 SSE 相同：在用量作用域内转发，结束后再写日志
+        let (parts, body) = response.into_parts();
+        let mut stream = body.into_data_stream();
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+
+        tokio::spawn(crate::proxy::monitor::scope_upstream_usage(upstream_usage.clone(), async move {
+            let mut total_bytes = 0;
+            let mut failed = false;
+            while let Some(chunk_res) = stream.next().await {
+                match chunk_res {
+                    Ok(chunk) => {
+                        total_bytes += chunk.len();
+                        let _ = tx.send(Ok::<_, axum::Error>(chunk)).await;
+                    }
+                    Err(e) => {
+                        failed = true;
+                        let _ = tx.send(Err(axum::Error::new(e))).await;
+                    }
+                }
+            }
+
+            log.response_body = Some(format!("[{}: {} bytes]", content_type, total_bytes));
+            if let Some(usage) = *upstream_usage.lock().unwrap() {
+                apply_upstream_usage(&mut log, usage);
+            }
+            if failed {
+                log.error = Some("Stream Error or Failed".to_string());
+            }
+            monitor.log_request(log, request_headers).await;
+        }));
+
+        Response::from_parts(parts, Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)))
+    }
+}
+
+/// 从客户端可见的 usage 对象 (OpenAI / Anthropic / Gemini) 提取 token 用量
+///
+/// 统一口径: `input_tokens` 包含缓存命中部分，`output_tokens` 不包含思考 token
+fn apply_usage(log: &mut ProxyRequestLog, usage: &Value) {
+    let get = |key: &str| usage.get(key).and_then(|v| v.as_u64()).map(|v| v as u32);
+    let get_nested = |outer: &str, key: &str| {
+        usage.get(outer).and_then(|o| o.get(key)).and_then(|v| v.as_u64()).map(|v| v as u32)
+    };
+
+    if let Some(input) = get("promptTokenCount") {
+        // Gemini
+        log.input_tokens = Some(input);
+        log.output_tokens = get("candidatesTokenCount").or(log.output_tokens);
+        log.cached_input_tokens = get("cachedContentTokenCount");
+        log.thinking_tokens = get("thoughtsTokenCount");
+    } else if usage.get("prompt_tokens").is_some() || usage.get("completion_tokens").is_some() {
+        // OpenAI Chat: completion_tokens 包含 reasoning_tokens
+        let reasoning = get_nested("completion_tokens_details", "reasoning_tokens");
+        log.input_tokens = get("prompt_tokens");
+        log.output_tokens = get("completion_tokens").map(|c| c.saturating_sub(reasoning.unwrap_or(0)));
+        log.cached_input_tokens = get_nested("prompt_tokens_details", "cached_tokens");
+        log.thinking_tokens = reasoning;
+    } else if usage.get("input_tokens_details").is_some() || usage.get("output_tokens_details").is_some() {
+        // OpenAI Responses: output_tokens 包含 reasoning_tokens
+        let reasoning = get_nested("output_tokens_details", "reasoning_tokens");
+        log.input_tokens = get("input_tokens");
+        log.output_tokens = get("output_tokens").map(|c| c.saturating_sub(reasoning.unwrap_or(0)));
+        log.cached_input_tokens = get_nested("input_tokens_details", "cached_tokens");
+        log.thinking_tokens = reasoning;
+    } else if usage.get("input_tokens").is_some() || usage.get("output_tokens").is_some() {
+        // Anthropic: input_tokens 不含缓存读取/写入；message_delta 可能只带 output_tokens
+        let cache_read = get("cache_read_input_tokens");
+        let cache_creation = get("cache_creation_input_tokens").unwrap_or(0);
+        if let Some(input) = get("input_tokens") {
+            log.input_tokens = Some(input + cache_read.unwrap_or(0) + cache_creation);
+            log.cached_input_tokens = cache_read;
+        }
+        log.output_tokens = get("output_tokens").or(log.output_tokens);
+    }
+
+    if log.input_tokens.is_none() && log.output_tokens.is_none() {
+        log.output_tokens = get("total_tokens").or(get("totalTokenCount"));
+    }
+}
+
+/// 以协议转换层上报的上游原始用量为准 (客户端可见的用量可能经过缩放，且不区分思考 token)
+fn apply_upstream_usage(log: &mut ProxyRequestLog, usage: UpstreamUsage) {
+    log.input_tokens = Some(usage.input);
+    log.output_tokens = Some(usage.output);
+    log.cached_input_tokens = Some(usage.cached_input);
+    log.thinking_tokens = Some(usage.thinking);
+}
+
+/// 按请求协议计算会话指纹 (与粘性调度使用的算法一致)
+fn extract_session_id(uri: &str, body: &[u8], model: Option<&str>) -> Option<String> {
+    use crate::proxy::session_manager::SessionManager;
+
+    if uri.starts_with("/v1/messages") && !uri.contains("count_tokens") {
+        let request = serde_json::from_slice::<crate::proxy::mappers::claude::models::ClaudeRequest>(body).ok()?;
+        Some(SessionManager::extract_session_id(&request))
+    } else if uri.starts_with("/v1/chat/completions") {
+        let request = serde_json::from_slice::<crate::proxy::mappers::openai::models::OpenAIRequest>(body).ok()?;
+        Some(SessionManager::extract_openai_session_id(&request))
+    } else if uri.starts_with("/v1beta/models/") && uri.contains("generateContent") {
+        let request = serde_json::from_slice::<Value>(body).ok()?;
+        Some(SessionManager::extract_gemini_session_id(&request, model.unwrap_or_default()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn empty_log() -> ProxyRequestLog {
+        serde_json::from_value(json!({
+            "id": "1", "timestamp": 0, "method": "POST", "url": "/v1/messages", "status": 200, "duration": 0,
+            "model": null, "mapped_model": null, "account_email": null, "error": null,
+            "request_body": null, "response_body": null, "input_tokens": null, "output_tokens": null, "protocol": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_apply_usage_per_protocol() {
+        let mut log = empty_log();
+        apply_usage(&mut log, &json!({
+            "promptTokenCount": 100, "candidatesTokenCount": 20, "cachedContentTokenCount": 60, "thoughtsTokenCount": 30
+        }));
+        assert_eq!((log.input_tokens, log.output_tokens), (Some(100), Some(20)));
+        assert_eq!((log.cached_input_tokens, log.thinking_tokens), (Some(60), Some(30)));
+
+        let mut log = empty_log();
+        apply_usage(&mut log, &json!({
+            "prompt_tokens": 100, "completion_tokens": 50, "total_tokens": 150,
+            "prompt_tokens_details": { "cached_tokens": 40 },
+            "completion_tokens_details": { "reasoning_tokens": 30 }
+        }));
+        assert_eq!((log.input_tokens, log.output_tokens), (Some(100), Some(20)));
+        assert_eq!((log.cached_input_tokens, log.thinking_tokens), (Some(40), Some(30)));
+
+        // Anthropic 流式: message_start 带输入与缓存，message_delta 只带输出
+        let mut log = empty_log();
+        apply_usage(&mut log, &json!({ "input_tokens": 10, "output_tokens": 1, "cache_read_input_tokens": 90 }));
+        apply_usage(&mut log, &json!({ "output_tokens": 25 }));
+        assert_eq!((log.input_tokens, log.output_tokens), (Some(100), Some(25)));
+        assert_eq!(log.cached_input_tokens, Some(90));
+
+        let mut log = empty_log();
+        apply_usage(&mut log, &json!({ "totalTokenCount": 7 }));
+        assert_eq!((log.input_tokens, log.output_tokens), (None, Some(7)));
     }
 }
@@ -0,0 +1,341 @@
+// 请求排队中间件
+// 1. 为每个请求设置排队键 (API Key 哈希)，供 TokenManager 公平排队使用
+// 2. 账号池耗尽 (或已有请求在排队) 且客户端要求流式响应时，先按客户端请求的帧格式返回响应头并在排队期间发送 keep-alive，防止连接超时
+//    - SSE: keep-alive 注释 `: keep-alive`，错误以 SSE 错误事件返回
+//    - JSON 数组 (Gemini `:streamGenerateContent` 未指定 alt=sse): 以空白字符保活，错误以数组元素 `{"error": {...}}` 返回
+// 3. 客户端断开时中止排队中的请求，不再占用排队位置与账号
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tracing::Instrument;
+
+use crate::proxy::mappers::gemini::streaming::StreamFraming;
+use crate::proxy::monitor::{current_upstream_usage, scope_upstream_usage};
+use crate::proxy::replay::{experimental_override, with_experimental_override};
+use crate::proxy::request_queue::{queue_key_from_headers, scope_queue_key};
+use crate::proxy::token_manager::{pinned_account, with_pinned_account};
+use crate::proxy::server::AppState;
+
+const MAX_INSPECT_BODY_SIZE: usize = 100 * 1024 * 1024; // 与 DefaultBodyLimit 保持一致
+
+pub async fn queue_middleware(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let queue_key = queue_key_from_headers(request.headers());
+    let queue_config = state.token_manager.get_sticky_config().await.queue;
+
+    // 仅在排队启用且 (已有请求在排队或当前无可用账号) 时才检查是否为流式请求，避免无谓地缓冲请求体
+    // 队列非空时新请求必然排在等待者之后，无论是否有空闲账号 (账号级检查不含模型并发与配额保护)
+    if !queue_config.enabled
+        || request.method() != axum::http::Method::POST
+        || request.uri().path().starts_with("/upload/")
+        || (state.token_manager.queue_depth() == 0 && state.token_manager.has_free_account().await)
+    {
+        return scope_queue_key(queue_key, next.run(request)).await;
+    }
+
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().map(|q| q.to_string());
+    let (parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_INSPECT_BODY_SIZE).await {
+        Ok(b) => b,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(format!("Failed to read request body: {}", e)))
+                .unwrap();
+        }
+    };
+    let request = Request::from_parts(parts, Body::from(bytes.clone()));
+
+    let framing = match streaming_framing(&path, query.as_deref(), &bytes) {
+        Some(framing) => framing,
+        None => return scope_queue_key(queue_key, next.run(request)).await,
+    };
+
+    tracing::info!("[Request-Queue] Pool exhausted or queue busy for streaming request {}, holding connection with keep-alive", path);
+
+    // 新任务不继承 task-local 与当前 span：固定账号、实验性配置覆盖、上游用量槽位与请求 span 需在任务内重新设置
+    let pinned = pinned_account();
+    let experimental = experimental_override();
+    let upstream_usage = current_upstream_usage();
+    let inner = AbortOnDrop(tokio::spawn(
+        async move {
+            let fut = with_pinned_account(
+                pinned,
+                with_experimental_override(experimental, scope_queue_key(queue_key, next.run(request))),
+            );
+            match upstream_usage {
+                Some(slot) => scope_upstream_usage(slot, fut).await,
+                None => fut.await,
+            }
+        }
+        .instrument(tracing::Span::current()),
+    ));
+    let interval = Duration::from_secs(queue_config.keepalive_interval_seconds.max(1));
+    let is_claude = path.starts_with("/v1/messages");
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, framing.content_type())
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::CONNECTION, "keep-alive")
+        .header("X-Accel-Buffering", "no")
+        .header("X-Request-Queued", "true")
+        .body(Body::from_stream(queued_stream(inner, interval, framing, is_claude)))
+        .unwrap()
+}
+
+/// 排队期间按帧格式发送 keep-alive，请求任务完成后转发其响应体 (或以对应帧格式返回错误)
+fn queued_stream(
+    mut inner: AbortOnDrop<Response>,
+    interval: Duration,
+    framing: StreamFraming,
+    is_claude: bool,
+) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+    async_stream::stream! {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await; // 第一次 tick 立即返回，跳过
+
+        let response = loop {
+            tokio::select! {
+                res = &mut inner => break res,
+                _ = ticker.tick() => {
+                    // JSON 数组开头前的空白字符不影响解析
+                    yield Ok::<Bytes, std::io::Error>(match framing {
+                        StreamFraming::Sse => Bytes::from_static(b": keep-alive\n\n"),
+                        StreamFraming::JsonArray => Bytes::from_static(b"\n"),
+                    });
+                }
+            }
+        };
+
+        match response {
+            Ok(resp) if resp.status().is_success() => {
+                let mut body = resp.into_body().into_data_stream();
+                let mut started = false;
+                while let Some(chunk) = body.next().await {
+                    match chunk {
+                        Ok(b) => {
+                            started |= !b.is_empty();
+                            yield Ok(b);
+                        }
+                        Err(e) => {
+                            yield Ok(error_chunk(framing, is_claude, started, 502, &e.to_string()));
+                            break;
+                        }
+                    }
+                }
+            }
+            Ok(resp) => {
+                // 响应头已发出，只能以流内错误的形式返回上游/排队错误
+                let status = resp.status().as_u16();
+                let message = match axum::body::to_bytes(resp.into_body(), 1024 * 1024).await {
+                    Ok(b) => String::from_utf8_lossy(&b).to_string(),
+                    Err(e) => e.to_string(),
+                };
+                yield Ok(error_chunk(framing, is_claude, false, status, &message));
+            }
+            Err(e) => {
+                yield Ok(error_chunk(framing, is_claude, false, 500, &format!("Request task failed: {}", e)));
+            }
+        }
+    }
+}
+
+/// 客户端断开 (响应流被丢弃) 时中止请求任务，使其退出排队并释放已占用的账号
+struct AbortOnDrop<T>(tokio::task::JoinHandle<T>);
+
+impl<T> Future for AbortOnDrop<T> {
+    type Output = Result<T, tokio::task::JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// 判断请求是否要求流式响应，返回客户端期望的帧格式 (非流式请求返回 None)
+fn streaming_framing(path: &str, query: Option<&str>, body: &[u8]) -> Option<StreamFraming> {
+    if path.contains(":streamGenerateContent") {
+        return Some(StreamFraming::from_query(query));
+    }
+    serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|v| v.get("stream").and_then(|s| s.as_bool()))
+        .filter(|stream| *stream)
+        .map(|_| StreamFraming::Sse)
+}
+
+/// 按帧格式构造流内错误；JSON 数组已输出元素时 (started) 追加为最后一个元素并闭合数组
+fn error_chunk(framing: StreamFraming, is_claude: bool, started: bool, status: u16, message: &str) -> Bytes {
+    match framing {
+        StreamFraming::Sse => sse_error_event(is_claude, status, message),
+        StreamFraming::JsonArray => {
+            let payload = json_error_payload(status, message);
+            if started {
+                Bytes::from(format!("\n,\r\n{}\n]", payload))
+            } else {
+                Bytes::from(format!("[{}]", payload))
+            }
+        }
+    }
+}
+
+/// Gemini 风格的错误对象；上游已返回 `{"error": {...}}` 时原样使用
+fn json_error_payload(status: u16, message: &str) -> Value {
+    if let Ok(v) = serde_json::from_str::<Value>(message) {
+        if v.get("error").is_some() {
+            return v;
+        }
+    }
+    let status_text = match status {
+        400 => "INVALID_ARGUMENT",
+        401 => "UNAUTHENTICATED",
+        403 => "PERMISSION_DENIED",
+        404 => "NOT_FOUND",
+        429 => "RESOURCE_EXHAUSTED",
+        503 => "UNAVAILABLE",
+        _ => "INTERNAL",
+    };
+    json!({ "error": { "code": status, "message": message, "status": status_text } })
+}
+
+/// 构造与客户端协议匹配的 SSE 错误事件
+fn sse_error_event(is_claude: bool, status: u16, message: &str) -> Bytes {
+    if is_claude {
+        let payload = json!({
+            "type": "error",
+            "error": { "type": "overloaded_error", "message": message }
+        });
+        Bytes::from(format!("event: error\ndata: {}\n\n", payload))
+    } else {
+        let payload = json!({
+            "error": { "message": message, "type": "upstream_error", "code": status }
+        });
+        Bytes::from(format!("data: {}\n\n", payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streaming_framing() {
+        let path = "/v1beta/models/gemini-3-flash:streamGenerateContent";
+        assert_eq!(streaming_framing(path, None, b""), Some(StreamFraming::JsonArray));
+        assert_eq!(streaming_framing(path, Some("alt=sse"), b""), Some(StreamFraming::Sse));
+        assert_eq!(
+            streaming_framing("/v1/messages", None, br#"{"model":"x","stream":true}"#),
+            Some(StreamFraming::Sse)
+        );
+        assert_eq!(streaming_framing("/v1/chat/completions", None, br#"{"model":"x"}"#), None);
+        assert_eq!(streaming_framing("/v1/chat/completions", None, br#"{"stream":false}"#), None);
+        assert_eq!(streaming_framing("/v1/chat/completions", None, b"not json"), None);
+    }
+
+    async fn collect_queued(inner: AbortOnDrop<Response>, framing: StreamFraming) -> String {
+        let chunks: Vec<_> = queued_stream(inner, Duration::from_millis(20), framing, false)
+            .collect()
+            .await;
+        let bytes: Vec<u8> = chunks.into_iter().flat_map(|c| c.unwrap().to_vec()).collect();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_queued_json_array_stream() {
+        // 排队期间发送的保活内容必须保持整体仍是合法的 JSON 数组
+        let inner = AbortOnDrop(tokio::spawn(async {
+            tokio::time::sleep(Duration::from_millis(70)).await;
+            Response::new(Body::from("[{\"candidates\":[]}\n,\r\n{\"candidates\":[]}\n]"))
+        }));
+        let out = collect_queued(inner, StreamFraming::JsonArray).await;
+        assert!(out.starts_with('\n'), "expected whitespace keep-alive, got {:?}", out);
+        assert!(!out.contains("keep-alive"));
+        let parsed: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 2);
+
+        // 排队失败 (响应头已发出) 时错误以 JSON 数组元素返回
+        let inner = AbortOnDrop(tokio::spawn(async {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(Body::from("Token acquisition timeout"))
+                .unwrap()
+        }));
+        let out = collect_queued(inner, StreamFraming::JsonArray).await;
+        let parsed: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(parsed[0]["error"]["code"], 503);
+        assert_eq!(parsed[0]["error"]["status"], "UNAVAILABLE");
+    }
+
+    #[tokio::test]
+    async fn test_queued_sse_stream_keepalive() {
+        let inner = AbortOnDrop(tokio::spawn(async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Response::new(Body::from("data: {}\n\n"))
+        }));
+        let out = collect_queued(inner, StreamFraming::Sse).await;
+        assert!(out.starts_with(": keep-alive\n\n"));
+        assert!(out.ends_with("data: {}\n\n"));
+    }
+
+    #[test]
+    fn test_json_array_error_chunk() {
+        let fresh = error_chunk(StreamFraming::JsonArray, false, false, 429, "quota");
+        let parsed: Value = serde_json::from_slice(&fresh).unwrap();
+        assert_eq!(parsed[0]["error"]["status"], "RESOURCE_EXHAUSTED");
+
+        // 已输出部分元素后追加错误并闭合数组
+        let mut partial = b"[{\"candidates\":[]}".to_vec();
+        partial.extend_from_slice(&error_chunk(StreamFraming::JsonArray, false, true, 502, "reset"));
+        let parsed: Value = serde_json::from_slice(&partial).unwrap();
+        assert_eq!(parsed[1]["error"]["code"], 502);
+
+        let upstream = r#"{"error":{"code":400,"message":"bad","status":"INVALID_ARGUMENT"}}"#;
+        let passthrough = error_chunk(StreamFraming::JsonArray, false, false, 400, upstream);
+        let parsed: Value = serde_json::from_slice(&passthrough).unwrap();
+        assert_eq!(parsed[0]["error"]["message"], "bad");
+    }
+
+    #[tokio::test]
+    async fn test_abort_on_drop_cancels_task() {
+        let finished = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = finished.clone();
+        let guard = AbortOnDrop(tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            flag.store(true, std::sync::atomic::Ordering::SeqCst);
+        }));
+        drop(guard);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!finished.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_sse_error_event_format() {
+        let claude = sse_error_event(true, 503, "busy");
+        let s = std::str::from_utf8(&claude).unwrap();
+        assert!(s.starts_with("event: error\ndata: "));
+        assert!(s.contains("overloaded_error"));
+
+        let openai = sse_error_event(false, 503, "busy");
+        let s = std::str::from_utf8(&openai).unwrap();
+        assert!(s.starts_with("data: "));
+        assert!(s.contains("\"code\":503"));
+    }
+}
@@ -0,0 +1,31 @@
+// 链路追踪中间件
+// 为每个请求创建根 span (proxy.request)，入站 `traceparent` 作为父上下文；鉴权及后续处理的 span 均挂在其下
+
+use axum::{extract::Request, middleware::Next, response::Response};
+use tracing::Instrument;
+
+pub async fn telemetry_middleware(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+
+    let span = tracing::info_span!(
+        "proxy.request",
+        otel.name = %format!("{} {}", method, path),
+        otel.kind = "server",
+        otel.status_code = tracing::field::Empty,
+        http.request.method = %method,
+        url.path = %path,
+        http.response.status_code = tracing::field::Empty,
+        proxy.trace_id = tracing::field::Empty,
+    );
+    crate::proxy::telemetry::set_parent_from_headers(&span, request.headers());
+
+    let response = next.run(request).instrument(span.clone()).await;
+
+    let status = response.status().as_u16();
+    span.record("http.response.status_code", status);
+    if status >= 500 {
+        span.record("otel.status_code", "ERROR");
+    }
+    response
+}
@@ -18,6 +18,19 @@ pub mod zai_vision_mcp;    // Built-in Vision MCP server state
 pub mod zai_vision_tools;  // Built-in Vision MCP tools (z.ai vision API)
 pub mod monitor;           // 监控
 pub mod rate_limit;        // 限流跟踪
+pub mod concurrency;       // 单账号并发租约
+pub mod request_queue;     // 账号池耗尽时的公平排队
+pub mod hedging;           // 非流式请求对冲
+pub mod background_tasks;  // 后台任务分类与路由
+pub mod response_cache;    // 确定性请求的精确匹配响应缓存
+pub mod log_policy;        // 请求日志脱敏与保留策略
+pub mod replay;            // 已记录请求的重放
+pub mod telemetry;         // OpenTelemetry 链路追踪导出
+pub mod budget;            // 费用统计与预算规则
+pub mod image_store;       // 生成图片的本地存储与签名链接
+pub mod cached_contents;   // Gemini cachedContents 本地模拟
+pub mod gemini_files;      // Gemini Files API 本地模拟
+pub mod batch;             // OpenAI Batch API (账号池后台执行)
 pub mod sticky_config;     // 粘性调度配置
 pub mod session_manager;   // 会话指纹管理
 pub mod audio;             // 音频处理模块
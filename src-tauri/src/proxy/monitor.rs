@@ -1,8 +1,13 @@
 use serde::{Serialize, Deserialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use tokio::sync::RwLock;
 use tauri::Emitter;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use crate::proxy::log_policy::{LogPolicy, LogPolicyConfig};
+
+/// 保留策略的执行间隔
+const RETENTION_INTERVAL_SECS: u64 = 3600;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyRequestLog {
@@ -21,6 +26,129 @@ pub struct ProxyRequestLog {
     pub input_tokens: Option<u32>,
     pub output_tokens: Option<u32>,
     pub protocol: Option<String>,     // 协议类型: "openai", "anthropic", "gemini"
+    #[serde(default)]
+    pub hedge: Option<String>,        // 对冲结果: "primary_won", "hedge_won", "skipped", "both_failed"
+    #[serde(default)]
+    pub background_task: Option<String>, // 命中的后台任务分类名
+    #[serde(default)]
+    pub cache: Option<String>,        // 响应缓存状态: "hit", "miss"
+    #[serde(default)]
+    pub api_key: Option<String>,      // 客户端 API Key 的哈希标识 (key-xxxx)
+    #[serde(default)]
+    pub cost: Option<f64>,            // 按价格表估算的费用 (美元)
+    #[serde(default)]
+    pub cached_input_tokens: Option<u32>, // 命中缓存的输入 token (包含在 input_tokens 内)
+    #[serde(default)]
+    pub thinking_tokens: Option<u32>, // 思考 token (不包含在 output_tokens 内)
+    #[serde(default)]
+    pub session_id: Option<String>,   // 会话指纹 (与粘性调度使用的一致)
+}
+
+/// 上游 (Gemini) 返回的原始用量：未经上下文缩放，并区分缓存与思考 token
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpstreamUsage {
+    pub input: u32,
+    pub output: u32,
+    pub cached_input: u32,
+    pub thinking: u32,
+}
+
+impl UpstreamUsage {
+    /// 从 Gemini `usageMetadata` 解析
+    pub fn from_gemini(usage: &serde_json::Value) -> Self {
+        let get = |key: &str| usage.get(key).and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+        Self {
+            input: get("promptTokenCount"),
+            output: get("candidatesTokenCount"),
+            cached_input: get("cachedContentTokenCount"),
+            thinking: get("thoughtsTokenCount"),
+        }
+    }
+}
+
+tokio::task_local! {
+    /// 当前请求的上游用量槽位 (由监控中间件设置，协议转换层写入)
+    static UPSTREAM_USAGE: Arc<std::sync::Mutex<Option<UpstreamUsage>>>;
+}
+
+/// 在上游用量槽位的作用域内执行请求处理 (或驱动响应流)
+pub async fn scope_upstream_usage<F: std::future::Future>(
+    slot: Arc<std::sync::Mutex<Option<UpstreamUsage>>>,
+    fut: F,
+) -> F::Output {
+    UPSTREAM_USAGE.scope(slot, fut).await
+}
+
+/// 当前作用域的上游用量槽位 (在新任务中执行请求处理时需重新设置作用域)
+pub fn current_upstream_usage() -> Option<Arc<std::sync::Mutex<Option<UpstreamUsage>>>> {
+    UPSTREAM_USAGE.try_with(|slot| slot.clone()).ok()
+}
+
+/// 协议转换层上报上游用量 (流式响应中多次上报时以最后一次为准；作用域外调用时忽略)
+pub fn report_upstream_usage(usage: UpstreamUsage) {
+    let _ = UPSTREAM_USAGE.try_with(|slot| *slot.lock().unwrap() = Some(usage));
+}
+
+/// 一次客户端请求由多次上游调用组成时 (如音频分片) 累加用量；作用域外调用时忽略
+pub fn add_upstream_usage(usage: UpstreamUsage) {
+    let _ = UPSTREAM_USAGE.try_with(|slot| {
+        let mut slot = slot.lock().unwrap();
+        let total = slot.unwrap_or_default();
+        *slot = Some(UpstreamUsage {
+            input: total.input + usage.input,
+            output: total.output + usage.output,
+            cached_input: total.cached_input + usage.cached_input,
+            thinking: total.thinking + usage.thinking,
+        });
+    });
+}
+
+/// 非流式响应：上报上游响应 (可带 v1internal 的 `response` 包装) 中的用量
+/// 由 handler 在收到真实上游响应时调用；协议转换层保持纯函数，缓存回放不会重复计费
+pub fn report_response_usage(upstream_response: &serde_json::Value) {
+    let raw = upstream_response.get("response").unwrap_or(upstream_response);
+    if let Some(usage) = raw.get("usageMetadata") {
+        report_upstream_usage(UpstreamUsage::from_gemini(usage));
+    }
+}
+
+/// 流式响应：透传上游 Gemini SSE 字节流，并上报其中最后一次出现的 usageMetadata
+pub fn report_stream_usage(
+    upstream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>>,
+) -> std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>> {
+    use futures::StreamExt;
+
+    Box::pin(async_stream::stream! {
+        let mut upstream = upstream;
+        let mut pending: Vec<u8> = Vec::new();
+        while let Some(item) = upstream.next().await {
+            if let Ok(bytes) = &item {
+                pending.extend_from_slice(bytes);
+                while let Some(pos) = pending.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = pending.drain(..=pos).collect();
+                    report_sse_line_usage(&line);
+                }
+            }
+            yield item;
+        }
+        report_sse_line_usage(&pending);
+    })
+}
+
+fn report_sse_line_usage(line: &[u8]) {
+    let Ok(line) = std::str::from_utf8(line) else {
+        return;
+    };
+    let Some(data) = line.trim().strip_prefix("data:") else {
+        return;
+    };
+    // 仅解析携带用量的分片
+    if !data.contains("usageMetadata") {
+        return;
+    }
+    if let Ok(chunk) = serde_json::from_str::<serde_json::Value>(data.trim()) {
+        report_response_usage(&chunk);
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -28,6 +156,9 @@ pub struct ProxyStats {
     pub total_requests: u64,
     pub success_count: u64,
     pub error_count: u64,
+    /// 各后台任务分类的命中次数
+    #[serde(default)]
+    pub background_tasks: BTreeMap<String, u64>,
 }
 
 pub struct ProxyMonitor {
@@ -35,6 +166,8 @@ pub struct ProxyMonitor {
     pub stats: RwLock<ProxyStats>,
     pub max_logs: usize,
     pub enabled: AtomicBool,
+    policy: Arc<std::sync::RwLock<Arc<LogPolicy>>>,
+    retention_started: AtomicBool,
     app_handle: Option<tauri::AppHandle>,
 }
 
@@ -45,29 +178,61 @@ impl ProxyMonitor {
             tracing::error!("Failed to initialize proxy DB: {}", e);
         }
 
-        // Auto cleanup old logs (keep last 30 days)
-        tokio::spawn(async {
-            match crate::modules::proxy_db::cleanup_old_logs(30) {
-                Ok(deleted) => {
-                    if deleted > 0 {
-                        tracing::info!("Auto cleanup: removed {} old logs (>30 days)", deleted);
-                    }
-                }
-                Err(e) => {
-                    tracing::error!("Failed to cleanup old logs: {}", e);
-                }
-            }
-        });
+        let policy = Arc::new(std::sync::RwLock::new(Arc::new(LogPolicy::default())));
 
         Self {
             logs: RwLock::new(VecDeque::with_capacity(max_logs)),
             stats: RwLock::new(ProxyStats::default()),
             max_logs,
             enabled: AtomicBool::new(false), // Default to disabled
+            policy,
+            retention_started: AtomicBool::new(false),
             app_handle,
         }
     }
 
+    /// 启动按保留策略自动清理的后台任务 (每小时检查一次)。
+    /// 需在 `set_policy` 应用配置后调用，避免首次清理使用默认策略；重复调用无效
+    pub fn start_retention(&self) {
+        if self.retention_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let retention_policy = self.policy.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(RETENTION_INTERVAL_SECS));
+            loop {
+                interval.tick().await;
+                let (body_days, metadata_days) = {
+                    let config = retention_policy.read().unwrap().config().clone();
+                    (config.body_retention_days, config.metadata_retention_days)
+                };
+                match tokio::task::spawn_blocking(move || {
+                    crate::modules::proxy_db::apply_retention(body_days, metadata_days)
+                }).await {
+                    Ok(Ok((cleared, deleted))) => {
+                        if cleared > 0 || deleted > 0 {
+                            tracing::info!(
+                                "Auto cleanup: cleared bodies of {} logs (>{} days), removed {} logs (>{} days)",
+                                cleared, body_days, deleted, metadata_days
+                            );
+                        }
+                    }
+                    Ok(Err(e)) => tracing::error!("Failed to cleanup old logs: {}", e),
+                    Err(e) => tracing::error!("Log cleanup task panicked: {}", e),
+                }
+            }
+        });
+    }
+
+    /// 更新日志脱敏与保留策略
+    pub fn set_policy(&self, config: LogPolicyConfig) {
+        *self.policy.write().unwrap() = Arc::new(LogPolicy::new(config));
+    }
+
+    pub fn policy(&self) -> Arc<LogPolicy> {
+        self.policy.read().unwrap().clone()
+    }
+
     pub fn set_enabled(&self, enabled: bool) {
         self.enabled.store(enabled, Ordering::Relaxed);
     }
@@ -76,17 +241,56 @@ impl ProxyMonitor {
         self.enabled.load(Ordering::Relaxed)
     }
 
-    pub async fn log_request(&self, log: ProxyRequestLog) {
+    /// `request_headers` 为供重放使用的请求头 (JSON)，只写入数据库，不进入内存日志与界面事件
+    pub async fn log_request(&self, mut log: ProxyRequestLog, request_headers: Option<String>) {
+        if log.protocol.is_some() && log.method == "POST" {
+            let account = log.account_email.clone().unwrap_or_default();
+            let status = log.status;
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = crate::modules::token_stats::record_request_status(&account, status) {
+                    tracing::debug!("Failed to record request status: {}", e);
+                }
+            });
+        }
+
         if let (Some(account), Some(input), Some(output)) = (
             &log.account_email,
             log.input_tokens,
             log.output_tokens,
         ) {
-            let model = log.model.clone().unwrap_or_else(|| "unknown".to_string());
-            let account = account.clone();
-            tokio::spawn(async move {
-                if let Err(e) = crate::modules::token_stats::record_usage(&account, &model, input, output) {
-                    tracing::debug!("Failed to record token stats: {}", e);
+            // 按价格表计算费用，并计入预算
+            let usage = crate::proxy::budget::UsageTokens {
+                input,
+                output,
+                cached_input: log.cached_input_tokens.unwrap_or(0),
+                thinking: log.thinking_tokens.unwrap_or(0),
+            };
+            log.cost = crate::proxy::budget::config().cost_of(log.mapped_model.as_deref(), log.model.as_deref(), &usage);
+            let record = crate::modules::token_stats::UsageRecord {
+                account_email: account.clone(),
+                model: log.model.clone().unwrap_or_else(|| "unknown".to_string()),
+                api_key: log.api_key.clone(),
+                session_id: log.session_id.clone(),
+                protocol: log.protocol.clone(),
+                input_tokens: input,
+                output_tokens: output,
+                cached_input_tokens: usage.cached_input,
+                thinking_tokens: usage.thinking,
+                cost: log.cost.unwrap_or(0.0),
+            };
+            let app_handle = self.app_handle.clone();
+            tokio::task::spawn_blocking(move || {
+                let alerts = match crate::proxy::budget::record_usage(&record) {
+                    Ok(alerts) => alerts,
+                    Err(e) => {
+                        tracing::debug!("Failed to record token stats: {}", e);
+                        return;
+                    }
+                };
+                if let Some(app) = app_handle {
+                    for alert in alerts {
+                        let _ = app.emit("proxy://budget-alert", &alert);
+                    }
                 }
             });
         }
@@ -95,6 +299,15 @@ impl ProxyMonitor {
             return;
         }
         tracing::info!("[Monitor] Logging request: {} {}", log.method, log.url);
+
+        // Apply redaction / truncation before the log reaches memory, DB or UI
+        let policy = self.policy();
+        let request_body = policy.apply_opt(log.request_body.as_deref());
+        // 策略改写过的请求体 (脱敏 / 剥离 / 截断) 无法原样重放
+        let request_body_lossy = request_body.is_some() && request_body != log.request_body;
+        log.request_body = request_body;
+        log.response_body = policy.apply_opt(log.response_body.as_deref());
+        log.error = log.error.as_deref().map(|e| policy.apply(e));
         // Update stats
         {
             let mut stats = self.stats.write().await;
@@ -118,21 +331,9 @@ impl ProxyMonitor {
         // Save to DB
         let log_to_save = log.clone();
         tokio::spawn(async move {
-            if let Err(e) = crate::modules::proxy_db::save_log(&log_to_save) {
+            if let Err(e) = crate::modules::proxy_db::save_log(&log_to_save, request_body_lossy, request_headers.as_deref()) {
                 tracing::error!("Failed to save proxy log to DB: {}", e);
             }
-            
-            // Record token stats if available
-            if let (Some(account), Some(input), Some(output)) = (
-                &log_to_save.account_email,
-                log_to_save.input_tokens,
-                log_to_save.output_tokens,
-            ) {
-                let model = log_to_save.model.clone().unwrap_or_else(|| "unknown".to_string());
-                if let Err(e) = crate::modules::token_stats::record_usage(account, &model, input, output) {
-                    tracing::debug!("Failed to record token stats: {}", e);
-                }
-            }
         });
 
         // Emit event (send summary only, without body to reduce memory)
@@ -153,6 +354,14 @@ impl ProxyMonitor {
                 input_tokens: log.input_tokens,
                 output_tokens: log.output_tokens,
                 protocol: log.protocol.clone(),
+                hedge: log.hedge.clone(),
+                background_task: log.background_task.clone(),
+                cache: log.cache.clone(),
+                api_key: log.api_key.clone(),
+                cost: log.cost,
+                cached_input_tokens: log.cached_input_tokens,
+                thinking_tokens: log.thinking_tokens,
+                session_id: log.session_id.clone(),
             };
             let _ = app.emit("proxy://request", &log_summary);
         }
@@ -171,6 +380,17 @@ impl ProxyMonitor {
         }
     }
 
+    /// 请求被分类为后台任务时计数 (在分类时调用，与请求日志是否开启及保留策略无关)
+    pub async fn record_background_task(&self, class: &str) {
+        *self.stats.write().await.background_tasks.entry(class.to_string()).or_insert(0) += 1;
+        let class = class.to_string();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = crate::modules::proxy_db::increment_background_task(&class) {
+                tracing::error!("Failed to record background task hit: {}", e);
+            }
+        });
+    }
+
     pub async fn get_stats(&self) -> ProxyStats {
         match crate::modules::proxy_db::get_stats() {
             Ok(stats) => stats,
@@ -191,4 +411,32 @@ impl ProxyMonitor {
             tracing::error!("Failed to clear logs in DB: {}", e);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_report_stream_usage_uses_last_chunk() {
+        // usageMetadata 分片跨越两个网络包
+        let chunks = vec![
+            "data: {\"response\":{\"usageMetadata\":{\"promptTokenCount\":10}}}\r\n\r\ndata: {\"response\":{\"usage",
+            "Metadata\":{\"promptTokenCount\":10,\"candidatesTokenCount\":5,\"thoughtsTokenCount\":7}}}\r\n\r\n",
+        ];
+        let upstream = futures::stream::iter(
+            chunks
+                .into_iter()
+                .map(|c| Ok::<bytes::Bytes, reqwest::Error>(bytes::Bytes::from(c)))
+                .collect::<Vec<_>>(),
+        );
+        let slot = Arc::new(std::sync::Mutex::new(None));
+        let passed: Vec<_> = scope_upstream_usage(slot.clone(), report_stream_usage(Box::pin(upstream)).collect()).await;
+        assert_eq!(passed.len(), 2);
+        assert_eq!(
+            *slot.lock().unwrap(),
+            Some(UpstreamUsage { input: 10, output: 5, cached_input: 0, thinking: 7 })
+        );
+    }
+}
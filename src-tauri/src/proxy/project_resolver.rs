@@ -3,6 +3,22 @@ use serde_json::Value;
 /// 使用 Antigravity 的 loadCodeAssist API 获取 project_id
 /// 这是获取 cloudaicompanionProject 的正确方式
 pub async fn fetch_project_id(access_token: &str) -> Result<String, String> {
+    match load_code_assist_project(access_token).await.map_err(|(_, e)| e)? {
+        Some(project_id) => Ok(project_id),
+        None => {
+            // 如果没有返回 project_id，说明账号无资格，使用内置随机生成逻辑作为兜底
+            let mock_id = generate_mock_project_id();
+            tracing::warn!("账号无资格获取官方 cloudaicompanionProject，将使用随机生成的 Project ID 作为兜底: {}", mock_id);
+            Ok(mock_id)
+        }
+    }
+}
+
+/// 调用 loadCodeAssist，返回官方 project_id (账号无资格时为 None)
+/// 失败时附带 HTTP 状态码 (网络错误为 None)，供账号健康检查区分 401/403
+pub async fn load_code_assist_project(
+    access_token: &str,
+) -> Result<Option<String>, (Option<reqwest::StatusCode>, String)> {
     let url = "https://cloudcode-pa.googleapis.com/v1internal:loadCodeAssist";
     
     let request_body = serde_json::json!({
@@ -21,28 +37,23 @@ pub async fn fetch_project_id(access_token: &str) -> Result<String, String> {
         .json(&request_body)
         .send()
         .await
-        .map_err(|e| format!("loadCodeAssist 请求失败: {}", e))?;
+        .map_err(|e| (None, format!("loadCodeAssist 请求失败: {}", e)))?;
     
     if !response.status().is_success() {
         let status = response.status();
         let body = response.text().await.unwrap_or_default();
-        return Err(format!("loadCodeAssist 返回错误 {}: {}", status, body));
+        return Err((Some(status), format!("loadCodeAssist 返回错误 {}: {}", status, body)));
     }
     
     let data: Value = response.json()
         .await
-        .map_err(|e| format!("解析响应失败: {}", e))?;
+        .map_err(|e| (None, format!("解析响应失败: {}", e)))?;
     
     // 提取 cloudaicompanionProject
-    if let Some(project_id) = data.get("cloudaicompanionProject")
-        .and_then(|v| v.as_str()) {
-        return Ok(project_id.to_string());
-    }
-    
-    // 如果没有返回 project_id，说明账号无资格，使用内置随机生成逻辑作为兜底
-    let mock_id = generate_mock_project_id();
-    tracing::warn!("账号无资格获取官方 cloudaicompanionProject，将使用随机生成的 Project ID 作为兜底: {}", mock_id);
-    Ok(mock_id)
+    Ok(data
+        .get("cloudaicompanionProject")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string()))
 }
 
 /// 生成随机 project_id（当无法从 API 获取时使用）
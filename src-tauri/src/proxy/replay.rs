@@ -0,0 +1,361 @@
+// 请求重放 (Request Replay)
+// 取出已记录请求的完整请求体与请求头，按需覆盖模型 / 账号 / 实验性配置后经正在运行的路由重新走一遍完整的处理流程
+// (鉴权、排队、监控、handler)，结果写入 log_replays 表并关联原始日志 ID，便于并排对比。
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tower::Service;
+
+use crate::proxy::config::ExperimentalConfig;
+use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::server::AppState;
+
+/// 请求头: 标记本次请求为重放，值为原始日志 ID
+pub const REPLAY_OF_HEADER: &str = "X-Replay-Of";
+/// 响应头: 重放请求对应的新日志 ID (由监控中间件写入)
+pub const LOG_ID_HEADER: &str = "X-Request-Log-Id";
+
+/// 重放响应体读取上限
+const MAX_REPLAY_RESPONSE_SIZE: usize = 100 * 1024 * 1024;
+
+/// 不随请求记录与重放的请求头 (逐跳头，以及随请求体变化的长度 / 主机)
+const SKIPPED_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+    "content-length",
+];
+
+/// 未记录请求头的旧日志重放时，沿用重放调用方的鉴权头
+const AUTH_HEADERS: &[&str] = &["authorization", "x-api-key", "x-goog-api-key"];
+
+tokio::task_local! {
+    /// 当前任务使用的实验性配置 (请求重放时覆盖)
+    static EXPERIMENTAL_OVERRIDE: Arc<ExperimentalConfig>;
+}
+
+/// 在指定实验性配置下执行 `fut`：其中的 handler 通过 [`experimental_config`] 读取该配置
+pub async fn with_experimental_override<F: std::future::Future>(
+    config: Option<Arc<ExperimentalConfig>>,
+    fut: F,
+) -> F::Output {
+    match config {
+        Some(config) => EXPERIMENTAL_OVERRIDE.scope(config, fut).await,
+        None => fut.await,
+    }
+}
+
+/// 当前任务覆盖的实验性配置 (作用域外返回 None)
+pub fn experimental_override() -> Option<Arc<ExperimentalConfig>> {
+    EXPERIMENTAL_OVERRIDE.try_with(|c| c.clone()).ok()
+}
+
+/// 当前生效的实验性配置：优先使用任务内覆盖的配置 (请求重放)，否则为 `live` 中的全局配置
+pub async fn experimental_config(live: &RwLock<ExperimentalConfig>) -> ExperimentalConfig {
+    match experimental_override() {
+        Some(config) => (*config).clone(),
+        None => live.read().await.clone(),
+    }
+}
+
+/// 序列化需要随日志保存的请求头 (JSON 名值对列表，去掉逐跳头)
+pub fn encode_headers(headers: &HeaderMap) -> String {
+    let pairs: Vec<(&str, &str)> = headers
+        .iter()
+        .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+        .filter_map(|(name, value)| value.to_str().ok().map(|v| (name.as_str(), v)))
+        .collect();
+    serde_json::to_string(&pairs).unwrap_or_default()
+}
+
+/// 还原已保存的请求头 (无法解析的条目被忽略)
+fn decode_headers(encoded: &str) -> HeaderMap {
+    let pairs: Vec<(String, String)> = serde_json::from_str(encoded).unwrap_or_default();
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value)) {
+            if !SKIPPED_HEADERS.contains(&name.as_str()) {
+                headers.append(name, value);
+            }
+        }
+    }
+    headers
+}
+
+/// 重放参数 (均为可选覆盖项)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplayOptions {
+    /// 覆盖请求模型
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 固定使用指定账号 (email)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_email: Option<String>,
+    /// 覆盖实验性配置的部分字段 (与当前配置合并)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub experimental: Option<Value>,
+}
+
+/// 一次重放的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogReplay {
+    pub id: String,
+    pub original_log_id: String,
+    /// 重放请求自身的日志 ID (未开启日志记录时为空)
+    pub replay_log_id: Option<String>,
+    pub timestamp: i64,
+    pub overrides: Value,
+    pub status: u16,
+    pub duration: u64,
+    pub model: Option<String>,
+    pub mapped_model: Option<String>,
+    pub account_email: Option<String>,
+    pub response_body: Option<String>,
+    pub error: Option<String>,
+}
+
+/// 原始日志与重放结果，供并排对比
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayOutcome {
+    pub original: ProxyRequestLog,
+    pub replay: LogReplay,
+}
+
+/// 重放一条已记录的请求
+///
+/// `caller_headers` 为重放调用方的请求头，仅在原始日志未记录请求头时用于鉴权
+pub async fn replay_log(
+    state: AppState,
+    log_id: &str,
+    options: ReplayOptions,
+    caller_headers: &HeaderMap,
+) -> Result<ReplayOutcome, String> {
+    let id = log_id.to_string();
+    let original = tokio::task::spawn_blocking(move || crate::modules::proxy_db::get_log_detail(&id))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    if original.method != "POST" {
+        return Err(format!("Only POST requests can be replayed (got {})", original.method));
+    }
+    if original.url.starts_with("/internal/") {
+        return Err(format!("Internal endpoint {} cannot be replayed", original.url));
+    }
+    let id = log_id.to_string();
+    let (lossy, stored_headers) = tokio::task::spawn_blocking(move || {
+        Ok::<_, String>((
+            crate::modules::proxy_db::is_request_body_lossy(&id)?,
+            crate::modules::proxy_db::get_request_headers(&id)?,
+        ))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    if lossy {
+        return Err(
+            "Recorded request body was altered by the log policy (redacted, base64-stripped or truncated) and cannot be replayed faithfully"
+                .to_string(),
+        );
+    }
+    let body: Value = original
+        .request_body
+        .as_deref()
+        .ok_or("Request body was not recorded (metadata-only logging or retention expired)")
+        .and_then(|b| {
+            serde_json::from_str(b)
+                .map_err(|_| "Recorded request body is not valid JSON (binary, truncated or redacted)")
+        })?;
+
+    let (url, body) = match options.model.as_deref() {
+        Some(model) => apply_model_override(&original.url, body, model),
+        None => (original.url.clone(), body),
+    };
+    let model = request_model(&url, &body).or_else(|| original.model.clone());
+
+    if let Some(email) = options.account_email.as_deref() {
+        state.token_manager.get_token_by_email(email).await?;
+    }
+
+    // 使用独立的实验性配置副本，覆盖项只作用于本次重放
+    let mut experimental = serde_json::to_value(&*state.experimental.read().await).map_err(|e| e.to_string())?;
+    if let Some(patch) = &options.experimental {
+        merge_json(&mut experimental, patch);
+    }
+    let mut experimental: ExperimentalConfig =
+        serde_json::from_value(experimental).map_err(|e| format!("Invalid experimental overrides: {}", e))?;
+    if options.account_email.is_some() {
+        // 固定账号时对冲只会重复请求同一账号，直接关闭
+        experimental.hedging.enabled = false;
+    }
+
+    let mut router = state.live_router().ok_or("Proxy server is not running")?;
+    let mut request = Request::builder()
+        .method(Method::POST)
+        .uri(&url)
+        .body(Body::from(serde_json::to_vec(&body).map_err(|e| e.to_string())?))
+        .map_err(|e| e.to_string())?;
+    let headers = request.headers_mut();
+    match stored_headers.as_deref() {
+        Some(encoded) => *headers = decode_headers(encoded),
+        None => {
+            for (name, value) in caller_headers.iter().filter(|(name, _)| AUTH_HEADERS.contains(&name.as_str())) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+    }
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.insert(crate::proxy::response_cache::CACHE_CONTROL_HEADER, HeaderValue::from_static("bypass"));
+    headers.insert(REPLAY_OF_HEADER, HeaderValue::from_str(log_id).map_err(|e| e.to_string())?);
+
+    tracing::info!("[Replay] Replaying log {} via {} (overrides: {:?})", log_id, url, options);
+    let started = std::time::Instant::now();
+    let replay = async {
+        futures::future::poll_fn(|cx| Service::<Request<Body>>::poll_ready(&mut router, cx)).await.map_err(|e| e.to_string())?;
+        let response = router.call(request).await.map_err(|e| e.to_string())?;
+        let (parts, body) = response.into_parts();
+        let bytes = axum::body::to_bytes(body, MAX_REPLAY_RESPONSE_SIZE)
+            .await
+            .map_err(|e| format!("Failed to read replay response: {}", e))?;
+        Ok::<_, String>((parts, bytes))
+    };
+    let (parts, bytes) = crate::proxy::token_manager::with_pinned_account(
+        options.account_email.clone(),
+        with_experimental_override(Some(Arc::new(experimental)), replay),
+    )
+    .await?;
+    let duration = started.elapsed().as_millis() as u64;
+
+    let header_str = |name: &str| {
+        parts
+            .headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string())
+    };
+    let policy = state.monitor.policy();
+    let text = String::from_utf8_lossy(&bytes);
+    let status = parts.status.as_u16();
+    let replay = LogReplay {
+        id: uuid::Uuid::new_v4().to_string(),
+        original_log_id: log_id.to_string(),
+        replay_log_id: header_str(LOG_ID_HEADER).filter(|_| state.monitor.is_enabled()),
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        overrides: serde_json::to_value(&options).unwrap_or(Value::Null),
+        status,
+        duration,
+        model,
+        mapped_model: header_str("X-Mapped-Model"),
+        account_email: header_str("X-Account-Email"),
+        response_body: policy.apply_opt(Some(&text)),
+        error: (status >= 400).then(|| policy.apply(&text)),
+    };
+
+    let to_save = replay.clone();
+    tokio::task::spawn_blocking(move || crate::modules::proxy_db::save_replay(&to_save))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    Ok(ReplayOutcome { original, replay })
+}
+
+/// 替换请求中的模型：Gemini 原生协议在 URL 路径中，其余协议在请求体 `model` 字段
+fn apply_model_override(url: &str, mut body: Value, model: &str) -> (String, Value) {
+    const GEMINI_PREFIX: &str = "/v1beta/models/";
+    if let Some(rest) = url.strip_prefix(GEMINI_PREFIX) {
+        let suffix = rest.find([':', '/', '?']).map_or("", |i| &rest[i..]);
+        return (format!("{}{}{}", GEMINI_PREFIX, model, suffix), body);
+    }
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("model".to_string(), Value::String(model.to_string()));
+    }
+    (url.to_string(), body)
+}
+
+fn request_model(url: &str, body: &Value) -> Option<String> {
+    if let Some(rest) = url.strip_prefix("/v1beta/models/") {
+        return rest.split([':', '/', '?']).next().map(|s| s.to_string());
+    }
+    body.get("model").and_then(|m| m.as_str()).map(|s| s.to_string())
+}
+
+/// JSON merge patch: 对象递归合并，其余类型直接覆盖
+fn merge_json(base: &mut Value, patch: &Value) {
+    match (base.as_object_mut(), patch.as_object()) {
+        (Some(base_obj), Some(patch_obj)) => {
+            for (key, value) in patch_obj {
+                merge_json(base_obj.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        _ => *base = patch.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_model_override() {
+        let (url, body) = apply_model_override(
+            "/v1beta/models/gemini-2.5-flash:streamGenerateContent?alt=sse",
+            json!({ "contents": [] }),
+            "gemini-2.5-pro",
+        );
+        assert_eq!(url, "/v1beta/models/gemini-2.5-pro:streamGenerateContent?alt=sse");
+        assert_eq!(body, json!({ "contents": [] }));
+        assert_eq!(request_model(&url, &body).as_deref(), Some("gemini-2.5-pro"));
+
+        let (url, body) = apply_model_override(
+            "/v1/messages",
+            json!({ "model": "claude-sonnet-4-5", "messages": [] }),
+            "claude-opus-4-5",
+        );
+        assert_eq!(url, "/v1/messages");
+        assert_eq!(request_model(&url, &body).as_deref(), Some("claude-opus-4-5"));
+    }
+
+    #[test]
+    fn test_headers_roundtrip_without_hop_by_hop() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("sk-test"));
+        headers.insert("anthropic-beta", HeaderValue::from_static("interleaved-thinking-2025-05-14"));
+        headers.append("anthropic-beta", HeaderValue::from_static("context-1m-2025-08-07"));
+        headers.insert("connection", HeaderValue::from_static("keep-alive"));
+        headers.insert("transfer-encoding", HeaderValue::from_static("chunked"));
+        headers.insert("content-length", HeaderValue::from_static("42"));
+        headers.insert("host", HeaderValue::from_static("127.0.0.1:8045"));
+
+        let decoded = decode_headers(&encode_headers(&headers));
+        assert_eq!(decoded.get("x-api-key").unwrap(), "sk-test");
+        assert_eq!(decoded.get_all("anthropic-beta").iter().count(), 2);
+        assert!(!decoded.contains_key("connection"));
+        assert!(!decoded.contains_key("transfer-encoding"));
+        assert!(!decoded.contains_key("content-length"));
+        assert!(!decoded.contains_key("host"));
+    }
+
+    #[test]
+    fn test_experimental_merge() {
+        let mut base = serde_json::to_value(ExperimentalConfig::default()).unwrap();
+        merge_json(
+            &mut base,
+            &json!({ "enable_signature_cache": false, "hedging": { "enabled": true } }),
+        );
+        let merged: ExperimentalConfig = serde_json::from_value(base).unwrap();
+        assert!(!merged.enable_signature_cache);
+        assert!(merged.hedging.enabled);
+        assert!(merged.enable_tool_loop_recovery);
+    }
+}
@@ -0,0 +1,232 @@
+// 请求排队 - 账号池暂时耗尽时代替立即 503
+// 按 API Key 轮转 (round-robin) 保证公平，同一 Key 内先进先出
+
+use axum::http::HeaderMap;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+tokio::task_local! {
+    /// 当前请求的排队键 (由排队中间件设置)
+    static QUEUE_KEY: String;
+}
+
+/// 未携带 API Key 的请求共用的排队键
+pub const ANONYMOUS_KEY: &str = "anonymous";
+
+/// 在指定排队键的作用域内执行请求处理
+pub async fn scope_queue_key<F: std::future::Future>(key: String, fut: F) -> F::Output {
+    QUEUE_KEY.scope(key, fut).await
+}
+
+/// 读取当前请求的排队键 (中间件作用域外调用时返回匿名键)
+pub fn current_queue_key() -> String {
+    QUEUE_KEY
+        .try_with(|k| k.clone())
+        .unwrap_or_else(|_| ANONYMOUS_KEY.to_string())
+}
+
+/// 从请求头提取客户端 API Key (与鉴权中间件使用相同的 Key 来源)
+pub fn api_key_from_headers(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.strip_prefix("Bearer ").unwrap_or(s))
+        .or_else(|| headers.get("x-api-key").and_then(|h| h.to_str().ok()))
+        .or_else(|| headers.get("x-goog-api-key").and_then(|h| h.to_str().ok()))
+}
+
+/// 从请求头提取排队键
+///
+/// 仅保存 Key 的哈希前缀，避免明文 Key 出现在日志或内存快照中
+pub fn queue_key_from_headers(headers: &HeaderMap) -> String {
+    match api_key_from_headers(headers) {
+        Some(k) if !k.is_empty() => api_key_id(k),
+        _ => ANONYMOUS_KEY.to_string(),
+    }
+}
+
+/// API Key 的哈希标识 (`key-` + SHA-256 前 6 字节)，同时用于排队、用量统计与预算规则
+pub fn api_key_id(api_key: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(api_key.as_bytes());
+    format!("key-{}", &hex_prefix(&digest, 6))
+}
+
+fn hex_prefix(bytes: &[u8], n: usize) -> String {
+    bytes.iter().take(n).map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Default)]
+struct QueueState {
+    /// 有等待者的排队键，按轮转顺序排列
+    rotation: VecDeque<String>,
+    /// 排队键 -> 该键下的票号 (FIFO)
+    waiters: HashMap<String, VecDeque<u64>>,
+    next_ticket: u64,
+    depth: usize,
+}
+
+/// 公平请求队列
+#[derive(Default)]
+pub struct RequestQueue {
+    state: Mutex<QueueState>,
+    turn_changed: Notify,
+}
+
+impl RequestQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 当前排队中的请求数
+    pub fn depth(&self) -> usize {
+        self.state.lock().unwrap().depth
+    }
+
+    /// 入队，队列已满时返回错误
+    pub fn enqueue(self: &Arc<Self>, key: &str, max_depth: usize) -> Result<QueueTicket, String> {
+        let mut state = self.state.lock().unwrap();
+        if state.depth >= max_depth {
+            return Err(format!("request queue is full ({} waiting)", state.depth));
+        }
+
+        let id = state.next_ticket;
+        state.next_ticket += 1;
+        state.depth += 1;
+
+        let waiters = state.waiters.entry(key.to_string()).or_default();
+        let first_for_key = waiters.is_empty();
+        waiters.push_back(id);
+        if first_for_key {
+            state.rotation.push_back(key.to_string());
+        }
+
+        Ok(QueueTicket {
+            queue: self.clone(),
+            key: key.to_string(),
+            id,
+        })
+    }
+
+    /// 轮次变化通知 (有请求出队时触发)
+    pub fn turn_changed(&self) -> tokio::sync::futures::Notified<'_> {
+        self.turn_changed.notified()
+    }
+}
+
+/// 排队票据，Drop 时出队并把轮次交给下一个排队键
+pub struct QueueTicket {
+    queue: Arc<RequestQueue>,
+    key: String,
+    id: u64,
+}
+
+impl QueueTicket {
+    /// 是否轮到本请求尝试获取账号
+    pub fn is_turn(&self) -> bool {
+        let state = self.queue.state.lock().unwrap();
+        state.rotation.front() == Some(&self.key)
+            && state.waiters.get(&self.key).and_then(|w| w.front()) == Some(&self.id)
+    }
+
+    /// 尝试失败后让出轮次，使其他 API Key 有机会先尝试
+    ///
+    /// 不主动唤醒其他等待者：池状态未变，立即重试没有意义，等下一次池变化即可
+    pub fn yield_turn(&self) {
+        let mut state = self.queue.state.lock().unwrap();
+        if state.rotation.front() == Some(&self.key) && state.rotation.len() > 1 {
+            if let Some(k) = state.rotation.pop_front() {
+                state.rotation.push_back(k);
+            }
+        }
+    }
+}
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        {
+            let mut state = self.queue.state.lock().unwrap();
+            state.depth = state.depth.saturating_sub(1);
+
+            let key_empty = match state.waiters.get_mut(&self.key) {
+                Some(w) => {
+                    w.retain(|id| *id != self.id);
+                    w.is_empty()
+                }
+                None => true,
+            };
+
+            let was_front = state.rotation.front() == Some(&self.key);
+            if key_empty {
+                state.waiters.remove(&self.key);
+                state.rotation.retain(|k| k != &self.key);
+            } else if was_front {
+                // 本键已服务一个请求，轮转到队尾
+                if let Some(k) = state.rotation.pop_front() {
+                    state.rotation.push_back(k);
+                }
+            }
+        }
+        self.queue.turn_changed.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fifo_within_key() {
+        let queue = Arc::new(RequestQueue::new());
+        let a1 = queue.enqueue("a", 10).unwrap();
+        let a2 = queue.enqueue("a", 10).unwrap();
+        assert!(a1.is_turn());
+        assert!(!a2.is_turn());
+        drop(a1);
+        assert!(a2.is_turn());
+        assert_eq!(queue.depth(), 1);
+    }
+
+    #[test]
+    fn test_round_robin_across_keys() {
+        let queue = Arc::new(RequestQueue::new());
+        let a1 = queue.enqueue("a", 10).unwrap();
+        let a2 = queue.enqueue("a", 10).unwrap();
+        let b1 = queue.enqueue("b", 10).unwrap();
+
+        assert!(a1.is_turn());
+        drop(a1);
+        // 服务完 a 的一个请求后轮到 b，而不是 a 的第二个请求
+        assert!(b1.is_turn());
+        assert!(!a2.is_turn());
+        drop(b1);
+        assert!(a2.is_turn());
+    }
+
+    #[test]
+    fn test_yield_turn_and_depth_limit() {
+        let queue = Arc::new(RequestQueue::new());
+        let a1 = queue.enqueue("a", 2).unwrap();
+        let b1 = queue.enqueue("b", 2).unwrap();
+        assert!(queue.enqueue("c", 2).is_err());
+
+        a1.yield_turn();
+        assert!(b1.is_turn());
+        assert!(!a1.is_turn());
+    }
+
+    #[test]
+    fn test_queue_key_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(queue_key_from_headers(&headers), ANONYMOUS_KEY);
+
+        headers.insert("x-api-key", "sk-test".parse().unwrap());
+        let k1 = queue_key_from_headers(&headers);
+        let mut bearer = HeaderMap::new();
+        bearer.insert("authorization", "Bearer sk-test".parse().unwrap());
+        assert_eq!(k1, queue_key_from_headers(&bearer));
+        assert!(!k1.contains("sk-test"));
+    }
+}
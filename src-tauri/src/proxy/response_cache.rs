@@ -0,0 +1,391 @@
+// 精确匹配响应缓存 (Exact-match Response Cache)
+// 以「映射后模型 + 归一化的上游请求体」哈希为键，缓存确定性请求 (temperature = 0 或客户端显式要求) 的响应。
+// 流式响应在正常结束后合并为一条非流式响应写入；命中时不消耗账号配额，流式客户端通过现有的 SSE 转换器回放缓存内容。
+
+use axum::http::HeaderMap;
+use axum::response::Response;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::pin::Pin;
+
+/// 客户端控制缓存的请求头: on/true/1 强制启用，off/false/0/bypass/no-store 跳过
+pub const CACHE_CONTROL_HEADER: &str = "x-proxy-cache";
+/// 响应头: hit / miss (仅在请求符合缓存条件时出现)
+pub const CACHE_STATUS_HEADER: &str = "X-Cache";
+
+/// 响应缓存配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResponseCacheConfig {
+    /// 是否启用 (默认关闭，需手动开启)
+    pub enabled: bool,
+    /// 缓存有效期 (秒)
+    pub ttl_secs: u64,
+    /// 缓存总大小上限 (MB)，超出时淘汰最早写入的条目
+    pub max_size_mb: u64,
+    /// 单条响应大小上限 (KB)，超出则不缓存
+    pub max_entry_kb: u64,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: 3600,
+            max_size_mb: 256,
+            max_entry_kb: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+impl CacheStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Miss => "miss",
+        }
+    }
+}
+
+/// 计算缓存键；请求不符合缓存条件时返回 None
+///
+/// 条件: 缓存已启用，且 temperature 为 0 或客户端通过 `x-proxy-cache` 请求头显式开启
+pub fn cache_key(
+    config: &ResponseCacheConfig,
+    headers: &HeaderMap,
+    mapped_model: &str,
+    upstream_body: &Value,
+) -> Option<String> {
+    if !config.enabled {
+        return None;
+    }
+
+    let client_opt_in = match headers
+        .get(CACHE_CONTROL_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("on" | "true" | "1") => true,
+        Some("off" | "false" | "0" | "bypass" | "no-store") => return None,
+        _ => false,
+    };
+
+    let deterministic = upstream_body
+        .pointer("/request/generationConfig/temperature")
+        .and_then(|t| t.as_f64())
+        .is_some_and(|t| t == 0.0);
+
+    if !client_opt_in && !deterministic {
+        return None;
+    }
+
+    let normalized = normalize_body(upstream_body);
+    let mut hasher = Sha256::new();
+    hasher.update(mapped_model.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(serde_json::to_string(&normalized).unwrap_or_default().as_bytes());
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// 去掉与账号或单次请求相关的字段 (project / requestId / sessionId)，其余内容参与哈希
+fn normalize_body(body: &Value) -> Value {
+    let mut normalized = body.clone();
+    if let Some(obj) = normalized.as_object_mut() {
+        obj.remove("project");
+        obj.remove("requestId");
+        if let Some(request) = obj.get_mut("request").and_then(|r| r.as_object_mut()) {
+            request.remove("sessionId");
+        }
+    }
+    normalized
+}
+
+/// 查询缓存 (返回上游原始响应 JSON)
+pub async fn lookup(key: &str) -> Option<Value> {
+    let key = key.to_string();
+    let body = tokio::task::spawn_blocking(move || crate::modules::response_cache::get(&key))
+        .await
+        .ok()?
+        .unwrap_or_else(|e| {
+            tracing::warn!("[Response-Cache] Lookup failed: {}", e);
+            None
+        })?;
+    serde_json::from_str(&body).ok()
+}
+
+/// 写入缓存 (后台执行，不阻塞响应)
+pub fn store(config: &ResponseCacheConfig, key: String, model: &str, upstream_response: &Value) {
+    let body = match serde_json::to_string(upstream_response) {
+        Ok(b) => b,
+        Err(_) => return,
+    };
+    if body.len() as u64 > config.max_entry_kb * 1024 {
+        tracing::debug!("[Response-Cache] Response too large to cache ({} bytes)", body.len());
+        return;
+    }
+
+    let model = model.to_string();
+    let ttl_secs = config.ttl_secs;
+    let max_total_bytes = config.max_size_mb * 1024 * 1024;
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = crate::modules::response_cache::put(&key, &model, &body, ttl_secs, max_total_bytes) {
+            tracing::warn!("[Response-Cache] Failed to store response: {}", e);
+        }
+    });
+}
+
+/// 将缓存的非流式响应包装为上游 SSE 字节流，供现有的流式转换器回放
+pub fn replay_stream(
+    upstream_response: &Value,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> {
+    let chunk = Bytes::from(format!(
+        "data: {}\n\n",
+        serde_json::to_string(upstream_response).unwrap_or_default()
+    ));
+    Box::pin(futures::stream::iter(vec![Ok(chunk)]))
+}
+
+/// 透传上游 SSE 字节流，流正常结束时将各分片合并为非流式响应写入缓存 (key 为 None 时不记录)
+pub fn record_stream<S>(
+    upstream: S,
+    config: &ResponseCacheConfig,
+    key: Option<String>,
+    model: &str,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>
+where
+    S: Stream<Item = Result<Bytes, reqwest::Error>> + Send + 'static,
+{
+    let Some(key) = key else {
+        return Box::pin(upstream);
+    };
+    let config = config.clone();
+    let model = model.to_string();
+    // 原始 SSE 中每个分片都重复携带元数据，按单条上限的 4 倍截止记录
+    let record_limit = (config.max_entry_kb * 1024 * 4) as usize;
+
+    Box::pin(async_stream::stream! {
+        let mut upstream = Box::pin(upstream);
+        let mut recorded = Some(Vec::new());
+        while let Some(item) = upstream.next().await {
+            match &item {
+                Ok(bytes) => {
+                    if let Some(buf) = recorded.as_mut() {
+                        buf.extend_from_slice(bytes);
+                        if buf.len() > record_limit {
+                            recorded = None;
+                        }
+                    }
+                }
+                Err(_) => recorded = None,
+            }
+            yield item;
+        }
+        match recorded.as_deref().and_then(merge_sse_chunks) {
+            Some(merged) => store(&config, key, &model, &merged),
+            None => tracing::debug!("[Response-Cache] Stream incomplete or too large, not cached"),
+        }
+    })
+}
+
+/// 将上游 SSE 分片合并为一条非流式响应；没有 finishReason (流未正常结束) 或分片无法解析时返回 None
+fn merge_sse_chunks(raw: &[u8]) -> Option<Value> {
+    let text = std::str::from_utf8(raw).ok()?;
+    let mut wrapped = false;
+    let mut last: Option<Value> = None;
+    let mut final_candidate: Option<Value> = None;
+    let mut usage: Option<Value> = None;
+    let mut parts: Vec<Value> = Vec::new();
+
+    for line in text.lines() {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            continue;
+        };
+        if data.is_empty() || data == "[DONE]" {
+            continue;
+        }
+        let chunk: Value = serde_json::from_str(data).ok()?;
+        let inner = match chunk.get("response") {
+            Some(r) => {
+                wrapped = true;
+                r.clone()
+            }
+            None => chunk,
+        };
+        if let Some(candidate) = inner.pointer("/candidates/0") {
+            if let Some(chunk_parts) = candidate.pointer("/content/parts").and_then(|p| p.as_array()) {
+                for part in chunk_parts {
+                    push_part(&mut parts, part);
+                }
+            }
+            if candidate.get("finishReason").is_some() {
+                final_candidate = Some(candidate.clone());
+            }
+        }
+        if let Some(u) = inner.get("usageMetadata") {
+            usage = Some(u.clone());
+        }
+        last = Some(inner);
+    }
+
+    let mut response = last?;
+    let mut candidate = final_candidate?;
+    candidate["content"] = json!({ "role": "model", "parts": parts });
+    response["candidates"] = json!([candidate]);
+    if let Some(u) = usage {
+        response["usageMetadata"] = u;
+    }
+    Some(if wrapped { json!({ "response": response }) } else { response })
+}
+
+/// 追加分片中的 part：与上一个同类文本 part (thought 标记相同且尚未携带签名) 拼接，其余原样追加
+fn push_part(parts: &mut Vec<Value>, part: &Value) {
+    let is_text = |p: &Value| {
+        p.as_object().is_some_and(|o| {
+            o.get("text").is_some_and(|t| t.is_string())
+                && o.keys().all(|k| matches!(k.as_str(), "text" | "thought" | "thoughtSignature"))
+        })
+    };
+    if let Some(prev) = parts.last_mut() {
+        if is_text(prev)
+            && is_text(part)
+            && prev.get("thoughtSignature").is_none()
+            && prev.get("thought") == part.get("thought")
+        {
+            let text = format!(
+                "{}{}",
+                prev["text"].as_str().unwrap_or_default(),
+                part["text"].as_str().unwrap_or_default()
+            );
+            prev["text"] = Value::String(text);
+            if let Some(sig) = part.get("thoughtSignature") {
+                prev["thoughtSignature"] = sig.clone();
+            }
+            return;
+        }
+    }
+    parts.push(part.clone());
+}
+
+/// 在响应上标记缓存状态 (供监控中间件记录)
+pub fn annotate_response(response: &mut Response, status: CacheStatus) {
+    response.headers_mut().insert(
+        CACHE_STATUS_HEADER,
+        axum::http::HeaderValue::from_static(status.as_str()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use serde_json::json;
+
+    fn enabled() -> ResponseCacheConfig {
+        ResponseCacheConfig { enabled: true, ..Default::default() }
+    }
+
+    fn body(project: &str, temperature: f64) -> Value {
+        json!({
+            "project": project,
+            "requestId": format!("agent-{}", project),
+            "model": "gemini-2.5-flash",
+            "request": {
+                "contents": [{ "role": "user", "parts": [{ "text": "hi" }] }],
+                "generationConfig": { "temperature": temperature },
+                "sessionId": project
+            }
+        })
+    }
+
+    #[test]
+    fn test_key_ignores_account_fields() {
+        let headers = HeaderMap::new();
+        let a = cache_key(&enabled(), &headers, "gemini-2.5-flash", &body("p1", 0.0)).unwrap();
+        let b = cache_key(&enabled(), &headers, "gemini-2.5-flash", &body("p2", 0.0)).unwrap();
+        assert_eq!(a, b);
+
+        let other_model = cache_key(&enabled(), &headers, "gemini-2.5-pro", &body("p1", 0.0)).unwrap();
+        assert_ne!(a, other_model);
+    }
+
+    #[test]
+    fn test_eligibility() {
+        let mut headers = HeaderMap::new();
+        assert!(cache_key(&ResponseCacheConfig::default(), &headers, "m", &body("p", 0.0)).is_none());
+        assert!(cache_key(&enabled(), &headers, "m", &body("p", 0.7)).is_none());
+
+        headers.insert(CACHE_CONTROL_HEADER, "on".parse().unwrap());
+        assert!(cache_key(&enabled(), &headers, "m", &body("p", 0.7)).is_some());
+
+        headers.insert(CACHE_CONTROL_HEADER, "bypass".parse().unwrap());
+        assert!(cache_key(&enabled(), &headers, "m", &body("p", 0.0)).is_none());
+    }
+
+    fn sse(chunks: &[Value]) -> Vec<u8> {
+        chunks
+            .iter()
+            .map(|c| format!("data: {}\r\n\r\n", c))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    #[test]
+    fn test_merge_sse_chunks() {
+        let raw = sse(&[
+            json!({ "response": { "candidates": [{ "content": { "role": "model", "parts": [{ "text": "think", "thought": true }] } }] } }),
+            json!({ "response": { "candidates": [{ "content": { "role": "model", "parts": [{ "text": "ing", "thought": true, "thoughtSignature": "sig" }] } }] } }),
+            json!({ "response": { "candidates": [{ "content": { "role": "model", "parts": [{ "text": "Hello" }] } }] } }),
+            json!({ "response": {
+                "candidates": [{ "content": { "role": "model", "parts": [{ "text": ", world" }] }, "finishReason": "STOP" }],
+                "usageMetadata": { "promptTokenCount": 3, "candidatesTokenCount": 2 },
+                "modelVersion": "gemini-2.5-flash"
+            } }),
+        ]);
+        let merged = merge_sse_chunks(&raw).unwrap();
+        let parts = merged.pointer("/response/candidates/0/content/parts").unwrap().as_array().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0], json!({ "text": "thinking", "thought": true, "thoughtSignature": "sig" }));
+        assert_eq!(parts[1], json!({ "text": "Hello, world" }));
+        assert_eq!(merged.pointer("/response/candidates/0/finishReason").unwrap(), "STOP");
+        assert_eq!(merged.pointer("/response/usageMetadata/candidatesTokenCount").unwrap(), 2);
+        assert_eq!(merged.pointer("/response/modelVersion").unwrap(), "gemini-2.5-flash");
+    }
+
+    #[test]
+    fn test_merge_rejects_incomplete_stream() {
+        let truncated = sse(&[json!({ "candidates": [{ "content": { "parts": [{ "text": "Hel" }] } }] })]);
+        assert!(merge_sse_chunks(&truncated).is_none());
+        assert!(merge_sse_chunks(b"data: {not json\n\n").is_none());
+        assert!(merge_sse_chunks(b"").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_record_stream_passes_through() {
+        let raw = sse(&[json!({ "candidates": [{ "content": { "parts": [{ "text": "hi" }] }, "finishReason": "STOP" }] })]);
+        let upstream = futures::stream::iter(vec![Ok::<Bytes, reqwest::Error>(Bytes::from(raw.clone()))]);
+        // 未启用缓存 (key 为 None) 时原样透传
+        let out: Vec<_> = record_stream(upstream, &enabled(), None, "m").collect().await;
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].as_ref().unwrap().as_ref(), raw.as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_replay_stream_is_single_sse_event() {
+        let cached = json!({ "response": { "candidates": [] } });
+        let chunks: Vec<_> = replay_stream(&cached).collect().await;
+        assert_eq!(chunks.len(), 1);
+        let text = String::from_utf8(chunks[0].as_ref().unwrap().to_vec()).unwrap();
+        assert!(text.starts_with("data: {\"response\""));
+        assert!(text.ends_with("\n\n"));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::proxy::TokenManager;
 use axum::{
-    extract::DefaultBodyLimit,
+    extract::{DefaultBodyLimit, State},
     http::StatusCode,
     response::{IntoResponse, Json, Response},
     routing::{any, get, post},
@@ -30,6 +30,16 @@ pub struct AppState {
     pub zai_vision_mcp: Arc<crate::proxy::zai_vision_mcp::ZaiVisionMcpState>,
     pub monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
     pub experimental: Arc<RwLock<crate::proxy::config::ExperimentalConfig>>,
+    pub background_tasks: Arc<RwLock<crate::proxy::background_tasks::BackgroundTaskClassifier>>,
+    /// 正在对外服务的完整路由 (含鉴权等中间件)，供请求重放使用；弱引用避免与路由内的状态形成循环
+    pub live_router: Arc<once_cell::sync::OnceCell<std::sync::Weak<Router>>>,
+}
+
+impl AppState {
+    /// 正在对外服务的完整路由 (服务已停止时返回 None)
+    pub fn live_router(&self) -> Option<Router> {
+        self.live_router.get().and_then(|r| r.upgrade()).map(|r| (*r).clone())
+    }
 }
 
 /// Axum 服务器实例
@@ -40,6 +50,9 @@ pub struct AxumServer {
     security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
     zai_state: Arc<RwLock<crate::proxy::ZaiConfig>>,
     experimental: Arc<RwLock<crate::proxy::config::ExperimentalConfig>>,
+    background_tasks: Arc<RwLock<crate::proxy::background_tasks::BackgroundTaskClassifier>>,
+    state: AppState,
+    batch_worker: tokio::task::JoinHandle<()>,
 }
 
 impl AxumServer {
@@ -75,6 +88,17 @@ impl AxumServer {
         *exp = config.experimental.clone();
         tracing::info!("实验性配置已热更新");
     }
+
+    pub async fn update_background_tasks(&self, config: &crate::proxy::config::ProxyConfig) {
+        let classifier = crate::proxy::background_tasks::BackgroundTaskClassifier::new(config.background_tasks.clone());
+        *self.background_tasks.write().await = classifier;
+        tracing::info!("后台任务分类规则已热更新");
+    }
+
+    /// 当前服务的应用状态 (供 Tauri 命令在服务外复用处理流程，如请求重放)
+    pub fn app_state(&self) -> AppState {
+        self.state.clone()
+    }
     /// 启动 Axum 服务器
     pub async fn start(
         host: String,
@@ -87,7 +111,8 @@ impl AxumServer {
         zai_config: crate::proxy::ZaiConfig,
         monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
         experimental_config: crate::proxy::config::ExperimentalConfig,
-
+        circuit_breaker_config: crate::proxy::upstream::circuit_breaker::CircuitBreakerConfig,
+        background_task_config: crate::proxy::background_tasks::BackgroundTaskConfig,
     ) -> Result<(Self, tokio::task::JoinHandle<()>), String> {
         let custom_mapping_state = Arc::new(tokio::sync::RwLock::new(custom_mapping));
 	        let proxy_state = Arc::new(tokio::sync::RwLock::new(upstream_proxy.clone()));
@@ -97,6 +122,9 @@ impl AxumServer {
 	        let zai_vision_mcp_state =
 	            Arc::new(crate::proxy::zai_vision_mcp::ZaiVisionMcpState::new());
 	        let experimental_state = Arc::new(RwLock::new(experimental_config));
+	        let background_tasks_state = Arc::new(RwLock::new(
+	            crate::proxy::background_tasks::BackgroundTaskClassifier::new(background_task_config),
+	        ));
 
 	        let state = AppState {
 	            token_manager: token_manager.clone(),
@@ -106,92 +134,32 @@ impl AxumServer {
                 std::collections::HashMap::new(),
             )),
             upstream_proxy: proxy_state.clone(),
-            upstream: Arc::new(crate::proxy::upstream::client::UpstreamClient::new(Some(
-                upstream_proxy.clone(),
-            ))),
+            upstream: Arc::new(crate::proxy::upstream::client::UpstreamClient::new(
+                Some(upstream_proxy.clone()),
+                circuit_breaker_config,
+            )),
             zai: zai_state.clone(),
             provider_rr: provider_rr.clone(),
             zai_vision_mcp: zai_vision_mcp_state,
             monitor: monitor.clone(),
             experimental: experimental_state.clone(),
+            background_tasks: background_tasks_state.clone(),
+            live_router: Arc::new(once_cell::sync::OnceCell::new()),
         };
 
-
         // 构建路由 - 使用新架构的 handlers！
-        use crate::proxy::handlers;
-        // 构建路由
-        let app = Router::new()
-            // OpenAI Protocol
-            .route("/v1/models", get(handlers::openai::handle_list_models))
-            .route(
-                "/v1/chat/completions",
-                post(handlers::openai::handle_chat_completions),
-            )
-            .route(
-                "/v1/completions",
-                post(handlers::openai::handle_completions),
-            )
-            .route("/v1/responses", post(handlers::openai::handle_completions)) // 兼容 Codex CLI
-            .route(
-                "/v1/images/generations",
-                post(handlers::openai::handle_images_generations),
-            ) // 图像生成 API
-            .route(
-                "/v1/images/edits",
-                post(handlers::openai::handle_images_edits),
-            ) // 图像编辑 API
-            .route(
-                "/v1/audio/transcriptions",
-                post(handlers::audio::handle_audio_transcription),
-            ) // 音频转录 API
-            // Claude Protocol
-            .route("/v1/messages", post(handlers::claude::handle_messages))
-            .route(
-                "/v1/messages/count_tokens",
-                post(handlers::claude::handle_count_tokens),
-            )
-            .route(
-                "/v1/models/claude",
-                get(handlers::claude::handle_list_models),
-            )
-            // z.ai MCP (optional reverse-proxy)
-            .route(
-                "/mcp/web_search_prime/mcp",
-                any(handlers::mcp::handle_web_search_prime),
-            )
-	            .route(
-	                "/mcp/web_reader/mcp",
-	                any(handlers::mcp::handle_web_reader),
-	            )
-	            .route(
-	                "/mcp/zai-mcp-server/mcp",
-	                any(handlers::mcp::handle_zai_mcp_server),
-	            )
-	            // Gemini Protocol (Native)
-	            .route("/v1beta/models", get(handlers::gemini::handle_list_models))
-            // Handle both GET (get info) and POST (generateContent with colon) at the same route
-            .route(
-                "/v1beta/models/:model",
-                get(handlers::gemini::handle_get_model).post(handlers::gemini::handle_generate),
-            )
-            .route(
-                "/v1beta/models/:model/countTokens",
-                post(handlers::gemini::handle_count_tokens),
-            ) // Specific route priority
-            .route("/v1/models/detect", post(handlers::common::handle_detect_model))
-            .route("/internal/warmup", post(handlers::warmup::handle_warmup)) // 内部预热端点
-            .route("/v1/api/event_logging/batch", post(silent_ok_handler))
-            .route("/v1/api/event_logging", post(silent_ok_handler))
-            .route("/healthz", get(health_check_handler))
-            .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
-            .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::monitor::monitor_middleware))
+        let app = build_router(state.clone())
             .layer(TraceLayer::new_for_http())
             .layer(axum::middleware::from_fn_with_state(
                 security_state.clone(),
                 crate::proxy::middleware::auth_middleware,
             ))
-            .layer(crate::proxy::middleware::cors_layer())
-            .with_state(state);
+            .layer(axum::middleware::from_fn(
+                crate::proxy::middleware::telemetry::telemetry_middleware,
+            ))
+            .layer(crate::proxy::middleware::cors_layer());
+        let app = Arc::new(app);
+        let _ = state.live_router.set(Arc::downgrade(&app));
 
         // 绑定地址
         let addr = format!("{}:{}", host, port);
@@ -211,6 +179,9 @@ impl AxumServer {
             security_state,
             zai_state,
             experimental: experimental_state.clone(),
+            background_tasks: background_tasks_state,
+            state: state.clone(),
+            batch_worker: crate::proxy::batch::worker::start(state.clone()),
         };
 
         // 在新任务中启动服务器
@@ -225,7 +196,7 @@ impl AxumServer {
                         match res {
                             Ok((stream, _)) => {
                                 let io = TokioIo::new(stream);
-                                let service = TowerToHyperService::new(app.clone());
+                                let service = TowerToHyperService::new((*app).clone());
 
                                 tokio::task::spawn(async move {
                                     if let Err(err) = http1::Builder::new()
@@ -258,15 +229,162 @@ impl AxumServer {
         if let Some(tx) = self.shutdown_tx.take() {
             let _ = tx.send(());
         }
+        self.batch_worker.abort();
     }
 }
 
+/// 构建业务路由 (含排队与监控中间件，不含鉴权 / CORS)
+///
+/// 服务启动与批处理执行共用同一套路由
+pub(crate) fn build_router(state: AppState) -> Router {
+    use crate::proxy::handlers;
+    Router::new()
+        // OpenAI Protocol
+        .route("/v1/models", get(handlers::openai::handle_list_models))
+        .route(
+            "/v1/chat/completions",
+            post(handlers::openai::handle_chat_completions),
+        )
+        .route(
+            "/v1/completions",
+            post(handlers::openai::handle_completions),
+        )
+        .route("/v1/responses", post(handlers::openai::handle_completions)) // 兼容 Codex CLI
+        .route(
+            "/v1/images/generations",
+            post(handlers::openai::handle_images_generations),
+        ) // 图像生成 API
+        .route(
+            "/v1/images/edits",
+            post(handlers::openai::handle_images_edits),
+        ) // 图像编辑 API
+        .route(
+            "/v1/images/variations",
+            post(handlers::openai::handle_images_variations),
+        ) // 图像变体 API
+        .route(
+            "/v1/files/images/:id",
+            get(handlers::files::handle_get_image),
+        ) // 本地存储的生成图片
+        .route(
+            "/v1/files",
+            get(handlers::batch::handle_list_files).post(handlers::batch::handle_upload_file),
+        ) // 批处理输入 / 结果文件
+        .route(
+            "/v1/files/:id",
+            get(handlers::batch::handle_get_file).delete(handlers::batch::handle_delete_file),
+        )
+        .route(
+            "/v1/files/:id/content",
+            get(handlers::batch::handle_get_file_content),
+        )
+        .route(
+            "/v1/batches",
+            get(handlers::batch::handle_list_batches).post(handlers::batch::handle_create_batch),
+        ) // 批处理任务
+        .route("/v1/batches/:id", get(handlers::batch::handle_get_batch))
+        .route(
+            "/v1/batches/:id/cancel",
+            post(handlers::batch::handle_cancel_batch),
+        )
+        .route(
+            "/v1/audio/transcriptions",
+            post(handlers::audio::handle_audio_transcription),
+        ) // 音频转录 API
+        .route(
+            "/v1/audio/translations",
+            post(handlers::audio::handle_audio_translation),
+        ) // 音频翻译 API
+        .route(
+            "/v1/audio/speech",
+            post(handlers::audio::handle_audio_speech),
+        ) // 语音合成 API
+        // Claude Protocol
+        .route("/v1/messages", post(handlers::claude::handle_messages))
+        .route(
+            "/v1/messages/count_tokens",
+            post(handlers::claude::handle_count_tokens),
+        )
+        .route(
+            "/v1/models/claude",
+            get(handlers::claude::handle_list_models),
+        )
+        // z.ai MCP (optional reverse-proxy)
+        .route(
+            "/mcp/web_search_prime/mcp",
+            any(handlers::mcp::handle_web_search_prime),
+        )
+        .route(
+            "/mcp/web_reader/mcp",
+            any(handlers::mcp::handle_web_reader),
+        )
+        .route(
+            "/mcp/zai-mcp-server/mcp",
+            any(handlers::mcp::handle_zai_mcp_server),
+        )
+        // Gemini Protocol (Native)
+        .route("/v1beta/models", get(handlers::gemini::handle_list_models))
+        // Handle both GET (get info) and POST (generateContent with colon) at the same route
+        .route(
+            "/v1beta/models/:model",
+            get(handlers::gemini::handle_get_model).post(handlers::gemini::handle_generate),
+        )
+        .route(
+            "/v1beta/models/:model/countTokens",
+            post(handlers::gemini::handle_count_tokens),
+        ) // Specific route priority
+        .route(
+            "/v1beta/cachedContents",
+            get(handlers::gemini::handle_list_cached_contents)
+                .post(handlers::gemini::handle_create_cached_content),
+        ) // 上下文缓存 (本地模拟)
+        .route(
+            "/v1beta/cachedContents/:id",
+            get(handlers::gemini::handle_get_cached_content)
+                .patch(handlers::gemini::handle_update_cached_content)
+                .delete(handlers::gemini::handle_delete_cached_content),
+        )
+        .route(
+            "/upload/v1beta/files",
+            post(handlers::files::handle_upload_file),
+        ) // Files API 上传 (本地模拟)
+        .route("/v1beta/files", get(handlers::files::handle_list_files))
+        .route(
+            "/v1beta/files/:id",
+            get(handlers::files::handle_get_file).delete(handlers::files::handle_delete_file),
+        )
+        .route("/v1/models/detect", post(handlers::common::handle_detect_model))
+        .route("/internal/warmup", post(handlers::warmup::handle_warmup)) // 内部预热端点
+        .route(
+            "/internal/background-tasks/classify",
+            post(handlers::background_tasks::handle_classify),
+        ) // 后台任务分类规则试运行
+        .route(
+            "/internal/logs/:id/replay",
+            post(handlers::replay::handle_replay),
+        ) // 重放已记录的请求
+        .route(
+            "/internal/logs/:id/replays",
+            get(handlers::replay::handle_list_replays),
+        )
+        .route("/v1/api/event_logging/batch", post(silent_ok_handler))
+        .route("/v1/api/event_logging", post(silent_ok_handler))
+        .route("/healthz", get(health_check_handler))
+        .route("/readyz", get(handlers::health::handle_readyz))
+        .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::queue::queue_middleware))
+        .layer(axum::middleware::from_fn(crate::proxy::middleware::budget::budget_middleware))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::monitor::monitor_middleware))
+        .with_state(state)
+}
+
 // ===== API 处理器 (旧代码已移除，由 src/proxy/handlers/* 接管) =====
 
-/// 健康检查处理器
-async fn health_check_handler() -> Response {
+/// 健康检查处理器 (附带上游端点熔断状态)
+async fn health_check_handler(State(state): State<AppState>) -> Response {
     Json(serde_json::json!({
-        "status": "ok"
+        "status": "ok",
+        "upstream_endpoints": state.upstream.endpoint_health(),
     }))
     .into_response()
 }
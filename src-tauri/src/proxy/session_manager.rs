@@ -156,4 +156,13 @@ impl SessionManager {
         tracing::debug!("[SessionManager-Gemini] Generated fingerprint: {}", sid);
         sid
     }
+
+    /// 引用 cachedContent 的请求按缓存名生成指纹，使同一缓存的请求固定到同一账号以命中上游隐式缓存
+    pub fn cached_content_session_id(cache_name: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(b"cachedContent:");
+        hasher.update(cache_name.as_bytes());
+        let hash = format!("{:x}", hasher.finalize());
+        format!("sid-{}", &hash[..16])
+    }
 }
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// 调度模式枚举
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -25,6 +26,10 @@ pub struct StickySessionConfig {
     pub mode: SchedulingMode,
     /// 缓存优先模式下的最大等待时间 (秒)
     pub max_wait_seconds: u64,
+    /// 【新增】单账号并发上限配置
+    pub concurrency: ConcurrencyLimitConfig,
+    /// 【新增】账号池暂时耗尽时的排队配置
+    pub queue: RequestQueueConfig,
 }
 
 impl Default for StickySessionConfig {
@@ -32,6 +37,88 @@ impl Default for StickySessionConfig {
         Self {
             mode: SchedulingMode::Balance,
             max_wait_seconds: 60,
+            concurrency: ConcurrencyLimitConfig::default(),
+            queue: RequestQueueConfig::default(),
+        }
+    }
+}
+
+/// 单账号并发上限 (in-flight leases)
+///
+/// 所有上限中 0 表示不限制。`per_tier` 限制账号的总并发，
+/// `per_model` 限制同一账号上同一模型的并发，两者同时生效。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConcurrencyLimitConfig {
+    /// 是否启用并发上限
+    pub enabled: bool,
+    /// 未命中 `per_tier` 时的默认账号并发上限
+    pub default_max_per_account: usize,
+    /// 按订阅等级覆盖账号并发上限 (键: "FREE" | "PRO" | "ULTRA")
+    pub per_tier: HashMap<String, usize>,
+    /// 按模型限制单账号并发 (键: 标准模型 ID，如 "claude-sonnet-4-5")
+    pub per_model: HashMap<String, usize>,
+    /// CacheFirst 模式下，绑定账号饱和时排队等待空位的最长时间 (毫秒)
+    pub queue_wait_ms: u64,
+}
+
+impl Default for ConcurrencyLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            default_max_per_account: 0,
+            per_tier: HashMap::new(),
+            per_model: HashMap::new(),
+            queue_wait_ms: 3000,
+        }
+    }
+}
+
+impl ConcurrencyLimitConfig {
+    /// 解析账号级并发上限 (None 表示不限制)
+    pub fn account_limit(&self, tier: Option<&str>) -> Option<usize> {
+        if !self.enabled {
+            return None;
+        }
+        let limit = tier
+            .and_then(|t| self.per_tier.get(&t.to_uppercase()).copied())
+            .unwrap_or(self.default_max_per_account);
+        (limit > 0).then_some(limit)
+    }
+
+    /// 解析单账号单模型并发上限 (None 表示不限制)
+    pub fn model_limit(&self, model: &str) -> Option<usize> {
+        if !self.enabled {
+            return None;
+        }
+        self.per_model.get(model).copied().filter(|l| *l > 0)
+    }
+}
+
+/// 请求排队配置
+///
+/// 启用后，账号池暂时耗尽 (全部限流或并发已满) 时请求不再立即返回 503，
+/// 而是按 API Key 公平排队，直到最早的限流重置或有账号释放。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestQueueConfig {
+    /// 是否启用排队
+    pub enabled: bool,
+    /// 单个请求的最长排队时间 (秒)
+    pub max_wait_seconds: u64,
+    /// 队列最大深度，超出后直接返回错误
+    pub max_depth: usize,
+    /// 流式请求排队期间发送 SSE keep-alive 注释的间隔 (秒)
+    pub keepalive_interval_seconds: u64,
+}
+
+impl Default for RequestQueueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_wait_seconds: 30,
+            max_depth: 200,
+            keepalive_interval_seconds: 5,
         }
     }
 }
@@ -0,0 +1,282 @@
+// OpenTelemetry 链路追踪导出 (OTLP)
+// 代理链路上的 tracing span (鉴权、路由、取 Token、请求转换、上游尝试、重试退避、流式转换) 可选地以 OTLP/HTTP 导出；
+// 入站请求携带的 W3C `traceparent` 会作为父上下文，出站上游请求同样注入 `traceparent`。
+// 导出层通过 tracing_subscriber::reload 挂在全局 subscriber 上，配置变更时热替换，关闭时开销可忽略。
+
+use once_cell::sync::{Lazy, OnceCell};
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, TracerProvider};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{reload, Registry};
+
+/// OTLP 传输格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OtlpProtocol {
+    #[default]
+    HttpProtobuf,
+    HttpJson,
+}
+
+/// 链路追踪导出配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// 是否启用 OTLP 导出 (默认关闭)
+    pub enabled: bool,
+    /// OTLP/HTTP traces 端点
+    pub endpoint: String,
+    pub protocol: OtlpProtocol,
+    pub service_name: String,
+    /// 附加请求头 (如采集端鉴权)
+    pub headers: HashMap<String, String>,
+    /// 采样比例 (0.0 - 1.0)；入站请求已携带采样决定时以父 span 为准
+    pub sample_ratio: f64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://127.0.0.1:4318/v1/traces".to_string(),
+            protocol: OtlpProtocol::HttpProtobuf,
+            service_name: "antigravity-tools".to_string(),
+            headers: HashMap::new(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+type OtelLayer = tracing_opentelemetry::OpenTelemetryLayer<Registry, opentelemetry_sdk::trace::Tracer>;
+
+static RELOAD_HANDLE: OnceCell<reload::Handle<Option<OtelLayer>, Registry>> = OnceCell::new();
+static PROVIDER: Lazy<RwLock<Option<TracerProvider>>> = Lazy::new(|| RwLock::new(None));
+
+/// 供日志系统初始化时挂载的导出层 (初始为空，由 [`apply_config`] 填充)
+pub fn layer() -> reload::Layer<Option<OtelLayer>, Registry> {
+    let (layer, handle) = reload::Layer::new(None);
+    let _ = RELOAD_HANDLE.set(handle);
+    layer
+}
+
+/// 应用导出配置：启用时重建导出器并热替换导出层，关闭时移除导出层
+///
+/// 需在 tokio 运行时内调用 (批量导出器运行在 tokio 上)
+pub fn apply_config(config: &TelemetryConfig) -> Result<(), String> {
+    let handle = RELOAD_HANDLE
+        .get()
+        .ok_or("Tracing subscriber was initialized without the telemetry layer")?;
+
+    let provider = if config.enabled {
+        Some(build_provider(config)?)
+    } else {
+        None
+    };
+    let layer = provider
+        .as_ref()
+        .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer("antigravity-proxy")));
+    handle.modify(|current| *current = layer).map_err(|e| e.to_string())?;
+
+    let previous = std::mem::replace(&mut *PROVIDER.write().unwrap(), provider);
+    if let Some(previous) = previous {
+        // shutdown 会阻塞等待剩余 span 导出完成，放到独立线程避免卡住运行时
+        std::thread::spawn(move || {
+            if let Err(e) = previous.shutdown() {
+                tracing::debug!("[Telemetry] Previous tracer provider shutdown: {}", e);
+            }
+        });
+    }
+
+    if config.enabled {
+        tracing::info!("[Telemetry] OTLP export enabled -> {} ({:?})", config.endpoint, config.protocol);
+    }
+    Ok(())
+}
+
+/// 构建 OTLP 导出的 TracerProvider
+pub fn build_provider(config: &TelemetryConfig) -> Result<TracerProvider, String> {
+    let protocol = match config.protocol {
+        OtlpProtocol::HttpProtobuf => opentelemetry_otlp::Protocol::HttpBinary,
+        OtlpProtocol::HttpJson => opentelemetry_otlp::Protocol::HttpJson,
+    };
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(config.endpoint.clone())
+        .with_protocol(protocol)
+        .with_headers(config.headers.clone())
+        .build()
+        .map_err(|e| format!("Failed to build OTLP exporter: {}", e))?;
+
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio.clamp(0.0, 1.0))));
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_sampler(sampler)
+        .with_resource(opentelemetry_sdk::Resource::new(vec![KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )]))
+        .build())
+}
+
+/// 以入站请求的 `traceparent` / `tracestate` 作为 span 的父上下文
+pub fn set_parent_from_headers(span: &tracing::Span, headers: &axum::http::HeaderMap) {
+    if !headers.contains_key("traceparent") {
+        return;
+    }
+    let context = TraceContextPropagator::new().extract(&opentelemetry_http::HeaderExtractor(headers));
+    span.set_parent(context);
+}
+
+/// 向出站请求注入当前 span 的 `traceparent` (未启用导出时不注入)
+pub fn inject_current_context(headers: &mut axum::http::HeaderMap) {
+    if PROVIDER.read().unwrap().is_none() {
+        return;
+    }
+    let context = tracing::Span::current().context();
+    TraceContextPropagator::new().inject_context(&context, &mut opentelemetry_http::HeaderInjector(headers));
+}
+
+/// 单次上游尝试的 span (重试时每次尝试一个，账号轮换后账号不同)
+pub fn attempt_span(attempt: usize, account: &str, model: &str) -> tracing::Span {
+    tracing::info_span!(
+        "proxy.upstream_attempt",
+        attempt,
+        account,
+        model,
+        http.response.status_code = tracing::field::Empty,
+        otel.status_code = tracing::field::Empty,
+    )
+}
+
+/// 记录上游响应状态码 (4xx/5xx 标记为错误)
+pub fn record_status(span: &tracing::Span, status: u16) {
+    span.record("http.response.status_code", status);
+    if status >= 400 {
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+/// 让 span 随流存活：流结束 (或客户端断开) 时 span 才关闭，并在每次轮询时进入该 span
+pub fn instrument_stream<S>(span: tracing::Span, stream: S) -> impl futures::Stream<Item = S::Item> + Send
+where
+    S: futures::Stream + Send,
+{
+    use futures::StreamExt;
+    let mut stream = Box::pin(stream);
+    futures::stream::poll_fn(move |cx| {
+        let _entered = span.enter();
+        stream.poll_next_unpin(cx)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Bytes, extract::State, routing::post, Router};
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// 读写全局 PROVIDER 的测试需串行执行
+    static PROVIDER_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// 本地 OTLP 采集端替身：记录收到的 /v1/traces 请求体
+    async fn spawn_collector() -> (String, Arc<Mutex<Vec<String>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new()
+            .route(
+                "/v1/traces",
+                post(|State(received): State<Arc<Mutex<Vec<String>>>>, body: Bytes| async move {
+                    received.lock().unwrap().push(String::from_utf8_lossy(&body).into_owned());
+                    "{}"
+                }),
+            )
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/v1/traces", addr), received)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_exports_spans_with_incoming_parent() {
+        let (endpoint, received) = spawn_collector().await;
+        let config = TelemetryConfig {
+            enabled: true,
+            endpoint,
+            protocol: OtlpProtocol::HttpJson,
+            ..Default::default()
+        };
+        let provider = build_provider(&config).unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap(),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("proxy.request");
+            set_parent_from_headers(&span, &headers);
+            let _entered = span.enter();
+            tracing::info_span!("proxy.token", account = "a@example.com").in_scope(|| {});
+        });
+
+        tokio::task::spawn_blocking(move || {
+            provider.force_flush();
+        })
+        .await
+        .unwrap();
+
+        let bodies = received.lock().unwrap().join("\n");
+        assert!(bodies.contains("proxy.request"), "{}", bodies);
+        assert!(bodies.contains("proxy.token"));
+        assert!(bodies.contains("a@example.com"));
+        assert!(bodies.to_lowercase().contains("4bf92f3577b34da6a3ce929d0e0e4736"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reload_enables_and_disables_export() {
+        let _lock = PROVIDER_LOCK.lock().await;
+        let (endpoint, received) = spawn_collector().await;
+        let subscriber = tracing_subscriber::registry().with(layer());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        tracing::info_span!("before.enable").in_scope(|| {});
+        let config = TelemetryConfig {
+            enabled: true,
+            endpoint,
+            protocol: OtlpProtocol::HttpJson,
+            ..Default::default()
+        };
+        apply_config(&config).unwrap();
+        tracing::info_span!("while.enabled").in_scope(|| {});
+        // 关闭时旧的 provider 在后台线程 shutdown，并导出剩余 span
+        apply_config(&TelemetryConfig::default()).unwrap();
+        tracing::info_span!("after.disable").in_scope(|| {});
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while received.lock().unwrap().is_empty() && std::time::Instant::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        let bodies = received.lock().unwrap().join("\n");
+        assert!(bodies.contains("while.enabled"), "{}", bodies);
+        assert!(!bodies.contains("before.enable"));
+        assert!(!bodies.contains("after.disable"));
+    }
+
+    #[test]
+    fn test_inject_is_noop_when_disabled() {
+        let _lock = PROVIDER_LOCK.blocking_lock();
+        let mut headers = axum::http::HeaderMap::new();
+        inject_current_context(&mut headers);
+        assert!(!headers.contains_key("traceparent"));
+    }
+}
@@ -5,9 +5,38 @@ use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use crate::proxy::concurrency::{LeaseRegistry, TokenLease};
 use crate::proxy::rate_limit::RateLimitTracker;
+use crate::proxy::request_queue::RequestQueue;
 use crate::proxy::sticky_config::StickySessionConfig;
 
+tokio::task_local! {
+    /// 当前任务固定使用的账号 email (请求重放时设置)
+    static PINNED_ACCOUNT: String;
+}
+
+/// 在指定账号上执行 `fut`：其中所有 `get_token` 调用都直接返回该账号，不参与轮换与排队
+pub async fn with_pinned_account<F: std::future::Future>(email: Option<String>, fut: F) -> F::Output {
+    match email {
+        Some(email) => PINNED_ACCOUNT.scope(email, fut).await,
+        None => fut.await,
+    }
+}
+
+/// 当前任务固定使用的账号 (作用域外返回 None)
+pub fn pinned_account() -> Option<String> {
+    PINNED_ACCOUNT.try_with(|e| e.clone()).ok()
+}
+
+/// 账号池就绪状态
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PoolReadiness {
+    pub pool_size: usize,
+    pub rate_limited: usize,
+    /// 标准模型 ID -> 当前可服务的账号数
+    pub available_per_model: std::collections::BTreeMap<String, usize>,
+}
+
 #[derive(Debug, Clone)]
 pub struct ProxyToken {
     pub account_id: String,
@@ -33,6 +62,9 @@ pub struct TokenManager {
     sticky_config: Arc<tokio::sync::RwLock<StickySessionConfig>>, // 新增：调度配置
     session_accounts: Arc<DashMap<String, String>>, // 新增：会话与账号映射 (SessionID -> AccountID)
     preferred_account_id: Arc<tokio::sync::RwLock<Option<String>>>, // [FIX #820] 优先使用的账号ID（固定账号模式）
+    leases: Arc<LeaseRegistry>, // 【新增】单账号在途请求租约
+    request_queue: Arc<RequestQueue>, // 【新增】账号池耗尽时的公平排队
+    quota_protection: Arc<tokio::sync::RwLock<crate::models::QuotaProtectionConfig>>, // 配额保护配置 (内存副本，随保存配置热更新)
 }
 
 impl TokenManager {
@@ -47,6 +79,9 @@ impl TokenManager {
             sticky_config: Arc::new(tokio::sync::RwLock::new(StickySessionConfig::default())),
             session_accounts: Arc::new(DashMap::new()),
             preferred_account_id: Arc::new(tokio::sync::RwLock::new(None)), // [FIX #820]
+            leases: Arc::new(LeaseRegistry::new()),
+            request_queue: Arc::new(RequestQueue::new()),
+            quota_protection: Arc::new(tokio::sync::RwLock::new(crate::models::QuotaProtectionConfig::default())),
         }
     }
 
@@ -472,18 +507,169 @@ impl TokenManager {
     /// 参数 `force_rotate` 为 true 时将忽略锁定，强制切换账号
     /// 参数 `session_id` 用于跨请求维持会话粘性
     /// 参数 `target_model` 用于检查配额保护 (Issue #621)
+    ///
+    /// 返回的 `TokenLease` 占用该账号的一个并发名额，调用方需持有到请求 (或流) 结束
+    ///
+    /// 启用排队时，账号池暂时耗尽的请求会按 API Key 公平排队等待，而不是立即失败
+    #[tracing::instrument(
+        name = "proxy.token",
+        skip(self, session_id),
+        fields(account = tracing::field::Empty, queued = tracing::field::Empty)
+    )]
     pub async fn get_token(
         &self, 
         quota_group: &str, 
         force_rotate: bool, 
         session_id: Option<&str>,
         target_model: &str,
-    ) -> Result<(String, String, String), String> {
+    ) -> Result<(String, String, String, TokenLease), String> {
+        let queue_config = self.sticky_config.read().await.queue.clone();
+        // 已有请求在排队时新请求直接排到队尾，不抢在等待者之前取号 (固定账号的请求不参与排队)
+        let queue_busy = queue_config.enabled && pinned_account().is_none() && self.request_queue.depth() > 0;
+        let first_error = if queue_busy {
+            format!("{} request(s) already waiting for an account", self.request_queue.depth())
+        } else {
+            match self.get_token_once(quota_group, force_rotate, session_id, target_model).await {
+                Err(e) if Self::is_pool_exhausted_error(&e) => e,
+                other => return Self::record_token_account(other),
+            }
+        };
+        tracing::Span::current().record("queued", true);
+
+        if !queue_config.enabled {
+            return Err(first_error);
+        }
+
+        let queue_key = crate::proxy::request_queue::current_queue_key();
+        let ticket = match self.request_queue.enqueue(&queue_key, queue_config.max_depth) {
+            Ok(t) => t,
+            Err(e) => return Err(format!("{} ({})", first_error, e)),
+        };
+        tracing::info!(
+            "[Request-Queue] Pool exhausted, queued request for {} (depth: {}, max wait: {}s)",
+            queue_key, self.request_queue.depth(), queue_config.max_wait_seconds
+        );
+
+        let started = std::time::Instant::now();
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(queue_config.max_wait_seconds);
+        let mut last_error = first_error;
+        loop {
+            // 先注册通知再检查轮次，避免错过期间发生的出队/释放
+            let turn_changed = self.request_queue.turn_changed();
+            let lease_released = self.leases.released();
+            tokio::pin!(turn_changed, lease_released);
+            turn_changed.as_mut().enable();
+            lease_released.as_mut().enable();
+
+            if ticket.is_turn() {
+                match self.get_token_once(quota_group, force_rotate, session_id, target_model).await {
+                    Ok(t) => {
+                        tracing::info!("[Request-Queue] Dequeued after {}ms", started.elapsed().as_millis());
+                        return Self::record_token_account(Ok(t));
+                    }
+                    Err(e) if Self::is_pool_exhausted_error(&e) => {
+                        last_error = e;
+                        ticket.yield_turn();
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            // 等待：轮次变化 / 有租约释放 / 最早的限流重置 (上限 1s 兜底轮询)
+            let wake_after = self
+                .earliest_rate_limit_reset()
+                .unwrap_or(QUEUE_POLL_INTERVAL)
+                .clamp(std::time::Duration::from_millis(50), QUEUE_POLL_INTERVAL);
+            let sleep_until = std::cmp::min(tokio::time::Instant::now() + wake_after, deadline);
+            tokio::select! {
+                _ = turn_changed => {}
+                _ = lease_released => {}
+                _ = tokio::time::sleep_until(sleep_until) => {}
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(format!(
+                    "Request queued for {}s without a free account: {}",
+                    queue_config.max_wait_seconds, last_error
+                ));
+            }
+        }
+    }
+
+    /// 在当前取 Token span 上记录分配到的账号
+    fn record_token_account(
+        result: Result<(String, String, String, TokenLease), String>,
+    ) -> Result<(String, String, String, TokenLease), String> {
+        if let Ok((_, _, email, _)) = &result {
+            tracing::Span::current().record("account", email.as_str());
+        }
+        result
+    }
+
+    /// 账号池暂时耗尽 (限流 / 并发已满) 类错误，可通过排队等待恢复
+    pub fn is_pool_exhausted_error(error: &str) -> bool {
+        error.starts_with("All accounts are currently limited")
+            || error.starts_with("All available accounts are at max concurrency")
+    }
+
+    /// 距最早一个账号限流重置的时间
+    fn earliest_rate_limit_reset(&self) -> Option<std::time::Duration> {
+        let now = std::time::SystemTime::now();
+        self.tokens
+            .iter()
+            .filter_map(|e| self.rate_limit_tracker.get(&e.value().account_id))
+            .filter_map(|info| info.reset_time.duration_since(now).ok())
+            .min()
+    }
+
+    /// 当前排队等待账号的请求数
+    pub fn queue_depth(&self) -> usize {
+        self.request_queue.depth()
+    }
+
+    /// 当前是否存在可立即分配的账号 (未限流且未达账号级并发上限)
+    pub async fn has_free_account(&self) -> bool {
+        let concurrency = self.sticky_config.read().await.concurrency.clone();
+        self.tokens.iter().any(|e| {
+            let t = e.value();
+            !self.is_rate_limited_by_account_id(&t.account_id)
+                && concurrency
+                    .account_limit(t.subscription_tier.as_deref())
+                    .is_none_or(|limit| self.leases.in_flight(&t.account_id) < limit)
+        })
+    }
+
+    /// 单次获取 Token (不排队)
+    ///
+    /// 对冲请求等"拿不到就放弃"的场景直接调用，避免占用排队名额
+    pub async fn get_token_once(
+        &self,
+        quota_group: &str,
+        force_rotate: bool,
+        session_id: Option<&str>,
+        target_model: &str,
+    ) -> Result<(String, String, String, TokenLease), String> {
+        if let Some(email) = pinned_account() {
+            return self.get_pinned_token(&email, target_model).await;
+        }
+
         // 【优化 Issue #284】添加 5 秒超时，防止死锁
-        let timeout_duration = std::time::Duration::from_secs(5);
+        // CacheFirst 模式下排队等待并发名额的时间额外计入
+        let queue_wait_ms = {
+            let scheduling = self.sticky_config.read().await;
+            if scheduling.mode == crate::proxy::sticky_config::SchedulingMode::CacheFirst && scheduling.concurrency.enabled {
+                scheduling.concurrency.queue_wait_ms
+            } else {
+                0
+            }
+        };
+        let timeout_duration = std::time::Duration::from_secs(5) + std::time::Duration::from_millis(queue_wait_ms);
         match tokio::time::timeout(timeout_duration, self.get_token_internal(quota_group, force_rotate, session_id, target_model)).await {
             Ok(result) => result,
-            Err(_) => Err("Token acquisition timeout (5s) - system too busy or deadlock detected".to_string()),
+            Err(_) => Err(format!(
+                "Token acquisition timeout ({:?}) - system too busy or deadlock detected",
+                timeout_duration
+            )),
         }
     }
 
@@ -494,13 +680,23 @@ impl TokenManager {
         force_rotate: bool, 
         session_id: Option<&str>,
         target_model: &str,
-    ) -> Result<(String, String, String), String> {
+    ) -> Result<(String, String, String, TokenLease), String> {
         let mut tokens_snapshot: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
-        let total = tokens_snapshot.len();
-        if total == 0 {
+        if tokens_snapshot.is_empty() {
             return Err("Token pool is empty".to_string());
         }
 
+        // 【新增】账号分组预算超额 (拦截) 时，该分组账号不再参与调度
+        let budget_blocked = crate::proxy::budget::blocked_accounts();
+        if !budget_blocked.is_empty() {
+            tokens_snapshot.retain(|t| !budget_blocked.contains_key(&t.email));
+            if tokens_snapshot.is_empty() {
+                let reason = budget_blocked.values().next().cloned().unwrap_or_default();
+                return Err(format!("All accounts are paused by budget rules: {}", reason));
+            }
+        }
+        let total = tokens_snapshot.len();
+
         // ===== 【优化】根据订阅等级和剩余配额排序 =====
         // [FIX #563] 优先级: ULTRA > PRO > FREE, 同tier内优先高配额账号
         // 理由: ULTRA/PRO 重置快，优先消耗；FREE 重置慢，用于兜底
@@ -542,9 +738,29 @@ impl TokenManager {
         use crate::proxy::sticky_config::SchedulingMode;
         
         // 【新增】检查配额保护是否启用（如果关闭，则忽略 protected_models 检查）
-        let quota_protection_enabled = crate::modules::config::load_app_config()
-            .map(|cfg| cfg.quota_protection.enabled)
-            .unwrap_or(false);
+        let quota_protection_enabled = self.quota_protection.read().await.enabled;
+
+        // 【新增】单账号并发上限：租约按标准模型 ID 计数
+        let concurrency = &scheduling.concurrency;
+        let lease_model = crate::proxy::common::model_mapping::normalize_to_standard_id(target_model)
+            .unwrap_or_else(|| target_model.to_string());
+        let model_limit = concurrency.model_limit(&lease_model);
+        let is_saturated = |t: &ProxyToken| {
+            self.leases.is_saturated(
+                &t.account_id,
+                &lease_model,
+                concurrency.account_limit(t.subscription_tier.as_deref()),
+                model_limit,
+            )
+        };
+        let try_lease = |t: &ProxyToken| {
+            self.leases.try_acquire(
+                &t.account_id,
+                &lease_model,
+                concurrency.account_limit(t.subscription_tier.as_deref()),
+                model_limit,
+            )
+        };
 
         // ===== [FIX #820] 固定账号模式：优先使用指定账号 =====
         let preferred_id = self.preferred_account_id.read().await.clone();
@@ -557,8 +773,13 @@ impl TokenManager {
 
                 let is_rate_limited = self.is_rate_limited_by_account_id(&preferred_token.account_id);
                 let is_quota_protected = quota_protection_enabled && preferred_token.protected_models.contains(&normalized_target);
+                let preferred_lease = if !is_rate_limited && !is_quota_protected {
+                    try_lease(preferred_token)
+                } else {
+                    None
+                };
 
-                if !is_rate_limited && !is_quota_protected {
+                if let Some(lease) = preferred_lease {
                     tracing::info!(
                         "🔒 [FIX #820] Using preferred account: {} (fixed mode)",
                         preferred_token.email
@@ -607,12 +828,14 @@ impl TokenManager {
                         }
                     };
 
-                    return Ok((token.access_token, project_id, token.email));
+                    return Ok((token.access_token, project_id, token.email, lease));
                 } else {
                     if is_rate_limited {
                         tracing::warn!("🔒 [FIX #820] Preferred account {} is rate-limited, falling back to round-robin", preferred_token.email);
-                    } else {
+                    } else if is_quota_protected {
                         tracing::warn!("🔒 [FIX #820] Preferred account {} is quota-protected for {}, falling back to round-robin", preferred_token.email, target_model);
+                    } else {
+                        tracing::warn!("🔒 [FIX #820] Preferred account {} is at max concurrency, falling back to round-robin", preferred_token.email);
                     }
                 }
             } else {
@@ -633,12 +856,15 @@ impl TokenManager {
         let mut attempted: HashSet<String> = HashSet::new();
         let mut last_error: Option<String> = None;
         let mut need_update_last_used: Option<(String, std::time::Instant)> = None;
+        let mut saturated_skipped = false;
 
         for attempt in 0..total {
             let rotate = force_rotate || attempt > 0;
 
             // ===== 【核心】粘性会话与智能调度逻辑 =====
             let mut target_token: Option<ProxyToken> = None;
+            // CacheFirst 排队等待中已取得的租约
+            let mut queued_lease: Option<TokenLease> = None;
             
             // 归一化目标模型名为标准 ID，用于配额保护检查
             let normalized_target = crate::proxy::common::model_mapping::normalize_to_standard_id(target_model)
@@ -665,8 +891,29 @@ impl TokenManager {
                             self.session_accounts.remove(sid);
                         } else if !attempted.contains(&bound_id) && !(quota_protection_enabled && bound_token.protected_models.contains(&normalized_target)) {
                             // 3. 账号可用且未被标记为尝试失败，优先复用
-                            tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", bound_token.email, sid);
-                            target_token = Some(bound_token.clone());
+                            if !is_saturated(bound_token) {
+                                tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", bound_token.email, sid);
+                                target_token = Some(bound_token.clone());
+                            } else if scheduling.mode == SchedulingMode::CacheFirst {
+                                // 【新增】缓存优先：绑定账号并发已满时有限排队，尽量保住 Prompt Cache
+                                tracing::debug!("Sticky Session: Bound account {} is at max concurrency, queueing up to {}ms", bound_token.email, concurrency.queue_wait_ms);
+                                queued_lease = self.leases.acquire_with_wait(
+                                    &bound_token.account_id,
+                                    &lease_model,
+                                    concurrency.account_limit(bound_token.subscription_tier.as_deref()),
+                                    model_limit,
+                                    std::time::Duration::from_millis(concurrency.queue_wait_ms),
+                                ).await;
+                                if queued_lease.is_some() {
+                                    target_token = Some(bound_token.clone());
+                                } else {
+                                    tracing::debug!("Sticky Session: Queue wait for {} timed out, switching account", bound_token.email);
+                                    saturated_skipped = true;
+                                }
+                            } else {
+                                tracing::debug!("Sticky Session: Bound account {} is at max concurrency, switching", bound_token.email);
+                                saturated_skipped = true;
+                            }
                         } else if quota_protection_enabled && bound_token.protected_models.contains(&normalized_target) {
                             tracing::debug!("Sticky Session: Bound account {} is quota-protected for model {} [{}], unbinding and switching.", bound_token.email, normalized_target, target_model);
                             self.session_accounts.remove(sid);
@@ -689,8 +936,13 @@ impl TokenManager {
                         if let Some(found) = tokens_snapshot.iter().find(|t| &t.account_id == account_id) {
                             // 【修复】检查限流状态和配额保护，避免复用已被锁定的账号
                             if !self.is_rate_limited_by_account_id(&found.account_id) && !(quota_protection_enabled && found.protected_models.contains(&normalized_target)) {
-                                tracing::debug!("60s Window: Force reusing last account: {}", found.email);
-                                target_token = Some(found.clone());
+                                if is_saturated(found) {
+                                    tracing::debug!("60s Window: Last account {} is at max concurrency, skipping", found.email);
+                                    saturated_skipped = true;
+                                } else {
+                                    tracing::debug!("60s Window: Force reusing last account: {}", found.email);
+                                    target_token = Some(found.clone());
+                                }
                             } else {
                                 if self.is_rate_limited_by_account_id(&found.account_id) {
                                     tracing::debug!("60s Window: Last account {} is rate-limited, skipping", found.email);
@@ -723,6 +975,12 @@ impl TokenManager {
                             continue;
                         }
 
+                        // 【新增】跳过并发已满的账号
+                        if is_saturated(candidate) {
+                            saturated_skipped = true;
+                            continue;
+                        }
+
                         target_token = Some(candidate.clone());
                         // 【优化】标记需要更新，稍后统一写回
                         need_update_last_used = Some((candidate.account_id.clone(), std::time::Instant::now()));
@@ -762,6 +1020,13 @@ impl TokenManager {
                         continue;
                     }
 
+                    // 【新增】跳过并发已满的账号
+                    if is_saturated(candidate) {
+                        tracing::info!("  🚦 {} - SKIP: at max concurrency", candidate.email);
+                        saturated_skipped = true;
+                        continue;
+                    }
+
                     tracing::debug!("  [{}] {} - SELECTED", idx, candidate.email);
                     target_token = Some(candidate.clone());
                     
@@ -775,6 +1040,11 @@ impl TokenManager {
             let mut token = match target_token {
                 Some(t) => t,
                 None => {
+                    // 【新增】账号被并发上限挡住时不做乐观重置 (限流状态并未失准)
+                    if saturated_skipped {
+                        return Err("All available accounts are at max concurrency. Please retry shortly.".to_string());
+                    }
+
                     // 乐观重置策略: 双层防护机制
                     // 当所有账号都无法选择时,可能是时序竞争导致的状态不同步
                     
@@ -837,6 +1107,18 @@ impl TokenManager {
             };
 
         
+            // 【新增】占用并发名额 (选择与占用之间可能被并发请求抢先，失败则换号)
+            let lease = match queued_lease.take().or_else(|| try_lease(&token)) {
+                Some(lease) => lease,
+                None => {
+                    tracing::debug!("账号 {} 并发名额已被占满，尝试下一个账号", token.email);
+                    saturated_skipped = true;
+                    last_error = Some("All available accounts are at max concurrency. Please retry shortly.".to_string());
+                    attempted.insert(token.account_id.clone());
+                    continue;
+                }
+            };
+
             // 3. 检查 token 是否过期（提前5分钟刷新）
             let now = chrono::Utc::now().timestamp();
             if now >= token.timestamp - 300 {
@@ -933,7 +1215,7 @@ impl TokenManager {
                 }
             }
 
-            return Ok((token.access_token, project_id, token.email));
+            return Ok((token.access_token, project_id, token.email, lease));
         }
 
         Err(last_error.unwrap_or_else(|| "All accounts failed".to_string()))
@@ -1016,6 +1298,40 @@ impl TokenManager {
         self.tokens.len()
     }
 
+    /// 账号池就绪状态快照 (供 /readyz 使用)
+    ///
+    /// `models` 中的模型名会先归一化为标准模型 ID；配额保护开启时，
+    /// 已被该模型保护的账号不计入可用数
+    pub fn pool_readiness(&self, models: &[String], quota_protection_enabled: bool) -> PoolReadiness {
+        let mut available_per_model = std::collections::BTreeMap::new();
+        let mut rate_limited = 0;
+
+        for model in models {
+            let standard_id = crate::proxy::common::model_mapping::normalize_to_standard_id(model)
+                .unwrap_or_else(|| model.clone());
+            available_per_model.entry(standard_id).or_insert(0usize);
+        }
+
+        for entry in self.tokens.iter() {
+            let token = entry.value();
+            if self.is_rate_limited_by_account_id(&token.account_id) {
+                rate_limited += 1;
+                continue;
+            }
+            for (model, count) in available_per_model.iter_mut() {
+                if !(quota_protection_enabled && token.protected_models.contains(model)) {
+                    *count += 1;
+                }
+            }
+        }
+
+        PoolReadiness {
+            pool_size: self.tokens.len(),
+            rate_limited,
+            available_per_model,
+        }
+    }
+
     /// 通过 email 获取指定账号的 Token（用于预热等需要指定账号的场景）
     /// 此方法会自动刷新过期的 token
     pub async fn get_token_by_email(&self, email: &str) -> Result<(String, String, String), String> {
@@ -1084,6 +1400,50 @@ impl TokenManager {
         }
     }
     
+    /// 固定账号取 Token：与常规调度相同地检查预算拦截、限流、配额保护与并发上限，
+    /// 只是不回退到其他账号。限流 / 并发已满返回账号池耗尽类错误，可进入排队等待
+    async fn get_pinned_token(&self, email: &str, target_model: &str) -> Result<(String, String, String, TokenLease), String> {
+        let token = self
+            .tokens
+            .iter()
+            .find(|e| e.value().email == email)
+            .map(|e| e.value().clone())
+            .ok_or_else(|| format!("未找到账号: {}", email))?;
+
+        if let Some(reason) = crate::proxy::budget::blocked_accounts().get(&token.email) {
+            return Err(format!("Pinned account {} is paused by budget rules: {}", email, reason));
+        }
+        if self.is_rate_limited_by_account_id(&token.account_id) {
+            let wait = self.rate_limit_tracker.get_reset_seconds(&token.account_id).unwrap_or(0);
+            return Err(format!(
+                "All accounts are currently limited (pinned account {}). Please wait {}s.",
+                email, wait
+            ));
+        }
+
+        let lease_model = crate::proxy::common::model_mapping::normalize_to_standard_id(target_model)
+            .unwrap_or_else(|| target_model.to_string());
+        let quota_protection_enabled = self.quota_protection.read().await.enabled;
+        if quota_protection_enabled && token.protected_models.contains(&lease_model) {
+            return Err(format!("Pinned account {} is quota-protected for {}", email, lease_model));
+        }
+
+        let concurrency = self.sticky_config.read().await.concurrency.clone();
+        let lease = self
+            .leases
+            .try_acquire(
+                &token.account_id,
+                &lease_model,
+                concurrency.account_limit(token.subscription_tier.as_deref()),
+                concurrency.model_limit(&lease_model),
+            )
+            .ok_or_else(|| {
+                format!("All available accounts are at max concurrency (pinned account {}). Please retry shortly.", email)
+            })?;
+        let (access_token, project_id, email) = self.get_token_by_email(email).await?;
+        Ok((access_token, project_id, email, lease))
+    }
+
     // ===== 限流管理方法 =====
     
     /// 标记账号限流(从外部调用,通常在 handler 中)
@@ -1168,9 +1528,7 @@ impl TokenManager {
     /// ```
     pub async fn has_available_account(&self, _quota_group: &str, target_model: &str) -> bool {
         // 检查配额保护是否启用
-        let quota_protection_enabled = crate::modules::config::load_app_config()
-            .map(|cfg| cfg.quota_protection.enabled)
-            .unwrap_or(false);
+        let quota_protection_enabled = self.quota_protection.read().await.enabled;
         
         // 遍历所有账号,检查是否有可用的
         for entry in self.tokens.iter() {
@@ -1430,6 +1788,18 @@ impl TokenManager {
         tracing::debug!("Scheduling configuration updated: {:?}", *config);
     }
 
+    /// 获取当前配额保护配置
+    pub async fn get_quota_protection(&self) -> crate::models::QuotaProtectionConfig {
+        self.quota_protection.read().await.clone()
+    }
+
+    /// 更新配额保护配置
+    pub async fn update_quota_protection(&self, new_config: crate::models::QuotaProtectionConfig) {
+        let mut config = self.quota_protection.write().await;
+        *config = new_config;
+        tracing::debug!("Quota protection configuration updated: {:?}", *config);
+    }
+
     /// 清除特定会话的粘性映射
     #[allow(dead_code)]
     pub fn clear_session_binding(&self, session_id: &str) {
@@ -1461,6 +1831,9 @@ impl TokenManager {
     }
 }
 
+/// 排队等待时的兜底轮询间隔
+const QUEUE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
 fn truncate_reason(reason: &str, max_len: usize) -> String {
     if reason.chars().count() <= max_len {
         return reason.to_string();
@@ -1469,3 +1842,128 @@ fn truncate_reason(reason: &str, max_len: usize) -> String {
     s.push('…');
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_token(id: &str, protected: &[&str]) -> ProxyToken {
+        ProxyToken {
+            account_id: id.to_string(),
+            access_token: "at".to_string(),
+            refresh_token: "rt".to_string(),
+            expires_in: 3600,
+            timestamp: chrono::Utc::now().timestamp() + 3600,
+            email: format!("{}@example.com", id),
+            account_path: PathBuf::from(format!("{}.json", id)),
+            project_id: Some("project".to_string()),
+            subscription_tier: None,
+            remaining_quota: None,
+            protected_models: protected.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_pool_readiness() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        manager.tokens.insert("a".to_string(), test_token("a", &["claude-sonnet-4-5"]));
+        manager.tokens.insert("b".to_string(), test_token("b", &[]));
+        manager.tokens.insert("c".to_string(), test_token("c", &[]));
+        manager.mark_rate_limited("c@example.com", 429, Some("60"), "");
+
+        let models = vec!["claude-sonnet-4-5-thinking".to_string(), "gemini-3-flash".to_string()];
+
+        let readiness = manager.pool_readiness(&models, true);
+        assert_eq!(readiness.pool_size, 3);
+        assert_eq!(readiness.rate_limited, 1);
+        // 模型名按标准 ID 归一化，受配额保护的账号不计入
+        assert_eq!(readiness.available_per_model.get("claude-sonnet-4-5"), Some(&1));
+        assert_eq!(readiness.available_per_model.get("gemini-3-flash"), Some(&2));
+
+        let readiness = manager.pool_readiness(&models, false);
+        assert_eq!(readiness.available_per_model.get("claude-sonnet-4-5"), Some(&2));
+    }
+
+    #[tokio::test]
+    async fn test_pinned_token_respects_limits() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        manager.tokens.insert("a".to_string(), test_token("a", &[]));
+        manager.tokens.insert("b".to_string(), test_token("b", &[]));
+        {
+            let mut config = manager.sticky_config.write().await;
+            config.concurrency.enabled = true;
+            config.concurrency.default_max_per_account = 1;
+        }
+
+        // 限流中的固定账号不会被直接使用
+        manager.mark_rate_limited("a@example.com", 429, Some("60"), "");
+        let err = manager.get_pinned_token("a@example.com", "gemini-3-flash").await.unwrap_err();
+        assert!(TokenManager::is_pool_exhausted_error(&err), "{}", err);
+
+        // 并发已满时同样返回账号池耗尽类错误
+        let (_, _, email, _lease) = manager.get_pinned_token("b@example.com", "gemini-3-flash").await.unwrap();
+        assert_eq!(email, "b@example.com");
+        let err = manager.get_pinned_token("b@example.com", "gemini-3-flash").await.unwrap_err();
+        assert!(TokenManager::is_pool_exhausted_error(&err), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_get_token_respects_concurrency_limit() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        manager.tokens.insert("a".to_string(), test_token("a", &[]));
+        manager.tokens.insert("b".to_string(), test_token("b", &[]));
+        {
+            let mut config = manager.sticky_config.write().await;
+            config.concurrency.enabled = true;
+            config.concurrency.default_max_per_account = 1;
+            config.queue.enabled = false;
+        }
+
+        let (_, _, first, first_lease) = manager.get_token("gemini", false, None, "gemini-3-flash").await.unwrap();
+        let (_, _, second, _second_lease) = manager.get_token("gemini", false, None, "gemini-3-flash").await.unwrap();
+        assert_ne!(first, second, "account over its concurrency limit was reused");
+
+        // 所有账号都已满载: 立即返回账号池耗尽类错误，而不是等到超时
+        let err = manager.get_token("gemini", false, None, "gemini-3-flash").await.unwrap_err();
+        assert!(TokenManager::is_pool_exhausted_error(&err), "{}", err);
+
+        // 释放名额后该账号重新可用
+        drop(first_lease);
+        let (_, _, email, _lease) = manager.get_token("gemini", false, None, "gemini-3-flash").await.unwrap();
+        assert_eq!(email, first);
+    }
+
+    #[tokio::test]
+    async fn test_new_requests_queue_behind_waiters() {
+        let manager = Arc::new(TokenManager::new(std::env::temp_dir()));
+        manager.tokens.insert("a".to_string(), test_token("a", &[]));
+        {
+            let mut config = manager.sticky_config.write().await;
+            config.concurrency.enabled = true;
+            config.concurrency.default_max_per_account = 1;
+            config.queue.enabled = true;
+            config.queue.max_wait_seconds = 5;
+        }
+
+        let (_, _, _, held) = manager.get_token("gemini", false, None, "gemini-3-flash").await.unwrap();
+        let waiter = {
+            let manager = manager.clone();
+            tokio::spawn(async move { manager.get_token("gemini", false, None, "gemini-3-flash").await.map(|t| t.3) })
+        };
+        while manager.queue_depth() == 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        // 释放名额后新请求不能抢在排队者之前拿到账号
+        drop(held);
+        let arrival = tokio::time::timeout(
+            std::time::Duration::from_millis(300),
+            manager.get_token("gemini", false, None, "gemini-3-flash"),
+        )
+        .await;
+        assert!(arrival.is_err(), "new request jumped the queue");
+        let lease = waiter.await.unwrap().unwrap();
+        drop(lease);
+        assert_eq!(manager.queue_depth(), 0);
+    }
+}
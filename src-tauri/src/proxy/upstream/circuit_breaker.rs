@@ -0,0 +1,361 @@
+// 上游端点熔断器 (Circuit Breaker)
+// 按端点统计最近请求的错误率与慢调用率:
+// Closed (正常) → Open (跳过该端点) → HalfOpen (放行单个探测请求) → Closed / Open
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 熔断器配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// 是否启用熔断
+    pub enabled: bool,
+    /// 滑动窗口大小 (最近 N 次调用)
+    pub window_size: usize,
+    /// 窗口内至少有多少次调用才开始评估
+    pub min_calls: usize,
+    /// 错误率阈值 (0.0 - 1.0)，达到即熔断
+    pub failure_rate_threshold: f64,
+    /// 首包耗时超过该值 (毫秒) 视为慢调用
+    pub slow_call_ms: u64,
+    /// 慢调用率阈值 (0.0 - 1.0)，达到即熔断
+    pub slow_call_rate_threshold: f64,
+    /// 熔断持续时间 (秒)，到期后放行探测请求
+    pub open_duration_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_size: 20,
+            min_calls: 10,
+            failure_rate_threshold: 0.5,
+            slow_call_ms: 60_000,
+            slow_call_rate_threshold: 0.8,
+            open_duration_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// 端点健康状态快照 (用于 /healthz 输出)
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointHealth {
+    pub endpoint: String,
+    pub state: CircuitState,
+    pub calls: usize,
+    pub failure_rate: f64,
+    pub slow_call_rate: f64,
+    /// 距下一次探测的剩余秒数 (仅 Open 状态)
+    pub retry_in_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CallRecord {
+    failed: bool,
+    slow: bool,
+}
+
+struct BreakerInner {
+    state: CircuitState,
+    window: VecDeque<CallRecord>,
+    opened_at: Option<Instant>,
+    /// 探测请求的发出时间 (HalfOpen 下同一时间只放行一个探测)
+    probe_started: Option<Instant>,
+}
+
+/// 单个端点的熔断器
+pub struct CircuitBreaker {
+    endpoint: String,
+    config: CircuitBreakerConfig,
+    inner: Mutex<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(endpoint: &str, config: CircuitBreakerConfig) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            config,
+            inner: Mutex::new(BreakerInner {
+                state: CircuitState::Closed,
+                window: VecDeque::new(),
+                opened_at: None,
+                probe_started: None,
+            }),
+        }
+    }
+
+    fn open_duration(&self) -> Duration {
+        Duration::from_secs(self.config.open_duration_secs)
+    }
+
+    #[cfg(test)]
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// 申请向该端点发送请求，熔断中返回 None
+    ///
+    /// Open 状态到期后转为 HalfOpen 并放行一个探测请求；
+    /// 探测请求长时间无结果 (超过熔断时长) 时允许再发一个，避免卡死在 HalfOpen。
+    pub fn try_acquire(&self) -> Option<CallPermit<'_>> {
+        if !self.config.enabled {
+            return Some(CallPermit::new(self, false));
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        match inner.state {
+            CircuitState::Closed => Some(CallPermit::new(self, false)),
+            CircuitState::Open => {
+                let expired = inner
+                    .opened_at
+                    .is_none_or(|t| now.duration_since(t) >= self.open_duration());
+                if !expired {
+                    return None;
+                }
+                tracing::info!("[Circuit-Breaker] {} half-open, sending probe request", self.endpoint);
+                inner.state = CircuitState::HalfOpen;
+                inner.probe_started = Some(now);
+                Some(CallPermit::new(self, true))
+            }
+            CircuitState::HalfOpen => {
+                let probe_stale = inner
+                    .probe_started
+                    .is_none_or(|t| now.duration_since(t) >= self.open_duration());
+                if !probe_stale {
+                    return None;
+                }
+                inner.probe_started = Some(now);
+                Some(CallPermit::new(self, true))
+            }
+        }
+    }
+
+    /// 当前是否会放行请求 (只读判断，不改变状态)
+    pub fn is_available(&self) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        let inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => inner.opened_at.is_none_or(|t| t.elapsed() >= self.open_duration()),
+            CircuitState::HalfOpen => inner.probe_started.is_none_or(|t| t.elapsed() >= self.open_duration()),
+        }
+    }
+
+    /// 所有端点都熔断时的兜底放行 (不占用探测名额)
+    pub fn force_acquire(&self) -> CallPermit<'_> {
+        CallPermit::new(self, false)
+    }
+
+    fn record(&self, probe: bool, failed: bool, latency: Duration) {
+        if !self.config.enabled {
+            return;
+        }
+        let slow = latency >= Duration::from_millis(self.config.slow_call_ms);
+        let mut inner = self.inner.lock().unwrap();
+
+        match inner.state {
+            CircuitState::HalfOpen if probe => {
+                inner.probe_started = None;
+                if failed || slow {
+                    tracing::warn!("[Circuit-Breaker] Probe to {} failed, re-opening circuit", self.endpoint);
+                    inner.state = CircuitState::Open;
+                    inner.opened_at = Some(Instant::now());
+                } else {
+                    tracing::info!("[Circuit-Breaker] Probe to {} succeeded, circuit closed", self.endpoint);
+                    inner.state = CircuitState::Closed;
+                    inner.opened_at = None;
+                    inner.window.clear();
+                }
+            }
+            CircuitState::Closed => {
+                inner.window.push_back(CallRecord { failed, slow });
+                while inner.window.len() > self.config.window_size.max(1) {
+                    inner.window.pop_front();
+                }
+                let (failure_rate, slow_rate) = rates(&inner.window);
+                if inner.window.len() >= self.config.min_calls.max(1)
+                    && (failure_rate >= self.config.failure_rate_threshold
+                        || slow_rate >= self.config.slow_call_rate_threshold)
+                {
+                    tracing::warn!(
+                        "[Circuit-Breaker] Opening circuit for {} (failure rate {:.0}%, slow rate {:.0}%) for {}s",
+                        self.endpoint,
+                        failure_rate * 100.0,
+                        slow_rate * 100.0,
+                        self.config.open_duration_secs
+                    );
+                    inner.state = CircuitState::Open;
+                    inner.opened_at = Some(Instant::now());
+                }
+            }
+            // 熔断期间的兜底请求或过期的探测结果不改变状态
+            _ => {}
+        }
+    }
+
+    /// 探测请求被取消 (如客户端断开)，释放探测名额
+    fn abandon_probe(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == CircuitState::HalfOpen {
+            inner.probe_started = None;
+        }
+    }
+
+    pub fn health(&self) -> EndpointHealth {
+        let inner = self.inner.lock().unwrap();
+        let (failure_rate, slow_call_rate) = rates(&inner.window);
+        let retry_in_secs = match (inner.state, inner.opened_at) {
+            (CircuitState::Open, Some(t)) => {
+                Some(self.open_duration().saturating_sub(t.elapsed()).as_secs())
+            }
+            _ => None,
+        };
+        EndpointHealth {
+            endpoint: self.endpoint.clone(),
+            state: inner.state,
+            calls: inner.window.len(),
+            failure_rate,
+            slow_call_rate,
+            retry_in_secs,
+        }
+    }
+}
+
+fn rates(window: &VecDeque<CallRecord>) -> (f64, f64) {
+    if window.is_empty() {
+        return (0.0, 0.0);
+    }
+    let total = window.len() as f64;
+    let failed = window.iter().filter(|r| r.failed).count() as f64;
+    let slow = window.iter().filter(|r| r.slow).count() as f64;
+    (failed / total, slow / total)
+}
+
+/// 调用许可，请求结束后通过 `record_*` 上报结果
+///
+/// 未上报即被丢弃 (请求被取消) 时，若为探测请求则释放探测名额
+pub struct CallPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    started: Instant,
+    recorded: bool,
+}
+
+impl<'a> CallPermit<'a> {
+    fn new(breaker: &'a CircuitBreaker, probe: bool) -> Self {
+        Self {
+            breaker,
+            probe,
+            started: Instant::now(),
+            recorded: false,
+        }
+    }
+
+    pub fn record_success(mut self) {
+        self.recorded = true;
+        self.breaker.record(self.probe, false, self.started.elapsed());
+    }
+
+    pub fn record_failure(mut self) {
+        self.recorded = true;
+        self.breaker.record(self.probe, true, self.started.elapsed());
+    }
+}
+
+impl Drop for CallPermit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.recorded {
+            self.breaker.abandon_probe();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            enabled: true,
+            window_size: 4,
+            min_calls: 4,
+            failure_rate_threshold: 0.5,
+            slow_call_ms: 60_000,
+            slow_call_rate_threshold: 1.0,
+            open_duration_secs: 0,
+        }
+    }
+
+    #[test]
+    fn test_opens_on_failure_rate() {
+        let breaker = CircuitBreaker::new("prod", CircuitBreakerConfig { open_duration_secs: 60, ..test_config() });
+        breaker.try_acquire().unwrap().record_success();
+        breaker.try_acquire().unwrap().record_success();
+        breaker.try_acquire().unwrap().record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed); // 样本不足
+        breaker.try_acquire().unwrap().record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.try_acquire().is_none());
+        assert!(breaker.health().retry_in_secs.is_some());
+    }
+
+    #[test]
+    fn test_probe_success_closes_circuit() {
+        let breaker = CircuitBreaker::new("prod", test_config());
+        for _ in 0..4 {
+            breaker.try_acquire().unwrap().record_failure();
+        }
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // open_duration = 0: 立即进入 HalfOpen 并放行探测
+        let probe = breaker.try_acquire().unwrap();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        probe.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.health().calls, 0);
+    }
+
+    #[test]
+    fn test_probe_failure_reopens_and_cancelled_probe_is_released() {
+        let breaker = CircuitBreaker::new("prod", CircuitBreakerConfig { open_duration_secs: 60, ..test_config() });
+        for _ in 0..4 {
+            breaker.try_acquire().unwrap().record_failure();
+        }
+        // 模拟熔断时长已过
+        breaker.inner.lock().unwrap().opened_at = Some(Instant::now() - Duration::from_secs(61));
+
+        let probe = breaker.try_acquire().unwrap();
+        assert!(breaker.try_acquire().is_none()); // 探测进行中，其他请求仍跳过
+        drop(probe); // 取消的探测释放名额
+        let probe = breaker.try_acquire().unwrap();
+        probe.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_slow_calls_open_circuit() {
+        let breaker = CircuitBreaker::new(
+            "prod",
+            CircuitBreakerConfig { slow_call_ms: 0, slow_call_rate_threshold: 0.5, open_duration_secs: 60, ..test_config() },
+        );
+        for _ in 0..4 {
+            breaker.try_acquire().unwrap().record_success();
+        }
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+}
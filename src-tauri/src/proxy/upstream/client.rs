@@ -3,7 +3,11 @@
 
 use reqwest::{header, Client, Response, StatusCode};
 use serde_json::Value;
+use std::sync::atomic::{AtomicI64, Ordering};
 use tokio::time::Duration;
+use tracing::Instrument;
+
+use super::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, EndpointHealth};
 
 // Cloud Code v1internal endpoints (fallback order: prod → daily)
 // 优先使用稳定的 prod 端点，避免影响缓存命中率
@@ -16,10 +20,19 @@ const V1_INTERNAL_BASE_URL_FALLBACKS: [&str; 2] = [
 
 pub struct UpstreamClient {
     http_client: Client,
+    /// v1internal 端点 (按优先级排列)
+    endpoints: Vec<String>,
+    /// 与 endpoints 一一对应的熔断器
+    breakers: Vec<CircuitBreaker>,
+    /// 最近一次成功调用上游的时间 (Unix 毫秒，0 表示尚无)
+    last_success_ms: AtomicI64,
 }
 
 impl UpstreamClient {
-    pub fn new(proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>) -> Self {
+    pub fn new(
+        proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>,
+        breaker_config: CircuitBreakerConfig,
+    ) -> Self {
         let mut builder = Client::builder()
             // Connection settings (优化连接复用，减少建立开销)
             .connect_timeout(Duration::from_secs(20))
@@ -40,7 +53,34 @@ impl UpstreamClient {
 
         let http_client = builder.build().expect("Failed to create HTTP client");
 
-        Self { http_client }
+        let endpoints = V1_INTERNAL_BASE_URL_FALLBACKS.iter().map(|s| s.to_string()).collect();
+        Self::with_endpoints(http_client, endpoints, breaker_config)
+    }
+
+    fn with_endpoints(http_client: Client, endpoints: Vec<String>, breaker_config: CircuitBreakerConfig) -> Self {
+        let breakers = endpoints
+            .iter()
+            .map(|e| CircuitBreaker::new(e, breaker_config.clone()))
+            .collect();
+        Self {
+            http_client,
+            endpoints,
+            breakers,
+            last_success_ms: AtomicI64::new(0),
+        }
+    }
+
+    /// 最近一次成功调用上游的时间 (Unix 毫秒)
+    pub fn last_success_at(&self) -> Option<i64> {
+        match self.last_success_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ts => Some(ts),
+        }
+    }
+
+    /// 各上游端点的熔断状态
+    pub fn endpoint_health(&self) -> Vec<EndpointHealth> {
+        self.breakers.iter().map(|b| b.health()).collect()
     }
 
     /// 构建 v1internal URL
@@ -68,6 +108,15 @@ impl UpstreamClient {
             || status.is_server_error()
     }
 
+    /// 判断响应是否计入端点熔断的错误率
+    ///
+    /// 429 通常是账号级配额限制，端点本身仍然可用，不计为端点故障
+    fn is_endpoint_failure(status: StatusCode) -> bool {
+        status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::NOT_FOUND
+            || status.is_server_error()
+    }
+
     /// 调用 v1internal API（基础方法）
     /// 
     /// 发起基础网络请求，支持多端点自动 Fallback
@@ -116,31 +165,69 @@ impl UpstreamClient {
         }
 
         let mut last_err: Option<String> = None;
+        let mut attempted = false;
+
+        // 遍历所有端点，失败时自动切换 (熔断中的端点直接跳过)
+        for (idx, base_url) in self.endpoints.iter().enumerate() {
+            let breaker = &self.breakers[idx];
+            let permit = match breaker.try_acquire() {
+                Some(p) => p,
+                // 全部端点都熔断时仍兜底尝试最后一个，避免熔断器本身造成完全不可用
+                None if !attempted && idx + 1 == self.endpoints.len() => breaker.force_acquire(),
+                None => {
+                    tracing::debug!("Upstream endpoint {} circuit open, skipping", base_url);
+                    continue;
+                }
+            };
+            attempted = true;
 
-        // 遍历所有端点，失败时自动切换
-        for (idx, base_url) in V1_INTERNAL_BASE_URL_FALLBACKS.iter().enumerate() {
             let url = Self::build_url(base_url, method, query_string);
-            let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
-
+            let has_next = self.breakers[idx + 1..].iter().any(|b| b.is_available());
+
+            let span = tracing::info_span!(
+                "proxy.upstream_request",
+                otel.kind = "client",
+                endpoint = %base_url,
+                upstream.method = method,
+                http.response.status_code = tracing::field::Empty,
+                otel.status_code = tracing::field::Empty,
+            );
+            let mut request_headers = headers.clone();
+            span.in_scope(|| crate::proxy::telemetry::inject_current_context(&mut request_headers));
             let response = self
                 .http_client
                 .post(&url)
-                .headers(headers.clone())
+                .headers(request_headers)
                 .json(&body)
                 .send()
+                .instrument(span.clone())
                 .await;
+            match &response {
+                Ok(resp) => crate::proxy::telemetry::record_status(&span, resp.status().as_u16()),
+                Err(_) => {
+                    span.record("otel.status_code", "ERROR");
+                }
+            }
 
             match response {
                 Ok(resp) => {
                     let status = resp.status();
+                    if Self::is_endpoint_failure(status) {
+                        permit.record_failure();
+                    } else {
+                        permit.record_success();
+                    }
+
                     if status.is_success() {
+                        self.last_success_ms
+                            .store(chrono::Utc::now().timestamp_millis(), Ordering::Relaxed);
                         if idx > 0 {
                             tracing::info!(
                                 "✓ Upstream fallback succeeded | Endpoint: {} | Status: {} | Attempt: {}/{}",
                                 base_url,
                                 status,
                                 idx + 1,
-                                V1_INTERNAL_BASE_URL_FALLBACKS.len()
+                                self.endpoints.len()
                             );
                         } else {
                             tracing::debug!("✓ Upstream request succeeded | Endpoint: {} | Status: {}", base_url, status);
@@ -164,6 +251,7 @@ impl UpstreamClient {
                     return Ok(resp);
                 }
                 Err(e) => {
+                    permit.record_failure();
                     let msg = format!("HTTP request failed at {}: {}", base_url, e);
                     tracing::debug!("{}", msg);
                     last_err = Some(msg);
@@ -307,4 +395,117 @@ mod tests {
         );
     }
 
+    use crate::proxy::upstream::circuit_breaker::CircuitState;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct StubState {
+        prod_hits: AtomicUsize,
+        daily_hits: AtomicUsize,
+        prod_healthy: AtomicBool,
+        daily_healthy: AtomicBool,
+    }
+
+    /// 本地桩服务: /prod 与 /daily 两个"端点"，可切换健康状态
+    async fn spawn_stub(stub: Arc<StubState>) -> String {
+        let app = axum::Router::new().fallback(move |uri: axum::http::Uri| {
+            let stub = stub.clone();
+            async move {
+                let healthy = if uri.path().starts_with("/prod") {
+                    stub.prod_hits.fetch_add(1, Ordering::SeqCst);
+                    stub.prod_healthy.load(Ordering::SeqCst)
+                } else {
+                    stub.daily_hits.fetch_add(1, Ordering::SeqCst);
+                    stub.daily_healthy.load(Ordering::SeqCst)
+                };
+                if healthy {
+                    (axum::http::StatusCode::OK, "{}")
+                } else {
+                    (axum::http::StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+                }
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    fn stub_client(base: &str, open_duration_secs: u64) -> UpstreamClient {
+        let config = CircuitBreakerConfig {
+            enabled: true,
+            window_size: 2,
+            min_calls: 2,
+            failure_rate_threshold: 0.5,
+            slow_call_ms: 60_000,
+            slow_call_rate_threshold: 1.0,
+            open_duration_secs,
+        };
+        UpstreamClient::with_endpoints(
+            Client::new(),
+            vec![format!("{}/prod/v1internal", base), format!("{}/daily/v1internal", base)],
+            config,
+        )
+    }
+
+    async fn call(client: &UpstreamClient) -> StatusCode {
+        client
+            .call_v1_internal("generateContent", "token", serde_json::json!({}), None)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_skips_degraded_endpoint() {
+        let stub = Arc::new(StubState::default());
+        stub.daily_healthy.store(true, Ordering::SeqCst);
+        let client = stub_client(&spawn_stub(stub.clone()).await, 60);
+
+        // 前两次请求都先撞上 prod 的 503 再回落到 daily
+        assert_eq!(call(&client).await, StatusCode::OK);
+        assert_eq!(call(&client).await, StatusCode::OK);
+        assert_eq!(stub.prod_hits.load(Ordering::SeqCst), 2);
+        assert_eq!(client.endpoint_health()[0].state, CircuitState::Open);
+
+        // 熔断后直接走 daily，不再为 prod 付出失败请求
+        assert_eq!(call(&client).await, StatusCode::OK);
+        assert_eq!(stub.prod_hits.load(Ordering::SeqCst), 2);
+        assert_eq!(stub.daily_hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_probe_closes_recovered_endpoint() {
+        let stub = Arc::new(StubState::default());
+        stub.daily_healthy.store(true, Ordering::SeqCst);
+        let client = stub_client(&spawn_stub(stub.clone()).await, 0);
+
+        call(&client).await;
+        call(&client).await;
+        assert_eq!(client.endpoint_health()[0].state, CircuitState::Open);
+
+        // prod 恢复后，熔断到期的探测请求成功即关闭熔断
+        stub.prod_healthy.store(true, Ordering::SeqCst);
+        assert_eq!(call(&client).await, StatusCode::OK);
+        assert_eq!(stub.prod_hits.load(Ordering::SeqCst), 3);
+        assert_eq!(client.endpoint_health()[0].state, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_all_open_still_tries_last_endpoint() {
+        let stub = Arc::new(StubState::default());
+        let client = stub_client(&spawn_stub(stub.clone()).await, 60);
+
+        call(&client).await;
+        call(&client).await;
+        assert!(client.endpoint_health().iter().all(|h| h.state == CircuitState::Open));
+
+        // 全部熔断时仍返回最后一个端点的真实响应，供上层按状态码处理
+        assert_eq!(call(&client).await, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(stub.prod_hits.load(Ordering::SeqCst), 2);
+        assert_eq!(stub.daily_hits.load(Ordering::SeqCst), 3);
+    }
 }
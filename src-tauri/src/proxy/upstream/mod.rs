@@ -2,5 +2,6 @@
 // 对应上游通讯接口
 
 pub mod client;
+pub mod circuit_breaker;
 pub mod retry;
 pub mod models;
@@ -1,8 +1,17 @@
 // 429 重试策略
 // Duration 解析
 
+use axum::http::StatusCode;
 use regex::Regex;
 use once_cell::sync::Lazy;
+use serde_json::Value;
+use tokio::time::{sleep, Duration};
+use tracing::{debug, error, info};
+
+use crate::proxy::concurrency::TokenLease;
+use crate::proxy::monitor::UpstreamUsage;
+use crate::proxy::token_manager::TokenManager;
+use crate::proxy::upstream::client::UpstreamClient;
 
 static DURATION_RE: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"([\d.]+)\s*(ms|s|m|h)").unwrap()
@@ -36,8 +45,6 @@ pub fn parse_duration_ms(duration_str: &str) -> Option<u64> {
 
 /// 从 429 错误中提取 retry delay
 pub fn parse_retry_delay(error_text: &str) -> Option<u64> {
-    use serde_json::Value;
-
     let json: Value = serde_json::from_str(error_text).ok()?;
     let details = json.get("error")?.get("details")?.as_array()?;
 
@@ -66,6 +73,268 @@ pub fn parse_retry_delay(error_text: &str) -> Option<u64> {
     None
 }
 
+/// 单次请求最多尝试的账号数 (Claude / OpenAI / v1internal 共用)
+pub const MAX_RETRY_ATTEMPTS: usize = 3;
+
+// ===== 统一退避策略模块 =====
+
+/// 重试策略枚举
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetryStrategy {
+    /// 不重试，直接返回错误
+    NoRetry,
+    /// 固定延迟
+    FixedDelay(Duration),
+    /// 线性退避：base_ms * (attempt + 1)
+    LinearBackoff { base_ms: u64 },
+    /// 指数退避：base_ms * 2^attempt，上限 max_ms
+    ExponentialBackoff { base_ms: u64, max_ms: u64 },
+}
+
+/// 根据错误状态码和错误信息确定重试策略
+///
+/// `retried_without_thinking` 为 true (已剥离过签名，或调用方无法剥离签名) 时，签名类 400 不重试
+pub fn determine_retry_strategy(
+    status_code: u16,
+    error_text: &str,
+    retried_without_thinking: bool,
+) -> RetryStrategy {
+    match status_code {
+        // 400 错误：Thinking 签名失败
+        400 if !retried_without_thinking
+            && (error_text.contains("Invalid `signature`")
+                || error_text.contains("thinking.signature")
+                || error_text.contains("thinking.thinking")) =>
+        {
+            // 固定 200ms 延迟后重试
+            RetryStrategy::FixedDelay(Duration::from_millis(200))
+        }
+
+        // 429 限流错误
+        429 => {
+            // 优先使用服务端返回的 Retry-After
+            if let Some(delay_ms) = parse_retry_delay(error_text) {
+                let actual_delay = delay_ms.saturating_add(200).min(10_000);
+                RetryStrategy::FixedDelay(Duration::from_millis(actual_delay))
+            } else {
+                // 否则使用线性退避：1s, 2s, 3s
+                RetryStrategy::LinearBackoff { base_ms: 1000 }
+            }
+        }
+
+        // 503 服务不可用 / 529 服务器过载
+        503 | 529 => {
+            // 指数退避：1s, 2s, 4s, 8s
+            RetryStrategy::ExponentialBackoff {
+                base_ms: 1000,
+                max_ms: 8000,
+            }
+        }
+
+        // 500 服务器内部错误
+        500 => {
+            // 线性退避：500ms, 1s, 1.5s
+            RetryStrategy::LinearBackoff { base_ms: 500 }
+        }
+
+        // 401/403 认证/权限错误：可重试（轮换账号）
+        401 | 403 => RetryStrategy::FixedDelay(Duration::from_millis(100)),
+
+        // 其他错误：不重试
+        _ => RetryStrategy::NoRetry,
+    }
+}
+
+/// 执行退避策略并返回是否应该继续重试
+#[tracing::instrument(name = "proxy.retry_backoff", skip(strategy, trace_id))]
+pub async fn apply_retry_strategy(
+    strategy: RetryStrategy,
+    attempt: usize,
+    status_code: u16,
+    trace_id: &str,
+) -> bool {
+    match strategy {
+        RetryStrategy::NoRetry => {
+            debug!("[{}] Non-retryable error {}, stopping", trace_id, status_code);
+            false
+        }
+
+        RetryStrategy::FixedDelay(duration) => {
+            let base_ms = duration.as_millis() as u64;
+            info!(
+                "[{}] ⏱️  Retry with fixed delay: status={}, attempt={}/{}, base={}ms",
+                trace_id,
+                status_code,
+                attempt + 1,
+                MAX_RETRY_ATTEMPTS,
+                base_ms
+            );
+            sleep(duration).await;
+            true
+        }
+
+        RetryStrategy::LinearBackoff { base_ms } => {
+            let calculated_ms = base_ms * (attempt as u64 + 1);
+            info!(
+                "[{}] ⏱️  Retry with linear backoff: status={}, attempt={}/{}, base={}ms",
+                trace_id,
+                status_code,
+                attempt + 1,
+                MAX_RETRY_ATTEMPTS,
+                calculated_ms
+            );
+            sleep(Duration::from_millis(calculated_ms)).await;
+            true
+        }
+
+        RetryStrategy::ExponentialBackoff { base_ms, max_ms } => {
+            let calculated_ms = (base_ms * 2_u64.pow(attempt as u32)).min(max_ms);
+            info!(
+                "[{}] ⏱️  Retry with exponential backoff: status={}, attempt={}/{}, base={}ms",
+                trace_id,
+                status_code,
+                attempt + 1,
+                MAX_RETRY_ATTEMPTS,
+                calculated_ms
+            );
+            sleep(Duration::from_millis(calculated_ms)).await;
+            true
+        }
+    }
+}
+
+/// 判断是否应该轮换账号
+pub fn should_rotate_account(status_code: u16) -> bool {
+    match status_code {
+        // 这些错误是账号级别的，需要轮换
+        429 | 401 | 403 | 500 => true,
+        // 这些错误是服务端级别的，轮换账号无意义
+        400 | 503 | 529 => false,
+        // 其他错误默认不轮换
+        _ => false,
+    }
+}
+
+// ===== 退避策略模块结束 =====
+
+/// 成功的 v1internal 调用
+pub struct UpstreamSuccess {
+    /// 上游响应 (已解包 v1internal 的 `response` 字段)
+    pub body: Value,
+    /// 实际使用的账号
+    pub email: String,
+}
+
+/// 已返回成功状态码、尚未读取响应体的上游连接 (流式场景需持有租约直至读完)
+pub struct UpstreamResponse {
+    pub response: reqwest::Response,
+    pub email: String,
+    pub lease: TokenLease,
+}
+
+impl UpstreamResponse {
+    /// 读取非流式响应: 解包 v1internal 的 `response` 字段并上报上游用量
+    pub async fn into_json(self) -> Result<UpstreamSuccess, (StatusCode, String)> {
+        let result: Value = self
+            .response
+            .json()
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+        let body = match result {
+            Value::Object(mut obj) if obj.contains_key("response") => obj.remove("response").unwrap_or_default(),
+            other => other,
+        };
+        if let Some(usage) = body.get("usageMetadata") {
+            crate::proxy::monitor::add_upstream_usage(UpstreamUsage::from_gemini(usage));
+        }
+        Ok(UpstreamSuccess { body, email: self.email })
+    }
+}
+
+/// v1internal 调用的通用重试流程: 取号 (重试时强制轮换)、模型级限流标记、
+/// 与 Claude / OpenAI handler 相同的退避策略。成功时返回未读取的响应，可用于流式转发；
+/// 非流式调用方使用 [`UpstreamResponse::into_json`] 读取。
+/// `build_body` 接收当前账号的 project_id，返回完整的 v1internal 请求体
+pub async fn send_v1_internal_with_retry<F>(
+    token_manager: &TokenManager,
+    upstream: &UpstreamClient,
+    quota_group: &str,
+    model: &str,
+    method: &str,
+    query_string: Option<&str>,
+    build_body: F,
+) -> Result<UpstreamResponse, (StatusCode, String)>
+where
+    F: Fn(&str) -> Value,
+{
+    let max_attempts = MAX_RETRY_ATTEMPTS.min(token_manager.len()).max(1);
+    let mut last_error = String::new();
+    let mut last_was_transport = false;
+
+    for attempt in 0..max_attempts {
+        let (access_token, project_id, email, lease) = token_manager
+            .get_token(quota_group, attempt > 0, None, model)
+            .await
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)))?;
+
+        let response = match upstream
+            .call_v1_internal(method, &access_token, build_body(&project_id), query_string)
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                debug!("Upstream request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                last_error = e;
+                last_was_transport = true;
+                continue;
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            token_manager.mark_account_success(&email);
+            return Ok(UpstreamResponse { response, email, lease });
+        }
+
+        let status_code = status.as_u16();
+        let retry_after = response
+            .headers()
+            .get("Retry-After")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string());
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| format!("HTTP {}", status_code));
+        last_error = format!("HTTP {}: {}", status_code, error_text);
+        last_was_transport = false;
+
+        if matches!(status_code, 429 | 500 | 503 | 529) {
+            token_manager
+                .mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, Some(model))
+                .await;
+        }
+
+        // 请求体原样重发，无法剥离 thinking 签名，签名错误不重试
+        let strategy = determine_retry_strategy(status_code, &error_text, true);
+        if !apply_retry_strategy(strategy, attempt, status_code, method).await {
+            error!("Upstream non-retryable error {} on account {}: {}", status_code, email, error_text);
+            return Err((status, error_text));
+        }
+    }
+
+    if last_was_transport {
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            format!("Upstream request failed: {}", last_error),
+        ));
+    }
+    Err((
+        StatusCode::TOO_MANY_REQUESTS,
+        format!("All accounts exhausted. Last error: {}", last_error),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,4 +360,43 @@ mod tests {
 
         assert_eq!(parse_retry_delay(error_json), Some(1204));
     }
+
+    #[test]
+    fn test_determine_retry_strategy() {
+        let retry_info = r#"{"error": {"details": [{
+            "@type": "type.googleapis.com/google.rpc.RetryInfo",
+            "retryDelay": "2s"
+        }]}}"#;
+        assert_eq!(
+            determine_retry_strategy(429, retry_info, false),
+            RetryStrategy::FixedDelay(Duration::from_millis(2200))
+        );
+        // QUOTA_EXHAUSTED 不再直接停止，允许轮换到其他账号
+        assert_eq!(
+            determine_retry_strategy(429, "RESOURCE_EXHAUSTED: QUOTA_EXHAUSTED", false),
+            RetryStrategy::LinearBackoff { base_ms: 1000 }
+        );
+        assert_eq!(
+            determine_retry_strategy(503, "overloaded", false),
+            RetryStrategy::ExponentialBackoff { base_ms: 1000, max_ms: 8000 }
+        );
+        assert_eq!(
+            determine_retry_strategy(403, "forbidden", false),
+            RetryStrategy::FixedDelay(Duration::from_millis(100))
+        );
+        assert_eq!(
+            determine_retry_strategy(400, "Invalid `signature`", false),
+            RetryStrategy::FixedDelay(Duration::from_millis(200))
+        );
+        assert_eq!(determine_retry_strategy(400, "Invalid `signature`", true), RetryStrategy::NoRetry);
+        assert_eq!(determine_retry_strategy(404, "not found", false), RetryStrategy::NoRetry);
+    }
+
+    #[test]
+    fn test_should_rotate_account() {
+        assert!(should_rotate_account(429));
+        assert!(should_rotate_account(401));
+        assert!(!should_rotate_account(503));
+        assert!(!should_rotate_account(400));
+    }
 }
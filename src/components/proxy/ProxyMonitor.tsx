@@ -26,12 +26,21 @@ interface ProxyRequestLog {
     output_tokens?: number;
     account_email?: string;
     protocol?: string;  // "openai" | "anthropic" | "gemini"
+    hedge?: string;     // 对冲结果 (仅在触发对冲时存在)
+    background_task?: string; // 命中的后台任务分类
+    cache?: string;     // 响应缓存状态: "hit" | "miss"
+    api_key?: string;   // API Key 哈希标识 (key-xxxx)
+    cost?: number;      // 估算费用 (美元)
+    cached_input_tokens?: number; // 命中缓存的输入 token
+    thinking_tokens?: number;     // 思考 token
+    session_id?: string;          // 会话指纹
 }
 
 interface ProxyStats {
     total_requests: number;
     success_count: number;
     error_count: number;
+    background_tasks?: Record<string, number>;
 }
 
 interface ProxyMonitorProps {
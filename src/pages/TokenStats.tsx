@@ -10,6 +10,9 @@ interface TokenStatsAggregated {
     total_output_tokens: number;
     total_tokens: number;
     request_count: number;
+    total_cost?: number;
+    total_cached_input_tokens?: number;
+    total_thinking_tokens?: number;
 }
 
 interface AccountTokenStats {
@@ -18,6 +21,11 @@ interface AccountTokenStats {
     total_output_tokens: number;
     total_tokens: number;
     request_count: number;
+    total_cost?: number;
+    total_cached_input_tokens?: number;
+    total_thinking_tokens?: number;
+    cache_hit_rate?: number;
+    request_count_by_status?: Record<string, number>;
 }
 
 interface ModelTokenStats {
@@ -26,16 +34,22 @@ interface ModelTokenStats {
     total_output_tokens: number;
     total_tokens: number;
     request_count: number;
+    total_cost?: number;
+    total_cached_input_tokens?: number;
+    total_thinking_tokens?: number;
+    cache_hit_rate?: number;
 }
 
 interface ModelTrendPoint {
     period: string;
     model_data: Record<string, number>;
+    cache_hit_rate?: Record<string, number>;
 }
 
 interface AccountTrendPoint {
     period: string;
     account_data: Record<string, number>;
+    cache_hit_rate?: Record<string, number>;
 }
 
 interface TokenStatsSummary {
@@ -44,6 +58,10 @@ interface TokenStatsSummary {
     total_tokens: number;
     total_requests: number;
     unique_accounts: number;
+    total_cost?: number;
+    total_cached_input_tokens?: number;
+    total_thinking_tokens?: number;
+    request_count_by_status?: Record<string, number>;
 }
 
 type TimeRange = 'hourly' | 'daily' | 'weekly';
@@ -17,6 +17,27 @@ export interface Account {
     last_used: number;
 }
 
+// 账号健康检查 (reason 写入 disabled_reason / proxy_disabled_reason 时带 "health_check:" 前缀)
+export type HealthReason =
+    | 'invalid_grant'
+    | 'forbidden'
+    | 'unauthorized'
+    | 'no_project'
+    | 'refresh_failed'
+    | 'project_error'
+    | 'quota_error';
+
+export interface HealthCheckRecord {
+    account_id: string;
+    email: string;
+    checked_at: number;
+    status: 'healthy' | 'degraded' | 'unhealthy';
+    reason?: HealthReason | null;
+    message?: string | null;
+    action?: 'disabled' | 'proxy_disabled' | 'recovered' | null;
+    duration_ms: number;
+}
+
 export interface TokenData {
     access_token: string;
     refresh_token: string;
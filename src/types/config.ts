@@ -17,6 +17,106 @@ export interface ProxyConfig {
     zai?: ZaiConfig;
     scheduling?: StickySessionConfig;
     experimental?: ExperimentalConfig;
+    circuit_breaker?: CircuitBreakerConfig;
+    background_tasks?: BackgroundTaskConfig;
+    log_policy?: LogPolicyConfig;
+    telemetry?: TelemetryConfig;
+    cost?: CostConfig;
+    image_store?: ImageStoreConfig;
+    batch?: BatchConfig;
+}
+
+export interface ModelPrice {
+    input: number;
+    output: number;
+    cached_input?: number | null;
+    thinking?: number | null;
+}
+
+export interface BudgetRule {
+    name: string;
+    enabled?: boolean;
+    scope: 'global' | 'api_key' | 'account_group';
+    target?: string;
+    period: 'daily' | 'monthly';
+    limit_usd: number;
+    action: 'warn' | 'block';
+}
+
+export interface CostConfig {
+    prices: Record<string, ModelPrice>;
+    account_groups: Record<string, string[]>;
+    budgets: BudgetRule[];
+}
+
+export interface ImageStoreConfig {
+    retention_hours: number;
+    max_size_mb: number;
+    signed_urls: boolean;
+    url_ttl_minutes: number;
+    public_base_url?: string | null;
+}
+
+export interface BatchConfig {
+    enabled: boolean;
+    concurrency: number;
+    max_attempts: number; // 429 与账号池不可用不计入
+}
+
+export interface TelemetryConfig {
+    enabled: boolean;
+    endpoint: string;
+    protocol: 'http_protobuf' | 'http_json';
+    service_name: string;
+    headers: Record<string, string>;
+    sample_ratio: number;
+}
+
+export interface RedactionRule {
+    name: string;
+    enabled?: boolean;
+    pattern?: string | null;
+    json_path?: string | null;
+    replacement?: string;
+}
+
+export interface LogPolicyConfig {
+    body_mode: 'full' | 'metadata_only';
+    max_body_kb: number;
+    strip_base64: boolean;
+    redaction_rules: RedactionRule[];
+    body_retention_days: number;
+    metadata_retention_days: number;
+}
+
+export type BackgroundTaskAction =
+    | { type: 'route'; model: string }
+    | { type: 'respond'; text: string };
+
+export interface BackgroundTaskClass {
+    name: string;
+    enabled?: boolean;
+    keywords?: string[];
+    regex?: string[];
+    max_length?: number | null;
+    has_tools?: boolean | null;
+    action?: BackgroundTaskAction;
+}
+
+export interface BackgroundTaskConfig {
+    enabled: boolean;
+    match_window_chars: number;
+    classes: BackgroundTaskClass[];
+}
+
+export interface CircuitBreakerConfig {
+    enabled: boolean;
+    window_size: number;
+    min_calls: number;
+    failure_rate_threshold: number;
+    slow_call_ms: number;
+    slow_call_rate_threshold: number;
+    open_duration_secs: number;
 }
 
 export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
@@ -24,6 +124,23 @@ export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
 export interface StickySessionConfig {
     mode: SchedulingMode;
     max_wait_seconds: number;
+    concurrency?: ConcurrencyLimitConfig;
+    queue?: RequestQueueConfig;
+}
+
+export interface RequestQueueConfig {
+    enabled: boolean;
+    max_wait_seconds: number;
+    max_depth: number;
+    keepalive_interval_seconds: number;
+}
+
+export interface ConcurrencyLimitConfig {
+    enabled: boolean;
+    default_max_per_account: number; // 0 = unlimited
+    per_tier: Record<string, number>;
+    per_model: Record<string, number>;
+    queue_wait_ms: number;
 }
 
 export type ZaiDispatchMode = 'off' | 'exclusive' | 'pooled' | 'fallback';
@@ -51,9 +168,49 @@ export interface ZaiConfig {
     mcp: ZaiMcpConfig;
 }
 
+export type WarmupTrigger =
+    | { type: 'cron'; expr: string } // 例如 "0 8 * * 1-5"
+    | { type: 'window'; start: string; end: string; days?: number[] }; // "HH:MM"，0=周日
+
+export interface WarmupSchedule {
+    name: string;
+    enabled: boolean;
+    trigger: WarmupTrigger;
+    accounts?: string[];
+    tags?: string[];
+    models?: string[]; // 为空时使用 monitored_models
+    require_full_quota?: boolean;
+    cooldown_minutes?: number | null;
+    catch_up?: boolean; // 休眠/重启错过后补跑一次
+}
+
 export interface ScheduledWarmupConfig {
     enabled: boolean;
     monitored_models: string[];
+    cooldown_minutes?: number;
+    schedules?: WarmupSchedule[];
+    account_tags?: Record<string, string[]>;
+}
+
+export interface WarmupRun {
+    id: number;
+    schedule: string;
+    trigger: 'schedule' | 'catch_up' | 'missed' | 'quota_full' | 'manual';
+    started_at: number;
+    finished_at?: number | null;
+    success_count: number;
+    failed_count: number;
+    skipped_count: number;
+}
+
+export interface WarmupRunResult {
+    run_id: number;
+    account_email: string;
+    model: string;
+    status: 'success' | 'failed' | 'skipped_cooldown' | 'skipped_quota';
+    percentage?: number | null;
+    message?: string | null;
+    timestamp: number;
 }
 
 export interface QuotaProtectionConfig {
@@ -66,11 +223,47 @@ export interface PinnedQuotaModelsConfig {
     models: string[];
 }
 
+export type UsageBucket = 'hour' | 'day' | 'week' | 'month' | 'total';
+export type UsageDimension = 'account' | 'model' | 'api_key' | 'protocol';
+
+export interface UsageReportConfig {
+    enabled: boolean;
+    directory?: string | null; // 默认 <数据目录>/reports
+    format: 'csv' | 'json';
+    bucket: UsageBucket;
+    group_by: UsageDimension[];
+}
+
+export interface AccountHealthConfig {
+    enabled: boolean;
+    interval_minutes: number;
+    failure_threshold: number; // 403/401/无 project 连续失败次数达到后停用反代
+    auto_disable: boolean;
+    auto_recover: boolean;
+}
+
 export interface ExperimentalConfig {
     enable_usage_scaling: boolean;
     context_compression_threshold_l1?: number;
     context_compression_threshold_l2?: number;
     context_compression_threshold_l3?: number;
+    hedging?: HedgingConfig;
+    response_cache?: ResponseCacheConfig;
+}
+
+export interface ResponseCacheConfig {
+    enabled: boolean;
+    ttl_secs: number;
+    max_size_mb: number;
+    max_entry_kb: number;
+}
+
+export interface HedgingConfig {
+    enabled: boolean;
+    percentile: number;
+    min_samples: number;
+    min_delay_ms: number;
+    max_delay_ms: number;
 }
 
 export interface AppConfig {
@@ -90,6 +283,8 @@ export interface AppConfig {
     scheduled_warmup: ScheduledWarmupConfig;
     quota_protection: QuotaProtectionConfig; // [NEW] 配额保护配置
     pinned_quota_models: PinnedQuotaModelsConfig; // [NEW] 配额关注列表
+    usage_report?: UsageReportConfig; // [NEW] 每日用量报表
+    account_health?: AccountHealthConfig; // [NEW] 账号健康检查
     proxy: ProxyConfig;
 }
 